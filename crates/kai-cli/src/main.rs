@@ -1,7 +1,21 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
 use kai_core::{
+    ast,
     ast::span::span,
-    parser::lexer::{Lexer, Token},
-    runtime::Engine,
+    attrs::{self, Cfg},
+    diagnostic::Diagnostic,
+    doc::{self, DocFormat, DocModule, DocOptions},
+    parser::{
+        self,
+        lexer::{Lexer, Token},
+    },
+    resolve,
+    runtime::{
+        self, disasm, kaic, Backend, Engine, GcConfig, Program, RuntimeError, RuntimeErrorKind,
+    },
+    typeck,
 };
 
 #[allow(unused)]
@@ -45,11 +59,501 @@ pub fn sandbox_lexer() {
     dbg!(thing);
 }
 
-pub fn main() {
+// a file that went through every check, diagnostics are already printed
+struct Loaded {
+    src: String,
+    file: ast::File,
+    resolutions: resolve::Resolutions,
+}
+
+// parses, resolves and type checks a file, printing every diagnostic
+fn load(path: &str, cfg: &Cfg) -> Result<Loaded, ExitCode> {
+    let src = match std::fs::read_to_string(path) {
+        Ok(src) => src,
+        Err(err) => {
+            eprintln!("error: could not read `{path}`: {err}");
+            return Err(ExitCode::FAILURE);
+        }
+    };
+
+    let mut file = match parser::parse(&src) {
+        Ok(file) => file,
+        Err(err) => {
+            eprintln!("{}", Diagnostic::from(err).render(&src, path));
+            return Err(ExitCode::FAILURE);
+        }
+    };
+
+    attrs::configure(&mut file, cfg);
+
+    let resolutions = resolve::resolve(&file);
+    let results = typeck::check(&file, &resolutions);
+
+    let mut diagnostics: Vec<&Diagnostic> = resolutions
+        .diagnostics
+        .iter()
+        .chain(results.diagnostics.iter())
+        .collect();
+    diagnostics.sort_by_key(|d| d.span.start);
+
+    for diagnostic in &diagnostics {
+        eprintln!("{}", diagnostic.render(&src, path));
+    }
+
+    if diagnostics.iter().any(|d| d.is_error()) {
+        return Err(ExitCode::FAILURE);
+    }
+
+    Ok(Loaded {
+        src,
+        file,
+        resolutions,
+    })
+}
+
+fn check(path: &str) -> ExitCode {
+    match load(path, &Cfg::host()) {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(code) => code,
+    }
+}
+
+// checks a file and calls its `main` function, the VM runs `.kaic` files and caches
+// the compiled program of scripts next to them. Arguments after the file are passed to
+// the script.
+fn run(args: &[&str]) -> ExitCode {
+    const USAGE: &str =
+        "usage: kai run [--backend=ast|vm] [--gc-threshold=<n>] [--gc-stress] <file> [args..]";
+
+    let mut path = None;
+    let mut backend = Backend::default();
+    let mut gc = GcConfig::default();
+    let mut script_args: &[&str] = &[];
+
+    for (i, arg) in args.iter().enumerate() {
+        if let Some(name) = arg.strip_prefix("--backend=") {
+            match Backend::from_name(name) {
+                Some(chosen) => backend = chosen,
+                None => {
+                    eprintln!("error: unknown backend `{name}`, expected `ast` or `vm`");
+                    return ExitCode::FAILURE;
+                }
+            }
+        } else if let Some(threshold) = arg.strip_prefix("--gc-threshold=") {
+            match threshold.parse() {
+                Ok(threshold) => gc.threshold = threshold,
+                Err(_) => {
+                    eprintln!("error: invalid gc threshold `{threshold}`");
+                    return ExitCode::FAILURE;
+                }
+            }
+        } else if *arg == "--gc-stress" {
+            gc.stress = true;
+        } else if !arg.starts_with("--") {
+            path = Some(*arg);
+            script_args = &args[i + 1..];
+            break;
+        } else {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let Some(path) = path else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+
+    let mut engine = Engine::default();
+    engine.set_backend(backend);
+    engine.set_gc_config(gc);
+    engine.set_args(script_args.iter().copied());
+
+    let (src, result) = if is_compiled(path) {
+        let kaic = match read_kaic(path) {
+            Ok(kaic) => kaic,
+            Err(code) => return code,
+        };
+        let src = find_source(path, &kaic);
+
+        match engine.run_program(&kaic.program) {
+            Ok(_) => return ExitCode::SUCCESS,
+            Err(RuntimeError {
+                kind: RuntimeErrorKind::Exit(code),
+                ..
+            }) => return exit_code(code),
+            Err(err) => {
+                match &src {
+                    Some((source_path, src)) => report_runtime_error(err, Some(src), source_path),
+                    None => report_runtime_error(err, None, path),
+                }
+                return ExitCode::FAILURE;
+            }
+        }
+    } else if backend == Backend::Vm {
+        match cached_program(path) {
+            Ok((src, program)) => (Some(src), engine.run_program(&program)),
+            Err(code) => return code,
+        }
+    } else {
+        match load(path, &Cfg::host()) {
+            Ok(loaded) => {
+                let result = engine.run(&loaded.file, &loaded.resolutions);
+                (Some(loaded.src), result)
+            }
+            Err(code) => return code,
+        }
+    };
+
+    match result {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(RuntimeError {
+            kind: RuntimeErrorKind::Exit(code),
+            ..
+        }) => exit_code(code),
+        Err(err) => {
+            report_runtime_error(err, src.as_deref(), path);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// the code given to `kai.process.exit`, codes outside of 0..=255 are cut like a shell
+// does
+fn exit_code(code: i32) -> ExitCode {
+    ExitCode::from(code as u8)
+}
+
+fn report_runtime_error(err: RuntimeError, src: Option<&str>, path: &str) {
+    match src {
+        Some(src) => eprintln!("{}", Diagnostic::from(err).render(src, path)),
+        // without the source only function names can be shown
+        None => {
+            eprintln!("error: {err}\n --> {path}");
+            if !err.trace.is_empty() {
+                eprintln!("stack trace:");
+            }
+            for frame in &err.trace {
+                eprintln!("  at {}", frame.function);
+            }
+        }
+    }
+}
+
+fn is_compiled(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext == kaic::EXTENSION)
+}
+
+// `foo.kai` is cached as `foo.kaic`
+fn cache_path(path: &str) -> PathBuf {
+    Path::new(path).with_extension(kaic::EXTENSION)
+}
+
+fn read_kaic(path: &str) -> Result<kaic::Kaic, ExitCode> {
+    let bytes = std::fs::read(path).map_err(|err| {
+        eprintln!("error: could not read `{path}`: {err}");
+        ExitCode::FAILURE
+    })?;
+
+    kaic::decode(&bytes).map_err(|err| {
+        eprintln!("error: could not load `{path}`: {err}");
+        ExitCode::FAILURE
+    })
+}
+
+// the script a compiled file was made from and its path, if it is still next to it
+// and unchanged
+fn find_source(path: &str, kaic: &kaic::Kaic) -> Option<(String, String)> {
+    let source_path = Path::new(path).with_extension("kai");
+    let src = std::fs::read_to_string(&source_path).ok()?;
+    (kaic::source_hash(&src) == kaic.source_hash).then(|| (source_path.display().to_string(), src))
+}
+
+// checks and compiles a file
+fn compile_file(path: &str) -> Result<(String, Program), ExitCode> {
+    let loaded = load(path, &Cfg::host())?;
+
+    match runtime::compile(&loaded.file, &loaded.resolutions) {
+        Ok(program) => Ok((loaded.src, program)),
+        Err(err) => {
+            report_runtime_error(err, Some(&loaded.src), path);
+            Err(ExitCode::FAILURE)
+        }
+    }
+}
+
+// the program of a script, taken from its cache while the source is unchanged
+fn cached_program(path: &str) -> Result<(String, Program), ExitCode> {
+    let cache = cache_path(path);
+
+    if let Ok(src) = std::fs::read_to_string(path) {
+        let cached = std::fs::read(&cache)
+            .ok()
+            .and_then(|bytes| kaic::decode(&bytes).ok())
+            .filter(|kaic| kaic.source_hash == kaic::source_hash(&src));
+
+        if let Some(kaic) = cached {
+            return Ok((src, kaic.program));
+        }
+    }
+
+    let (src, program) = compile_file(path)?;
+    // a missing cache only costs startup time, failing to write one is fine
+    let _ = std::fs::write(&cache, kaic::encode(&program, kaic::source_hash(&src)));
+    Ok((src, program))
+}
+
+fn compile(args: &[&str]) -> ExitCode {
+    const USAGE: &str = "usage: kai compile <file> [-o <out>]";
+
+    let (path, out) = match args {
+        [path] => (*path, cache_path(path)),
+        [path, "-o", out] => (*path, PathBuf::from(out)),
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let (src, program) = match compile_file(path) {
+        Ok(compiled) => compiled,
+        Err(code) => return code,
+    };
+
+    match std::fs::write(&out, kaic::encode(&program, kaic::source_hash(&src))) {
+        Ok(()) => {
+            println!("Compiled `{path}` to `{}`", out.display());
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("error: could not write to `{}`: {err}", out.display());
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// prints the bytecode of a script or of a `.kaic` file
+fn disasm(path: &str) -> ExitCode {
+    let (src, program) = if is_compiled(path) {
+        match read_kaic(path) {
+            Ok(kaic) => (find_source(path, &kaic).map(|(_, src)| src), kaic.program),
+            Err(code) => return code,
+        }
+    } else {
+        match compile_file(path) {
+            Ok((src, program)) => (Some(src), program),
+            Err(code) => return code,
+        }
+    };
+
+    print!("{}", disasm::disassemble(&program, src.as_deref()));
+    ExitCode::SUCCESS
+}
+
+// runs every `@test` function with `@cfg(test)` items enabled
+fn test(path: &str) -> ExitCode {
+    let mut cfg = Cfg::host();
+    cfg.enable("test");
+
+    let loaded = match load(path, &cfg) {
+        Ok(loaded) => loaded,
+        Err(code) => return code,
+    };
+
+    let mut engine = Engine::default();
+    let outcomes = engine.run_tests(&loaded.file, &loaded.resolutions);
+    let mut failed = 0;
+
+    for outcome in &outcomes {
+        match &outcome.result {
+            Ok(()) => println!("test {} ... ok", outcome.name),
+            Err(err) => {
+                failed += 1;
+                println!("test {} ... FAILED", outcome.name);
+                eprintln!(
+                    "{}",
+                    Diagnostic::from(err.clone()).render(&loaded.src, path)
+                );
+            }
+        }
+    }
+
+    let status = if failed == 0 { "ok" } else { "FAILED" };
+    println!(
+        "\ntest result: {status}. {} passed; {failed} failed",
+        outcomes.len() - failed
+    );
+
+    if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+// every `.kai` file under `root` with its module name, `app/models.kai` is `app.models`
+fn find_modules(root: &Path) -> std::io::Result<Vec<(String, PathBuf)>> {
+    if root.is_file() {
+        let name = root.file_stem().unwrap_or_default().to_string_lossy();
+        return Ok(vec![(name.into_owned(), root.to_owned())]);
+    }
+
+    let mut modules = Vec::new();
+    let mut dirs = vec![root.to_owned()];
+
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "kai") {
+                let relative = path.strip_prefix(root).unwrap_or(&path).with_extension("");
+                let segments: Vec<String> = relative
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                    .collect();
+                modules.push((segments.join("."), path));
+            }
+        }
+    }
+
+    modules.sort();
+    Ok(modules)
+}
+
+// `kai doc <path> [--out <dir>] [--format html|md] [--private]`
+fn doc(args: &[&str]) -> ExitCode {
+    const USAGE: &str = "usage: kai doc <path> [--out <dir>] [--format html|md] [--private]";
+
+    let mut root = None;
+    let mut out = PathBuf::from("doc");
+    let mut options = DocOptions {
+        format: DocFormat::Html,
+        private: false,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match (*arg, args.clone().next()) {
+            ("--out", Some(dir)) => {
+                out = PathBuf::from(dir);
+                args.next();
+            }
+            ("--format", Some(&"html")) => {
+                options.format = DocFormat::Html;
+                args.next();
+            }
+            ("--format", Some(&"md" | &"markdown")) => {
+                options.format = DocFormat::Markdown;
+                args.next();
+            }
+            ("--private", _) => options.private = true,
+            (path, _) if root.is_none() && !path.starts_with("--") => root = Some(path),
+            _ => {
+                eprintln!("{USAGE}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    let Some(root) = root else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+
+    let paths = match find_modules(Path::new(root)) {
+        Ok(paths) => paths,
+        Err(err) => {
+            eprintln!("error: could not read `{root}`: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut files = Vec::new();
+
+    for (name, path) in &paths {
+        let display = path.display().to_string();
+        let src = match std::fs::read_to_string(path) {
+            Ok(src) => src,
+            Err(err) => {
+                eprintln!("error: could not read `{display}`: {err}");
+                return ExitCode::FAILURE;
+            }
+        };
+
+        match parser::parse(&src) {
+            Ok(file) => files.push((name.as_str(), file)),
+            Err(err) => {
+                eprintln!("{}", Diagnostic::from(err).render(&src, &display));
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    let modules: Vec<DocModule> = files
+        .iter()
+        .map(|(name, file)| DocModule { name, file })
+        .collect();
+
+    let result = std::fs::create_dir_all(&out).and_then(|_| {
+        for page in doc::generate(&modules, &options) {
+            std::fs::write(out.join(&page.path), page.contents)?;
+        }
+        Ok(())
+    });
+
+    match result {
+        Ok(()) => {
+            println!(
+                "Documented {} modules in `{}`",
+                modules.len(),
+                out.display()
+            );
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("error: could not write to `{}`: {err}", out.display());
+            ExitCode::FAILURE
+        }
+    }
+}
+
+pub fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["check", path] => return check(path),
+        ["check", ..] => {
+            eprintln!("usage: kai check <file>");
+            return ExitCode::FAILURE;
+        }
+        ["run", args @ ..] => return run(args),
+        ["compile", args @ ..] => return compile(args),
+        ["disasm", path] => return disasm(path),
+        ["disasm", ..] => {
+            eprintln!("usage: kai disasm <file>");
+            return ExitCode::FAILURE;
+        }
+        ["test", path] => return test(path),
+        ["test", ..] => {
+            eprintln!("usage: kai test <file>");
+            return ExitCode::FAILURE;
+        }
+        ["doc", args @ ..] => return doc(args),
+        _ => {}
+    }
+
     println!("Welcome to kai");
 
     sandbox_lexer();
 
-    let mut engine = Engine::default();
-    engine.run(false);
+    ExitCode::SUCCESS
 }
@@ -1,9 +1,19 @@
+use ecow::EcoString;
 use thin_vec::ThinVec;
 
 use self::span::Span;
 
 pub mod span;
 
+/// Identifies a node in the ast, assigned by the parser in source order.
+/// Later passes (resolver, checker, runtime) keep their results in side tables keyed by it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct NodeId(pub u32);
+
+impl NodeId {
+    pub const DUMMY: NodeId = NodeId(u32::MAX);
+}
+
 #[derive(Debug)]
 pub struct File {
     pub shebang: Option<String>,
@@ -11,8 +21,51 @@ pub struct File {
     pub span: Span,
 }
 
+impl File {
+    /// Every item including the ones nested in function and method bodies
+    pub fn all_items(&self) -> Vec<&Item> {
+        let mut out = Vec::new();
+        collect_items(self.items.iter(), &mut out);
+        out
+    }
+}
+
+fn collect_items<'a>(items: impl Iterator<Item = &'a Item>, out: &mut Vec<&'a Item>) {
+    for item in items {
+        out.push(item);
+
+        let bodies: Vec<&Block> = match &item.kind {
+            ItemKind::Fn(item_fn) => item_fn.body.as_deref().into_iter().collect(),
+            kind => kind
+                .methods()
+                .iter()
+                .filter_map(|method| match &method.kind {
+                    ItemKind::Fn(item_fn) => item_fn.body.as_deref(),
+                    _ => None,
+                })
+                .collect(),
+        };
+
+        for body in bodies {
+            collect_block_items(body, out);
+        }
+    }
+}
+
+fn collect_block_items<'a>(block: &'a Block, out: &mut Vec<&'a Item>) {
+    let items = block.stmts.iter().filter_map(|stmt| match &stmt.kind {
+        StmtKind::Item(item) => Some(item.as_ref()),
+        _ => None,
+    });
+
+    collect_items(items, out);
+}
+
 #[derive(Debug, Clone)]
 pub struct Item {
+    pub id: NodeId,
+    // the text of the `/** .. */` comments in front of the item
+    pub doc: Option<String>,
     pub kind: ItemKind,
     pub span: Span,
     pub ident: Ident,
@@ -24,13 +77,87 @@ pub enum ItemKind {
     Fn(Box<ItemFn>),
 
     Use(ItemUse),
+
+    Class(Box<ItemClass>),
+
+    Interface(Box<ItemInterface>),
+
+    Impl(Box<ItemImpl>),
+}
+
+impl ItemKind {
+    /// Methods of a class, an interface or an impl
+    pub fn methods(&self) -> &[Item] {
+        match self {
+            ItemKind::Class(item_class) => &item_class.methods,
+            ItemKind::Interface(item_interface) => &item_interface.methods,
+            ItemKind::Impl(item_impl) => &item_impl.methods,
+            ItemKind::Fn(_) | ItemKind::Use(_) => &[],
+        }
+    }
+
+    pub fn attrs(&self) -> &Attrs {
+        match self {
+            ItemKind::Fn(item_fn) => &item_fn.attrs,
+            ItemKind::Use(item_use) => &item_use.attrs,
+            ItemKind::Class(item_class) => &item_class.attrs,
+            ItemKind::Interface(item_interface) => &item_interface.attrs,
+            ItemKind::Impl(item_impl) => &item_impl.attrs,
+        }
+    }
 }
 
+/*
+    @test
+    @deprecated("use `bar` instead")
+    @cfg(os = "linux")
+*/
 #[derive(Debug, Clone)]
 pub struct Attribute {
+    pub path: Path,
+    pub args: ThinVec<AttrArg>,
     pub span: Span,
 }
 
+impl Attribute {
+    /// The path joined with `.`, eg. `deprecated`
+    pub fn name(&self) -> String {
+        let names: Vec<&str> = self.path.segments.iter().map(|i| i.name.as_str()).collect();
+        names.join(".")
+    }
+
+    /// The first string argument, the message of `@deprecated("use bar")`
+    pub fn str_arg(&self) -> Option<&str> {
+        self.args.iter().find_map(|arg| match arg {
+            AttrArg::Lit(Lit {
+                kind: LitKind::Str(value),
+                ..
+            }) => Some(value.as_str()),
+            _ => None,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum AttrArg {
+    // `"use bar"`
+    Lit(Lit),
+    // `test` in `@cfg(test)`
+    Word(Ident),
+    // `os = "linux"`
+    KeyValue(Ident, Lit),
+}
+
+impl AttrArg {
+    pub fn span(&self) -> Span {
+        match self {
+            AttrArg::Lit(lit) => lit.span,
+            AttrArg::Word(ident) => ident.span,
+            AttrArg::KeyValue(key, value) => Span::new(key.span.start, value.span.end),
+        }
+    }
+}
+
 pub type Attrs = ThinVec<Attribute>;
 
 #[derive(Debug, Clone)]
@@ -46,6 +173,20 @@ pub struct Ident {
     pub span: Span,
 }
 
+impl Ident {
+    pub fn new(name: impl Into<String>, span: Span) -> Self {
+        Self {
+            name: name.into(),
+            span,
+        }
+    }
+
+    /// Names starting with `_` are never reported as unused
+    pub fn is_discarded(&self) -> bool {
+        self.name.starts_with('_')
+    }
+}
+
 //BEGIN ItemUse
 
 /*
@@ -70,7 +211,7 @@ pub struct Stmt {
 #[derive(Debug, Clone)]
 pub enum StmtKind {
     // let binding
-    Let,
+    Let(Box<Local>),
     // any item
     Item(Box<Item>),
     // expr which ends without a semi-colon
@@ -81,32 +222,255 @@ pub enum StmtKind {
     Empty,
 }
 
+/*
+    let x = 10;
+    let mut y;
+*/
+#[derive(Debug, Clone)]
+pub struct Local {
+    pub id: NodeId,
+    pub pat: Pattern,
+    pub ty: Option<Ty>,
+    pub init: Option<Box<Expr>>,
+    pub span: Span,
+}
+
 #[derive(Debug, Clone)]
 pub struct Expr {
+    pub id: NodeId,
     pub span: Span,
     pub kind: ExprKind,
 }
 
 #[derive(Debug, Clone)]
-pub enum ExprKind {}
+pub enum ExprKind {
+    Lit(Lit),
+    // a name or a `self`
+    Path(Path),
+    Unary(UnOp, Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    // a = b
+    Assign(Box<Expr>, Box<Expr>),
+    // a += b
+    AssignOp(BinOp, Box<Expr>, Box<Expr>),
+    // callee(args)
+    Call(Box<Expr>, ThinVec<Expr>),
+    // receiver.method(args)
+    MethodCall(Box<MethodCall>),
+    // thing.field
+    Field(Box<Expr>, Ident),
+    // Person { name, age: 20 }
+    ClassInit(Box<ClassInit>),
+    // [1, 2, 3]
+    List(ThinVec<Expr>),
+    // {"a": 1, "b": 2}, `{:}` is the empty map
+    Map(ThinVec<(Expr, Expr)>),
+    // {1, 2, 3}, a single element needs a trailing comma to not be a block
+    Set(ThinVec<Expr>),
+    // xs[i], or xs[1..3] for a slice
+    Index(Box<Expr>, Box<Expr>),
+    // 1..10 or 1..=10
+    Range(Option<Box<Expr>>, Option<Box<Expr>>, RangeLimits),
+    If(Box<Expr>, Box<Block>, Option<Box<Expr>>),
+    While(Box<Expr>, Box<Block>),
+    Loop(Box<Block>),
+    For(Box<Pattern>, Box<Expr>, Box<Block>),
+    Block(Box<Block>),
+    Paren(Box<Expr>),
+    Return(Option<Box<Expr>>),
+    Break(Option<Box<Expr>>),
+    Continue,
+    Panic(Option<Box<Expr>>),
+    // value?, returns the value from the current fn if it is an error. A fn without a return
+    // type passes it to its caller, `main` ends the script with it.
+    Try(Box<Expr>),
+    // yield value, evaluates to the value the generator is resumed with
+    Yield(Option<Box<Expr>>),
+    // await future
+    Await(Box<Expr>),
+    Todo,
+}
+
+impl ExprKind {
+    /// Block like expressions can be used as statements without a trailing `;`
+    pub fn is_block_like(&self) -> bool {
+        matches!(
+            self,
+            ExprKind::If(..)
+                | ExprKind::While(..)
+                | ExprKind::Loop(..)
+                | ExprKind::For(..)
+                | ExprKind::Block(..)
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MethodCall {
+    pub receiver: Expr,
+    pub method: Ident,
+    pub args: ThinVec<Expr>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClassInit {
+    pub path: Path,
+    pub fields: ThinVec<FieldInit>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct FieldInit {
+    pub ident: Ident,
+    pub expr: Expr,
+    // `Person { name }` instead of `Person { name: name }`
+    pub is_shorthand: bool,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeLimits {
+    // ..
+    HalfOpen,
+    // ..=
+    Closed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnOp {
+    Neg,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    And,
+    Or,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+impl BinOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Rem => "%",
+            BinOp::And => "&&",
+            BinOp::Or => "||",
+            BinOp::Eq => "==",
+            BinOp::NotEq => "!=",
+            BinOp::Lt => "<",
+            BinOp::LtEq => "<=",
+            BinOp::Gt => ">",
+            BinOp::GtEq => ">=",
+        }
+    }
+
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            BinOp::Eq | BinOp::NotEq | BinOp::Lt | BinOp::LtEq | BinOp::Gt | BinOp::GtEq
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Lit {
+    pub kind: LitKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LitKind {
+    Int(i64),
+    Float(f64),
+    Str(EcoString),
+    Bool(bool),
+    Nil,
+}
 
 #[derive(Debug, Clone)]
 pub struct Block {
+    pub id: NodeId,
     pub span: Span,
     pub stmts: ThinVec<Stmt>,
 }
 
 #[derive(Debug, Clone)]
 pub enum PatternKind {
-    // TODO
+    // x, mut x, _x
+    Ident { ident: Ident, mutable: bool },
+    // _
+    Wild,
 }
 
 #[derive(Debug, Clone)]
 pub struct Pattern {
+    pub id: NodeId,
     pub kind: PatternKind,
     pub span: Span,
 }
 
+//BEGIN Ty
+
+/*
+    int
+    list<string>
+    map<string, Person>
+    fn(int, int) -> int
+    int?
+*/
+#[derive(Debug, Clone)]
+pub struct Ty {
+    pub id: NodeId,
+    pub kind: TyKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum TyKind {
+    Path(Path, ThinVec<Ty>),
+    Fn(ThinVec<Ty>, Option<Box<Ty>>),
+    // the inner type or `nil`
+    Optional(Box<Ty>),
+}
+
+/*
+    fn first<T>(xs: list<T>) -> T
+    class Pair<A, B: Ord>
+    fn sum<T: Num + Ord>(xs: list<T>) -> T
+
+    generics only exist for the checker, the runtime never sees type arguments
+*/
+#[derive(Debug, Clone, Default)]
+pub struct Generics {
+    pub params: ThinVec<GenericParam>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct GenericParam {
+    pub id: NodeId,
+    pub ident: Ident,
+    pub bounds: ThinVec<Ty>,
+    pub span: Span,
+}
+
+//END Ty
+
 //BEGIN  ItemUse
 #[derive(Debug, Clone)]
 pub struct Path {
@@ -114,8 +478,18 @@ pub struct Path {
     pub span: Span,
 }
 
+impl Path {
+    pub fn from_ident(ident: Ident) -> Self {
+        let span = ident.span;
+        let mut segments = ThinVec::new();
+        segments.push(ident);
+        Self { segments, span }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct UseTree {
+    pub id: NodeId,
     pub prefix: Path,
     pub span: Span,
     pub kind: UseTreeKind,
@@ -148,6 +522,17 @@ pub enum UseTreeKind {
     Glob,
 }
 
+impl UseTree {
+    /// The name this tree binds in the current scope, `None` for groups and globs
+    pub fn binding(&self) -> Option<&Ident> {
+        match &self.kind {
+            UseTreeKind::Simple(Some(rename)) => Some(rename),
+            UseTreeKind::Simple(None) => self.prefix.segments.last(),
+            _ => None,
+        }
+    }
+}
+
 //END  ItemUse
 
 //BEGIN ItemFn
@@ -156,15 +541,158 @@ pub enum UseTreeKind {
 pub struct Param {
     pub attrs: Attrs,
     pub pat: Pattern,
+    pub ty: Option<Ty>,
     pub span: Span,
 }
 
+impl Param {
+    pub fn is_self(&self) -> bool {
+        matches!(&self.pat.kind, PatternKind::Ident { ident, .. } if ident.name == "self")
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ItemFn {
     pub attrs: Attrs,
     pub ident: Ident,
+    pub generics: Generics,
     pub inputs: ThinVec<Param>,
+    pub output: Option<Ty>,
     pub body: Option<Box<Block>>,
+    // `async fn`
+    pub is_async: bool,
+    // the body contains a `yield`
+    pub is_generator: bool,
+}
+
+impl ItemFn {
+    /// Methods taking `self` as their first parameter are called on instances
+    pub fn has_self(&self) -> bool {
+        self.inputs.first().is_some_and(Param::is_self)
+    }
+
+    /// Calling a coroutine fn returns a coroutine instead of running the body
+    pub fn coroutine(&self) -> Option<CoroutineKind> {
+        if self.is_async {
+            Some(CoroutineKind::Async)
+        } else if self.is_generator {
+            Some(CoroutineKind::Generator)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoroutineKind {
+    // resumed by `next`, `send` or a `for` loop, suspends at every `yield`
+    Generator,
+    // run by the executor, suspends at every `await` which is not ready yet
+    Async,
 }
 
 //END ItemFn
+
+//BEGIN ItemClass
+
+/*
+    pub class Person {
+        pub name: string;
+
+        pub new(name: string) {
+            Person { name }
+        }
+
+        pub fn greet(self) -> string {
+            self.name
+        }
+    }
+*/
+#[derive(Debug, Clone)]
+pub struct ItemClass {
+    pub attrs: Attrs,
+    pub ident: Ident,
+    pub generics: Generics,
+    pub fields: ThinVec<FieldDef>,
+    // every method is an `Item` with `ItemKind::Fn`
+    pub methods: ThinVec<Item>,
+}
+
+impl ItemClass {
+    pub fn field(&self, name: &str) -> Option<&FieldDef> {
+        self.fields.iter().find(|field| field.ident.name == name)
+    }
+
+    pub fn method(&self, name: &str) -> Option<&ItemFn> {
+        find_method(&self.methods, name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FieldDef {
+    pub id: NodeId,
+    pub doc: Option<String>,
+    pub attrs: Attrs,
+    pub visibility: Visibility,
+    pub ident: Ident,
+    pub ty: Ty,
+    pub span: Span,
+}
+
+//END ItemClass
+
+//BEGIN ItemInterface
+
+/*
+    pub interface Shape {
+        fn area(self) -> float;
+
+        // methods with a body are used when an impl leaves them out
+        fn describe(self) -> string {
+            "a shape"
+        }
+    }
+
+    impl Shape for Circle {
+        fn area(self) -> float {
+            3.14 * self.radius * self.radius
+        }
+    }
+*/
+#[derive(Debug, Clone)]
+pub struct ItemInterface {
+    pub attrs: Attrs,
+    pub ident: Ident,
+    // every method is an `Item` with `ItemKind::Fn`
+    pub methods: ThinVec<Item>,
+}
+
+impl ItemInterface {
+    pub fn method(&self, name: &str) -> Option<&ItemFn> {
+        find_method(&self.methods, name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ItemImpl {
+    pub attrs: Attrs,
+    pub interface: Ty,
+    // the class implementing the interface
+    pub class: Ty,
+    pub methods: ThinVec<Item>,
+}
+
+impl ItemImpl {
+    pub fn method(&self, name: &str) -> Option<&ItemFn> {
+        find_method(&self.methods, name)
+    }
+}
+
+fn find_method<'a>(methods: &'a [Item], name: &str) -> Option<&'a ItemFn> {
+    methods.iter().find_map(|item| match &item.kind {
+        ItemKind::Fn(item_fn) if item_fn.ident.name == name => Some(item_fn.as_ref()),
+        _ => None,
+    })
+}
+
+//END ItemInterface
@@ -0,0 +1,383 @@
+use std::collections::{HashMap, HashSet};
+
+use thin_vec::ThinVec;
+
+use crate::ast::*;
+use crate::diagnostic::Diagnostic;
+
+/// What an attribute is written on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttrTarget {
+    Fn,
+    Method,
+    Class,
+    Interface,
+    Impl,
+    Use,
+    Field,
+    Param,
+}
+
+impl AttrTarget {
+    pub fn of_item(kind: &ItemKind) -> Self {
+        match kind {
+            ItemKind::Fn(_) => AttrTarget::Fn,
+            ItemKind::Use(_) => AttrTarget::Use,
+            ItemKind::Class(_) => AttrTarget::Class,
+            ItemKind::Interface(_) => AttrTarget::Interface,
+            ItemKind::Impl(_) => AttrTarget::Impl,
+        }
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            AttrTarget::Fn => "a function",
+            AttrTarget::Method => "a method",
+            AttrTarget::Class => "a class",
+            AttrTarget::Interface => "an interface",
+            AttrTarget::Impl => "an impl",
+            AttrTarget::Use => "a `using` item",
+            AttrTarget::Field => "a field",
+            AttrTarget::Param => "a parameter",
+        }
+    }
+}
+
+/// The arguments an attribute takes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttrArgs {
+    // `@inline`
+    None,
+    // `@deprecated` or `@deprecated("use bar")`
+    OptionalStr,
+    // `@cfg(test)`, `@cfg(os = "linux", arch = "x86_64")`
+    List,
+}
+
+/// Describes an attribute the checker knows about
+#[derive(Debug, Clone)]
+pub struct AttrSpec {
+    pub name: &'static str,
+    pub targets: &'static [AttrTarget],
+    pub args: AttrArgs,
+}
+
+impl AttrSpec {
+    // how the attribute is written, used when it is malformed
+    fn template(&self) -> String {
+        let name = self.name;
+
+        match self.args {
+            AttrArgs::None => format!("`@{name}`"),
+            AttrArgs::OptionalStr => format!("`@{name}` or `@{name}(\"message\")`"),
+            AttrArgs::List => format!("`@{name}(key = \"value\")`"),
+        }
+    }
+}
+
+const ITEMS: &[AttrTarget] = &[
+    AttrTarget::Fn,
+    AttrTarget::Method,
+    AttrTarget::Class,
+    AttrTarget::Interface,
+    AttrTarget::Impl,
+    AttrTarget::Use,
+    AttrTarget::Field,
+];
+
+pub const BUILTIN_ATTRS: &[AttrSpec] = &[
+    // run by `kai test`
+    AttrSpec {
+        name: "test",
+        targets: &[AttrTarget::Fn],
+        args: AttrArgs::None,
+    },
+    // uses of the item are warned about
+    AttrSpec {
+        name: "deprecated",
+        targets: ITEMS,
+        args: AttrArgs::OptionalStr,
+    },
+    // a hint for the compiler
+    AttrSpec {
+        name: "inline",
+        targets: &[AttrTarget::Fn, AttrTarget::Method],
+        args: AttrArgs::None,
+    },
+    // the item is removed unless the configuration matches, see `Cfg`
+    AttrSpec {
+        name: "cfg",
+        targets: ITEMS,
+        args: AttrArgs::List,
+    },
+];
+
+/// Every attribute the checker knows about, unknown attributes are warned about
+#[derive(Debug, Clone)]
+pub struct AttrRegistry {
+    specs: HashMap<&'static str, AttrSpec>,
+}
+
+impl Default for AttrRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+
+        for spec in BUILTIN_ATTRS {
+            registry.register(spec.clone());
+        }
+
+        registry
+    }
+}
+
+impl AttrRegistry {
+    /// A registry without even the builtin attributes
+    pub fn empty() -> Self {
+        Self {
+            specs: HashMap::new(),
+        }
+    }
+
+    /// Makes `@name` known, replacing any attribute with the same name
+    pub fn register(&mut self, spec: AttrSpec) {
+        self.specs.insert(spec.name, spec);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&AttrSpec> {
+        self.specs.get(name)
+    }
+
+    /// Checks the attributes written on `target` against their specs
+    pub fn check(&self, attrs: &[Attribute], target: AttrTarget) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+
+        for attr in attrs {
+            let name = attr.name();
+
+            let Some(spec) = self.get(&name) else {
+                let message = format!("unknown attribute `{name}`");
+                diagnostics.push(Diagnostic::warning(message, attr.path.span));
+                continue;
+            };
+
+            if !spec.targets.contains(&target) {
+                let message = format!("attribute `{name}` cannot be used on {}", target.describe());
+                diagnostics.push(Diagnostic::error(message, attr.span));
+                continue;
+            }
+
+            let well_formed = match spec.args {
+                AttrArgs::None => attr.args.is_empty(),
+                AttrArgs::OptionalStr => match &attr.args[..] {
+                    [] => true,
+                    [AttrArg::Lit(lit)] => matches!(lit.kind, LitKind::Str(_)),
+                    _ => false,
+                },
+                AttrArgs::List => {
+                    !attr.args.is_empty()
+                        && attr.args.iter().all(|arg| !matches!(arg, AttrArg::Lit(_)))
+                }
+            };
+
+            if !well_formed {
+                let message = format!("malformed `{name}` attribute, expected {}", spec.template());
+                diagnostics.push(Diagnostic::error(message, attr.span));
+            }
+        }
+
+        diagnostics
+    }
+}
+
+/// Finds `@name` among `attrs`
+pub fn find<'a>(attrs: &'a [Attribute], name: &str) -> Option<&'a Attribute> {
+    attrs.iter().find(|attr| attr.name() == name)
+}
+
+/// The configuration `@cfg(..)` is evaluated against
+#[derive(Debug, Clone, Default)]
+pub struct Cfg {
+    // `os = "linux"`
+    values: HashMap<String, String>,
+    // `test`
+    flags: HashSet<String>,
+}
+
+impl Cfg {
+    /// The configuration of the machine running kai
+    pub fn host() -> Self {
+        let mut cfg = Self::default();
+        cfg.set("os", std::env::consts::OS);
+        cfg.set("arch", std::env::consts::ARCH);
+        cfg.set("family", std::env::consts::FAMILY);
+        cfg
+    }
+
+    pub fn set(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.values.insert(key.into(), value.into());
+    }
+
+    pub fn enable(&mut self, flag: impl Into<String>) {
+        self.flags.insert(flag.into());
+    }
+
+    /// Whether every `@cfg(..)` among `attrs` holds, each of their arguments must match.
+    /// `@test` functions behave as if they had `@cfg(test)`.
+    pub fn is_enabled(&self, attrs: &[Attribute]) -> bool {
+        if find(attrs, "test").is_some() && !self.flags.contains("test") {
+            return false;
+        }
+
+        attrs
+            .iter()
+            .filter(|attr| attr.name() == "cfg")
+            .flat_map(|attr| attr.args.iter())
+            .all(|arg| match arg {
+                AttrArg::Word(flag) => self.flags.contains(&flag.name),
+                AttrArg::KeyValue(
+                    key,
+                    Lit {
+                        kind: LitKind::Str(value),
+                        ..
+                    },
+                ) => self
+                    .values
+                    .get(&key.name)
+                    .is_some_and(|v| v == value.as_str()),
+                _ => false,
+            })
+    }
+}
+
+/// Removes the items, fields and methods whose `@cfg(..)` does not hold
+pub fn configure(file: &mut File, cfg: &Cfg) {
+    configure_items(&mut file.items, cfg);
+}
+
+fn configure_items(items: &mut Vec<Item>, cfg: &Cfg) {
+    items.retain(|item| cfg.is_enabled(item.kind.attrs()));
+
+    for item in items.iter_mut() {
+        match &mut item.kind {
+            ItemKind::Class(item_class) => {
+                item_class
+                    .fields
+                    .retain(|field| cfg.is_enabled(&field.attrs));
+                configure_methods(&mut item_class.methods, cfg);
+            }
+            ItemKind::Interface(item_interface) => {
+                configure_methods(&mut item_interface.methods, cfg)
+            }
+            ItemKind::Impl(item_impl) => configure_methods(&mut item_impl.methods, cfg),
+            ItemKind::Fn(_) | ItemKind::Use(_) => {}
+        }
+    }
+}
+
+fn configure_methods(methods: &mut ThinVec<Item>, cfg: &Cfg) {
+    methods.retain(|method| cfg.is_enabled(method.kind.attrs()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::Severity;
+    use crate::parser::parse;
+
+    fn check(code: &str) -> Vec<(Severity, String)> {
+        let file = parse(code).unwrap();
+        let registry = AttrRegistry::default();
+
+        file.items
+            .iter()
+            .flat_map(|item| registry.check(item.kind.attrs(), AttrTarget::of_item(&item.kind)))
+            .map(|d| (d.severity, d.message))
+            .collect()
+    }
+
+    #[test]
+    fn checks_attributes_against_the_registry() {
+        assert_eq!(
+            check(r#"@test @inline @deprecated("use b") @cfg(test) fn a() {}"#),
+            vec![]
+        );
+        assert_eq!(
+            check("@tset fn a() {} @test class A {}"),
+            vec![
+                (Severity::Warning, "unknown attribute `tset`".to_owned()),
+                (
+                    Severity::Error,
+                    "attribute `test` cannot be used on a class".to_owned()
+                ),
+            ]
+        );
+        assert_eq!(
+            check(r#"@inline(1) fn a() {} @deprecated(1) fn b() {} @cfg fn c() {}"#),
+            vec![
+                (
+                    Severity::Error,
+                    "malformed `inline` attribute, expected `@inline`".to_owned()
+                ),
+                (
+                    Severity::Error,
+                    r#"malformed `deprecated` attribute, expected `@deprecated` or `@deprecated("message")`"#.to_owned()
+                ),
+                (
+                    Severity::Error,
+                    r#"malformed `cfg` attribute, expected `@cfg(key = "value")`"#.to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn registers_custom_attributes() {
+        let file = parse("@route(path = \"/\") fn index() {}").unwrap();
+        let attrs = file.items[0].kind.attrs();
+
+        let mut registry = AttrRegistry::default();
+        assert_eq!(registry.check(attrs, AttrTarget::Fn).len(), 1);
+
+        registry.register(AttrSpec {
+            name: "route",
+            targets: &[AttrTarget::Fn],
+            args: AttrArgs::List,
+        });
+        assert_eq!(registry.check(attrs, AttrTarget::Fn), vec![]);
+    }
+
+    #[test]
+    fn removes_disabled_items() {
+        let mut file = parse(
+            r#"
+            @cfg(os = "linux") fn linux() {}
+            @cfg(os = "plan9") fn plan9() {}
+            @cfg(test) fn helper() {}
+            @test fn check() {}
+            class A {
+                @cfg(os = "plan9") x: int;
+                @cfg(test) fn check(self) {}
+            }
+            "#,
+        )
+        .unwrap();
+
+        let mut cfg = Cfg::default();
+        cfg.set("os", "linux");
+        configure(&mut file, &cfg);
+
+        let names: Vec<&str> = file.items.iter().map(|i| i.ident.name.as_str()).collect();
+        assert_eq!(names, ["linux", "A"]);
+
+        let ItemKind::Class(item_class) = &file.items[1].kind else {
+            panic!("expected a class");
+        };
+        assert!(item_class.fields.is_empty());
+        assert!(item_class.methods.is_empty());
+
+        let mut file = parse("@cfg(test) fn helper() {} @test fn check() {}").unwrap();
+        cfg.enable("test");
+        configure(&mut file, &cfg);
+        assert_eq!(file.items.len(), 2);
+    }
+}
@@ -0,0 +1,249 @@
+use std::fmt::{Display, Write};
+
+use crate::ast::span::Span;
+use crate::parser::error::ParseError;
+
+// lines of a stack trace shown before the rest is cut, deep mutual recursion isn't collapsed
+const MAX_TRACE_LINES: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    // secondary spans, eg. the previous definition of a duplicate name
+    pub labels: Vec<Label>,
+    // calls active when a runtime error happened, innermost first
+    pub trace: Vec<TraceFrame>,
+}
+
+/// A function being run when an error happened and where it was in that function
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceFrame {
+    pub function: String,
+    pub span: Span,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.into(),
+            span,
+            labels: Vec::new(),
+            trace: Vec::new(),
+        }
+    }
+
+    pub fn warning(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message: message.into(),
+            span,
+            labels: Vec::new(),
+            trace: Vec::new(),
+        }
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_trace(mut self, trace: Vec<TraceFrame>) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Renders the diagnostic with the offending source line and a `^^^` marker under the
+    /// span, followed by the stack trace if there is one
+    pub fn render(&self, src: &str, file_name: &str) -> String {
+        let mut out = String::new();
+        let (line, col) = line_col(src, self.span.start);
+
+        let _ = writeln!(out, "{}: {}", self.severity, self.message);
+        let _ = writeln!(out, "  --> {file_name}:{line}:{col}");
+        render_snippet(&mut out, src, self.span, "");
+
+        for label in &self.labels {
+            render_snippet(&mut out, src, label.span, &label.message);
+        }
+
+        if !self.trace.is_empty() {
+            out.push_str("stack trace:\n");
+        }
+
+        // a recursive call repeats the same frame, it is shown once with how often it repeats
+        let mut frames = self.trace.iter().peekable();
+        let mut shown = 0;
+        while let Some(frame) = frames.next() {
+            if shown == MAX_TRACE_LINES {
+                let _ = writeln!(out, "  ... {} more calls", frames.len() + 1);
+                break;
+            }
+            shown += 1;
+
+            let (line, col) = line_col(src, frame.span.start);
+            let _ = writeln!(out, "  at {} ({file_name}:{line}:{col})", frame.function);
+
+            let mut repeats = 0;
+            while frames.next_if_eq(&frame).is_some() {
+                repeats += 1;
+            }
+            match repeats {
+                0 => {}
+                1 => {
+                    let _ = writeln!(out, "  ... 1 more call to `{}`", frame.function);
+                }
+                _ => {
+                    let _ = writeln!(out, "  ... {repeats} more calls to `{}`", frame.function);
+                }
+            }
+        }
+
+        out
+    }
+}
+
+impl From<ParseError> for Diagnostic {
+    fn from(value: ParseError) -> Self {
+        Diagnostic::error(value.kind.to_string(), value.location)
+    }
+}
+
+/// 1 based line and column of a char offset, spans count chars not bytes
+pub fn line_col(src: &str, offset: usize) -> (usize, usize) {
+    let mut line = 1;
+    let mut col = 1;
+
+    for c in src.chars().take(offset) {
+        if c == '\n' {
+            line += 1;
+            col = 1;
+        } else {
+            col += 1;
+        }
+    }
+
+    (line, col)
+}
+
+fn render_snippet(out: &mut String, src: &str, span: Span, message: &str) {
+    let (line, col) = line_col(src, span.start);
+    let text = src.lines().nth(line - 1).unwrap_or_default();
+    let gutter = " ".repeat(line.to_string().len());
+
+    let width = text.chars().count().saturating_sub(col - 1);
+    let len = (span.end - span.start).clamp(1, width.max(1));
+
+    let _ = writeln!(out, "{gutter} |");
+    let _ = writeln!(out, "{line} | {text}");
+    let _ = write!(out, "{gutter} | {}{}", " ".repeat(col - 1), "^".repeat(len));
+
+    if message.is_empty() {
+        out.push('\n');
+    } else {
+        let _ = writeln!(out, " {message}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_line_and_column() {
+        let src = "ab\ncd\n\nef";
+        assert_eq!(line_col(src, 0), (1, 1));
+        assert_eq!(line_col(src, 4), (2, 2));
+        assert_eq!(line_col(src, 7), (4, 1));
+    }
+
+    #[test]
+    fn renders_span_with_carets() {
+        let src = "fn main() {\n    foo(1);\n}";
+        let diagnostic = Diagnostic::error("cannot find `foo` in this scope", Span::new(16, 19));
+
+        assert_eq!(
+            diagnostic.render(src, "main.kai"),
+            "error: cannot find `foo` in this scope\n  --> main.kai:2:5\n  |\n2 |     foo(1);\n  |     ^^^\n"
+        );
+    }
+
+    #[test]
+    fn renders_stack_traces() {
+        let src = "fn f() {\n    panic \"no\";\n}\nfn main() { f() }";
+        let diagnostic = Diagnostic::error("panicked: no", Span::new(13, 23)).with_trace(vec![
+            TraceFrame {
+                function: "f".into(),
+                span: Span::new(13, 23),
+            },
+            TraceFrame {
+                function: "main".into(),
+                span: Span::new(39, 42),
+            },
+        ]);
+
+        assert!(diagnostic.render(src, "main.kai").ends_with(
+            "  |     ^^^^^^^^^^\nstack trace:\n  at f (main.kai:2:5)\n  at main (main.kai:4:13)\n"
+        ));
+    }
+
+    #[test]
+    fn collapses_recursive_frames() {
+        let src = "fn f(n) {\n    f(n + 1)\n}\nfn g() { f(0) }\nfn main() { g() }";
+        let frame = |function: &str, start, end| TraceFrame {
+            function: function.into(),
+            span: Span::new(start, end),
+        };
+
+        let mut trace = vec![frame("f", 14, 22); 1020];
+        trace.push(frame("g", 34, 38));
+        trace.push(frame("g", 34, 38));
+        trace.push(frame("main", 53, 56));
+        let diagnostic = Diagnostic::error("stack overflow", Span::new(14, 22)).with_trace(trace);
+
+        assert!(diagnostic.render(src, "main.kai").ends_with(
+            "stack trace:\n  at f (main.kai:2:5)\n  ... 1019 more calls to `f`\n  \
+             at g (main.kai:4:10)\n  ... 1 more call to `g`\n  at main (main.kai:5:13)\n"
+        ));
+
+        // calls between several functions aren't collapsed, only the first ones are shown
+        let trace = (0..100)
+            .map(|i| frame(if i % 2 == 0 { "f" } else { "g" }, 14, 22))
+            .collect();
+        let diagnostic = Diagnostic::error("stack overflow", Span::new(14, 22)).with_trace(trace);
+        let rendered = diagnostic.render(src, "main.kai");
+
+        assert_eq!(rendered.matches("\n  at ").count(), MAX_TRACE_LINES);
+        assert!(rendered.ends_with("  at g (main.kai:2:5)\n  ... 68 more calls\n"));
+    }
+}
@@ -0,0 +1,910 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::ast::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocFormat {
+    Html,
+    Markdown,
+}
+
+impl DocFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            DocFormat::Html => "html",
+            DocFormat::Markdown => "md",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DocOptions {
+    pub format: DocFormat,
+    // also document items which are not `pub`
+    pub private: bool,
+}
+
+/// A module to document, `name` is its dotted path like `app.models`
+#[derive(Debug, Clone, Copy)]
+pub struct DocModule<'a> {
+    pub name: &'a str,
+    pub file: &'a File,
+}
+
+/// A rendered page, `path` is relative to the output directory
+#[derive(Debug, Clone)]
+pub struct DocPage {
+    pub path: String,
+    pub contents: String,
+}
+
+/// Renders a page for every module and an index page linking to all of them
+pub fn generate(modules: &[DocModule<'_>], options: &DocOptions) -> Vec<DocPage> {
+    let docs: Vec<ModuleDocs> = modules
+        .iter()
+        .map(|module| collect_module(module, options))
+        .collect();
+
+    let mut index: HashMap<&str, Vec<Target>> = HashMap::new();
+    for module in &docs {
+        for entry in &module.entries {
+            index.entry(&entry.name).or_default().push(Target {
+                module: module.name.clone(),
+                anchor: entry.anchor.clone(),
+            });
+        }
+    }
+
+    let ext = options.format.extension();
+    let mut pages = vec![DocPage {
+        path: format!("index.{ext}"),
+        contents: render_index(&docs, options.format),
+    }];
+
+    for module in &docs {
+        let renderer = Renderer {
+            format: options.format,
+            module: &module.name,
+            index: &index,
+        };
+
+        pages.push(DocPage {
+            path: format!("{}.{ext}", module.name),
+            contents: renderer.render_module(module),
+        });
+    }
+
+    pages
+}
+
+//BEGIN collecting
+
+// where an item is documented
+#[derive(Debug, Clone)]
+struct Target {
+    module: String,
+    anchor: String,
+}
+
+// a signature is plain text with names that may link to other items
+#[derive(Debug, Clone)]
+enum Part {
+    Text(String),
+    // a type name, linked when it names a documented item
+    Name(String),
+}
+
+type Signature = Vec<Part>;
+
+#[derive(Debug)]
+struct Entry {
+    kind: EntryKind,
+    name: String,
+    anchor: String,
+    signature: Signature,
+    doc: Option<String>,
+    fields: Vec<Entry>,
+    methods: Vec<Entry>,
+    // `impl Shape for Circle` with its methods, listed on the class
+    impls: Vec<(Signature, Vec<Entry>)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EntryKind {
+    Class,
+    Interface,
+    Fn,
+    Field,
+    Method,
+}
+
+impl EntryKind {
+    fn heading(&self) -> &'static str {
+        match self {
+            EntryKind::Class => "Classes",
+            EntryKind::Interface => "Interfaces",
+            EntryKind::Fn => "Functions",
+            EntryKind::Field => "Fields",
+            EntryKind::Method => "Methods",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ModuleDocs {
+    name: String,
+    entries: Vec<Entry>,
+}
+
+fn is_public(visibility: &Visibility) -> bool {
+    matches!(visibility, Visibility::Public { .. })
+}
+
+fn collect_module(module: &DocModule<'_>, options: &DocOptions) -> ModuleDocs {
+    let shown = |visibility: &Visibility| options.private || is_public(visibility);
+    let mut entries = Vec::new();
+
+    for item in &module.file.items {
+        if !shown(&item.visibility) {
+            continue;
+        }
+
+        let name = item.ident.name.clone();
+
+        let entry = match &item.kind {
+            ItemKind::Fn(item_fn) => Entry {
+                anchor: format!("fn.{name}"),
+                signature: fn_signature(&item.visibility, item_fn, &[]),
+                ..Entry::new(EntryKind::Fn, item)
+            },
+            ItemKind::Class(item_class) => {
+                let generics = generic_names(&item_class.generics);
+                let mut signature = vec![Part::Text(format!(
+                    "{}class {name}",
+                    visibility_prefix(&item.visibility)
+                ))];
+                push_generics(&mut signature, &item_class.generics);
+
+                let fields = item_class
+                    .fields
+                    .iter()
+                    .filter(|field| shown(&field.visibility))
+                    .map(|field| {
+                        let mut signature = vec![Part::Text(format!(
+                            "{}{}: ",
+                            visibility_prefix(&field.visibility),
+                            field.ident.name
+                        ))];
+                        push_ty(&mut signature, &field.ty, &generics);
+
+                        Entry {
+                            kind: EntryKind::Field,
+                            name: field.ident.name.clone(),
+                            anchor: format!("field.{name}.{}", field.ident.name),
+                            signature,
+                            doc: field.doc.clone(),
+                            fields: Vec::new(),
+                            methods: Vec::new(),
+                            impls: Vec::new(),
+                        }
+                    })
+                    .collect();
+
+                let methods = item_class
+                    .methods
+                    .iter()
+                    .filter(|method| shown(&method.visibility))
+                    .filter_map(|method| method_entry(&name, method, &generics))
+                    .collect();
+
+                let impls = module
+                    .file
+                    .items
+                    .iter()
+                    .filter(|other| other.ident.name == name)
+                    .filter_map(|other| match &other.kind {
+                        ItemKind::Impl(item_impl) => Some(item_impl),
+                        _ => None,
+                    })
+                    .map(|item_impl| {
+                        let mut signature = vec![Part::Text("impl ".to_owned())];
+                        push_ty(&mut signature, &item_impl.interface, &[]);
+                        signature.push(Part::Text(" for ".to_owned()));
+                        push_ty(&mut signature, &item_impl.class, &[]);
+
+                        let methods = item_impl
+                            .methods
+                            .iter()
+                            .filter_map(|method| method_entry(&name, method, &generics))
+                            .collect();
+                        (signature, methods)
+                    })
+                    .collect();
+
+                Entry {
+                    anchor: format!("class.{name}"),
+                    signature,
+                    fields,
+                    methods,
+                    impls,
+                    ..Entry::new(EntryKind::Class, item)
+                }
+            }
+            ItemKind::Interface(item_interface) => Entry {
+                anchor: format!("interface.{name}"),
+                signature: vec![Part::Text(format!(
+                    "{}interface {name}",
+                    visibility_prefix(&item.visibility)
+                ))],
+                // every method of an interface is part of its api
+                methods: item_interface
+                    .methods
+                    .iter()
+                    .filter_map(|method| method_entry(&name, method, &[]))
+                    .collect(),
+                ..Entry::new(EntryKind::Interface, item)
+            },
+            ItemKind::Use(_) | ItemKind::Impl(_) => continue,
+        };
+
+        entries.push(entry);
+    }
+
+    ModuleDocs {
+        name: module.name.to_owned(),
+        entries,
+    }
+}
+
+impl Entry {
+    fn new(kind: EntryKind, item: &Item) -> Self {
+        Self {
+            kind,
+            name: item.ident.name.clone(),
+            anchor: String::new(),
+            signature: Vec::new(),
+            doc: item.doc.clone(),
+            fields: Vec::new(),
+            methods: Vec::new(),
+            impls: Vec::new(),
+        }
+    }
+}
+
+fn method_entry(owner: &str, method: &Item, generics: &[&str]) -> Option<Entry> {
+    let ItemKind::Fn(item_fn) = &method.kind else {
+        return None;
+    };
+
+    Some(Entry {
+        anchor: format!("method.{owner}.{}", method.ident.name),
+        signature: fn_signature(&method.visibility, item_fn, generics),
+        ..Entry::new(EntryKind::Method, method)
+    })
+}
+
+fn visibility_prefix(visibility: &Visibility) -> &'static str {
+    if is_public(visibility) {
+        "pub "
+    } else {
+        ""
+    }
+}
+
+fn generic_names(generics: &Generics) -> Vec<&str> {
+    generics
+        .params
+        .iter()
+        .map(|param| param.ident.name.as_str())
+        .collect()
+}
+
+// `pub fn first<T: Ord>(xs: list<T>) -> T`, `outer` are generics of the enclosing class
+fn fn_signature(visibility: &Visibility, item_fn: &ItemFn, outer: &[&str]) -> Signature {
+    let mut generics = outer.to_vec();
+    generics.extend(generic_names(&item_fn.generics));
+
+    let mut sig = vec![Part::Text(format!(
+        "{}{}fn {}",
+        visibility_prefix(visibility),
+        if item_fn.is_async { "async " } else { "" },
+        item_fn.ident.name
+    ))];
+    push_generics(&mut sig, &item_fn.generics);
+    sig.push(Part::Text("(".to_owned()));
+
+    for (i, param) in item_fn.inputs.iter().enumerate() {
+        if i > 0 {
+            sig.push(Part::Text(", ".to_owned()));
+        }
+
+        let name = match &param.pat.kind {
+            PatternKind::Ident { ident, .. } => ident.name.as_str(),
+            PatternKind::Wild => "_",
+        };
+        sig.push(Part::Text(name.to_owned()));
+
+        if let Some(ty) = &param.ty {
+            sig.push(Part::Text(": ".to_owned()));
+            push_ty(&mut sig, ty, &generics);
+        }
+    }
+
+    sig.push(Part::Text(")".to_owned()));
+
+    if let Some(output) = &item_fn.output {
+        sig.push(Part::Text(" -> ".to_owned()));
+        push_ty(&mut sig, output, &generics);
+    }
+
+    sig
+}
+
+fn push_generics(sig: &mut Signature, generics: &Generics) {
+    if generics.params.is_empty() {
+        return;
+    }
+
+    sig.push(Part::Text("<".to_owned()));
+
+    for (i, param) in generics.params.iter().enumerate() {
+        if i > 0 {
+            sig.push(Part::Text(", ".to_owned()));
+        }
+        sig.push(Part::Text(param.ident.name.clone()));
+
+        for (j, bound) in param.bounds.iter().enumerate() {
+            sig.push(Part::Text(if j == 0 { ": " } else { " + " }.to_owned()));
+            push_ty(sig, bound, &[]);
+        }
+    }
+
+    sig.push(Part::Text(">".to_owned()));
+}
+
+fn push_ty(sig: &mut Signature, ty: &Ty, generics: &[&str]) {
+    match &ty.kind {
+        TyKind::Path(path, args) => {
+            let names: Vec<&str> = path.segments.iter().map(|i| i.name.as_str()).collect();
+            let name = names.join(".");
+
+            // generic parameters never link, even when an item has the same name
+            if generics.contains(&name.as_str()) {
+                sig.push(Part::Text(name));
+            } else {
+                sig.push(Part::Name(name));
+            }
+
+            if !args.is_empty() {
+                sig.push(Part::Text("<".to_owned()));
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        sig.push(Part::Text(", ".to_owned()));
+                    }
+                    push_ty(sig, arg, generics);
+                }
+                sig.push(Part::Text(">".to_owned()));
+            }
+        }
+        TyKind::Fn(inputs, output) => {
+            sig.push(Part::Text("fn(".to_owned()));
+            for (i, input) in inputs.iter().enumerate() {
+                if i > 0 {
+                    sig.push(Part::Text(", ".to_owned()));
+                }
+                push_ty(sig, input, generics);
+            }
+            sig.push(Part::Text(")".to_owned()));
+
+            if let Some(output) = output {
+                sig.push(Part::Text(" -> ".to_owned()));
+                push_ty(sig, output, generics);
+            }
+        }
+        TyKind::Optional(inner) => {
+            push_ty(sig, inner, generics);
+            sig.push(Part::Text("?".to_owned()));
+        }
+    }
+}
+
+//END collecting
+
+//BEGIN rendering
+
+struct Renderer<'a> {
+    format: DocFormat,
+    // the module being rendered, its own items are preferred when linking
+    module: &'a str,
+    index: &'a HashMap<&'a str, Vec<Target>>,
+}
+
+impl Renderer<'_> {
+    // `Point` prefers the module being rendered, `geom.Point` or `app.geom.Point` look in the
+    // module the path names and fall back to any `Point` when there is none
+    fn href(&self, name: &str) -> Option<String> {
+        let (module, name) = match name.rsplit_once('.') {
+            Some((module, name)) => (Some(module), name),
+            None => (None, name),
+        };
+
+        let targets = self.index.get(name)?;
+        let named = module.and_then(|module| {
+            targets.iter().find(|target| {
+                target.module == module || target.module.ends_with(&format!(".{module}"))
+            })
+        });
+        let target = named
+            .or_else(|| targets.iter().find(|target| target.module == self.module))
+            .or_else(|| targets.first())?;
+
+        Some(format!(
+            "{}.{}#{}",
+            target.module,
+            self.format.extension(),
+            target.anchor
+        ))
+    }
+
+    fn render_module(&self, module: &ModuleDocs) -> String {
+        match self.format {
+            DocFormat::Html => self.render_module_html(module),
+            DocFormat::Markdown => self.render_module_md(module),
+        }
+    }
+
+    // entries grouped by kind, in source order within a group
+    fn groups<'e>(&self, entries: &'e [Entry]) -> Vec<(EntryKind, Vec<&'e Entry>)> {
+        let kinds = [EntryKind::Class, EntryKind::Interface, EntryKind::Fn];
+
+        kinds
+            .into_iter()
+            .map(|kind| (kind, entries.iter().filter(|e| e.kind == kind).collect()))
+            .filter(|(_, entries): &(EntryKind, Vec<&Entry>)| !entries.is_empty())
+            .collect()
+    }
+
+    //BEGIN html
+
+    fn render_module_html(&self, module: &ModuleDocs) -> String {
+        let mut out = String::new();
+        let title = format!("Module {}", module.name);
+
+        html_header(&mut out, &title);
+        let _ = writeln!(out, "<nav><a href=\"index.html\">index</a></nav>");
+        let _ = writeln!(out, "<h1>Module <code>{}</code></h1>", escape(&module.name));
+
+        for (kind, entries) in self.groups(&module.entries) {
+            let _ = writeln!(out, "<h2>{}</h2>", kind.heading());
+
+            for entry in entries {
+                let _ = writeln!(out, "<section id=\"{}\" class=\"item\">", entry.anchor);
+                let _ = writeln!(
+                    out,
+                    "<h3><code>{}</code></h3>",
+                    self.signature_html(&entry.signature)
+                );
+                self.doc_html(&mut out, entry.doc.as_deref());
+
+                self.members_html(&mut out, EntryKind::Field, &entry.fields);
+                self.members_html(&mut out, EntryKind::Method, &entry.methods);
+
+                if !entry.impls.is_empty() {
+                    let _ = writeln!(out, "<h4>Implementations</h4>");
+                }
+                for (signature, methods) in &entry.impls {
+                    let _ = writeln!(
+                        out,
+                        "<h5><code>{}</code></h5>",
+                        self.signature_html(signature)
+                    );
+                    self.members_html(&mut out, EntryKind::Method, methods);
+                }
+
+                let _ = writeln!(out, "</section>");
+            }
+        }
+
+        let _ = writeln!(out, "</body>\n</html>");
+        out
+    }
+
+    fn members_html(&self, out: &mut String, kind: EntryKind, members: &[Entry]) {
+        if members.is_empty() {
+            return;
+        }
+
+        let _ = writeln!(out, "<h4>{}</h4>", kind.heading());
+
+        for member in members {
+            let _ = writeln!(out, "<div id=\"{}\" class=\"member\">", member.anchor);
+            let _ = writeln!(
+                out,
+                "<code>{}</code>",
+                self.signature_html(&member.signature)
+            );
+            self.doc_html(out, member.doc.as_deref());
+            let _ = writeln!(out, "</div>");
+        }
+    }
+
+    fn signature_html(&self, signature: &Signature) -> String {
+        let mut out = String::new();
+
+        for part in signature {
+            match part {
+                Part::Text(text) => out.push_str(&escape(text)),
+                Part::Name(name) => match self.href(name) {
+                    Some(href) => {
+                        let _ = write!(out, "<a href=\"{href}\">{}</a>", escape(name));
+                    }
+                    None => out.push_str(&escape(name)),
+                },
+            }
+        }
+
+        out
+    }
+
+    // paragraphs are separated by blank lines, lines indented by 4 spaces are code blocks
+    fn doc_html(&self, out: &mut String, doc: Option<&str>) {
+        let Some(doc) = doc else {
+            return;
+        };
+
+        let _ = writeln!(out, "<div class=\"docs\">");
+
+        for block in doc.split("\n\n").filter(|block| !block.trim().is_empty()) {
+            let lines: Vec<&str> = block.lines().collect();
+
+            if lines.iter().all(|line| line.starts_with("    ")) {
+                let code: Vec<&str> = lines.iter().map(|line| &line[4..]).collect();
+                let _ = writeln!(out, "<pre><code>{}</code></pre>", escape(&code.join("\n")));
+            } else {
+                let _ = writeln!(out, "<p>{}</p>", self.inline_html(&lines.join(" ")));
+            }
+        }
+
+        let _ = writeln!(out, "</div>");
+    }
+
+    // `code` and [`Name`] links
+    fn inline_html(&self, text: &str) -> String {
+        let mut out = String::new();
+        let mut rest = text;
+
+        while let Some(idx) = rest.find(['`', '[']) {
+            out.push_str(&escape(&rest[..idx]));
+            rest = &rest[idx..];
+
+            if let Some((name, after)) = self.link(rest) {
+                let href = self.href(name).expect("link should be resolved");
+                let _ = write!(out, "<a href=\"{href}\"><code>{}</code></a>", escape(name));
+                rest = after;
+            } else if let Some(end) = rest[1..].find('`').filter(|_| rest.starts_with('`')) {
+                let _ = write!(out, "<code>{}</code>", escape(&rest[1..end + 1]));
+                rest = &rest[end + 2..];
+            } else {
+                out.push_str(&escape(&rest[..1]));
+                rest = &rest[1..];
+            }
+        }
+
+        out.push_str(&escape(rest));
+        out
+    }
+
+    //END html
+
+    //BEGIN markdown
+
+    fn render_module_md(&self, module: &ModuleDocs) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# Module `{}`\n", module.name);
+
+        for (kind, entries) in self.groups(&module.entries) {
+            let _ = writeln!(out, "## {}\n", kind.heading());
+
+            for entry in entries {
+                let _ = writeln!(out, "<a id=\"{}\"></a>", entry.anchor);
+                let _ = writeln!(out, "### {}\n", self.signature_md(&entry.signature));
+                self.doc_md(&mut out, entry.doc.as_deref());
+
+                self.members_md(&mut out, EntryKind::Field, &entry.fields);
+                self.members_md(&mut out, EntryKind::Method, &entry.methods);
+
+                for (signature, methods) in &entry.impls {
+                    let _ = writeln!(out, "#### {}\n", self.signature_md(signature));
+                    self.members_md(&mut out, EntryKind::Method, methods);
+                }
+            }
+        }
+
+        out
+    }
+
+    fn members_md(&self, out: &mut String, kind: EntryKind, members: &[Entry]) {
+        if members.is_empty() {
+            return;
+        }
+
+        let _ = writeln!(out, "**{}**\n", kind.heading());
+
+        for member in members {
+            let _ = writeln!(out, "<a id=\"{}\"></a>", member.anchor);
+            let _ = writeln!(out, "- {}\n", self.signature_md(&member.signature));
+
+            if let Some(doc) = &member.doc {
+                for line in self.linked_md(doc).lines() {
+                    let _ = writeln!(out, "  {line}");
+                }
+                out.push('\n');
+            }
+        }
+    }
+
+    // text goes in code spans, linked names in between them
+    fn signature_md(&self, signature: &Signature) -> String {
+        let mut out = String::new();
+        let mut text = String::new();
+
+        for part in signature {
+            match part {
+                Part::Name(name) if self.href(name).is_some() => {
+                    if !text.is_empty() {
+                        let _ = write!(out, "`{}`", std::mem::take(&mut text));
+                    }
+                    let href = self.href(name).expect("link should be resolved");
+                    let _ = write!(out, "[`{name}`]({href})");
+                }
+                Part::Text(part) | Part::Name(part) => text.push_str(part),
+            }
+        }
+
+        if !text.is_empty() {
+            let _ = write!(out, "`{text}`");
+        }
+
+        out
+    }
+
+    fn doc_md(&self, out: &mut String, doc: Option<&str>) {
+        if let Some(doc) = doc {
+            let _ = writeln!(out, "{}\n", self.linked_md(doc));
+        }
+    }
+
+    // resolves [`Name`] links, everything else is already markdown
+    fn linked_md(&self, text: &str) -> String {
+        let mut out = String::new();
+        let mut rest = text;
+
+        while let Some(idx) = rest.find('[') {
+            out.push_str(&rest[..idx]);
+            rest = &rest[idx..];
+
+            match self.link(rest) {
+                Some((name, after)) => {
+                    let href = self.href(name).expect("link should be resolved");
+                    let _ = write!(out, "[`{name}`]({href})");
+                    rest = after;
+                }
+                None => {
+                    out.push('[');
+                    rest = &rest[1..];
+                }
+            }
+        }
+
+        out.push_str(rest);
+        out
+    }
+
+    //END markdown
+
+    // `[Name]` or [`Name`] naming a documented item and not already a markdown link,
+    // returns the name and the text after the link
+    fn link<'t>(&self, text: &'t str) -> Option<(&'t str, &'t str)> {
+        let inner = text.strip_prefix('[')?;
+        let end = inner.find(']')?;
+        let after = &inner[end + 1..];
+        let name = inner[..end].trim_matches('`');
+
+        if after.starts_with('(') || self.href(name).is_none() {
+            return None;
+        }
+
+        Some((name, after))
+    }
+}
+
+fn render_index(modules: &[ModuleDocs], format: DocFormat) -> String {
+    let mut out = String::new();
+    let ext = format.extension();
+
+    match format {
+        DocFormat::Html => {
+            html_header(&mut out, "Modules");
+            let _ = writeln!(out, "<h1>Modules</h1>\n<ul>");
+            for module in modules {
+                let name = escape(&module.name);
+                let _ = writeln!(out, "<li><a href=\"{name}.{ext}\">{name}</a></li>");
+            }
+            let _ = writeln!(out, "</ul>\n</body>\n</html>");
+        }
+        DocFormat::Markdown => {
+            let _ = writeln!(out, "# Modules\n");
+            for module in modules {
+                let _ = writeln!(out, "- [`{0}`]({0}.{ext})", module.name);
+            }
+        }
+    }
+
+    out
+}
+
+fn html_header(out: &mut String, title: &str) {
+    let _ = writeln!(out, "<!DOCTYPE html>");
+    let _ = writeln!(out, "<html>\n<head>\n<meta charset=\"utf-8\">");
+    let _ = writeln!(out, "<title>{} - kai docs</title>", escape(title));
+    let _ = writeln!(out, "<style>{STYLE}</style>\n</head>\n<body>");
+}
+
+const STYLE: &str = "body { font-family: sans-serif; max-width: 60em; margin: auto; } \
+    .item { border-top: 1px solid #ddd; } .member { margin: 0.5em 0 0.5em 1em; } \
+    pre { background: #f5f5f5; padding: 0.5em; }";
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+//END rendering
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    const SHAPES: &str = r#"
+        /** Something with an area, see [`Square`] */
+        pub interface Shape {
+            /** The area in square units */
+            fn area(self) -> float;
+        }
+
+        /**
+         * A square.
+         *
+         *     Square { side: 2.0 }
+         */
+        pub class Square {
+            /** Length of a side */
+            pub side: float;
+            secret: int;
+
+            pub fn grow(self, by: float) -> Square { self }
+            fn helper(self) {}
+        }
+
+        impl Shape for Square {
+            fn area(self) -> float { self.side * self.side }
+        }
+
+        /** Sums the areas of `shapes` */
+        pub fn total<T: Shape>(shapes: list<T>, f: fn(T) -> float) -> float { 0.0 }
+
+        fn private_helper() {}
+    "#;
+
+    const APP: &str = r#"
+        /** Draws a [Shape], [unknown] stays as is */
+        pub fn draw(shape: Shape) {}
+
+        /** A square on screen, not the one of `geo.shapes` */
+        pub class Square {}
+
+        /** Fits a [`shapes.Square`] */
+        pub fn fit(square: shapes.Square, into: geo.shapes.Shape) {}
+    "#;
+
+    fn pages(private: bool, format: DocFormat) -> HashMap<String, String> {
+        let shapes = parse(SHAPES).unwrap();
+        let app = parse(APP).unwrap();
+        let modules = [
+            DocModule {
+                name: "geo.shapes",
+                file: &shapes,
+            },
+            DocModule {
+                name: "app",
+                file: &app,
+            },
+        ];
+
+        generate(&modules, &DocOptions { format, private })
+            .into_iter()
+            .map(|page| (page.path, page.contents))
+            .collect()
+    }
+
+    #[test]
+    fn renders_html_pages() {
+        let pages = pages(false, DocFormat::Html);
+        let mut paths: Vec<&String> = pages.keys().collect();
+        paths.sort();
+        assert_eq!(paths, ["app.html", "geo.shapes.html", "index.html"]);
+
+        let index = &pages["index.html"];
+        assert!(index.contains(r#"<li><a href="geo.shapes.html">geo.shapes</a></li>"#));
+
+        let shapes = &pages["geo.shapes.html"];
+        assert!(shapes.contains(r#"<section id="class.Square" class="item">"#));
+        assert!(shapes.contains(
+            r#"<code>pub fn total&lt;T: <a href="geo.shapes.html#interface.Shape">Shape</a>&gt;(shapes: list&lt;T&gt;, f: fn(T) -&gt; float) -&gt; float</code>"#
+        ));
+        assert!(shapes.contains(
+            r#"<code>impl <a href="geo.shapes.html#interface.Shape">Shape</a> for <a href="geo.shapes.html#class.Square">Square</a></code>"#
+        ));
+        assert!(shapes.contains(r#"<div id="method.Square.area" class="member">"#));
+        assert!(shapes.contains("<pre><code>Square { side: 2.0 }</code></pre>"));
+        assert!(shapes.contains("<p>Sums the areas of <code>shapes</code></p>"));
+        assert!(shapes
+            .contains(r#"see <a href="geo.shapes.html#class.Square"><code>Square</code></a>"#));
+
+        // only public items and members unless asked for
+        assert!(!shapes.contains("secret"));
+        assert!(!shapes.contains("helper"));
+
+        let app = &pages["app.html"];
+        assert!(app.contains(
+            r#"Draws a <a href="geo.shapes.html#interface.Shape"><code>Shape</code></a>, [unknown] stays as is"#
+        ));
+
+        // qualified names link through the module they name
+        assert!(app.contains(
+            r#"<code>pub fn fit(square: <a href="geo.shapes.html#class.Square">shapes.Square</a>, into: <a href="geo.shapes.html#interface.Shape">geo.shapes.Shape</a>)</code>"#
+        ));
+        assert!(app.contains(
+            r#"Fits a <a href="geo.shapes.html#class.Square"><code>shapes.Square</code></a>"#
+        ));
+    }
+
+    #[test]
+    fn includes_private_items_when_asked() {
+        let pages = pages(true, DocFormat::Html);
+        let shapes = &pages["geo.shapes.html"];
+
+        assert!(shapes.contains("<code>secret: int</code>"));
+        assert!(shapes.contains("fn helper(self)"));
+        assert!(shapes.contains(r#"<section id="fn.private_helper" class="item">"#));
+    }
+
+    #[test]
+    fn renders_markdown_pages() {
+        let pages = pages(false, DocFormat::Markdown);
+        let shapes = &pages["geo.shapes.md"];
+
+        assert!(pages["index.md"].contains("- [`geo.shapes`](geo.shapes.md)"));
+        assert!(
+            shapes.contains("## Classes\n\n<a id=\"class.Square\"></a>\n### `pub class Square`\n")
+        );
+        assert!(shapes.contains(
+            "#### `impl `[`Shape`](geo.shapes.md#interface.Shape)` for `[`Square`](geo.shapes.md#class.Square)"
+        ));
+        assert!(shapes.contains("- `pub side: float`\n\n  Length of a side\n"));
+        assert!(pages["app.md"].contains("Draws a [`Shape`](geo.shapes.md#interface.Shape)"));
+    }
+}
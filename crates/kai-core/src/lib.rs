@@ -1,3 +1,8 @@
 pub mod ast;
+pub mod attrs;
+pub mod diagnostic;
+pub mod doc;
 pub mod parser;
+pub mod resolve;
 pub mod runtime;
+pub mod typeck;
@@ -1,8 +1,19 @@
+use thin_vec::ThinVec;
+
+use crate::ast::span::Span;
+use crate::ast::*;
+
+use self::error::{LexerErrorKind, ParseError, ParseErrorKind};
+use self::lexer::{Lexer, Token};
+use self::token::TokenKind;
+
 pub mod error;
 pub mod lexer;
 pub mod token;
 
-pub fn parse_int_value(value: &str) -> Option<i32> {
+/// The value of an integer literal without its sign, it can be one more than `i64::MAX`
+/// for `-9223372036854775808`
+pub fn parse_int_value(value: &str) -> Option<u64> {
     let (radix, value) = if let Some(value) = value.strip_prefix("0x") {
         (16, value)
     } else if let Some(value) = value.strip_prefix("0o") {
@@ -13,5 +24,1965 @@ pub fn parse_int_value(value: &str) -> Option<i32> {
         (10, value)
     };
 
-    i32::from_str_radix(value, radix).ok()
+    u64::from_str_radix(value, radix)
+        .ok()
+        .filter(|value| *value <= i64::MIN.unsigned_abs())
+}
+
+pub type ParseResult<T> = Result<T, ParseError>;
+
+/// Strips the `*` gutter and the common indentation from the text of a doc comment
+fn clean_doc(raw: &str) -> String {
+    let lines: Vec<&str> = raw.lines().collect();
+    // the text right after `/**` is on the first line, the indentation is taken from the rest
+    let rest = || lines.iter().skip(1).filter(|line| !line.trim().is_empty());
+
+    let starred = rest().all(|line| line.trim_start().starts_with('*'));
+    let indent = rest()
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+
+    let cleaned: Vec<&str> = lines
+        .into_iter()
+        .enumerate()
+        .map(|(i, line)| {
+            if i == 0 {
+                line.trim()
+            } else if starred {
+                let line = line
+                    .trim_start()
+                    .strip_prefix('*')
+                    .unwrap_or(line.trim_start());
+                line.strip_prefix(' ').unwrap_or(line).trim_end()
+            } else {
+                line.get(indent..).unwrap_or("").trim_end()
+            }
+        })
+        .collect();
+
+    cleaned.join("\n").trim_matches('\n').to_owned()
+}
+
+/// Parses a whole source file
+pub fn parse(src: &str) -> ParseResult<File> {
+    Parser::new(src)?.parse_file()
+}
+
+/// Name of the fn holding the statements of a script
+pub const SCRIPT_FN: &str = "<script>";
+
+/// Parses a script, items and statements mixed like in a block. The items end up at
+/// the top of the file, the statements in the body of a fn named [`SCRIPT_FN`] which
+/// returns the value of the last one.
+pub fn parse_script(src: &str) -> ParseResult<File> {
+    Parser::new(src)?.parse_script()
+}
+
+// binding power of binary operators, higher binds tighter
+fn binary_op(kind: &TokenKind) -> Option<(BinOp, u8)> {
+    let op = match kind {
+        TokenKind::PipePipe => (BinOp::Or, 1),
+        TokenKind::AmperAmper => (BinOp::And, 2),
+        TokenKind::EqEq => (BinOp::Eq, 3),
+        TokenKind::NotEq => (BinOp::NotEq, 3),
+        TokenKind::Lt => (BinOp::Lt, 4),
+        TokenKind::LtEq => (BinOp::LtEq, 4),
+        TokenKind::Gt => (BinOp::Gt, 4),
+        TokenKind::GtEq => (BinOp::GtEq, 4),
+        TokenKind::Plus => (BinOp::Add, 5),
+        TokenKind::Minus => (BinOp::Sub, 5),
+        TokenKind::Star => (BinOp::Mul, 6),
+        TokenKind::Slash => (BinOp::Div, 6),
+        TokenKind::Percent => (BinOp::Rem, 6),
+        _ => return None,
+    };
+
+    Some(op)
+}
+
+// tokens continuing an expression in `parse_postfix`, they bind tighter than a sign
+fn is_postfix_op(kind: &TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::LParen | TokenKind::Dot | TokenKind::Question | TokenKind::LBracket
+    )
+}
+
+fn assign_op(kind: &TokenKind) -> Option<BinOp> {
+    let op = match kind {
+        TokenKind::PlusEq => BinOp::Add,
+        TokenKind::MinusEq => BinOp::Sub,
+        TokenKind::MulEq => BinOp::Mul,
+        TokenKind::DivEq => BinOp::Div,
+        TokenKind::ModEq => BinOp::Rem,
+        _ => return None,
+    };
+
+    Some(op)
+}
+
+pub struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    // end of the last consumed token
+    prev_end: usize,
+    shebang: Option<String>,
+    next_id: u32,
+    // set while parsing `if`, `while` and `for` heads where `Name {` starts the body
+    no_class_init: bool,
+    // set once the fn body being parsed contains a `yield`
+    yields: bool,
+}
+
+impl Parser {
+    pub fn new(src: &str) -> ParseResult<Self> {
+        let (shebang, offset) = match src.strip_prefix("#!") {
+            Some(rest) => {
+                let line = rest.lines().next().unwrap_or_default();
+                (Some(line.to_owned()), line.chars().count() + 2)
+            }
+            None => (None, 0),
+        };
+
+        let mut tokens = Vec::new();
+
+        for token in Lexer::new(src.chars().skip(offset)) {
+            let (start, kind, end) = token.map_err(|mut err| {
+                err.location = Span::new(err.location.start + offset, err.location.end + offset);
+                err
+            })?;
+
+            if matches!(kind, TokenKind::Comment | TokenKind::NewLine) {
+                continue;
+            }
+
+            tokens.push((start + offset, kind, end + offset));
+        }
+
+        let end = src.chars().count();
+        tokens.push((end, TokenKind::Eof, end));
+
+        Ok(Self {
+            tokens,
+            pos: 0,
+            prev_end: 0,
+            shebang,
+            next_id: 0,
+            no_class_init: false,
+            yields: false,
+        })
+    }
+
+    pub fn parse_file(&mut self) -> ParseResult<File> {
+        let start = self.peek_span().start;
+        let mut items = Vec::new();
+
+        while !self.at_eof() {
+            let doc = self.parse_docs();
+            if self.at_eof() {
+                break;
+            }
+            items.push(self.parse_item(doc)?);
+        }
+
+        Ok(File {
+            shebang: self.shebang.take(),
+            items,
+            span: Span::new(start.min(self.prev_end), self.prev_end),
+        })
+    }
+
+    pub fn parse_script(&mut self) -> ParseResult<File> {
+        let start = self.peek_span().start;
+        let id = self.next_id();
+        let block_id = self.next_id();
+        let mut items = Vec::new();
+        let mut stmts = ThinVec::new();
+
+        while !self.at_eof() {
+            let doc = self.parse_docs();
+            if self.at_eof() {
+                break;
+            }
+
+            let stmt = self.parse_stmt(doc)?;
+            match stmt.kind {
+                StmtKind::Item(item) => items.push(*item),
+                _ => stmts.push(stmt),
+            }
+        }
+
+        let span = Span::new(start.min(self.prev_end), self.prev_end);
+        let ident = Ident::new(SCRIPT_FN, Span::new(span.start, span.start));
+        let body = Block {
+            id: block_id,
+            span,
+            stmts,
+        };
+
+        items.push(Item {
+            id,
+            doc: None,
+            kind: ItemKind::Fn(Box::new(ItemFn {
+                attrs: Attrs::new(),
+                ident: ident.clone(),
+                generics: Generics::default(),
+                inputs: ThinVec::new(),
+                output: None,
+                body: Some(Box::new(body)),
+                is_async: false,
+                is_generator: std::mem::take(&mut self.yields),
+            })),
+            span,
+            ident,
+            visibility: Visibility::Inherited,
+        });
+
+        Ok(File {
+            shebang: self.shebang.take(),
+            items,
+            span,
+        })
+    }
+
+    //BEGIN items
+
+    fn parse_item(&mut self, doc: Option<String>) -> ParseResult<Item> {
+        let start = self.peek_span().start;
+        let attrs = self.parse_attrs()?;
+        let visibility = self.parse_visibility();
+
+        let mut item = match self.peek() {
+            TokenKind::Fn | TokenKind::Async => self.parse_item_fn(start, attrs, visibility),
+            TokenKind::Using => self.parse_item_use(start, attrs, visibility),
+            TokenKind::Class => self.parse_item_class(start, attrs, visibility),
+            TokenKind::Interface => self.parse_item_interface(start, attrs, visibility),
+            TokenKind::Impl => self.parse_item_impl(start, attrs, visibility),
+            _ => Err(self.unexpected("an item")),
+        }?;
+
+        item.doc = doc;
+        Ok(item)
+    }
+
+    // `/** .. */` comments in front of an item or a member
+    fn parse_docs(&mut self) -> Option<String> {
+        let mut docs = Vec::new();
+
+        while let TokenKind::DocComment { comment } = self.peek() {
+            docs.push(clean_doc(comment));
+            self.bump();
+        }
+
+        (!docs.is_empty()).then(|| docs.join("\n"))
+    }
+
+    // `@test @cfg(os = "linux")`, in front of items, members and parameters
+    fn parse_attrs(&mut self) -> ParseResult<Attrs> {
+        let mut attrs = Attrs::new();
+
+        while self.check(&TokenKind::At) {
+            let start = self.bump_span().start;
+
+            let mut segments = ThinVec::new();
+            segments.push(self.parse_ident()?);
+            while self.eat(&TokenKind::Dot) {
+                segments.push(self.parse_ident()?);
+            }
+            let path = Path {
+                segments,
+                span: self.span_from(start + 1),
+            };
+
+            let args = if self.eat(&TokenKind::LParen) {
+                let args = self.parse_comma_separated(TokenKind::RParen, Self::parse_attr_arg)?;
+                self.expect(TokenKind::RParen, "`)`")?;
+                args
+            } else {
+                ThinVec::new()
+            };
+
+            attrs.push(Attribute {
+                path,
+                args,
+                span: self.span_from(start),
+            });
+        }
+
+        Ok(attrs)
+    }
+
+    fn parse_attr_arg(&mut self) -> ParseResult<AttrArg> {
+        if !matches!(self.peek(), TokenKind::Name { .. }) {
+            return Ok(AttrArg::Lit(self.parse_lit()?));
+        }
+
+        let key = self.parse_ident()?;
+
+        if self.eat(&TokenKind::Eq) {
+            Ok(AttrArg::KeyValue(key, self.parse_lit()?))
+        } else {
+            Ok(AttrArg::Word(key))
+        }
+    }
+
+    fn parse_lit(&mut self) -> ParseResult<Lit> {
+        let is_lit = matches!(
+            self.peek(),
+            TokenKind::Int { .. }
+                | TokenKind::Float { .. }
+                | TokenKind::String { .. }
+                | TokenKind::Nil
+                | TokenKind::True
+                | TokenKind::False
+        );
+
+        if !is_lit {
+            return Err(self.unexpected("a literal"));
+        }
+
+        match self.parse_primary()?.kind {
+            ExprKind::Lit(lit) => Ok(lit),
+            _ => unreachable!("literal tokens always parse to a literal"),
+        }
+    }
+
+    fn parse_visibility(&mut self) -> Visibility {
+        if self.check(&TokenKind::Pub) {
+            let span = self.bump_span();
+            Visibility::Public { span }
+        } else {
+            Visibility::Inherited
+        }
+    }
+
+    fn parse_item_fn(
+        &mut self,
+        start: usize,
+        attrs: Attrs,
+        visibility: Visibility,
+    ) -> ParseResult<Item> {
+        let is_async = self.eat(&TokenKind::Async);
+        self.expect(TokenKind::Fn, "`fn`")?;
+        self.parse_fn_rest(start, attrs, visibility, is_async)
+    }
+
+    // everything after the `fn` keyword, methods inside a class can omit it
+    fn parse_fn_rest(
+        &mut self,
+        start: usize,
+        attrs: Attrs,
+        visibility: Visibility,
+        is_async: bool,
+    ) -> ParseResult<Item> {
+        let id = self.next_id();
+        let ident = self.parse_ident()?;
+        let generics = self.parse_generics()?;
+
+        self.expect(TokenKind::LParen, "`(`")?;
+        let inputs = self.parse_comma_separated(TokenKind::RParen, Self::parse_param)?;
+        self.expect(TokenKind::RParen, "`)`")?;
+
+        let output = if self.eat(&TokenKind::ArrowRight) {
+            Some(self.parse_ty()?)
+        } else {
+            None
+        };
+
+        // fns declared inside the body have their own `yields`
+        let outer_yields = std::mem::take(&mut self.yields);
+        let body = if self.eat(&TokenKind::SemiColon) {
+            None
+        } else {
+            Some(Box::new(self.parse_block()?))
+        };
+        let is_generator = std::mem::replace(&mut self.yields, outer_yields);
+
+        let item_fn = ItemFn {
+            attrs,
+            ident: ident.clone(),
+            generics,
+            inputs,
+            output,
+            body,
+            is_async,
+            is_generator,
+        };
+
+        Ok(Item {
+            id,
+            doc: None,
+            kind: ItemKind::Fn(Box::new(item_fn)),
+            span: self.span_from(start),
+            ident,
+            visibility,
+        })
+    }
+
+    fn parse_param(&mut self) -> ParseResult<Param> {
+        let start = self.peek_span().start;
+        let attrs = self.parse_attrs()?;
+        let pat = self.parse_pattern()?;
+
+        let ty = if self.eat(&TokenKind::Colon) {
+            Some(self.parse_ty()?)
+        } else {
+            None
+        };
+
+        Ok(Param {
+            attrs,
+            pat,
+            ty,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_item_class(
+        &mut self,
+        start: usize,
+        attrs: Attrs,
+        visibility: Visibility,
+    ) -> ParseResult<Item> {
+        let id = self.next_id();
+        self.expect(TokenKind::Class, "`class`")?;
+        let ident = self.parse_ident()?;
+        let generics = self.parse_generics()?;
+        self.expect(TokenKind::LCurly, "`{`")?;
+
+        let mut fields = ThinVec::new();
+        let mut methods = ThinVec::new();
+
+        loop {
+            let doc = self.parse_docs();
+
+            if self.eat(&TokenKind::RCurly) {
+                break;
+            }
+
+            let member_start = self.peek_span().start;
+            let member_attrs = self.parse_attrs()?;
+            let member_visibility = self.parse_visibility();
+
+            let is_async = self.eat(&TokenKind::Async);
+            let is_method = self.eat(&TokenKind::Fn) || is_async;
+
+            if is_method || self.peek_nth(1) == &TokenKind::LParen {
+                let mut method =
+                    self.parse_fn_rest(member_start, member_attrs, member_visibility, is_async)?;
+                method.doc = doc;
+                methods.push(method);
+            } else if self.peek_nth(1) == &TokenKind::Colon {
+                let mut field =
+                    self.parse_field_def(member_start, member_attrs, member_visibility)?;
+                field.doc = doc;
+                fields.push(field);
+            } else {
+                return Err(self.unexpected("a field or a method"));
+            }
+        }
+
+        let item_class = ItemClass {
+            attrs,
+            ident: ident.clone(),
+            generics,
+            fields,
+            methods,
+        };
+
+        Ok(Item {
+            id,
+            doc: None,
+            kind: ItemKind::Class(Box::new(item_class)),
+            span: self.span_from(start),
+            ident,
+            visibility,
+        })
+    }
+
+    fn parse_field_def(
+        &mut self,
+        start: usize,
+        attrs: Attrs,
+        visibility: Visibility,
+    ) -> ParseResult<FieldDef> {
+        let id = self.next_id();
+        let ident = self.parse_ident()?;
+        self.expect(TokenKind::Colon, "`:`")?;
+        let ty = self.parse_ty()?;
+        self.expect(TokenKind::SemiColon, "`;`")?;
+
+        Ok(FieldDef {
+            id,
+            doc: None,
+            attrs,
+            visibility,
+            ident,
+            ty,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_item_interface(
+        &mut self,
+        start: usize,
+        attrs: Attrs,
+        visibility: Visibility,
+    ) -> ParseResult<Item> {
+        let id = self.next_id();
+        self.expect(TokenKind::Interface, "`interface`")?;
+        let ident = self.parse_ident()?;
+        let methods = self.parse_methods()?;
+
+        let item_interface = ItemInterface {
+            attrs,
+            ident: ident.clone(),
+            methods,
+        };
+
+        Ok(Item {
+            id,
+            doc: None,
+            kind: ItemKind::Interface(Box::new(item_interface)),
+            span: self.span_from(start),
+            ident,
+            visibility,
+        })
+    }
+
+    // impl Display for Person { .. }
+    fn parse_item_impl(
+        &mut self,
+        start: usize,
+        attrs: Attrs,
+        visibility: Visibility,
+    ) -> ParseResult<Item> {
+        let id = self.next_id();
+        self.expect(TokenKind::Impl, "`impl`")?;
+        let interface = self.parse_ty()?;
+        self.expect(TokenKind::For, "`for`")?;
+
+        // the item is named after the class it implements the interface for
+        let ident = match self.peek().clone() {
+            TokenKind::Name { name } => Ident::new(name.as_str(), self.peek_span()),
+            _ => return Err(self.unexpected("a class name")),
+        };
+
+        let class = self.parse_ty()?;
+        let methods = self.parse_methods()?;
+
+        let item_impl = ItemImpl {
+            attrs,
+            interface,
+            class,
+            methods,
+        };
+
+        Ok(Item {
+            id,
+            doc: None,
+            kind: ItemKind::Impl(Box::new(item_impl)),
+            span: self.span_from(start),
+            ident,
+            visibility,
+        })
+    }
+
+    // `{ fn a(self); b(self) {} }`, the body of an interface or an impl
+    fn parse_methods(&mut self) -> ParseResult<ThinVec<Item>> {
+        self.expect(TokenKind::LCurly, "`{`")?;
+        let mut methods = ThinVec::new();
+
+        loop {
+            let doc = self.parse_docs();
+
+            if self.eat(&TokenKind::RCurly) {
+                break;
+            }
+
+            let start = self.peek_span().start;
+            let attrs = self.parse_attrs()?;
+            let visibility = self.parse_visibility();
+            let is_async = self.eat(&TokenKind::Async);
+            self.eat(&TokenKind::Fn);
+
+            if !matches!(self.peek_nth(1), TokenKind::LParen | TokenKind::Lt) {
+                return Err(self.unexpected("a method"));
+            }
+
+            let mut method = self.parse_fn_rest(start, attrs, visibility, is_async)?;
+            method.doc = doc;
+            methods.push(method);
+        }
+
+        Ok(methods)
+    }
+
+    fn parse_item_use(
+        &mut self,
+        start: usize,
+        attrs: Attrs,
+        visibility: Visibility,
+    ) -> ParseResult<Item> {
+        let id = self.next_id();
+        self.expect(TokenKind::Using, "`using`")?;
+        let path = self.parse_use_tree()?;
+        self.expect(TokenKind::SemiColon, "`;`")?;
+
+        let span = self.span_from(start);
+        let ident = match path.binding() {
+            Some(ident) => ident.clone(),
+            None => Ident::new("", path.span),
+        };
+
+        let item_use = ItemUse {
+            attrs,
+            path,
+            visibility: visibility.clone(),
+            span,
+        };
+
+        Ok(Item {
+            id,
+            doc: None,
+            kind: ItemKind::Use(item_use),
+            span,
+            ident,
+            visibility,
+        })
+    }
+
+    fn parse_use_tree(&mut self) -> ParseResult<UseTree> {
+        let id = self.next_id();
+        let start = self.peek_span().start;
+        let mut segments = ThinVec::new();
+
+        let kind = loop {
+            segments.push(self.parse_use_segment()?);
+
+            if self.eat(&TokenKind::Dot) {
+                match self.peek() {
+                    TokenKind::LCurly => {
+                        let group_start = self.bump_span().start;
+                        let items =
+                            self.parse_comma_separated(TokenKind::RCurly, Self::parse_use_tree)?;
+                        self.expect(TokenKind::RCurly, "`}`")?;
+
+                        break UseTreeKind::Group {
+                            items,
+                            span: self.span_from(group_start),
+                        };
+                    }
+                    TokenKind::Star => {
+                        self.bump();
+                        break UseTreeKind::Glob;
+                    }
+                    _ => continue,
+                }
+            }
+
+            if self.eat(&TokenKind::As) {
+                break UseTreeKind::Simple(Some(self.parse_ident()?));
+            }
+
+            break UseTreeKind::Simple(None);
+        };
+
+        let prefix_span = segments
+            .first()
+            .map(|first: &Ident| first.span.merge(&segments.last().unwrap().span))
+            .unwrap_or_default();
+
+        Ok(UseTree {
+            id,
+            prefix: Path {
+                segments,
+                span: prefix_span,
+            },
+            span: self.span_from(start),
+            kind,
+        })
+    }
+
+    fn parse_use_segment(&mut self) -> ParseResult<Ident> {
+        if self.check(&TokenKind::ClassSelf) {
+            let span = self.bump_span();
+            Ok(Ident::new("self", span))
+        } else {
+            self.parse_ident()
+        }
+    }
+
+    //END items
+
+    //BEGIN statements
+
+    pub fn parse_block(&mut self) -> ParseResult<Block> {
+        self.with_class_init(Self::parse_block_inner)
+    }
+
+    fn parse_block_inner(&mut self) -> ParseResult<Block> {
+        let id = self.next_id();
+        let start = self.peek_span().start;
+        self.expect(TokenKind::LCurly, "`{`")?;
+        self.parse_block_rest(id, start, ThinVec::new())
+    }
+
+    // the statements of a block after `stmts`, up to and including the `}`
+    fn parse_block_rest(
+        &mut self,
+        id: NodeId,
+        start: usize,
+        mut stmts: ThinVec<Stmt>,
+    ) -> ParseResult<Block> {
+        loop {
+            let doc = self.parse_docs();
+
+            if self.eat(&TokenKind::RCurly) {
+                break;
+            }
+
+            if self.at_eof() {
+                return Err(self.unexpected("`}`"));
+            }
+
+            stmts.push(self.parse_stmt(doc)?);
+        }
+
+        Ok(Block {
+            id,
+            span: self.span_from(start),
+            stmts,
+        })
+    }
+
+    // `doc` is attached when the statement is an item
+    fn parse_stmt(&mut self, doc: Option<String>) -> ParseResult<Stmt> {
+        let start = self.peek_span().start;
+
+        let kind = match self.peek() {
+            TokenKind::SemiColon => {
+                self.bump();
+                StmtKind::Empty
+            }
+            TokenKind::Let => StmtKind::Let(Box::new(self.parse_local()?)),
+            TokenKind::Fn
+            | TokenKind::Async
+            | TokenKind::At
+            | TokenKind::Pub
+            | TokenKind::Using
+            | TokenKind::Class
+            | TokenKind::Interface
+            | TokenKind::Impl => StmtKind::Item(Box::new(self.parse_item(doc)?)),
+            _ => {
+                let expr = self.parse_expr()?;
+                return self.finish_expr_stmt(expr);
+            }
+        };
+
+        Ok(Stmt {
+            span: self.span_from(start),
+            kind,
+        })
+    }
+
+    fn finish_expr_stmt(&mut self, expr: Expr) -> ParseResult<Stmt> {
+        let start = expr.span.start;
+
+        let kind = if self.eat(&TokenKind::SemiColon) {
+            StmtKind::Semi(Box::new(expr))
+        } else if expr.kind.is_block_like() || self.check(&TokenKind::RCurly) || self.at_eof() {
+            StmtKind::Expr(Box::new(expr))
+        } else {
+            return Err(ParseError {
+                kind: ParseErrorKind::MissingSemiColon,
+                location: self.peek_span(),
+            });
+        };
+
+        Ok(Stmt {
+            span: self.span_from(start),
+            kind,
+        })
+    }
+
+    fn parse_local(&mut self) -> ParseResult<Local> {
+        let id = self.next_id();
+        let start = self.peek_span().start;
+        self.expect(TokenKind::Let, "`let`")?;
+
+        let pat = self.parse_pattern()?;
+
+        let ty = if self.eat(&TokenKind::Colon) {
+            Some(self.parse_ty()?)
+        } else {
+            None
+        };
+
+        let init = if self.eat(&TokenKind::Eq) {
+            Some(Box::new(self.parse_expr()?))
+        } else {
+            None
+        };
+
+        self.expect(TokenKind::SemiColon, "`;`")?;
+
+        Ok(Local {
+            id,
+            pat,
+            ty,
+            init,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_pattern(&mut self) -> ParseResult<Pattern> {
+        let id = self.next_id();
+        let start = self.peek_span().start;
+        let mutable = self.eat(&TokenKind::Mut);
+
+        let kind = match self.peek().clone() {
+            TokenKind::DiscardName { name } if name == "_" && !mutable => {
+                self.bump();
+                PatternKind::Wild
+            }
+            TokenKind::Name { name } | TokenKind::DiscardName { name } => {
+                let span = self.bump_span();
+                PatternKind::Ident {
+                    ident: Ident::new(name.as_str(), span),
+                    mutable,
+                }
+            }
+            TokenKind::ClassSelf if !mutable => {
+                let span = self.bump_span();
+                PatternKind::Ident {
+                    ident: Ident::new("self", span),
+                    mutable,
+                }
+            }
+            _ => return Err(self.unexpected("a pattern")),
+        };
+
+        Ok(Pattern {
+            id,
+            kind,
+            span: self.span_from(start),
+        })
+    }
+
+    //END statements
+
+    //BEGIN types
+
+    pub fn parse_ty(&mut self) -> ParseResult<Ty> {
+        let id = self.next_id();
+        let start = self.peek_span().start;
+
+        let kind = if self.eat(&TokenKind::Fn) {
+            self.expect(TokenKind::LParen, "`(`")?;
+            let inputs = self.parse_comma_separated(TokenKind::RParen, Self::parse_ty)?;
+            self.expect(TokenKind::RParen, "`)`")?;
+
+            let output = if self.eat(&TokenKind::ArrowRight) {
+                Some(Box::new(self.parse_ty()?))
+            } else {
+                None
+            };
+
+            TyKind::Fn(inputs, output)
+        } else {
+            let mut segments = ThinVec::new();
+            segments.push(self.parse_ident()?);
+
+            while self.eat(&TokenKind::Dot) {
+                segments.push(self.parse_ident()?);
+            }
+
+            let path = Path {
+                span: self.span_from(start),
+                segments,
+            };
+
+            let args = if self.eat(&TokenKind::Lt) {
+                let args = self.parse_comma_separated(TokenKind::Gt, Self::parse_ty)?;
+                self.expect(TokenKind::Gt, "`>`")?;
+                args
+            } else {
+                ThinVec::new()
+            };
+
+            TyKind::Path(path, args)
+        };
+
+        let mut ty = Ty {
+            id,
+            kind,
+            span: self.span_from(start),
+        };
+        while self.eat(&TokenKind::Question) {
+            ty = Ty {
+                id: self.next_id(),
+                kind: TyKind::Optional(Box::new(ty)),
+                span: self.span_from(start),
+            };
+        }
+        Ok(ty)
+    }
+
+    fn parse_generics(&mut self) -> ParseResult<Generics> {
+        let start = self.peek_span().start;
+
+        if !self.eat(&TokenKind::Lt) {
+            return Ok(Generics::default());
+        }
+
+        let params = self.parse_comma_separated(TokenKind::Gt, |this| {
+            let id = this.next_id();
+            let ident = this.parse_ident()?;
+            let mut bounds = ThinVec::new();
+
+            if this.eat(&TokenKind::Colon) {
+                bounds.push(this.parse_ty()?);
+                while this.eat(&TokenKind::Plus) {
+                    bounds.push(this.parse_ty()?);
+                }
+            }
+
+            Ok(GenericParam {
+                id,
+                span: this.span_from(ident.span.start),
+                ident,
+                bounds,
+            })
+        })?;
+
+        self.expect(TokenKind::Gt, "`>`")?;
+
+        Ok(Generics {
+            params,
+            span: self.span_from(start),
+        })
+    }
+
+    //END types
+
+    //BEGIN expressions
+
+    pub fn parse_expr(&mut self) -> ParseResult<Expr> {
+        self.parse_assign()
+    }
+
+    fn parse_assign(&mut self) -> ParseResult<Expr> {
+        let lhs = self.parse_range()?;
+
+        if self.eat(&TokenKind::Eq) {
+            let rhs = self.parse_assign()?;
+            let span = lhs.span.merge(&rhs.span);
+            return Ok(self.mk_expr(ExprKind::Assign(Box::new(lhs), Box::new(rhs)), span));
+        }
+
+        if let Some(op) = assign_op(self.peek()) {
+            self.bump();
+            let rhs = self.parse_assign()?;
+            let span = lhs.span.merge(&rhs.span);
+            return Ok(self.mk_expr(ExprKind::AssignOp(op, Box::new(lhs), Box::new(rhs)), span));
+        }
+
+        Ok(lhs)
+    }
+
+    fn parse_range(&mut self) -> ParseResult<Expr> {
+        let start = self.peek_span().start;
+
+        let lhs = if self.is_range_op() {
+            None
+        } else {
+            let lhs = self.parse_binary(0)?;
+            if !self.is_range_op() {
+                return Ok(lhs);
+            }
+            Some(Box::new(lhs))
+        };
+
+        let limits = match self.bump().1 {
+            TokenKind::DotDotEq => RangeLimits::Closed,
+            _ => RangeLimits::HalfOpen,
+        };
+
+        let rhs = if self.can_begin_expr() {
+            Some(Box::new(self.parse_binary(0)?))
+        } else {
+            None
+        };
+
+        let span = self.span_from(start);
+        Ok(self.mk_expr(ExprKind::Range(lhs, rhs, limits), span))
+    }
+
+    fn is_range_op(&self) -> bool {
+        matches!(self.peek(), TokenKind::DotDot | TokenKind::DotDotEq)
+    }
+
+    fn parse_binary(&mut self, min_prec: u8) -> ParseResult<Expr> {
+        let mut lhs = self.parse_unary()?;
+
+        while let Some((op, prec)) = binary_op(self.peek()) {
+            if prec <= min_prec {
+                break;
+            }
+
+            self.bump();
+            let rhs = self.parse_binary(prec)?;
+            let span = lhs.span.merge(&rhs.span);
+            lhs = self.mk_expr(ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)), span);
+        }
+
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> ParseResult<Expr> {
+        let op = match self.peek() {
+            TokenKind::Minus => UnOp::Neg,
+            TokenKind::Bang => UnOp::Not,
+            TokenKind::Await => {
+                let start = self.bump_span().start;
+                let operand = self.parse_unary()?;
+                let span = self.span_from(start);
+                return Ok(self.mk_expr(ExprKind::Await(Box::new(operand)), span));
+            }
+            _ => return self.parse_postfix(),
+        };
+
+        let start = self.bump_span().start;
+
+        // the sign is part of a negative literal, `-9223372036854775808` only fits in an
+        // `int` with it. `-1?` negates the `?` like for any other operand.
+        if let (UnOp::Neg, TokenKind::Int { int_value, .. }) = (op, self.peek()) {
+            if !is_postfix_op(self.peek_nth(1)) {
+                let value = 0i64.wrapping_sub_unsigned(*int_value);
+                self.bump();
+                let span = self.span_from(start);
+                let lit = Lit {
+                    kind: LitKind::Int(value),
+                    span,
+                };
+                return Ok(self.mk_expr(ExprKind::Lit(lit), span));
+            }
+        }
+
+        let operand = self.parse_unary()?;
+        let span = self.span_from(start);
+
+        Ok(self.mk_expr(ExprKind::Unary(op, Box::new(operand)), span))
+    }
+
+    fn parse_postfix(&mut self) -> ParseResult<Expr> {
+        let mut expr = self.parse_primary()?;
+
+        loop {
+            match self.peek() {
+                TokenKind::LParen => {
+                    self.bump();
+                    let args = self.parse_call_args()?;
+                    self.expect(TokenKind::RParen, "`)`")?;
+
+                    let span = self.span_from(expr.span.start);
+                    expr = self.mk_expr(ExprKind::Call(Box::new(expr), args), span);
+                }
+                TokenKind::Dot => {
+                    self.bump();
+                    let name = self.parse_ident()?;
+
+                    if self.eat(&TokenKind::LParen) {
+                        let args = self.parse_call_args()?;
+                        self.expect(TokenKind::RParen, "`)`")?;
+
+                        let span = self.span_from(expr.span.start);
+                        let call = MethodCall {
+                            receiver: expr,
+                            method: name,
+                            args,
+                            span,
+                        };
+                        expr = self.mk_expr(ExprKind::MethodCall(Box::new(call)), span);
+                    } else {
+                        let span = self.span_from(expr.span.start);
+                        expr = self.mk_expr(ExprKind::Field(Box::new(expr), name), span);
+                    }
+                }
+                TokenKind::Question => {
+                    self.bump();
+                    let span = self.span_from(expr.span.start);
+                    expr = self.mk_expr(ExprKind::Try(Box::new(expr)), span);
+                }
+                TokenKind::LBracket => {
+                    self.bump();
+                    let index = self.with_class_init(Self::parse_expr)?;
+                    self.expect(TokenKind::RBracket, "`]`")?;
+
+                    let span = self.span_from(expr.span.start);
+                    expr = self.mk_expr(ExprKind::Index(Box::new(expr), Box::new(index)), span);
+                }
+                _ => break,
+            }
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_primary(&mut self) -> ParseResult<Expr> {
+        let start = self.peek_span().start;
+
+        let kind = match self.peek().clone() {
+            TokenKind::Int { int_value, .. } => {
+                let span = self.bump_span();
+                let Ok(int_value) = i64::try_from(int_value) else {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::Lexer(LexerErrorKind::IntOverflow),
+                        location: span,
+                    });
+                };
+                ExprKind::Lit(Lit {
+                    kind: LitKind::Int(int_value),
+                    span,
+                })
+            }
+            TokenKind::Float { value } => {
+                let span = self.bump_span();
+                ExprKind::Lit(Lit {
+                    kind: LitKind::Float(value.parse().unwrap_or_default()),
+                    span,
+                })
+            }
+            TokenKind::String { value } => {
+                let span = self.bump_span();
+                ExprKind::Lit(Lit {
+                    kind: LitKind::Str(value),
+                    span,
+                })
+            }
+            TokenKind::Nil => {
+                let span = self.bump_span();
+                ExprKind::Lit(Lit {
+                    kind: LitKind::Nil,
+                    span,
+                })
+            }
+            TokenKind::True | TokenKind::False => {
+                let (start, token, end) = self.bump();
+                ExprKind::Lit(Lit {
+                    kind: LitKind::Bool(token == TokenKind::True),
+                    span: Span::new(start, end),
+                })
+            }
+            TokenKind::Name { name } | TokenKind::DiscardName { name } => {
+                let span = self.bump_span();
+                let path = Path::from_ident(Ident::new(name.as_str(), span));
+
+                if !self.no_class_init && self.check(&TokenKind::LCurly) {
+                    ExprKind::ClassInit(Box::new(self.parse_class_init(path)?))
+                } else {
+                    ExprKind::Path(path)
+                }
+            }
+            TokenKind::ClassSelf => {
+                let span = self.bump_span();
+                ExprKind::Path(Path::from_ident(Ident::new("self", span)))
+            }
+            TokenKind::LParen => {
+                self.bump();
+                let inner = self.with_class_init(Self::parse_expr)?;
+                self.expect(TokenKind::RParen, "`)`")?;
+                ExprKind::Paren(Box::new(inner))
+            }
+            TokenKind::LBracket => {
+                self.bump();
+                let items = self.with_class_init(|this| {
+                    this.parse_comma_separated(TokenKind::RBracket, Self::parse_expr)
+                })?;
+                self.expect(TokenKind::RBracket, "`]`")?;
+                ExprKind::List(items)
+            }
+            TokenKind::LCurly => return self.with_class_init(Self::parse_curly),
+            TokenKind::If => return self.parse_if(),
+            TokenKind::While => {
+                self.bump();
+                let cond = self.parse_cond()?;
+                let body = self.parse_block()?;
+                ExprKind::While(Box::new(cond), Box::new(body))
+            }
+            TokenKind::Loop => {
+                self.bump();
+                ExprKind::Loop(Box::new(self.parse_block()?))
+            }
+            TokenKind::For => {
+                self.bump();
+                let pat = self.parse_pattern()?;
+                self.expect(TokenKind::In, "`in`")?;
+                let iter = self.parse_cond()?;
+                let body = self.parse_block()?;
+                ExprKind::For(Box::new(pat), Box::new(iter), Box::new(body))
+            }
+            TokenKind::Return => {
+                self.bump();
+                ExprKind::Return(self.parse_opt_expr()?)
+            }
+            TokenKind::Break => {
+                self.bump();
+                ExprKind::Break(self.parse_opt_expr()?)
+            }
+            TokenKind::Continue => {
+                self.bump();
+                ExprKind::Continue
+            }
+            TokenKind::Panic => {
+                self.bump();
+                ExprKind::Panic(self.parse_opt_expr()?)
+            }
+            TokenKind::Yield => {
+                self.bump();
+                self.yields = true;
+                ExprKind::Yield(self.parse_opt_expr()?)
+            }
+            TokenKind::Todo => {
+                self.bump();
+                ExprKind::Todo
+            }
+            _ => return Err(self.unexpected("an expression")),
+        };
+
+        let span = self.span_from(start);
+        Ok(self.mk_expr(kind, span))
+    }
+
+    fn parse_if(&mut self) -> ParseResult<Expr> {
+        let start = self.peek_span().start;
+        self.expect(TokenKind::If, "`if`")?;
+
+        let cond = self.parse_cond()?;
+        let then = self.parse_block()?;
+
+        let otherwise = if self.eat(&TokenKind::Else) {
+            let expr = if self.check(&TokenKind::If) {
+                self.parse_if()?
+            } else {
+                let block = self.parse_block()?;
+                let span = block.span;
+                self.mk_expr(ExprKind::Block(Box::new(block)), span)
+            };
+            Some(Box::new(expr))
+        } else {
+            None
+        };
+
+        let span = self.span_from(start);
+        Ok(self.mk_expr(
+            ExprKind::If(Box::new(cond), Box::new(then), otherwise),
+            span,
+        ))
+    }
+
+    // a `{` in an expression starts a map when the first expression in it is followed by a
+    // `:`, a set when it is followed by a `,` and a block otherwise
+    fn parse_curly(&mut self) -> ParseResult<Expr> {
+        let start = self.peek_span().start;
+
+        if self.peek_nth(1) == &TokenKind::Colon && self.peek_nth(2) == &TokenKind::RCurly {
+            self.bump();
+            self.bump();
+            self.bump();
+            let span = self.span_from(start);
+            return Ok(self.mk_expr(ExprKind::Map(ThinVec::new()), span));
+        }
+
+        let starts_stmt = matches!(
+            self.peek_nth(1),
+            TokenKind::SemiColon
+                | TokenKind::RCurly
+                | TokenKind::DocComment { .. }
+                | TokenKind::Let
+                | TokenKind::Fn
+                | TokenKind::Async
+                | TokenKind::At
+                | TokenKind::Pub
+                | TokenKind::Using
+                | TokenKind::Class
+                | TokenKind::Interface
+                | TokenKind::Impl
+        );
+        if starts_stmt {
+            let block = self.parse_block_inner()?;
+            let span = block.span;
+            return Ok(self.mk_expr(ExprKind::Block(Box::new(block)), span));
+        }
+
+        let id = self.next_id();
+        self.bump();
+        let first = self.parse_expr()?;
+
+        let kind = if self.eat(&TokenKind::Colon) {
+            let value = self.parse_expr()?;
+            let mut entries = ThinVec::from([(first, value)]);
+
+            if self.eat(&TokenKind::Comma) {
+                entries.extend(self.parse_comma_separated(TokenKind::RCurly, |this| {
+                    let key = this.parse_expr()?;
+                    this.expect(TokenKind::Colon, "`:`")?;
+                    Ok((key, this.parse_expr()?))
+                })?);
+            }
+            self.expect(TokenKind::RCurly, "`}`")?;
+            ExprKind::Map(entries)
+        } else if self.eat(&TokenKind::Comma) {
+            let mut items = ThinVec::from([first]);
+            items.extend(self.parse_comma_separated(TokenKind::RCurly, Self::parse_expr)?);
+            self.expect(TokenKind::RCurly, "`}`")?;
+            ExprKind::Set(items)
+        } else {
+            let stmt = self.finish_expr_stmt(first)?;
+            let block = self.parse_block_rest(id, start, ThinVec::from([stmt]))?;
+            ExprKind::Block(Box::new(block))
+        };
+
+        let span = self.span_from(start);
+        Ok(self.mk_expr(kind, span))
+    }
+
+    fn parse_class_init(&mut self, path: Path) -> ParseResult<ClassInit> {
+        let start = path.span.start;
+        self.expect(TokenKind::LCurly, "`{`")?;
+
+        let fields = self.parse_comma_separated(TokenKind::RCurly, |this| {
+            let ident = this.parse_ident()?;
+
+            let (expr, is_shorthand) = if this.eat(&TokenKind::Colon) {
+                (this.parse_expr()?, false)
+            } else {
+                let path = Path::from_ident(ident.clone());
+                (this.mk_expr(ExprKind::Path(path), ident.span), true)
+            };
+
+            Ok(FieldInit {
+                span: ident.span.merge(&expr.span),
+                ident,
+                expr,
+                is_shorthand,
+            })
+        })?;
+
+        self.expect(TokenKind::RCurly, "`}`")?;
+
+        Ok(ClassInit {
+            path,
+            fields,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_call_args(&mut self) -> ParseResult<ThinVec<Expr>> {
+        self.with_class_init(|this| this.parse_comma_separated(TokenKind::RParen, Self::parse_expr))
+    }
+
+    // the head of an `if`, `while` or `for`, a `{` here always starts the body
+    fn parse_cond(&mut self) -> ParseResult<Expr> {
+        let prev = std::mem::replace(&mut self.no_class_init, true);
+        let expr = self.parse_expr();
+        self.no_class_init = prev;
+        expr
+    }
+
+    fn with_class_init<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> ParseResult<T>,
+    ) -> ParseResult<T> {
+        let prev = std::mem::replace(&mut self.no_class_init, false);
+        let result = f(self);
+        self.no_class_init = prev;
+        result
+    }
+
+    fn parse_opt_expr(&mut self) -> ParseResult<Option<Box<Expr>>> {
+        if self.can_begin_expr() {
+            Ok(Some(Box::new(self.parse_expr()?)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn can_begin_expr(&self) -> bool {
+        !matches!(
+            self.peek(),
+            TokenKind::SemiColon
+                | TokenKind::RCurly
+                | TokenKind::RParen
+                | TokenKind::RBracket
+                | TokenKind::Comma
+                | TokenKind::LCurly
+                | TokenKind::Eof
+        )
+    }
+
+    //END expressions
+
+    //BEGIN helpers
+
+    fn parse_ident(&mut self) -> ParseResult<Ident> {
+        match self.peek().clone() {
+            TokenKind::Name { name } | TokenKind::DiscardName { name } => {
+                let span = self.bump_span();
+                Ok(Ident::new(name.as_str(), span))
+            }
+            _ => Err(self.unexpected("an identifier")),
+        }
+    }
+
+    fn parse_comma_separated<T>(
+        &mut self,
+        close: TokenKind,
+        mut parse: impl FnMut(&mut Self) -> ParseResult<T>,
+    ) -> ParseResult<ThinVec<T>> {
+        let mut items = ThinVec::new();
+
+        while !self.check(&close) {
+            items.push(parse(self)?);
+
+            if !self.eat(&TokenKind::Comma) {
+                break;
+            }
+        }
+
+        Ok(items)
+    }
+
+    fn mk_expr(&mut self, kind: ExprKind, span: Span) -> Expr {
+        Expr {
+            id: self.next_id(),
+            span,
+            kind,
+        }
+    }
+
+    fn next_id(&mut self) -> NodeId {
+        let id = NodeId(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    #[inline]
+    fn peek(&self) -> &TokenKind {
+        &self.tokens[self.pos].1
+    }
+
+    fn peek_nth(&self, n: usize) -> &TokenKind {
+        let idx = (self.pos + n).min(self.tokens.len() - 1);
+        &self.tokens[idx].1
+    }
+
+    #[inline]
+    fn peek_span(&self) -> Span {
+        let (start, _, end) = &self.tokens[self.pos];
+        Span::new(*start, *end)
+    }
+
+    fn at_eof(&self) -> bool {
+        matches!(self.peek(), TokenKind::Eof)
+    }
+
+    fn bump(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if self.pos + 1 < self.tokens.len() {
+            self.pos += 1;
+        }
+        self.prev_end = token.2;
+        token
+    }
+
+    fn bump_span(&mut self) -> Span {
+        let (start, _, end) = self.bump();
+        Span::new(start, end)
+    }
+
+    fn check(&self, kind: &TokenKind) -> bool {
+        self.peek() == kind
+    }
+
+    fn eat(&mut self, kind: &TokenKind) -> bool {
+        if self.check(kind) {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, kind: TokenKind, expected: &'static str) -> ParseResult<Span> {
+        if self.check(&kind) {
+            Ok(self.bump_span())
+        } else {
+            Err(self.unexpected(expected))
+        }
+    }
+
+    fn unexpected(&self, expected: &'static str) -> ParseError {
+        let kind = match self.peek() {
+            TokenKind::Eof => ParseErrorKind::UnexpectedEof { expected },
+            found => ParseErrorKind::UnexpectedToken {
+                expected,
+                found: found.clone(),
+            },
+        };
+
+        ParseError {
+            kind,
+            location: self.peek_span(),
+        }
+    }
+
+    fn span_from(&self, start: usize) -> Span {
+        Span::new(start, self.prev_end.max(start))
+    }
+
+    //END helpers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fn_body(file: &File, idx: usize) -> &Block {
+        match &file.items[idx].kind {
+            ItemKind::Fn(item_fn) => item_fn.body.as_deref().expect("fn should have a body"),
+            _ => panic!("expected a fn item"),
+        }
+    }
+
+    fn tail_expr(block: &Block) -> &Expr {
+        match &block.stmts.last().expect("expected a statement").kind {
+            StmtKind::Expr(expr) => expr,
+            other => panic!("expected a tail expression found {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_sample_program() {
+        let code = r#"
+            using kai.io;
+
+            fn square(a) {
+                a * a
+            }
+
+            fn main() {
+                for i in 1..=10 {
+                    io.print(square(i));
+                }
+            }
+        "#;
+
+        let file = parse(code).unwrap();
+        assert_eq!(file.items.len(), 3);
+        assert_eq!(file.items[0].ident.name, "io");
+        assert_eq!(file.items[1].ident.name, "square");
+        assert_eq!(file.items[2].ident.name, "main");
+
+        let body = fn_body(&file, 2);
+        let ExprKind::For(pat, iter, _) = &tail_expr(body).kind else {
+            panic!("expected a for loop");
+        };
+
+        assert!(matches!(&pat.kind, PatternKind::Ident { ident, .. } if ident.name == "i"));
+        assert!(matches!(
+            iter.kind,
+            ExprKind::Range(Some(_), Some(_), RangeLimits::Closed)
+        ));
+    }
+
+    #[test]
+    fn binary_operators_respect_precedence() {
+        let file = parse("fn main() { 1 + 2 * 3 == 7 && true }").unwrap();
+        let expr = tail_expr(fn_body(&file, 0));
+
+        let ExprKind::Binary(BinOp::And, lhs, _) = &expr.kind else {
+            panic!("expected && at the root");
+        };
+        let ExprKind::Binary(BinOp::Eq, lhs, _) = &lhs.kind else {
+            panic!("expected == below &&");
+        };
+        let ExprKind::Binary(BinOp::Add, _, rhs) = &lhs.kind else {
+            panic!("expected + below ==");
+        };
+        assert!(matches!(rhs.kind, ExprKind::Binary(BinOp::Mul, _, _)));
+    }
+
+    #[test]
+    fn parses_use_trees() {
+        let file = parse("using kai.io.{self, print as p}; using kai.json.*;").unwrap();
+
+        let ItemKind::Use(item_use) = &file.items[0].kind else {
+            panic!("expected a use item");
+        };
+        let UseTreeKind::Group { items, .. } = &item_use.path.kind else {
+            panic!("expected a group");
+        };
+        assert_eq!(item_use.path.prefix.segments.len(), 2);
+        assert_eq!(items[0].prefix.segments[0].name, "self");
+        assert_eq!(items[1].binding().unwrap().name, "p");
+
+        let ItemKind::Use(item_use) = &file.items[1].kind else {
+            panic!("expected a use item");
+        };
+        assert!(matches!(item_use.path.kind, UseTreeKind::Glob));
+    }
+
+    #[test]
+    fn parses_statements() {
+        let code = "
+            fn main() {
+                let mut x = 1;
+                let _unused;
+                ;
+                x += 2;
+                if x > 2 { x } else if x < 0 { 0 } else { 1 }
+            }
+        ";
+
+        let file = parse(code).unwrap();
+        let body = fn_body(&file, 0);
+        assert_eq!(body.stmts.len(), 5);
+        assert!(matches!(body.stmts[0].kind, StmtKind::Let(_)));
+        assert!(matches!(body.stmts[2].kind, StmtKind::Empty));
+        assert!(matches!(
+            &body.stmts[3].kind,
+            StmtKind::Semi(expr) if matches!(expr.kind, ExprKind::AssignOp(BinOp::Add, _, _))
+        ));
+        assert!(matches!(tail_expr(body).kind, ExprKind::If(_, _, Some(_))));
+    }
+
+    #[test]
+    fn node_ids_are_unique() {
+        let file = parse("fn a(x) { x + 1 } fn b() { a(2) }").unwrap();
+        assert_ne!(file.items[0].id, file.items[1].id);
+
+        let lhs = tail_expr(fn_body(&file, 0));
+        let rhs = tail_expr(fn_body(&file, 1));
+        assert_ne!(lhs.id, rhs.id);
+    }
+
+    #[test]
+    fn parses_generics() {
+        let file =
+            parse("class Pair<A, B: Num + Ord> {} fn first<T>(xs: list<T>) -> T {}").unwrap();
+
+        let ItemKind::Class(item_class) = &file.items[0].kind else {
+            panic!("expected a class");
+        };
+        let params = &item_class.generics.params;
+        assert_eq!(params.len(), 2);
+        assert_eq!(params[1].ident.name, "B");
+        assert_eq!(params[1].bounds.len(), 2);
+
+        let ItemKind::Fn(item_fn) = &file.items[1].kind else {
+            panic!("expected a fn");
+        };
+        assert_eq!(item_fn.generics.params[0].ident.name, "T");
+        let Some(TyKind::Path(path, args)) = item_fn.inputs[0].ty.as_ref().map(|ty| &ty.kind)
+        else {
+            panic!("expected a path type");
+        };
+        assert_eq!(path.segments[0].name, "list");
+        assert_eq!(args.len(), 1);
+    }
+
+    #[test]
+    fn parses_optional_types() {
+        let file = parse("fn next(self) -> list<int?>? { f()? }").unwrap();
+
+        let ItemKind::Fn(item_fn) = &file.items[0].kind else {
+            panic!("expected a fn");
+        };
+        let Some(TyKind::Optional(inner)) = item_fn.output.as_ref().map(|ty| &ty.kind) else {
+            panic!("expected an optional type");
+        };
+        let TyKind::Path(_, args) = &inner.kind else {
+            panic!("expected a path type");
+        };
+        assert!(matches!(args[0].kind, TyKind::Optional(_)));
+    }
+
+    #[test]
+    fn parses_interfaces_and_impls() {
+        let file = parse(
+            "interface Shape { fn area(self) -> float; describe(self) { \"shape\" } }
+             impl Shape for Circle { fn area(self) -> float { 1.0 } }",
+        )
+        .unwrap();
+
+        let ItemKind::Interface(shape) = &file.items[0].kind else {
+            panic!("expected an interface");
+        };
+        assert!(shape.method("area").unwrap().body.is_none());
+        assert!(shape.method("describe").unwrap().body.is_some());
+
+        let ItemKind::Impl(item_impl) = &file.items[1].kind else {
+            panic!("expected an impl");
+        };
+        assert_eq!(file.items[1].ident.name, "Circle");
+        assert!(
+            matches!(&item_impl.interface.kind, TyKind::Path(path, _) if path.segments[0].name == "Shape")
+        );
+        assert!(item_impl.method("area").is_some());
+
+        let err = parse("interface Shape { radius: float; }").unwrap_err();
+        assert_eq!(
+            err.kind,
+            ParseErrorKind::UnexpectedToken {
+                expected: "a method",
+                found: TokenKind::Name {
+                    name: "radius".into()
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn parses_try_expressions() {
+        let file = parse("fn main() { load()?.name; -value? }").unwrap();
+
+        let ItemKind::Fn(item_fn) = &file.items[0].kind else {
+            panic!("expected a fn");
+        };
+        let body = item_fn.body.as_ref().unwrap();
+
+        let StmtKind::Semi(expr) = &body.stmts[0].kind else {
+            panic!("expected a statement");
+        };
+        let ExprKind::Field(base, _) = &expr.kind else {
+            panic!("expected a field access");
+        };
+        assert!(
+            matches!(&base.kind, ExprKind::Try(inner) if matches!(inner.kind, ExprKind::Call(..)))
+        );
+
+        // `?` binds tighter than unary operators
+        let StmtKind::Expr(expr) = &body.stmts[1].kind else {
+            panic!("expected an expression");
+        };
+        assert!(
+            matches!(&expr.kind, ExprKind::Unary(UnOp::Neg, inner) if matches!(inner.kind, ExprKind::Try(_)))
+        );
+    }
+
+    #[test]
+    fn folds_the_sign_into_int_literals() {
+        let file = parse("fn main() { -9223372036854775808; -1?; - 2 }").unwrap();
+        let body = fn_body(&file, 0);
+
+        let StmtKind::Semi(min) = &body.stmts[0].kind else {
+            panic!("expected a statement");
+        };
+        assert!(matches!(&min.kind, ExprKind::Lit(lit) if lit.kind == LitKind::Int(i64::MIN)));
+        assert_eq!(min.span, Span::new(12, 32));
+
+        // `?` binds tighter than the sign
+        let StmtKind::Semi(try_expr) = &body.stmts[1].kind else {
+            panic!("expected a statement");
+        };
+        assert!(matches!(
+            &try_expr.kind,
+            ExprKind::Unary(UnOp::Neg, inner) if matches!(inner.kind, ExprKind::Try(_))
+        ));
+        assert!(matches!(
+            &tail_expr(body).kind,
+            ExprKind::Lit(lit) if lit.kind == LitKind::Int(-2)
+        ));
+
+        for code in [
+            "fn main() { 9223372036854775808 }",
+            "fn main() { -9223372036854775808? }",
+            "fn main() { -(9223372036854775808) }",
+            "fn main() { -9223372036854775809 }",
+        ] {
+            let err = parse(code).unwrap_err();
+            assert_eq!(err.kind, ParseErrorKind::Lexer(LexerErrorKind::IntOverflow));
+        }
+    }
+
+    #[test]
+    fn parses_collection_literals() {
+        let file = parse(
+            r#"fn main() { [1, 2,]; {"a": 1, "b": [x][0]}; {1, 2}; {x,}; {:}; xs[1..]; { x } }"#,
+        )
+        .unwrap();
+        let body = fn_body(&file, 0);
+
+        let semi = |idx: usize| match &body.stmts[idx].kind {
+            StmtKind::Semi(expr) => &expr.kind,
+            other => panic!("expected a statement found {other:?}"),
+        };
+        assert!(matches!(semi(0), ExprKind::List(items) if items.len() == 2));
+        assert!(matches!(
+            semi(1),
+            ExprKind::Map(entries) if matches!(entries[1].1.kind, ExprKind::Index(..))
+        ));
+        assert!(matches!(semi(2), ExprKind::Set(items) if items.len() == 2));
+        assert!(matches!(semi(3), ExprKind::Set(items) if items.len() == 1));
+        assert!(matches!(semi(4), ExprKind::Map(entries) if entries.is_empty()));
+        assert!(matches!(
+            semi(5),
+            ExprKind::Index(_, index) if matches!(index.kind, ExprKind::Range(Some(_), None, _))
+        ));
+        // a single expression without a trailing comma stays a block
+        assert!(matches!(tail_expr(body).kind, ExprKind::Block(_)));
+    }
+
+    #[test]
+    fn parses_coroutines() {
+        let code = "
+            async fn fetch() { await get().body }
+            fn outer() { fn numbers() { yield 1; yield; } numbers() }
+            class Client { async send(self) {} }
+        ";
+        let file = parse(code).unwrap();
+
+        let kind = |item: &Item| match &item.kind {
+            ItemKind::Fn(item_fn) => item_fn.coroutine(),
+            _ => panic!("expected a fn"),
+        };
+        let items = file.all_items();
+        assert_eq!(kind(items[0]), Some(CoroutineKind::Async));
+        // only the fn the `yield` is in becomes a generator
+        assert_eq!(kind(items[1]), None);
+        assert_eq!(kind(items[2]), Some(CoroutineKind::Generator));
+        assert_eq!(
+            kind(&file.items[2].kind.methods()[0]),
+            Some(CoroutineKind::Async)
+        );
+
+        // `await` takes the whole postfix expression
+        let ExprKind::Await(inner) = &tail_expr(fn_body(&file, 0)).kind else {
+            panic!("expected an await");
+        };
+        assert!(matches!(inner.kind, ExprKind::Field(..)));
+    }
+
+    #[test]
+    fn parses_scripts() {
+        let file = parse_script("let a = 1; fn f() {} a + 1").unwrap();
+        let names: Vec<&str> = file.items.iter().map(|i| i.ident.name.as_str()).collect();
+        assert_eq!(names, ["f", SCRIPT_FN]);
+
+        let ItemKind::Fn(item_fn) = &file.items[1].kind else {
+            panic!("expected a fn");
+        };
+        let body = item_fn.body.as_ref().unwrap();
+        assert_eq!(body.stmts.len(), 2);
+        assert!(matches!(body.stmts[1].kind, StmtKind::Expr(_)));
+    }
+
+    #[test]
+    fn parses_attributes() {
+        let file = parse(
+            r#"@test @cfg(os = "linux", test) fn a(@unused x) {}
+               class A { @deprecated("use b") pub a: int; @inline fn get(self) {} }"#,
+        )
+        .unwrap();
+
+        let ItemKind::Fn(item_fn) = &file.items[0].kind else {
+            panic!("expected a fn");
+        };
+        let names: Vec<String> = item_fn.attrs.iter().map(Attribute::name).collect();
+        assert_eq!(names, ["test", "cfg"]);
+        assert!(matches!(
+            &item_fn.attrs[1].args[..],
+            [AttrArg::KeyValue(key, _), AttrArg::Word(word)] if key.name == "os" && word.name == "test"
+        ));
+        assert_eq!(item_fn.inputs[0].attrs[0].name(), "unused");
+        assert_eq!(file.items[0].span.start, 0);
+
+        let ItemKind::Class(item_class) = &file.items[1].kind else {
+            panic!("expected a class");
+        };
+        assert_eq!(item_class.fields[0].attrs[0].str_arg(), Some("use b"));
+        assert_eq!(item_class.methods[0].kind.attrs()[0].name(), "inline");
+
+        let err = parse("@cfg(os = linux) fn a() {}").unwrap_err();
+        assert!(matches!(
+            err.kind,
+            ParseErrorKind::UnexpectedToken {
+                expected: "a literal",
+                found: TokenKind::Name { .. },
+            }
+        ));
+    }
+
+    #[test]
+    fn attaches_doc_comments() {
+        let file = parse(
+            "/**
+              * Adds two numbers.
+              *
+              *     add(1, 2)
+              */
+             fn add(a, b) { /** inner */ fn helper() {} }
+
+             /** A point */
+             class Point {
+                 /** horizontal */
+                 x: int;
+                 /**
+                    Moves the point
+                 */
+                 fn shift(self) {}
+             }
+             /** dangling */",
+        )
+        .unwrap();
+
+        assert_eq!(
+            file.items[0].doc.as_deref(),
+            Some("Adds two numbers.\n\n    add(1, 2)")
+        );
+
+        let ItemKind::Fn(item_fn) = &file.items[0].kind else {
+            panic!("expected a fn");
+        };
+        let StmtKind::Item(helper) = &item_fn.body.as_ref().unwrap().stmts[0].kind else {
+            panic!("expected an item");
+        };
+        assert_eq!(helper.doc.as_deref(), Some("inner"));
+
+        let ItemKind::Class(item_class) = &file.items[1].kind else {
+            panic!("expected a class");
+        };
+        assert_eq!(file.items[1].doc.as_deref(), Some("A point"));
+        assert_eq!(item_class.fields[0].doc.as_deref(), Some("horizontal"));
+        assert_eq!(
+            item_class.methods[0].doc.as_deref(),
+            Some("Moves the point")
+        );
+    }
+
+    #[test]
+    fn records_shebang() {
+        let file = parse("#!/usr/bin/env kai\nfn main() {}").unwrap();
+        assert_eq!(file.shebang.as_deref(), Some("/usr/bin/env kai"));
+        assert_eq!(file.items[0].ident.span, Span::new(22, 26));
+    }
+
+    #[test]
+    fn reports_missing_semicolon() {
+        let err = parse("fn main() { let a = 1 a }").unwrap_err();
+        assert_eq!(
+            err.kind,
+            ParseErrorKind::UnexpectedToken {
+                expected: "`;`",
+                found: TokenKind::Name { name: "a".into() }
+            }
+        );
+        assert_eq!(err.location, Span::new(22, 23));
+
+        let err = parse("fn main() { 1 2 }").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::MissingSemiColon);
+    }
 }
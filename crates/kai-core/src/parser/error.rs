@@ -1,11 +1,16 @@
+use std::fmt::Display;
+
 use crate::ast::span::Span;
 
+use super::token::TokenKind;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LexerErrorKind {
     NumberTrailingUnderScore,
     NonTerminatedStringLiteral,
     MissingExponentValue,
     EmptyRadix,
+    IntOverflow,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -13,3 +18,61 @@ pub struct LexerError {
     pub kind: LexerErrorKind,
     pub location: Span,
 }
+
+impl Display for LexerErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            LexerErrorKind::NumberTrailingUnderScore => "numbers cannot end with an `_`",
+            LexerErrorKind::NonTerminatedStringLiteral => "unterminated string literal",
+            LexerErrorKind::MissingExponentValue => "missing exponent value",
+            LexerErrorKind::EmptyRadix => "expected digits after the radix prefix",
+            LexerErrorKind::IntOverflow => "integer literal is too large for `int`",
+        };
+
+        write!(f, "{s}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseErrorKind {
+    Lexer(LexerErrorKind),
+    UnexpectedToken {
+        expected: &'static str,
+        found: TokenKind,
+    },
+    UnexpectedEof {
+        expected: &'static str,
+    },
+    // an expression statement which is not the last one in a block
+    MissingSemiColon,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub location: Span,
+}
+
+impl From<LexerError> for ParseError {
+    fn from(value: LexerError) -> Self {
+        Self {
+            kind: ParseErrorKind::Lexer(value.kind),
+            location: value.location,
+        }
+    }
+}
+
+impl Display for ParseErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseErrorKind::Lexer(kind) => write!(f, "{kind}"),
+            ParseErrorKind::UnexpectedToken { expected, found } => {
+                write!(f, "expected {expected}, found `{found}`")
+            }
+            ParseErrorKind::UnexpectedEof { expected } => {
+                write!(f, "expected {expected}, found end of file")
+            }
+            ParseErrorKind::MissingSemiColon => write!(f, "expected `;` after expression"),
+        }
+    }
+}
@@ -93,17 +93,10 @@ where
 
         loop {
             match self.ch0 {
-                Some('*') => {
-                    if self.ch1 == Some('/') {
-                        self.next_char();
-                        self.next_char();
-                        break;
-                    } else {
-                        let c = self.next_char().expect("expected a character");
-                        if let Some(ref mut doc) = doc_str {
-                            doc.push(c);
-                        }
-                    }
+                Some('*') if self.ch1 == Some('/') => {
+                    self.next_char();
+                    self.next_char();
+                    break;
                 }
                 _ => {
                     let c = self.next_char().expect("expected a character");
@@ -211,10 +204,11 @@ where
                     _ => Some(self.eat_single_token(TokenKind::Minus)),
                 },
 
-                '+' | '*' | '!' | '=' | '>' | '<' => {
+                '+' | '*' | '%' | '!' | '=' | '>' | '<' => {
                     let tok = match c {
                         '+' => (TokenKind::Plus, TokenKind::PlusEq),
                         '*' => (TokenKind::Star, TokenKind::MulEq),
+                        '%' => (TokenKind::Percent, TokenKind::ModEq),
                         '!' => (TokenKind::Bang, TokenKind::NotEq),
                         '=' => (TokenKind::Eq, TokenKind::EqEq),
                         '>' => (TokenKind::Gt, TokenKind::GtEq),
@@ -260,8 +254,20 @@ where
                     }
                 }
 
+                '&' if self.ch1 == Some('&') => {
+                    self.next_char();
+                    Some(self.eat_single_token(TokenKind::AmperAmper))
+                }
+                '|' if self.ch1 == Some('|') => {
+                    self.next_char();
+                    Some(self.eat_single_token(TokenKind::PipePipe))
+                }
+
+                ',' => Some(self.eat_single_token(TokenKind::Comma)),
                 ';' => Some(self.eat_single_token(TokenKind::SemiColon)),
                 ':' => Some(self.eat_single_token(TokenKind::Colon)),
+                '?' => Some(self.eat_single_token(TokenKind::Question)),
+                '@' => Some(self.eat_single_token(TokenKind::At)),
                 '"' => {
                     let spanned = self.eat_double_quoted_string()?;
                     self.queue(spanned);
@@ -310,7 +316,7 @@ where
     }
 
     fn is_name_start(&self, c: char) -> bool {
-        matches!(c, '_' | 'a'..='z')
+        matches!(c, '_' | 'a'..='z' | 'A'..='Z')
     }
 
     fn is_name_continuation(&self) -> bool {
@@ -424,17 +430,7 @@ where
             })
         } else {
             let value = format!("{prefix}{num}");
-            let int_value =
-                super::parse_int_value(&value).expect("should have parsed into an int value");
-
-            Ok((
-                start,
-                TokenKind::Int {
-                    value: value.into(),
-                    int_value,
-                },
-                self.cursor(),
-            ))
+            Ok((start, self.int(start, value)?, self.cursor()))
         }
     }
 
@@ -457,8 +453,8 @@ where
 
         // if matches!(self.ch0, Some('e') | Some('E') | Some('.')) {
 
-        if (self.ch0 == Some('.')
-            && (self.ch1 != Some('.') || (!self.ch1.is_some_and(|c| self.is_name_start(c)))))
+        // `1..10` and `1..=10` are ranges, not a float followed by a dot
+        if (self.ch0 == Some('.') && self.ch1 != Some('.'))
             || matches!(self.ch0, Some('e') | Some('E'))
         {
             is_decimal = true;
@@ -497,13 +493,7 @@ where
                 value: value.into(),
             }
         } else {
-            let int_value =
-                super::parse_int_value(&value).expect("should have parsed to an int value");
-
-            TokenKind::Int {
-                value: value.into(),
-                int_value,
-            }
+            self.int(start, value)?
         };
 
         let end = self.cursor();
@@ -511,6 +501,23 @@ where
         Ok((start, token, end))
     }
 
+    // an integer literal starting at `start`, which must fit in an `int` once negated
+    fn int(&self, start: usize, value: String) -> Result<TokenKind, LexerError> {
+        match super::parse_int_value(&value) {
+            Some(int_value) => Ok(TokenKind::Int {
+                value: value.into(),
+                int_value,
+            }),
+            None => Err(LexerError {
+                kind: LexerErrorKind::IntOverflow,
+                location: Span {
+                    start,
+                    end: self.cursor(),
+                },
+            }),
+        }
+    }
+
     // the e or E should be eaten before caliing this
     fn parse_exponent(&mut self) -> Result<String, LexerError> {
         let mut value = String::new();
@@ -633,6 +640,17 @@ fn get_keyword_from_str(string: &str) -> Option<TokenKind> {
         "for" => TokenKind::For,
         "in" => TokenKind::In,
         "loop" => TokenKind::Loop,
+        "while" => TokenKind::While,
+        "break" => TokenKind::Break,
+        "continue" => TokenKind::Continue,
+        "true" => TokenKind::True,
+        "false" => TokenKind::False,
+        "interface" => TokenKind::Interface,
+        "impl" => TokenKind::Impl,
+        "nil" => TokenKind::Nil,
+        "async" => TokenKind::Async,
+        "await" => TokenKind::Await,
+        "yield" => TokenKind::Yield,
 
         _ => TokenKind::Unknown,
     };
@@ -1079,6 +1097,40 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_logical_and_remainder_operators() {
+        let input = "&& || % %= , 1..=10";
+        let tokens = lex_input(input);
+        assert_eq!(
+            tokens,
+            vec![
+                (0, TokenKind::AmperAmper, 2),
+                (3, TokenKind::PipePipe, 5),
+                (6, TokenKind::Percent, 7),
+                (8, TokenKind::ModEq, 10),
+                (11, TokenKind::Comma, 12),
+                (
+                    13,
+                    TokenKind::Int {
+                        value: "1".into(),
+                        int_value: 1
+                    },
+                    14
+                ),
+                (14, TokenKind::DotDotEq, 17),
+                (
+                    17,
+                    TokenKind::Int {
+                        value: "10".into(),
+                        int_value: 10
+                    },
+                    19
+                ),
+                (19, TokenKind::Eof, 19),
+            ]
+        );
+    }
+
     #[test]
     fn test_newlines_and_whitespace() {
         let input = " \t\n\r\n";
@@ -1095,7 +1147,7 @@ mod tests {
 
     #[test]
     fn test_invalid_tokens() {
-        let input = "#$%";
+        let input = "#$~";
         let tokens = lex_input(input);
         assert_eq!(
             tokens,
@@ -1134,9 +1186,48 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_int_bounds() {
+        let tokens = lex_input("9223372036854775807 0x8000_0000_0000_0000");
+        assert_eq!(
+            tokens,
+            vec![
+                (
+                    0,
+                    TokenKind::Int {
+                        value: "9223372036854775807".into(),
+                        int_value: i64::MAX as u64
+                    },
+                    19
+                ),
+                (
+                    20,
+                    TokenKind::Int {
+                        value: "0x8000000000000000".into(),
+                        int_value: i64::MIN.unsigned_abs()
+                    },
+                    41
+                ),
+                (41, TokenKind::Eof, 41),
+            ]
+        );
+
+        // one more than `i64::MAX` is let through for `-9223372036854775808`
+        for (input, end) in [("9223372036854775809", 19), ("0x8000000000000001", 18)] {
+            let mut lexer = Lexer::new(input.chars());
+            assert_eq!(
+                lexer.advance(),
+                Err(LexerError {
+                    kind: LexerErrorKind::IntOverflow,
+                    location: Span { start: 0, end }
+                })
+            );
+        }
+    }
+
     #[test]
     fn test_comments() {
-        use TokenKind::*; 
+        use TokenKind::*;
         let input = "
             // hello code
             /*
@@ -1155,7 +1246,6 @@ mod tests {
             .filter(|t| !matches!(t.1, TokenKind::NewLine))
             .collect::<Vec<Token>>();
 
-
         assert_eq!(tokens,
             [
                 ( 13, Comment, 27, ),
@@ -17,16 +17,27 @@ pub enum TokenKind {
     For,
     In,
     Loop,
+    While,
+    Break,
+    Continue,
+    True,
+    False,
     Else,
     Return,
     Using,
     Todo,
+    Interface,
+    Impl,
+    Nil,
+    Async,
+    Await,
+    Yield,
 
     Name { name: EcoString },
     // _thing
     DiscardName { name: EcoString },
     // TODO: maybe switch to bigint
-    Int { value: EcoString, int_value: i32 },
+    Int { value: EcoString, int_value: u64 },
     Float { value: EcoString },
     // qoutes not included
     String { value: EcoString },
@@ -35,10 +46,15 @@ pub enum TokenKind {
     Minus,
     Star,
     Slash,
+    Percent,
     PlusEq,
     MinusEq,
     MulEq,
     DivEq,
+    ModEq,
+
+    AmperAmper,
+    PipePipe,
 
     Comment,
     DocComment { comment: EcoString },
@@ -66,8 +82,10 @@ pub enum TokenKind {
     EqEq,
     Bang,
     NotEq,
+    Comma,
     SemiColon,
     Colon,
+    Question,
     Unknown,
 
     NewLine,
@@ -93,6 +111,17 @@ impl Display for TokenKind {
             TokenKind::For => "for",
             TokenKind::In => "in",
             TokenKind::Loop => "loop",
+            TokenKind::While => "while",
+            TokenKind::Break => "break",
+            TokenKind::Continue => "continue",
+            TokenKind::True => "true",
+            TokenKind::False => "false",
+            TokenKind::Interface => "interface",
+            TokenKind::Impl => "impl",
+            TokenKind::Nil => "nil",
+            TokenKind::Async => "async",
+            TokenKind::Await => "await",
+            TokenKind::Yield => "yield",
 
             TokenKind::Gt => ">",
             TokenKind::Lt => "<",
@@ -117,8 +146,10 @@ impl Display for TokenKind {
             TokenKind::NotEq => "!=",
             TokenKind::Eq => "=",
             TokenKind::EqEq => "==",
+            TokenKind::Comma => ",",
             TokenKind::SemiColon => ";",
             TokenKind::Colon => ":",
+            TokenKind::Question => "?",
 
             TokenKind::Comment => "// ... comment",
             TokenKind::DocComment { .. } => "Doc comment",
@@ -135,11 +166,16 @@ impl Display for TokenKind {
             TokenKind::Minus => "-",
             TokenKind::Star => "*",
             TokenKind::Slash => "/",
+            TokenKind::Percent => "%",
 
             TokenKind::PlusEq => "+=",
             TokenKind::MinusEq => "-=",
             TokenKind::MulEq => "*=",
             TokenKind::DivEq => "/=",
+            TokenKind::ModEq => "%=",
+
+            TokenKind::AmperAmper => "&&",
+            TokenKind::PipePipe => "||",
 
             TokenKind::Unknown => "Unknown",
         };
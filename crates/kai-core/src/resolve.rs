@@ -0,0 +1,1260 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::span::Span;
+use crate::ast::*;
+use crate::attrs::{self, AttrRegistry, AttrTarget};
+use crate::diagnostic::Diagnostic;
+use crate::runtime::stdlib;
+
+/// Names which are always in scope
+pub const PRELUDE: &[&str] = &["kai"];
+
+/// Built in type names, these can be shadowed by classes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrimTy {
+    Int,
+    Float,
+    String,
+    Bool,
+    Nil,
+    List,
+    Map,
+    Set,
+    Result,
+    Error,
+    Future,
+    Generator,
+}
+
+impl PrimTy {
+    pub fn from_name(name: &str) -> Option<Self> {
+        let prim = match name {
+            "int" => PrimTy::Int,
+            "float" => PrimTy::Float,
+            "string" => PrimTy::String,
+            "bool" => PrimTy::Bool,
+            "nil" => PrimTy::Nil,
+            "list" => PrimTy::List,
+            "map" => PrimTy::Map,
+            "set" => PrimTy::Set,
+            "result" => PrimTy::Result,
+            "error" => PrimTy::Error,
+            "future" => PrimTy::Future,
+            "generator" => PrimTy::Generator,
+            _ => return None,
+        };
+
+        Some(prim)
+    }
+}
+
+/// Bounds understood by the checker without being declared anywhere
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuiltinBound {
+    // supports `+ - * / %`
+    Num,
+    // supports `< <= > >=`
+    Ord,
+}
+
+impl BuiltinBound {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Num" => Some(BuiltinBound::Num),
+            "Ord" => Some(BuiltinBound::Ord),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BuiltinBound::Num => "Num",
+            BuiltinBound::Ord => "Ord",
+        }
+    }
+}
+
+/// Interfaces the runtime relies on, user classes implement them with `impl`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BuiltinInterface {
+    // `to_string(self) -> string`, used when printing
+    Display,
+    // `eq(self, other) -> bool`, used by `==` and `!=`
+    Eq,
+    // `next(self)` returning `nil` once done, used by `for .. in`
+    Iterator,
+    // `to_json(self)` returning what `kai.json` encodes in place of the instance
+    Serialize,
+}
+
+impl BuiltinInterface {
+    pub const ALL: [BuiltinInterface; 4] = [
+        BuiltinInterface::Display,
+        BuiltinInterface::Eq,
+        BuiltinInterface::Iterator,
+        BuiltinInterface::Serialize,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|iface| iface.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BuiltinInterface::Display => "Display",
+            BuiltinInterface::Eq => "Eq",
+            BuiltinInterface::Iterator => "Iterator",
+            BuiltinInterface::Serialize => "Serialize",
+        }
+    }
+
+    /// The only method of the interface
+    pub fn method(&self) -> &'static str {
+        match self {
+            BuiltinInterface::Display => "to_string",
+            BuiltinInterface::Eq => "eq",
+            BuiltinInterface::Iterator => "next",
+            BuiltinInterface::Serialize => "to_json",
+        }
+    }
+}
+
+/// What a name refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Res {
+    // a `let`, `for` or parameter binding, points to its pattern
+    Local(NodeId),
+    // points to the item
+    Item(NodeId),
+    // points to the use tree which introduced the name
+    Import(NodeId),
+    Builtin,
+    // only for paths in type annotations
+    PrimTy(PrimTy),
+    // a generic parameter, points to its declaration
+    TyParam(NodeId),
+    // only for generic bounds
+    Bound(BuiltinBound),
+    // only for paths in type annotations and impls
+    BuiltinInterface(BuiltinInterface),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefKind {
+    Local,
+    Param,
+    Fn,
+    Class,
+    Interface,
+    Field,
+    TyParam,
+    Import,
+}
+
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub name: String,
+    pub span: Span,
+    pub kind: DefKind,
+}
+
+/// What a `using` tree imports
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    pub path: String,
+    // `using kai.io.*`, the name being looked up is appended to the path
+    pub glob: bool,
+}
+
+impl Import {
+    /// The full path of `name` imported through this tree
+    pub fn full_path(&self, name: &str) -> String {
+        match self.glob {
+            true => format!("{}.{name}", self.path),
+            false => self.path.clone(),
+        }
+    }
+}
+
+/// The output of name resolution, a side table keyed by node id
+#[derive(Debug, Default)]
+pub struct Resolutions {
+    // path expression / type id -> what it refers to
+    res: HashMap<NodeId, Res>,
+    // pattern / item / use tree id -> the definition it introduces
+    defs: HashMap<NodeId, Definition>,
+    // use tree id -> the path it imports
+    imports: HashMap<NodeId, Import>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Resolutions {
+    pub fn get(&self, id: NodeId) -> Option<Res> {
+        self.res.get(&id).copied()
+    }
+
+    pub fn definition(&self, id: NodeId) -> Option<&Definition> {
+        self.defs.get(&id)
+    }
+
+    pub fn import(&self, tree: NodeId) -> Option<&Import> {
+        self.imports.get(&tree)
+    }
+
+    /// Every path expression which refers to the definition `def`
+    pub fn uses_of(&self, def: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        self.res.iter().filter_map(move |(id, res)| match res {
+            Res::Local(target) | Res::Item(target) | Res::Import(target) if *target == def => {
+                Some(*id)
+            }
+            _ => None,
+        })
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(Diagnostic::is_error)
+    }
+}
+
+pub fn resolve(file: &File) -> Resolutions {
+    resolve_with(file, AttrRegistry::default())
+}
+
+/// Resolves a file, checking its attributes against `registry`
+pub fn resolve_with(file: &File, registry: AttrRegistry) -> Resolutions {
+    let mut resolver = Resolver {
+        registry,
+        ..Default::default()
+    };
+    resolver.resolve_file(file);
+    resolver.finish()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScopeKind {
+    Module,
+    // generic parameters of a class, visible in its methods
+    Class,
+    // locals of enclosing functions are not visible past this scope
+    Fn,
+    Block,
+}
+
+#[derive(Debug)]
+struct Binding {
+    res: Res,
+    span: Span,
+    used: bool,
+}
+
+#[derive(Debug)]
+struct Scope {
+    kind: ScopeKind,
+    names: HashMap<String, Binding>,
+    // glob imports, any name not found otherwise may come from these
+    globs: Vec<NodeId>,
+}
+
+impl Scope {
+    fn new(kind: ScopeKind) -> Self {
+        Self {
+            kind,
+            names: HashMap::new(),
+            globs: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Resolver {
+    scopes: Vec<Scope>,
+    registry: AttrRegistry,
+    // items marked `@deprecated`, with their message
+    deprecated: HashMap<NodeId, Option<String>>,
+    // paths the embedder provides and every prefix of them, the roots are in scope
+    // everywhere like the prelude
+    externs: HashSet<String>,
+    // the fn being resolved is `async`, `await` is only allowed there
+    in_async: bool,
+    // bindings declared with `mut`, the only ones which can be assigned to
+    mutable: HashSet<NodeId>,
+    out: Resolutions,
+}
+
+impl Resolver {
+    /// Declares a value or module an embedder provides, eg. `myapp.db.query`. Its root
+    /// resolves to a builtin in every file and imports under that root are checked.
+    pub fn add_extern(&mut self, path: &str) {
+        let mut prefix = String::new();
+
+        for segment in path.split('.') {
+            if !prefix.is_empty() {
+                prefix.push('.');
+            }
+            prefix.push_str(segment);
+            self.externs.insert(prefix.clone());
+        }
+    }
+
+    pub fn resolve_file(&mut self, file: &File) {
+        self.with_scope(ScopeKind::Module, |this| {
+            this.declare_items(file.items.iter());
+
+            for item in &file.items {
+                this.resolve_item(item);
+            }
+        });
+    }
+
+    pub fn finish(mut self) -> Resolutions {
+        self.out.diagnostics.sort_by_key(|d| d.span.start);
+        self.out
+    }
+
+    fn with_scope(&mut self, kind: ScopeKind, f: impl FnOnce(&mut Self)) {
+        self.scopes.push(Scope::new(kind));
+        f(self);
+        let scope = self.scopes.pop().expect("scope should exist");
+        self.report_unused(scope);
+    }
+
+    fn report_unused(&mut self, scope: Scope) {
+        let mut unused: Vec<(&String, &Binding)> = scope
+            .names
+            .iter()
+            .filter(|(name, binding)| {
+                !binding.used
+                    && !name.starts_with('_')
+                    && name.as_str() != "self"
+                    && matches!(binding.res, Res::Local(_) | Res::Import(_))
+            })
+            .collect();
+
+        unused.sort_by_key(|(_, binding)| binding.span.start);
+
+        for (name, binding) in unused {
+            let message = match binding.res {
+                Res::Import(_) => format!("unused import `{name}`"),
+                _ => format!("unused variable `{name}`"),
+            };
+            let diagnostic = Diagnostic::warning(message, binding.span);
+            self.out.diagnostics.push(diagnostic);
+        }
+    }
+
+    //BEGIN declarations
+
+    fn declare_items<'a>(&mut self, items: impl Iterator<Item = &'a Item>) {
+        for item in items {
+            if let Some(attr) = attrs::find(item.kind.attrs(), "deprecated") {
+                let message = attr.str_arg().map(str::to_owned);
+                self.deprecated.insert(item.id, message);
+            }
+
+            match &item.kind {
+                ItemKind::Fn(item_fn) => {
+                    self.define(&item_fn.ident, item.id, DefKind::Fn);
+                    self.declare(&item_fn.ident, Res::Item(item.id));
+                }
+                ItemKind::Use(item_use) => self.declare_use_tree(&item_use.path, None),
+                ItemKind::Class(item_class) => {
+                    self.define(&item_class.ident, item.id, DefKind::Class);
+                    self.declare(&item_class.ident, Res::Item(item.id));
+                }
+                ItemKind::Interface(item_interface) => {
+                    self.define(&item_interface.ident, item.id, DefKind::Interface);
+                    self.declare(&item_interface.ident, Res::Item(item.id));
+                }
+                // impls are only reachable through their class
+                ItemKind::Impl(_) => {}
+            }
+        }
+    }
+
+    fn declare_use_tree(&mut self, tree: &UseTree, parent: Option<&Path>) {
+        match &tree.kind {
+            UseTreeKind::Simple(rename) => {
+                let last = tree.prefix.segments.last();
+
+                // `using kai.io.{self}` binds `io`
+                let ident = match (rename, last) {
+                    (Some(rename), _) => Some(rename),
+                    (None, Some(last)) if last.name == "self" => {
+                        parent.and_then(|parent| parent.segments.last())
+                    }
+                    (None, last) => last,
+                };
+
+                if let Some(ident) = ident {
+                    let ident = Ident::new(ident.name.clone(), tree.span);
+                    self.define(&ident, tree.id, DefKind::Import);
+                    self.declare(&ident, Res::Import(tree.id));
+                }
+            }
+            UseTreeKind::Group { items, .. } => {
+                for item in items {
+                    self.declare_use_tree(item, Some(&tree.prefix));
+                }
+            }
+            UseTreeKind::Glob => {
+                let scope = self.scopes.last_mut().expect("scope should exist");
+                scope.globs.push(tree.id);
+            }
+        }
+    }
+
+    // records the path of every tree, imports from `kai` and the modules of the host must exist
+    fn resolve_use_tree(&mut self, tree: &UseTree, prefix: &str) {
+        let mut path = prefix.to_owned();
+
+        for segment in &tree.prefix.segments {
+            if segment.name == "self" {
+                continue;
+            }
+            if !path.is_empty() {
+                path.push('.');
+            }
+            path.push_str(&segment.name);
+        }
+
+        let glob = match &tree.kind {
+            UseTreeKind::Simple(_) => false,
+            UseTreeKind::Glob => true,
+            UseTreeKind::Group { items, .. } => {
+                for item in items {
+                    self.resolve_use_tree(item, &path);
+                }
+                return;
+            }
+        };
+
+        let root = path.split('.').next().unwrap_or_default();
+        let missing = match root {
+            "kai" => stdlib::lookup(&path).is_none(),
+            root => self.externs.contains(root) && !self.externs.contains(&path),
+        };
+        if missing {
+            let message = format!("unresolved import `{path}`");
+            self.out
+                .diagnostics
+                .push(Diagnostic::error(message, tree.span));
+        }
+
+        self.out.imports.insert(tree.id, Import { path, glob });
+    }
+
+    fn define(&mut self, ident: &Ident, id: NodeId, kind: DefKind) {
+        self.out.defs.insert(
+            id,
+            Definition {
+                name: ident.name.clone(),
+                span: ident.span,
+                kind,
+            },
+        );
+    }
+
+    /// Adds a name to the innermost scope, reporting duplicate items
+    fn declare(&mut self, ident: &Ident, res: Res) {
+        let scope = self.scopes.last_mut().expect("scope should exist");
+
+        let binding = Binding {
+            res,
+            span: ident.span,
+            used: false,
+        };
+
+        if let Some(previous) = scope.names.insert(ident.name.clone(), binding) {
+            let diagnostic = match previous.res {
+                Res::Local(_) if matches!(res, Res::Local(_)) => Diagnostic::warning(
+                    format!("`{}` shadows a previous binding", ident.name),
+                    ident.span,
+                )
+                .with_label(previous.span, "previously bound here"),
+                _ => Diagnostic::error(
+                    format!("`{}` is defined multiple times", ident.name),
+                    ident.span,
+                )
+                .with_label(previous.span, "previous definition here"),
+            };
+
+            self.out.diagnostics.push(diagnostic);
+        }
+    }
+
+    fn bind_pattern(&mut self, pat: &Pattern, kind: DefKind) {
+        let PatternKind::Ident { ident, mutable } = &pat.kind else {
+            return;
+        };
+
+        self.define(ident, pat.id, kind);
+        if *mutable {
+            self.mutable.insert(pat.id);
+        }
+
+        let in_current_scope = self
+            .scopes
+            .last()
+            .is_some_and(|scope| scope.names.contains_key(&ident.name));
+
+        if kind == DefKind::Param && in_current_scope {
+            let previous = self.scopes.last().unwrap().names[&ident.name].span;
+            let diagnostic = Diagnostic::error(
+                format!("parameter `{}` is bound more than once", ident.name),
+                ident.span,
+            )
+            .with_label(previous, "first bound here");
+            self.out.diagnostics.push(diagnostic);
+            return;
+        }
+
+        // shadowing inside the same scope is reported by `declare`
+        if !in_current_scope && !ident.is_discarded() {
+            if let Some(previous) = self.lookup_local(&ident.name) {
+                let diagnostic = Diagnostic::warning(
+                    format!("`{}` shadows a binding from an outer scope", ident.name),
+                    ident.span,
+                )
+                .with_label(previous, "previously bound here");
+                self.out.diagnostics.push(diagnostic);
+            }
+        }
+
+        self.declare(ident, Res::Local(pat.id));
+    }
+
+    // span of a local binding visible from the current function
+    fn lookup_local(&self, name: &str) -> Option<Span> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(binding) = scope.names.get(name) {
+                if matches!(binding.res, Res::Local(_)) {
+                    return Some(binding.span);
+                }
+            }
+
+            if scope.kind == ScopeKind::Fn {
+                break;
+            }
+        }
+
+        None
+    }
+
+    //END declarations
+
+    //BEGIN walking
+
+    fn resolve_item(&mut self, item: &Item) {
+        self.check_attrs(item.kind.attrs(), AttrTarget::of_item(&item.kind));
+
+        match &item.kind {
+            ItemKind::Fn(item_fn) => self.resolve_fn(item_fn),
+            ItemKind::Use(item_use) => self.resolve_use_tree(&item_use.path, ""),
+            ItemKind::Class(item_class) => self.resolve_class(item_class),
+            ItemKind::Interface(item_interface) => self.resolve_methods(&item_interface.methods),
+            ItemKind::Impl(item_impl) => {
+                self.resolve_ty(&item_impl.interface);
+                self.resolve_ty(&item_impl.class);
+                self.resolve_methods(&item_impl.methods);
+            }
+        }
+    }
+
+    fn resolve_class(&mut self, item_class: &ItemClass) {
+        self.with_scope(ScopeKind::Class, |this| {
+            this.declare_generics(&item_class.generics);
+            this.resolve_class_members(item_class);
+        });
+    }
+
+    fn resolve_class_members(&mut self, item_class: &ItemClass) {
+        let mut seen: HashMap<&str, Span> = HashMap::new();
+
+        for field in &item_class.fields {
+            self.check_attrs(&field.attrs, AttrTarget::Field);
+            self.resolve_ty(&field.ty);
+            self.define(&field.ident, field.id, DefKind::Field);
+
+            if let Some(previous) = seen.insert(&field.ident.name, field.ident.span) {
+                let diagnostic = Diagnostic::error(
+                    format!("field `{}` is already declared", field.ident.name),
+                    field.ident.span,
+                )
+                .with_label(previous, "first declared here");
+                self.out.diagnostics.push(diagnostic);
+            }
+        }
+
+        self.resolve_methods(&item_class.methods);
+    }
+
+    fn resolve_methods(&mut self, methods: &[Item]) {
+        let mut seen: HashMap<&str, Span> = HashMap::new();
+
+        for method in methods {
+            let ItemKind::Fn(item_fn) = &method.kind else {
+                continue;
+            };
+
+            self.check_attrs(&item_fn.attrs, AttrTarget::Method);
+            self.define(&item_fn.ident, method.id, DefKind::Fn);
+            self.resolve_fn(item_fn);
+
+            if let Some(previous) = seen.insert(&item_fn.ident.name, item_fn.ident.span) {
+                let diagnostic = Diagnostic::error(
+                    format!("method `{}` is defined multiple times", item_fn.ident.name),
+                    item_fn.ident.span,
+                )
+                .with_label(previous, "previous definition here");
+                self.out.diagnostics.push(diagnostic);
+            }
+        }
+    }
+
+    fn resolve_fn(&mut self, item_fn: &ItemFn) {
+        self.with_scope(ScopeKind::Fn, |this| {
+            this.declare_generics(&item_fn.generics);
+
+            for ty in item_fn.inputs.iter().filter_map(|param| param.ty.as_ref()) {
+                this.resolve_ty(ty);
+            }
+
+            if let Some(output) = &item_fn.output {
+                this.resolve_ty(output);
+            }
+
+            for param in &item_fn.inputs {
+                this.check_attrs(&param.attrs, AttrTarget::Param);
+                this.bind_pattern(&param.pat, DefKind::Param);
+            }
+
+            if let Some(body) = &item_fn.body {
+                let outer = std::mem::replace(&mut this.in_async, item_fn.is_async);
+                this.resolve_block(body);
+                this.in_async = outer;
+            }
+        });
+    }
+
+    fn declare_generics(&mut self, generics: &Generics) {
+        for param in &generics.params {
+            self.define(&param.ident, param.id, DefKind::TyParam);
+            self.declare(&param.ident, Res::TyParam(param.id));
+        }
+
+        // bounds can mention any of the parameters
+        for param in &generics.params {
+            for bound in &param.bounds {
+                self.resolve_ty(bound);
+            }
+        }
+    }
+
+    fn resolve_block(&mut self, block: &Block) {
+        self.with_scope(ScopeKind::Block, |this| {
+            let items = block.stmts.iter().filter_map(|stmt| match &stmt.kind {
+                StmtKind::Item(item) => Some(item.as_ref()),
+                _ => None,
+            });
+            this.declare_items(items);
+
+            for stmt in &block.stmts {
+                this.resolve_stmt(stmt);
+            }
+        });
+    }
+
+    fn resolve_stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Let(local) => {
+                if let Some(ty) = &local.ty {
+                    self.resolve_ty(ty);
+                }
+                if let Some(init) = &local.init {
+                    self.resolve_expr(init);
+                }
+                self.bind_pattern(&local.pat, DefKind::Local);
+            }
+            StmtKind::Item(item) => self.resolve_item(item),
+            StmtKind::Expr(expr) | StmtKind::Semi(expr) => self.resolve_expr(expr),
+            StmtKind::Empty => {}
+        }
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Lit(_) | ExprKind::Continue | ExprKind::Todo => {}
+            ExprKind::Path(path) => self.resolve_path(expr.id, path),
+            ExprKind::Unary(_, operand) => self.resolve_expr(operand),
+            ExprKind::Assign(lhs, rhs) | ExprKind::AssignOp(_, lhs, rhs) => {
+                self.resolve_expr(lhs);
+                self.resolve_expr(rhs);
+                self.check_assign(lhs);
+            }
+            ExprKind::Binary(_, lhs, rhs) | ExprKind::Index(lhs, rhs) => {
+                self.resolve_expr(lhs);
+                self.resolve_expr(rhs);
+            }
+            ExprKind::Call(callee, args) => {
+                self.resolve_expr(callee);
+                for arg in args {
+                    self.resolve_expr(arg);
+                }
+            }
+            ExprKind::MethodCall(call) => {
+                self.resolve_expr(&call.receiver);
+                for arg in &call.args {
+                    self.resolve_expr(arg);
+                }
+            }
+            ExprKind::Field(base, _) => self.resolve_expr(base),
+            ExprKind::ClassInit(init) => {
+                self.resolve_path(expr.id, &init.path);
+                for field in &init.fields {
+                    self.resolve_expr(&field.expr);
+                }
+            }
+            ExprKind::Range(start, end, _) => {
+                for expr in [start, end].into_iter().flatten() {
+                    self.resolve_expr(expr);
+                }
+            }
+            ExprKind::List(items) | ExprKind::Set(items) => {
+                for item in items {
+                    self.resolve_expr(item);
+                }
+            }
+            ExprKind::Map(entries) => {
+                for (key, value) in entries {
+                    self.resolve_expr(key);
+                    self.resolve_expr(value);
+                }
+            }
+            ExprKind::If(cond, then, otherwise) => {
+                self.resolve_expr(cond);
+                self.resolve_block(then);
+                if let Some(otherwise) = otherwise {
+                    self.resolve_expr(otherwise);
+                }
+            }
+            ExprKind::While(cond, body) => {
+                self.resolve_expr(cond);
+                self.resolve_block(body);
+            }
+            ExprKind::Loop(body) | ExprKind::Block(body) => self.resolve_block(body),
+            ExprKind::For(pat, iter, body) => {
+                self.resolve_expr(iter);
+                self.with_scope(ScopeKind::Block, |this| {
+                    this.bind_pattern(pat, DefKind::Local);
+                    this.resolve_block(body);
+                });
+            }
+            ExprKind::Paren(inner) | ExprKind::Try(inner) => self.resolve_expr(inner),
+            ExprKind::Return(value) | ExprKind::Break(value) | ExprKind::Panic(value) => {
+                if let Some(value) = value {
+                    self.resolve_expr(value);
+                }
+            }
+            ExprKind::Yield(value) => {
+                if self.in_async {
+                    let diagnostic =
+                        Diagnostic::error("`yield` is not allowed in `async` fns", expr.span);
+                    self.out.diagnostics.push(diagnostic);
+                }
+                if let Some(value) = value {
+                    self.resolve_expr(value);
+                }
+            }
+            ExprKind::Await(inner) => {
+                if !self.in_async {
+                    let diagnostic =
+                        Diagnostic::error("`await` is only allowed inside `async` fns", expr.span);
+                    self.out.diagnostics.push(diagnostic);
+                }
+                self.resolve_expr(inner);
+            }
+        }
+    }
+
+    // only bindings declared `mut` can be assigned to, fields and elements always can
+    fn check_assign(&mut self, target: &Expr) {
+        let ExprKind::Path(path) = &target.kind else {
+            return;
+        };
+        let Some(Res::Local(binding)) = self.out.res.get(&target.id).copied() else {
+            return;
+        };
+        if self.mutable.contains(&binding) {
+            return;
+        }
+
+        let name = &path.segments[0].name;
+        let span = self.out.defs[&binding].span;
+        let diagnostic = Diagnostic::error(
+            format!("cannot assign to `{name}`, it is not declared `mut`"),
+            target.span,
+        )
+        .with_label(span, format!("declare it as `mut {name}` to assign to it"));
+        self.out.diagnostics.push(diagnostic);
+    }
+
+    fn resolve_path(&mut self, id: NodeId, path: &Path) {
+        let Some(ident) = path.segments.first() else {
+            return;
+        };
+
+        match self.lookup(&ident.name) {
+            Some(Res::TyParam(_)) => {
+                let diagnostic = Diagnostic::error(
+                    format!("`{}` is a type parameter, not a value", ident.name),
+                    ident.span,
+                );
+                self.out.diagnostics.push(diagnostic);
+            }
+            Some(res) => {
+                self.warn_deprecated(res, ident);
+                self.out.res.insert(id, res);
+            }
+            None => {
+                let diagnostic = Diagnostic::error(
+                    format!("cannot find `{}` in this scope", ident.name),
+                    ident.span,
+                );
+                self.out.diagnostics.push(diagnostic);
+            }
+        }
+    }
+
+    fn resolve_ty(&mut self, ty: &Ty) {
+        match &ty.kind {
+            TyKind::Path(path, args) => {
+                for arg in args {
+                    self.resolve_ty(arg);
+                }
+
+                let Some(ident) = path.segments.first() else {
+                    return;
+                };
+
+                let res = match self.lookup(&ident.name) {
+                    Some(res @ (Res::Item(_) | Res::Import(_) | Res::TyParam(_))) => Some(res),
+                    _ => PrimTy::from_name(&ident.name)
+                        .map(Res::PrimTy)
+                        .or_else(|| BuiltinBound::from_name(&ident.name).map(Res::Bound))
+                        .or_else(|| {
+                            BuiltinInterface::from_name(&ident.name).map(Res::BuiltinInterface)
+                        }),
+                };
+
+                match res {
+                    Some(res) => {
+                        self.warn_deprecated(res, ident);
+                        self.out.res.insert(ty.id, res);
+                    }
+                    None => {
+                        let diagnostic = Diagnostic::error(
+                            format!("cannot find type `{}` in this scope", ident.name),
+                            ident.span,
+                        );
+                        self.out.diagnostics.push(diagnostic);
+                    }
+                }
+            }
+            TyKind::Fn(inputs, output) => {
+                for ty in inputs.iter().chain(output.as_deref()) {
+                    self.resolve_ty(ty);
+                }
+            }
+            TyKind::Optional(inner) => self.resolve_ty(inner),
+        }
+    }
+
+    fn check_attrs(&mut self, attrs: &[Attribute], target: AttrTarget) {
+        let diagnostics = self.registry.check(attrs, target);
+        self.out.diagnostics.extend(diagnostics);
+    }
+
+    fn warn_deprecated(&mut self, res: Res, ident: &Ident) {
+        let Res::Item(id) = res else {
+            return;
+        };
+        let Some(message) = self.deprecated.get(&id) else {
+            return;
+        };
+
+        let kind = match self.out.defs.get(&id).map(|def| def.kind) {
+            Some(DefKind::Class) => "class",
+            Some(DefKind::Interface) => "interface",
+            _ => "function",
+        };
+
+        let message = match message {
+            Some(message) => format!("use of deprecated {kind} `{}`: {message}", ident.name),
+            None => format!("use of deprecated {kind} `{}`", ident.name),
+        };
+        self.out
+            .diagnostics
+            .push(Diagnostic::warning(message, ident.span));
+    }
+
+    fn lookup(&mut self, name: &str) -> Option<Res> {
+        let mut crossed_fn = false;
+        let mut glob = None;
+
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(binding) = scope.names.get_mut(name) {
+                // locals and generic parameters of an enclosing fn are not captured
+                let hidden = crossed_fn
+                    && match binding.res {
+                        Res::Local(_) => true,
+                        Res::TyParam(_) => scope.kind != ScopeKind::Class,
+                        _ => false,
+                    };
+
+                if !hidden {
+                    binding.used = true;
+                    return Some(binding.res);
+                }
+            }
+
+            if glob.is_none() {
+                glob = scope.globs.first().copied();
+            }
+
+            if scope.kind == ScopeKind::Fn {
+                crossed_fn = true;
+            }
+        }
+
+        if PRELUDE.contains(&name) || self.externs.contains(name) {
+            return Some(Res::Builtin);
+        }
+
+        glob.map(Res::Import)
+    }
+
+    //END walking
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::Severity;
+    use crate::parser::parse;
+
+    fn diagnostics(code: &str) -> Vec<(Severity, String)> {
+        let file = parse(code).unwrap();
+        resolve(&file)
+            .diagnostics
+            .into_iter()
+            .map(|d| (d.severity, d.message))
+            .collect()
+    }
+
+    fn errors(code: &str) -> Vec<String> {
+        diagnostics(code)
+            .into_iter()
+            .filter(|(severity, _)| *severity == Severity::Error)
+            .map(|(_, message)| message)
+            .collect()
+    }
+
+    fn fn_body(file: &File, idx: usize) -> &Block {
+        match &file.items[idx].kind {
+            ItemKind::Fn(item_fn) => item_fn.body.as_deref().unwrap(),
+            _ => panic!("expected a fn"),
+        }
+    }
+
+    #[test]
+    fn resolves_sample_program_without_diagnostics() {
+        let code = r#"
+            using kai.io;
+
+            fn square(a) {
+                a * a
+            }
+
+            fn main() {
+                for i in 1..=10 {
+                    io.print(square(i));
+                }
+            }
+        "#;
+
+        assert_eq!(diagnostics(code), vec![]);
+    }
+
+    #[test]
+    fn binds_uses_to_definitions() {
+        let file = parse("fn id(x) { x } fn main() { let y = id(1); y }").unwrap();
+        let resolutions = resolve(&file);
+
+        let StmtKind::Expr(tail) = &fn_body(&file, 0).stmts[0].kind else {
+            panic!("expected a tail expression");
+        };
+        let ItemKind::Fn(item_fn) = &file.items[0].kind else {
+            unreachable!()
+        };
+
+        let param = item_fn.inputs[0].pat.id;
+        assert_eq!(resolutions.get(tail.id), Some(Res::Local(param)));
+        assert_eq!(resolutions.definition(param).unwrap().kind, DefKind::Param);
+        assert_eq!(
+            resolutions.uses_of(param).collect::<Vec<_>>(),
+            vec![tail.id]
+        );
+
+        let StmtKind::Let(local) = &fn_body(&file, 1).stmts[0].kind else {
+            panic!("expected a let");
+        };
+        let Some(init) = &local.init else {
+            panic!("expected an initializer")
+        };
+        let ExprKind::Call(callee, _) = &init.kind else {
+            panic!("expected a call");
+        };
+        assert_eq!(
+            resolutions.get(callee.id),
+            Some(Res::Item(file.items[0].id))
+        );
+    }
+
+    #[test]
+    fn reports_undefined_names() {
+        assert_eq!(
+            diagnostics("fn main() { foo(bar) }"),
+            vec![
+                (Severity::Error, "cannot find `foo` in this scope".into()),
+                (Severity::Error, "cannot find `bar` in this scope".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn items_are_visible_before_their_definition() {
+        assert_eq!(diagnostics("fn main() { helper() } fn helper() {}"), vec![]);
+        assert_eq!(diagnostics("fn main() { inner(); fn inner() {} }"), vec![]);
+    }
+
+    #[test]
+    fn locals_are_not_visible_in_nested_fns() {
+        assert_eq!(
+            diagnostics("fn main() { let x = 1; fn inner() { x } x }"),
+            vec![(Severity::Error, "cannot find `x` in this scope".into())]
+        );
+    }
+
+    #[test]
+    fn locals_are_scoped_to_their_block() {
+        assert_eq!(
+            diagnostics("fn main() { { let x = 1; x; } x }"),
+            vec![(Severity::Error, "cannot find `x` in this scope".into())]
+        );
+    }
+
+    #[test]
+    fn checks_where_coroutines_suspend() {
+        assert_eq!(
+            diagnostics("async fn f() { yield 1; } fn g() { await f(); }"),
+            vec![
+                (
+                    Severity::Error,
+                    "`yield` is not allowed in `async` fns".into()
+                ),
+                (
+                    Severity::Error,
+                    "`await` is only allowed inside `async` fns".into()
+                ),
+            ]
+        );
+        // nested fns do not inherit `async`
+        assert_eq!(
+            diagnostics("async fn f() { fn g() { await h(); } await h(); } async fn h() {}"),
+            vec![(
+                Severity::Error,
+                "`await` is only allowed inside `async` fns".into()
+            )]
+        );
+    }
+
+    #[test]
+    fn reports_duplicate_definitions() {
+        assert_eq!(
+            diagnostics("fn a() {} fn a() {} fn b(x, x) { x }"),
+            vec![
+                (Severity::Error, "`a` is defined multiple times".into()),
+                (
+                    Severity::Error,
+                    "parameter `x` is bound more than once".into()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_mut_bindings_can_be_assigned() {
+        assert_eq!(
+            errors(
+                "fn main(a, mut b) { let f = 1; f = 2; let mut g = 1; g += f; a = b; b = a; \
+                 for i in 0..2 { i += 1; } let xs = [1]; xs[0] = g; }"
+            ),
+            vec![
+                "cannot assign to `f`, it is not declared `mut`",
+                "cannot assign to `a`, it is not declared `mut`",
+                "cannot assign to `i`, it is not declared `mut`",
+            ]
+        );
+    }
+
+    #[test]
+    fn warns_on_shadowing() {
+        assert_eq!(
+            diagnostics("fn main(x) { let y = x; let y = y; { let x = y; x; } }"),
+            vec![
+                (Severity::Warning, "`y` shadows a previous binding".into()),
+                (
+                    Severity::Warning,
+                    "`x` shadows a binding from an outer scope".into()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn warns_on_unused_bindings_unless_discarded() {
+        assert_eq!(
+            diagnostics(
+                "using kai.io; fn main(a, _b, self) { let c = 1; let _d = 2; for _ in 1..2 {} }"
+            ),
+            vec![
+                (Severity::Warning, "unused import `io`".into()),
+                (Severity::Warning, "unused variable `a`".into()),
+                (Severity::Warning, "unused variable `c`".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_attributes() {
+        assert_eq!(
+            diagnostics(
+                r#"
+                @deprecated("use `new`") fn old() {}
+                @deprecated class Legacy {}
+                @test fn a(@unknown _x: Legacy) { old(); }
+                class B { @inline x: int; @tset fn get(self) {} }
+                "#
+            ),
+            vec![
+                (Severity::Warning, "unknown attribute `unknown`".to_owned()),
+                (
+                    Severity::Warning,
+                    "use of deprecated class `Legacy`".to_owned()
+                ),
+                (
+                    Severity::Warning,
+                    "use of deprecated function `old`: use `new`".to_owned()
+                ),
+                (
+                    Severity::Error,
+                    "attribute `inline` cannot be used on a field".to_owned()
+                ),
+                (Severity::Warning, "unknown attribute `tset`".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn resolves_generic_parameters() {
+        let code = "
+            class Box<T> {
+                value: T;
+                fn get(self) -> T { self.value }
+            }
+            fn first<T: Num + Ord>(_xs: list<T>) -> T { let x: T = todo; x }
+            fn outer<U>() { fn inner(_u: U) {} }
+            fn value<V>() { V }
+        ";
+
+        assert_eq!(
+            diagnostics(code),
+            vec![
+                (Severity::Error, "cannot find type `U` in this scope".into()),
+                (
+                    Severity::Error,
+                    "`V` is a type parameter, not a value".into()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn resolves_interfaces_and_impls() {
+        let code = "
+            interface Shape { fn area(self) -> float; }
+            class Square { side: float; }
+            impl Shape for Square { fn area(self) -> float { self.side * self.side } }
+            impl Display for Square { fn to_string(self) -> string { \"square\" } }
+            impl Shape for Circle {}
+            fn total(a: Shape, b: Iterator) {}
+        ";
+
+        let file = parse(code).unwrap();
+        let resolutions = resolve(&file);
+
+        let ItemKind::Impl(display) = &file.items[3].kind else {
+            panic!("expected an impl");
+        };
+        assert_eq!(
+            resolutions.get(display.interface.id),
+            Some(Res::BuiltinInterface(BuiltinInterface::Display))
+        );
+        assert_eq!(
+            resolutions.definition(file.items[0].id).unwrap().kind,
+            DefKind::Interface
+        );
+
+        assert_eq!(
+            diagnostics(code),
+            vec![
+                (
+                    Severity::Error,
+                    "cannot find type `Circle` in this scope".into()
+                ),
+                (Severity::Warning, "unused variable `a`".into()),
+                (Severity::Warning, "unused variable `b`".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn glob_imports_suppress_unresolved_names() {
+        let file = parse("using kai.io.*; fn main() { print(1) }").unwrap();
+        let resolutions = resolve(&file);
+        assert!(resolutions.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn reports_unresolved_stdlib_imports() {
+        assert_eq!(
+            errors("using kai.nope; using kai.io.{print, prnt}; using kai.math.pi;"),
+            [
+                "unresolved import `kai.nope`",
+                "unresolved import `kai.io.prnt`"
+            ]
+        );
+        assert_eq!(
+            errors("using kai.nope.*; using kai.time.Duration;"),
+            ["unresolved import `kai.nope`"]
+        );
+    }
+
+    #[test]
+    fn group_imports_bind_each_name() {
+        assert_eq!(
+            diagnostics("using kai.io.{self, print as p}; fn main() { io; p; }"),
+            vec![]
+        );
+    }
+}
@@ -1,10 +1,2061 @@
-#[derive(Default, Debug)]
-pub struct Engine {}
+use std::io::Write;
+use std::rc::Rc;
+
+use ecow::EcoString;
+
+use crate::ast::span::Span;
+use crate::ast::{File, Item, ItemKind};
+use crate::attrs::{self, Cfg};
+use crate::diagnostic::Diagnostic;
+use crate::parser::{self, SCRIPT_FN};
+use crate::resolve::{Resolutions, Resolver};
+use crate::typeck::Checker;
+
+use self::executor::Executor;
+use self::gc::Heap;
+use self::host::Host;
+use self::interp::Interp;
+use self::vm::Vm;
+
+pub mod bytecode;
+mod compiler;
+mod coroutine;
+pub mod disasm;
+pub mod error;
+mod executor;
+pub mod gc;
+pub mod host;
+mod interp;
+pub mod kaic;
+pub mod limits;
+mod load;
+mod ops;
+pub(crate) mod stdlib;
+pub mod value;
+mod vm;
+
+pub use self::bytecode::Program;
+pub use self::compiler::compile;
+pub use self::coroutine::Coroutine;
+pub use self::error::{EvalError, RuntimeError, RuntimeErrorKind, TraceFrame};
+pub use self::executor::{Future, Source, TaskStatus, Wake};
+pub use self::gc::{GcConfig, GcStats};
+pub use self::host::{
+    FromValue, HostClass, IntoArgs, IntoNative, IntoValue, Methods, NativeModule,
+};
+pub use self::limits::{Capabilities, Capability, Limits};
+pub use self::value::Value;
+
+/// How the engine runs programs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// Walks the syntax tree
+    #[default]
+    Ast,
+    /// Compiles to bytecode first and runs it on a stack machine
+    Vm,
+}
+
+impl Backend {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ast" => Some(Backend::Ast),
+            "vm" => Some(Backend::Vm),
+            _ => None,
+        }
+    }
+}
+
+/// Runs resolved programs, everything they print goes to `stdout`
+pub struct Engine {
+    stdout: Box<dyn Write>,
+    backend: Backend,
+    // instances outlive single runs, values returned by one can be kept by the embedder
+    heap: Heap,
+    limits: Limits,
+    host: Host,
+    script: Option<Script>,
+    // tasks spawned by the script, they are dropped when it is replaced
+    executor: Executor,
+}
+
+/// The script last run by `Engine::eval`
+struct Script {
+    file: File,
+    res: Resolutions,
+    // compiled when the engine runs on the VM
+    program: Option<Program>,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self {
+            stdout: Box::new(std::io::stdout()),
+            backend: Backend::default(),
+            heap: Heap::default(),
+            limits: Limits::default(),
+            host: Host::default(),
+            script: None,
+            executor: Executor::default(),
+        }
+    }
+}
 
 impl Engine {
-    pub fn run(&mut self, work: bool) {
-        if work {
-            println!("bootstrap machine!")
+    /// Sends everything printed by programs to `out` instead of the process stdout
+    pub fn set_stdout(&mut self, out: impl Write + 'static) {
+        self.stdout = Box::new(out);
+    }
+
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend;
+    }
+
+    pub fn set_gc_config(&mut self, config: GcConfig) {
+        self.heap.set_config(config);
+    }
+
+    /// Limits every later run, fuel and time are counted per run or per test
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.heap.set_limit(limits.heap_bytes);
+        self.limits = limits;
+    }
+
+    pub fn gc_stats(&self) -> GcStats {
+        self.heap.stats()
+    }
+
+    /// Frees unreachable cycles, returns how many objects were freed
+    pub fn collect_garbage(&mut self) -> usize {
+        self.heap.collect()
+    }
+
+    /// Makes a rust closure callable from kai as `name`, arguments and the result are
+    /// converted with `FromValue` and `IntoValue`
+    pub fn register_fn<Args, F: IntoNative<Args>>(&mut self, name: &str, f: F) {
+        let native = f.into_native(name);
+        self.host
+            .define(name, Value::Native(Rc::new(native)), F::ty());
+    }
+
+    /// The arguments `kai.process.args` returns, like the ones given to `kai run` after
+    /// the path of the script
+    pub fn set_args<S: Into<EcoString>>(&mut self, args: impl IntoIterator<Item = S>) {
+        self.host
+            .set_args(args.into_iter().map(Into::into).collect());
+    }
+
+    /// Makes `value` available to kai code as `name`
+    pub fn set_global<V: IntoValue>(&mut self, name: &str, value: V) {
+        self.host.define(name, value.into_value(), V::ty());
+    }
+
+    /// Makes a module available to kai code, which imports it with `using`
+    ///
+    /// # Panics
+    ///
+    /// If the module is under `kai`, which belongs to the standard library
+    pub fn register_module(&mut self, module: NativeModule) {
+        let root = module.path().split('.').next().unwrap_or_default();
+        assert!(root != "kai", "`{}` is under `kai`", module.path());
+        self.host.add_module(module);
+    }
+
+    /// A resolver which knows every name and module registered on the engine, for
+    /// tools checking the scripts it runs
+    pub fn resolver(&self) -> Resolver {
+        let mut resolver = Resolver::default();
+        for path in self.host.paths() {
+            resolver.add_extern(path);
+        }
+        resolver
+    }
+
+    /// A checker which knows the signatures of everything registered on the engine
+    pub fn checker<'a>(&self, res: &'a Resolutions) -> Checker<'a> {
+        let mut checker = Checker::new(res);
+        for (path, ty) in self.host.types() {
+            checker.add_extern(path, ty.clone());
+        }
+        checker
+    }
+
+    /// Runs a script and returns the value of its last statement. The items it declares
+    /// replace the ones of the previous script and can be called with `call`.
+    pub fn eval<T: FromValue>(&mut self, src: &str) -> Result<T, EvalError> {
+        let mut file =
+            parser::parse_script(src).map_err(|err| EvalError::Compile(vec![err.into()]))?;
+        attrs::configure(&mut file, &Cfg::host());
+
+        let mut resolver = self.resolver();
+        resolver.resolve_file(&file);
+        let res = resolver.finish();
+
+        let mut checker = self.checker(&res);
+        checker.check_file(&file);
+        let results = checker.finish();
+
+        let errors: Vec<Diagnostic> = res
+            .diagnostics
+            .iter()
+            .chain(&results.diagnostics)
+            .filter(|d| d.is_error())
+            .cloned()
+            .collect();
+        if !errors.is_empty() {
+            return Err(EvalError::Compile(errors));
+        }
+
+        let program = match self.backend {
+            Backend::Ast => None,
+            Backend::Vm => Some(compile(&file, &res)?),
+        };
+
+        self.script = Some(Script { file, res, program });
+        self.executor.clear();
+        self.call(SCRIPT_FN, ())
+    }
+
+    /// Calls a top level fn of the script last run by `eval`, an `async fn` is run to
+    /// completion together with the other tasks
+    pub fn call<T: FromValue>(&mut self, name: &str, args: impl IntoArgs) -> Result<T, EvalError> {
+        let (script, item) = find_fn(&self.script, name)?;
+
+        let args = args.into_args();
+        let stdout = self.stdout.as_mut();
+        let result = match &script.program {
+            None => Interp::new(
+                &script.file,
+                &script.res,
+                stdout,
+                &mut self.heap,
+                &self.limits,
+                &self.host,
+                &mut self.executor,
+            )
+            .run_fn(item, args),
+            Some(program) => Vm::new(
+                program,
+                stdout,
+                &mut self.heap,
+                &self.limits,
+                &self.host,
+                &mut self.executor,
+            )
+            .run_fn(item.id, args),
+        };
+
+        let _ = self.stdout.flush();
+        host::convert(result?).map_err(EvalError::Convert)
+    }
+
+    /// Starts a task calling an `async fn` of the script last run by `eval`, it runs
+    /// once the engine is polled
+    pub fn spawn(&mut self, name: &str, args: impl IntoArgs) -> Result<(), EvalError> {
+        let (script, item) = find_fn(&self.script, name)?;
+
+        let args = args.into_args();
+        let stdout = self.stdout.as_mut();
+        let result = match &script.program {
+            None => Interp::new(
+                &script.file,
+                &script.res,
+                stdout,
+                &mut self.heap,
+                &self.limits,
+                &self.host,
+                &mut self.executor,
+            )
+            .spawn_fn(item, args),
+            Some(program) => Vm::new(
+                program,
+                stdout,
+                &mut self.heap,
+                &self.limits,
+                &self.host,
+                &mut self.executor,
+            )
+            .spawn_fn(item.id, args),
+        };
+
+        Ok(result?)
+    }
+
+    /// Runs the tasks of the script until none can go on without waiting. Applications
+    /// with their own event loop call this instead of blocking in `call`, and poll again
+    /// at `TaskStatus::wake_at`. Fuel and time are counted per poll.
+    pub fn poll(&mut self) -> Result<TaskStatus, EvalError> {
+        let Some(script) = &self.script else {
+            return Ok(TaskStatus {
+                pending: 0,
+                wake_at: None,
+            });
+        };
+
+        let stdout = self.stdout.as_mut();
+        let span = script.file.span;
+        let result = match &script.program {
+            None => Interp::new(
+                &script.file,
+                &script.res,
+                stdout,
+                &mut self.heap,
+                &self.limits,
+                &self.host,
+                &mut self.executor,
+            )
+            .poll_tasks(span),
+            Some(program) => Vm::new(
+                program,
+                stdout,
+                &mut self.heap,
+                &self.limits,
+                &self.host,
+                &mut self.executor,
+            )
+            .poll_tasks(span),
+        };
+
+        let _ = self.stdout.flush();
+        Ok(result?)
+    }
+
+    /// Calls the `main` function of the file and returns its value once every task it
+    /// spawned returned too. Tasks left by `eval` are dropped.
+    pub fn run(&mut self, file: &File, res: &Resolutions) -> Result<Value, RuntimeError> {
+        self.executor.clear();
+        let result = match self.backend {
+            Backend::Ast => Interp::new(
+                file,
+                res,
+                self.stdout.as_mut(),
+                &mut self.heap,
+                &self.limits,
+                &self.host,
+                &mut self.executor,
+            )
+            .run_main(file),
+            Backend::Vm => compile(file, res).and_then(|program| self.run_program(&program)),
+        };
+
+        let _ = self.stdout.flush();
+        result
+    }
+
+    /// Calls the `main` function of an already compiled program on the VM
+    pub fn run_program(&mut self, program: &Program) -> Result<Value, RuntimeError> {
+        self.executor.clear();
+        let result = Vm::new(
+            program,
+            self.stdout.as_mut(),
+            &mut self.heap,
+            &self.limits,
+            &self.host,
+            &mut self.executor,
+        )
+        .run_main();
+        let _ = self.stdout.flush();
+        result
+    }
+
+    /// Calls every `@test` function of the file in order, a test passes unless it or a
+    /// task it spawned errors
+    pub fn run_tests(&mut self, file: &File, res: &Resolutions) -> Vec<TestOutcome> {
+        self.executor.clear();
+        let tests: Vec<&Item> = file
+            .all_items()
+            .into_iter()
+            .filter(|item| {
+                matches!(&item.kind, ItemKind::Fn(item_fn) if attrs::find(&item_fn.attrs, "test").is_some())
+            })
+            .collect();
+
+        let results: Vec<Result<Value, RuntimeError>> = match self.backend {
+            Backend::Ast => {
+                let mut interp = Interp::new(
+                    file,
+                    res,
+                    self.stdout.as_mut(),
+                    &mut self.heap,
+                    &self.limits,
+                    &self.host,
+                    &mut self.executor,
+                );
+                tests.iter().map(|item| interp.run_test(item)).collect()
+            }
+            Backend::Vm => match compile(file, res) {
+                Ok(program) => {
+                    let mut vm = Vm::new(
+                        &program,
+                        self.stdout.as_mut(),
+                        &mut self.heap,
+                        &self.limits,
+                        &self.host,
+                        &mut self.executor,
+                    );
+                    tests
+                        .iter()
+                        .map(|item| vm.run_test(item.id, item.ident.span))
+                        .collect()
+                }
+                Err(err) => tests.iter().map(|_| Err(err.clone())).collect(),
+            },
+        };
+
+        let _ = self.stdout.flush();
+
+        tests
+            .into_iter()
+            .zip(results)
+            .map(|(item, result)| TestOutcome {
+                name: item.ident.name.clone(),
+                result: result.map(|_| ()),
+            })
+            .collect()
+    }
+}
+
+fn find_fn<'a>(
+    script: &'a Option<Script>,
+    name: &str,
+) -> Result<(&'a Script, &'a Item), EvalError> {
+    let item = script.as_ref().and_then(|script| {
+        script
+            .file
+            .items
+            .iter()
+            .find(|item| matches!(item.kind, ItemKind::Fn(_)) && item.ident.name == name)
+    });
+
+    match (script, item) {
+        (Some(script), Some(item)) => Ok((script, item)),
+        _ => {
+            let message = format!("cannot find fn `{name}`");
+            Err(RuntimeError::new(message, Span::default()).into())
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TestOutcome {
+    pub name: String,
+    pub result: Result<(), RuntimeError>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::ast::span::Span;
+    use crate::diagnostic::line_col;
+    use crate::parser::parse;
+    use crate::resolve::resolve;
+
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
         }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run_on(backend: Backend, gc: GcConfig, code: &str) -> (Result<Value, RuntimeError>, String) {
+        let file = parse(code).unwrap();
+        let resolutions = resolve(&file);
+        assert!(
+            !resolutions.has_errors(),
+            "unexpected resolve errors {:?}",
+            resolutions.diagnostics
+        );
+
+        let output = Output::default();
+        let mut engine = Engine::default();
+        engine.set_stdout(output.clone());
+        engine.set_backend(backend);
+        engine.set_gc_config(gc);
+
+        let result = engine.run(&file, &resolutions);
+        let printed = String::from_utf8(output.0.take()).unwrap();
+        (result, printed)
+    }
+
+    // runs on both backends, which must agree on everything, and again collecting on
+    // every allocation to catch objects freed while still in use
+    fn run(code: &str) -> (Result<Value, RuntimeError>, String) {
+        let stress = GcConfig {
+            stress: true,
+            ..GcConfig::default()
+        };
+        let (ast, ast_printed) = run_on(Backend::Ast, GcConfig::default(), code);
+
+        for (backend, gc) in [
+            (Backend::Vm, GcConfig::default()),
+            (Backend::Ast, stress),
+            (Backend::Vm, stress),
+        ] {
+            let (other, other_printed) = run_on(backend, gc, code);
+
+            assert_eq!(
+                ast_printed, other_printed,
+                "{backend:?} printed different output"
+            );
+            match (&ast, &other) {
+                (Ok(ast), Ok(other)) => assert_eq!(ast.to_string(), other.to_string()),
+                _ => assert_eq!(&ast, &other, "{backend:?} failed differently"),
+            }
+        }
+
+        (ast, ast_printed)
+    }
+
+    fn output(code: &str) -> String {
+        let (result, printed) = run(code);
+        result.unwrap();
+        printed
+    }
+
+    fn error(code: &str) -> String {
+        run(code).0.unwrap_err().message
+    }
+
+    // runs on both backends with `limits`, which must stop the program the same way
+    fn limited(limits: Limits, code: &str) -> (RuntimeErrorKind, String) {
+        let file = parse(code).unwrap();
+        let resolutions = resolve(&file);
+        let mut errors = [Backend::Ast, Backend::Vm].map(|backend| {
+            let mut engine = Engine::default();
+            engine.set_stdout(Output::default());
+            engine.set_backend(backend);
+            engine.set_limits(limits);
+            engine.run(&file, &resolutions).unwrap_err()
+        });
+
+        // the VM spends fuel per instruction, it stops somewhere else
+        if limits.fuel.is_none() {
+            assert_eq!(errors[0].message, errors[1].message);
+        }
+        assert_eq!(errors[0].kind, errors[1].kind);
+        let [err, _] = &mut errors;
+        (err.kind, std::mem::take(&mut err.message))
+    }
+
+    #[test]
+    fn runs_sample_program() {
+        let code = r#"
+            using kai.io;
+
+            fn square(a: int) -> int {
+                a * a
+            }
+
+            fn main() {
+                for i in 1..=5 {
+                    io.println(square(i));
+                }
+            }
+        "#;
+
+        assert_eq!(output(code), "1\n4\n9\n16\n25\n");
+    }
+
+    #[test]
+    fn evaluates_expressions() {
+        let code = r#"
+            using kai.io.{print, println};
+
+            fn fib(n: int) -> int {
+                if n < 2 { return n; }
+                fib(n - 1) + fib(n - 2)
+            }
+
+            fn main() {
+                let mut total = 0;
+                let mut i = 0;
+                while true {
+                    i += 1;
+                    if i % 2 == 0 { continue; }
+                    if i > 9 { break; }
+                    total += i;
+                }
+                let found = loop { break total * 2; };
+                println(total, found, fib(15), 7 / 2, 7.0 / 2, "a" + "b", !(1 < 2 || false));
+                for c in "hé" { println(c); }
+                for c in "hé" { print(c, ""); }
+                println();
+            }
+        "#;
+
+        assert_eq!(output(code), "25 50 610 3 3.5 ab false\nh\né\nh é \n");
+    }
+
+    #[test]
+    fn runs_classes_and_methods() {
+        let code = r#"
+            using kai.io;
+
+            class Counter {
+                count: int;
+
+                pub new() -> Counter {
+                    Counter { count: 0 }
+                }
+
+                pub fn bump(self, by: int) {
+                    self.count += by;
+                }
+            }
+
+            fn main() {
+                let counter = Counter.new();
+                counter.bump(2);
+                counter.bump(3);
+                io.println(counter.count, counter);
+            }
+        "#;
+
+        assert_eq!(output(code), "5 Counter { count: 5 }\n");
+    }
+
+    #[test]
+    fn dispatches_through_interfaces() {
+        let code = r#"
+            using kai.io;
+
+            interface Shape {
+                fn area(self) -> float;
+                fn describe(self) -> string { "a shape" }
+            }
+
+            class Square { side: float; }
+            class Circle { radius: float; }
+
+            impl Shape for Square {
+                fn area(self) -> float { self.side * self.side }
+                fn describe(self) -> string { "a square" }
+            }
+
+            impl Shape for Circle {
+                fn area(self) -> float { 3.0 * self.radius * self.radius }
+            }
+
+            fn show(shape: Shape) {
+                io.println(shape.describe(), shape.area());
+            }
+
+            fn main() {
+                show(Square { side: 2.0 });
+                show(Circle { radius: 1.0 });
+            }
+        "#;
+
+        assert_eq!(output(code), "a square 4.0\na shape 3.0\n");
+    }
+
+    #[test]
+    fn uses_builtin_interfaces() {
+        let code = r#"
+            using kai.io;
+
+            class Point { x: int; y: int; }
+
+            impl Display for Point {
+                fn to_string(self) -> string { "(x, y)" }
+            }
+
+            impl Eq for Point {
+                fn eq(self, other: Point) -> bool { self.x == other.x && self.y == other.y }
+            }
+
+            class Countdown { n: int; }
+
+            impl Iterator for Countdown {
+                fn next(self) {
+                    if self.n == 0 { return nil; }
+                    self.n -= 1;
+                    self.n + 1
+                }
+            }
+
+            fn main() {
+                let a = Point { x: 1, y: 2 };
+                io.println(a, a == Point { x: 1, y: 2 }, a != Point { x: 2, y: 2 });
+                let countdown = Countdown { n: 3 };
+                for i in countdown { io.println(i); }
+            }
+        "#;
+
+        assert_eq!(output(code), "(x, y) true true\n3\n2\n1\n");
+    }
+
+    #[test]
+    fn runs_tests() {
+        let code = r#"
+            @test fn adds() { if 1 + 1 != 2 { panic "math is broken"; } }
+            @test fn fails() { panic "oh no" }
+            fn helper() { panic "not a test" }
+        "#;
+
+        let file = parse(code).unwrap();
+        let resolutions = resolve(&file);
+
+        for backend in [Backend::Ast, Backend::Vm] {
+            let mut engine = Engine::default();
+            engine.set_backend(backend);
+            let outcomes = engine.run_tests(&file, &resolutions);
+
+            let names: Vec<&str> = outcomes.iter().map(|o| o.name.as_str()).collect();
+            assert_eq!(names, ["adds", "fails"]);
+            assert!(outcomes[0].result.is_ok());
+            assert_eq!(
+                outcomes[1].result.as_ref().unwrap_err().message,
+                "panicked: oh no"
+            );
+        }
+    }
+
+    #[test]
+    fn collects_cycles() {
+        let code = r#"
+            using kai.io;
+            using kai.gc;
+
+            class Node { next: Node; }
+
+            fn main() {
+                for i in 0..10 {
+                    let node = Node { next: nil };
+                    node.next = node;
+                }
+                let kept = Node { next: nil };
+                kept.next = Node { next: kept };
+                io.println(gc.collect(), gc.collect());
+
+                let stats = gc.stats();
+                io.println(stats.collections, stats.allocations, stats.freed, stats.live);
+            }
+        "#;
+
+        for backend in [Backend::Ast, Backend::Vm] {
+            let (result, printed) = run_on(backend, GcConfig::default(), code);
+            result.unwrap();
+            assert_eq!(printed, "9 0\n2 12 9 3\n");
+
+            let stress = GcConfig {
+                stress: true,
+                ..GcConfig::default()
+            };
+            let (result, printed) = run_on(backend, stress, code);
+            result.unwrap();
+            assert_eq!(printed, "0 0\n14 12 9 3\n");
+        }
+    }
+
+    #[test]
+    fn shows_and_compares_cyclic_values() {
+        let code = r#"
+            using kai.io;
+
+            class Node { next: Node; }
+
+            fn main() {
+                let xs = [1];
+                xs.push(xs);
+                let ys = [1];
+                ys.push(ys);
+                let m = { "a": 1 };
+                m["self"] = m;
+                let node = Node { next: nil };
+                node.next = node;
+                io.println(xs, m, node);
+                io.println(xs == ys, xs == [1, [1]], m == { "a": 1, "self": m }, xs != xs);
+            }
+        "#;
+
+        assert_eq!(
+            output(code),
+            "[1, [...]] { \"a\": 1, \"self\": {...} } Node { next: Node {...} }\ntrue false true false\n"
+        );
+    }
+
+    #[test]
+    fn collects_cycles_through_collections() {
+        let code = r#"
+            using kai.io;
+            using kai.gc;
+
+            class Tree { children: list<Tree>; }
+            class Graph { edges: map<string, Graph>; }
+
+            fn main() {
+                for _ in 0..5 {
+                    let tree = Tree { children: [] };
+                    tree.children.push(tree);
+                    let graph = Graph { edges: {:} };
+                    graph.edges["self"] = graph;
+                }
+                io.println(gc.collect(), gc.stats().live);
+            }
+        "#;
+
+        for backend in [Backend::Ast, Backend::Vm] {
+            let (result, printed) = run_on(backend, GcConfig::default(), code);
+            result.unwrap();
+            assert_eq!(printed, "16 4\n");
+        }
+    }
+
+    #[test]
+    fn propagates_errors() {
+        let code = r#"
+            using kai.io;
+            using kai.error;
+
+            fn digit(s: string) -> result<int> {
+                if s == "1" { return 1; }
+                error.new("not a digit: " + s)
+            }
+
+            fn sum(a: string, b: string) -> result<int> {
+                digit(a)? + digit(b)?
+            }
+
+            fn load() -> result<int> {
+                let n = sum("1", "x");
+                if n.is_error() { return n.wrap("loading"); }
+                n
+            }
+
+            fn main() {
+                io.println(sum("1", "1"), sum("2", "1"));
+                let err = load();
+                io.println(err, err.message, err.cause.message, err.cause.cause);
+            }
+        "#;
+
+        assert_eq!(
+            output(code),
+            "2 not a digit: 2\nloading: not a digit: x loading not a digit: x nil\n"
+        );
+        assert_eq!(
+            error("using kai.error; fn main() { error.wrap(error.new(\"gone\"), \"open\")?; }"),
+            "`main` returned an error: open: gone"
+        );
+        assert_eq!(
+            output(
+                "using kai.io; using kai.error; fn main() { io.println(error.new(\"x\").is_error(), error.is_error(1)); }"
+            ),
+            "true false\n"
+        );
+        assert_eq!(
+            error("using kai.error; fn main() { error.new(1) }"),
+            "expected `string`, found `int`"
+        );
+    }
+
+    #[test]
+    fn backends_agree_on_control_flow() {
+        let code = r#"
+            using kai.io;
+
+            class Node { value: int; }
+
+            impl Display for Node {
+                fn to_string(self) -> string { "node" }
+            }
+
+            fn find(limit: int) -> int {
+                for i in 0..limit {
+                    for j in 0..i {
+                        if j == 3 { break; }
+                        if (i + j) % 2 == 0 { continue; }
+                        if i * j > 20 { return i * 100 + j; }
+                    }
+                }
+                return -1;
+            }
+
+            fn main() {
+                let node = Node { value: 1 };
+                node.value += 2;
+                let value = loop { let x = 1 + { break node.value * 2; }; };
+                let mut s = "";
+                for c in "abc" { s = s + c + "-"; }
+                io.println(find(3), find(20), value, s, node, 1..=3, 1..3, true && !false || false);
+            }
+        "#;
+
+        assert_eq!(output(code), "-1 1102 6 a-b-c- node 1..=3 1..3 true\n");
+    }
+
+    #[test]
+    fn reports_runtime_errors() {
+        assert_eq!(error("fn main() { 1 / 0 }"), "attempt to divide by zero");
+        assert_eq!(
+            error("fn main() { let mut x = 2; loop { x *= x; } }"),
+            "attempt to multiply with overflow"
+        );
+        assert_eq!(error(r#"fn main() { panic "oh no" }"#), "panicked: oh no");
+        assert_eq!(error("fn main() { todo }"), "not yet implemented");
+        assert_eq!(
+            error("class A {} fn main() { for a in A {} {} }"),
+            "`class A` is not iterable"
+        );
+        assert_eq!(error("fn helper() {}"), "no `main` function found");
+        assert_eq!(
+            error("fn main() { let mut x; if false { x = 1; } x }"),
+            "`x` is used before being assigned"
+        );
+        assert_eq!(
+            error("fn main() { break; }"),
+            "`break` or `continue` outside of a loop"
+        );
+        assert_eq!(
+            error("fn f(a: int) {} fn main() { f() }"),
+            "`f` takes 1 argument but 0 were supplied"
+        );
+        assert_eq!(
+            error("fn main() { 1..true }"),
+            "expected `int`, found `bool`"
+        );
+
+        let (result, _) = run("fn main() { let x = 1; x.y }");
+        let span = Span::new(25, 26);
+        assert_eq!(
+            result.unwrap_err(),
+            RuntimeError {
+                trace: vec![TraceFrame {
+                    function: "main".into(),
+                    span,
+                }],
+                ..RuntimeError::new("no field `y` on `int`", span)
+            }
+        );
+    }
+
+    #[test]
+    fn traces_errors_through_calls() {
+        let code = r#"
+            class Stack {
+                depth: int;
+
+                pub fn push(self) {
+                    if self.depth == 2 { panic "too deep"; }
+                    self.depth += 1;
+                    self.push();
+                }
+            }
+
+            fn grow(stack: Stack) { stack.push() }
+
+            fn main() {
+                let stack = Stack { depth: 0 };
+                grow(stack);
+            }
+        "#;
+
+        let err = run(code).0.unwrap_err();
+        let trace: Vec<(&str, String)> = err
+            .trace
+            .iter()
+            .map(|frame| {
+                let (line, col) = line_col(code, frame.span.start);
+                (frame.function.as_str(), format!("{line}:{col}"))
+            })
+            .collect();
+
+        assert_eq!(err.message, "panicked: too deep");
+        assert_eq!(
+            trace,
+            [
+                ("push", "6:42".into()),
+                ("push", "8:21".into()),
+                ("push", "8:21".into()),
+                ("grow", "12:37".into()),
+                ("main", "16:17".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn enforces_limits() {
+        let depth = Limits {
+            call_depth: 500,
+            ..Limits::default()
+        };
+        assert_eq!(
+            limited(depth, "fn f(n: int) -> int { f(n + 1) } fn main() { f(0) }"),
+            (
+                RuntimeErrorKind::StackOverflow,
+                "stack overflow, more than 500 nested calls".into()
+            )
+        );
+
+        let fuel = Limits {
+            fuel: Some(1000),
+            ..Limits::default()
+        };
+        let (kind, _) = limited(fuel, "fn main() { loop {} }");
+        assert_eq!(kind, RuntimeErrorKind::OutOfFuel);
+        // natives spend fuel for every item they go through
+        let (kind, _) = limited(fuel, "fn main() { (0..100000000).collect() }");
+        assert_eq!(kind, RuntimeErrorKind::OutOfFuel);
+        let (kind, _) = limited(fuel, r#"fn main() { "ab".repeat(5000).chars() }"#);
+        assert_eq!(kind, RuntimeErrorKind::OutOfFuel);
+
+        let timeout = Limits {
+            timeout: Some(std::time::Duration::from_millis(20)),
+            ..Limits::default()
+        };
+        assert_eq!(
+            limited(timeout, "fn main() { loop {} }"),
+            (RuntimeErrorKind::Timeout, "timed out after 20ms".into())
+        );
+        let code = "fn main() { (0..9223372036854775807).step(9223372036854775807).collect() }";
+        assert_eq!(
+            limited(timeout, code),
+            (RuntimeErrorKind::Timeout, "timed out after 20ms".into())
+        );
+
+        let heap = Limits {
+            heap_bytes: Some(4096),
+            ..Limits::default()
+        };
+        let code = r#"
+            class Node { next: Node; }
+            fn main() {
+                let mut node = Node { next: nil };
+                loop { node = Node { next: node }; }
+            }
+        "#;
+        assert_eq!(
+            limited(heap, code),
+            (
+                RuntimeErrorKind::OutOfMemory,
+                "heap limit of 4096 bytes exceeded".into()
+            )
+        );
+        let code = r#"fn main() { let mut s = "ab"; loop { s = s + s; } }"#;
+        assert_eq!(limited(heap, code).0, RuntimeErrorKind::OutOfMemory);
+        let code = r#"fn main() { "ab".repeat(4096); }"#;
+        assert_eq!(
+            limited(heap, code),
+            (
+                RuntimeErrorKind::OutOfMemory,
+                "heap limit of 4096 bytes exceeded".into()
+            )
+        );
+        let code = r#"fn main() { let items = [1]; loop { items.push(1); } }"#;
+        assert_eq!(limited(heap, code).0, RuntimeErrorKind::OutOfMemory);
+        let code = "fn main() { (0..100000000).collect() }";
+        assert_eq!(limited(heap, code).0, RuntimeErrorKind::OutOfMemory);
+        // strings are held against the limit by the collections storing them
+        let code = r#"
+            fn main() {
+                let s = "a".repeat(1000);
+                let items = [];
+                for i in 0..100 { items.push(s); }
+            }
+        "#;
+        assert_eq!(limited(heap, code).0, RuntimeErrorKind::OutOfMemory);
+        let code = r#"fn main() { let names = { "a": 1 }; names["b".repeat(4000)] = 2; }"#;
+        assert_eq!(limited(heap, code).0, RuntimeErrorKind::OutOfMemory);
+        let code = r#"fn main() { "-".repeat(3000).join(["a", "b", "c"]) }"#;
+        assert_eq!(limited(heap, code).0, RuntimeErrorKind::OutOfMemory);
+        let code = r#"fn main() { "a".repeat(3000).replace("a", "bb") }"#;
+        assert_eq!(limited(heap, code).0, RuntimeErrorKind::OutOfMemory);
+        let code = r#"
+            fn main() {
+                let squares = { 0: 0 };
+                let mut i = 1;
+                loop { squares[i] = i * i; i = i + 1; }
+            }
+        "#;
+        assert_eq!(limited(heap, code).0, RuntimeErrorKind::OutOfMemory);
+
+        let denied = Limits {
+            capabilities: Capabilities::ALL.deny(Capability::Env),
+            ..Limits::default()
+        };
+        let no_fs = Limits {
+            capabilities: Capabilities::ALL.deny(Capability::Fs),
+            ..Limits::default()
+        };
+        assert_eq!(
+            limited(
+                no_fs,
+                r#"using kai.io; fn main() { io.println(1); io.read_file("a") }"#
+            ),
+            (
+                RuntimeErrorKind::Denied,
+                "`read_file` needs the `fs` capability, which this engine denies".into()
+            )
+        );
+        assert_eq!(
+            limited(no_fs, r#"using kai.fs; fn main() { fs.list_dir(".") }"#),
+            (
+                RuntimeErrorKind::Denied,
+                "`list_dir` needs the `fs` capability, which this engine denies".into()
+            )
+        );
+        assert_eq!(
+            limited(denied, r#"using kai.env; fn main() { env.get("HOME") }"#),
+            (
+                RuntimeErrorKind::Denied,
+                "`get` needs the `env` capability, which this engine denies".into()
+            )
+        );
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Point {
+        x: i64,
+        y: i64,
+    }
+
+    impl HostClass for Point {
+        const NAME: &'static str = "Point";
+        const FIELDS: &'static [&'static str] = &["x", "y"];
+
+        fn into_fields(self) -> Vec<Value> {
+            vec![self.x.into_value(), self.y.into_value()]
+        }
+
+        fn from_fields(fields: Vec<Value>) -> Option<Self> {
+            let [x, y] = fields.try_into().ok()?;
+            Some(Point {
+                x: i64::from_value(x)?,
+                y: i64::from_value(y)?,
+            })
+        }
+
+        fn methods(methods: &mut Methods<Self>) {
+            methods
+                .add("sum", |p: Point| p.x + p.y)
+                .add("shift", |p: Point, by: i64| Point {
+                    x: p.x + by,
+                    y: p.y + by,
+                })
+                .add_static("at", |x: i64| Point { x, y: x });
+        }
+    }
+
+    #[test]
+    fn reads_and_writes_files() {
+        let path = std::env::temp_dir().join(format!("kai-io-{}.txt", std::process::id()));
+        let code = r#"
+            using kai.io;
+
+            fn main() {
+                let path = "PATH";
+                let out = io.create(path);
+                out.write(1);
+                out.write_line(" one");
+                out.write_line("two");
+                out.close();
+                io.append_file(path, "three\r\n");
+
+                let file = io.open(path);
+                io.println(file.read_line());
+                for line in file.lines() { io.println("line", line); }
+                io.println(file.read_line());
+                file.close();
+                io.println(file.read_line());
+
+                io.println(io.read_file(path) == "1 one\ntwo\nthree\r\n", io.exists(path));
+                io.println(io.open("/nonexistent/kai.txt"));
+            }
+        "#
+        .replace("PATH", path.to_str().unwrap());
+
+        let printed = output(&code);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            printed,
+            "1 one\nline two\nline three\nnil\nthe file is closed\ntrue true\n\
+             `/nonexistent/kai.txt`: No such file or directory (os error 2)\n"
+        );
+    }
+
+    #[test]
+    fn parses_and_stringifies_json() {
+        let code = r#"
+            using kai.io;
+            using kai.json;
+
+            class Point { x: int; y: int; }
+
+            class Celsius {
+                degrees: float;
+
+                pub fn from_json(degrees) -> Celsius { Celsius { degrees: degrees } }
+            }
+
+            impl Serialize for Celsius {
+                fn to_json(self) { self.degrees }
+            }
+
+            fn main() {
+                let doc = json.parse("{\"b\": [1, 2.5, \"s\"], \"a\": null}");
+                io.println(doc);
+                io.println(json.stringify(doc), json.stringify(doc, false, true));
+                io.println(json.stringify(doc, true));
+
+                let p = json.parse(json.stringify(Point { x: 1, y: 2 }), Point);
+                io.println(p, json.stringify(Celsius { degrees: 21.5 }));
+                io.println(json.parse("3.0", Celsius));
+
+                io.println(json.parse("[1,\n 2"));
+                io.println(json.parse("{\"x\": 1}", Point));
+                io.println(json.stringify(io.print));
+            }
+        "#;
+
+        assert_eq!(
+            output(code),
+            "{ \"b\": [1, 2.5, \"s\"], \"a\": nil }\n\
+             {\"b\":[1,2.5,\"s\"],\"a\":null} {\"a\":null,\"b\":[1,2.5,\"s\"]}\n\
+             {\n  \"b\": [\n    1,\n    2.5,\n    \"s\"\n  ],\n  \"a\": null\n}\n\
+             Point { x: 1, y: 2 } 21.5\n\
+             Celsius { degrees: 3.0 }\n\
+             invalid JSON at 2:3: expected `,` or `]`\n\
+             missing field `y` of `Point`\n\
+             `fn` can't be encoded as JSON\n"
+        );
+    }
+
+    #[test]
+    fn scripts_the_file_system() {
+        let code = r#"
+            using kai.io;
+            using kai.fs;
+            using kai.path;
+            using kai.path.Path;
+
+            fn main() {
+                let temp = fs.temp_dir();
+                io.println(fs.list_dir(temp), fs.remove(temp));
+
+                let root = Path.new("ROOT");
+                fs.create_dir(root.join("src", "lib"));
+                io.write_file(root.join("src", "main.kai"), "fn main() {}");
+                io.write_file(path.join(root, "src/lib/a.kai"), "");
+                io.write_file(root.join("notes.txt"), "hi");
+
+                io.println(fs.list_dir(root));
+                io.println(fs.walk(root, "**/*.kai"));
+
+                fs.rename(root.join("notes.txt"), root.join("todo.txt"));
+                io.println(fs.copy(root.join("todo.txt"), root.join("src/todo.txt")));
+                let meta = fs.metadata(root.join("todo.txt"));
+                io.println(meta.size, meta.is_file, meta.is_dir, meta.modified > 0.0);
+                io.println(fs.remove(root.join("src")));
+
+                fs.remove_all(root);
+                io.println(io.exists(root));
+            }
+        "#;
+        let root = std::env::temp_dir().join(format!("kai-fs-{}", std::process::id()));
+        let root = root.to_str().unwrap();
+
+        let printed = output(&code.replace("ROOT", root)).replace(root, "ROOT");
+        let mut lines = printed.lines();
+        assert_eq!(lines.next(), Some("[] nil"));
+        assert_eq!(lines.next(), Some(r#"["notes.txt", "src"]"#));
+        assert_eq!(
+            lines.next(),
+            Some(r#"["ROOT/src/lib/a.kai", "ROOT/src/main.kai"]"#)
+        );
+        assert_eq!(lines.next(), Some("2"));
+        assert_eq!(lines.next(), Some("2 true false true"));
+        assert!(lines.next().unwrap().starts_with('`'));
+        assert_eq!(lines.next(), Some("false"));
+    }
+
+    #[test]
+    fn manipulates_paths() {
+        let code = r#"
+            using kai.io;
+            using kai.path;
+            using kai.path.Path;
+
+            fn main() {
+                let p = Path.new("src/./lib/../main.kai");
+                io.println(p, p.normalize(), p.file_name(), p.stem(), p.extension());
+                io.println(p.parent().parent(), Path.new("a").parent(), p.is_absolute());
+                io.println(p.join("/etc"), Path.new("a") == Path.new("a"), p.normalize() == "src/main.kai");
+                io.println(path.normalize("../a/.."), path.extension("a/b"), path.join("a", "b", "c"));
+            }
+        "#;
+
+        assert_eq!(
+            output(code),
+            "src/./lib/../main.kai src/main.kai main.kai main kai\n\
+             src/./lib nil false\n\
+             /etc true true\n\
+             .. nil a/b/c\n"
+        );
+    }
+
+    #[test]
+    fn operates_on_strings() {
+        let code = r#"
+            using kai.io;
+            using kai.string;
+
+            fn main() {
+                let s = "  héllo wörld  ".trim();
+                io.println(s.len(), s.byte_len(), s.slice(1, 4), s.slice(6), s.char_at(1), s.char_at(20));
+                io.println(s.split(" "), "a,b,,c".split(","), "ab".split(""), "x\ny\r\n".lines());
+                io.println("-".join(s.split(" ")), string.join(", ", "abc".chars()));
+                io.println(s.replace("l", "L"), s.find("wö"), s.find("z"), s.contains("lo w"));
+                io.println(s.starts_with("hé"), s.ends_with("x"), s.to_upper(), "ÀB".to_lower());
+                io.println("[" + " x ".trim_start() + "]", "ab".repeat(3), "".is_empty());
+                io.println("noeACCENTl".reverse(), "noeACCENTl".graphemes());
+                io.println("{} is {} {{ok}}".format("kai", 1), string.len("日本"));
+                for c in "añ" { io.println(c); }
+            }
+        "#;
+
+        assert_eq!(
+            output(&code.replace("ACCENT", "\u{301}")),
+            "11 13 éll wörld é nil\n\
+             [\"héllo\", \"wörld\"] [\"a\", \"b\", \"\", \"c\"] [\"a\", \"b\"] [\"x\", \"y\"]\n\
+             héllo-wörld a, b, c\n\
+             héLLo wörLd 6 nil true\n\
+             true false HÉLLO WÖRLD àb\n\
+             [x ] ababab true\n\
+             le\u{301}on [\"n\", \"o\", \"e\\u{301}\", \"l\"]\n\
+             kai is 1 {ok} 2\n\
+             a\nñ\n"
+        );
+
+        assert_eq!(
+            error(r#"fn main() { "abc".slice(2, 5) }"#),
+            "char index 5 is out of range for a string of 3 chars"
+        );
+        assert_eq!(
+            error(r#"fn main() { "{} {}".format(1) }"#),
+            "the template has more `{}` than the 1 arguments"
+        );
+        assert_eq!(
+            error(r#"fn main() { "a".shout() }"#),
+            "no method `shout` on `string`"
+        );
+        assert_eq!(
+            error(r#"fn main() { "ab".repeat(9223372036854775807) }"#),
+            "cannot repeat a string 9223372036854775807 times, it would be too long"
+        );
+        assert_eq!(
+            error(r#"fn main() { "ab".repeat(-1) }"#),
+            "cannot repeat a string -1 times"
+        );
+        assert_eq!(
+            output(
+                r#"using kai.io; fn main() { io.println("".repeat(9223372036854775807).len()) }"#
+            ),
+            "0\n"
+        );
+    }
+
+    #[test]
+    fn uses_collections() {
+        let code = r#"
+            using kai.io;
+            using kai.collections;
+
+            class Point { x: int; }
+
+            fn double(x) { x * 2 }
+            fn is_even(x) { x % 2 == 0 }
+            fn add(a, b) { a + b }
+            fn by_x(a, b) { b.x - a.x }
+            fn next(counter) { counter.x += 1; counter.x - 1 }
+
+            fn main() {
+                let xs = [3, 1, 2];
+                xs.push(4);
+                io.println(xs, xs.len(), xs[0], xs[1..3], xs[2..], xs[..1], xs.pop(), xs);
+                xs[0] = 10;
+                xs[1] += 5;
+                io.println(xs, xs.map(double), xs.filter(is_even), xs.reduce(add), xs.reduce(add, 100));
+                io.println([].pop(), [].reduce(add), xs.contains(6), xs.contains("6"), [[1], 2][0][0]);
+
+                let points = [Point { x: 2 }, Point { x: 5 }, Point { x: 1 }];
+                points.sort_by(by_x);
+                io.println(points);
+
+                let counter = Point { x: 0 };
+                let ys = [0, 0];
+                ys[next(counter)] += 7;
+                io.println(ys, counter.x);
+
+                let m = {"b": 1, "a": [2]};
+                m["c"] = 3;
+                m["b"] += 1;
+                io.println(m, m["a"], m["z"], m.keys(), m.values(), m.contains("c"), m.len());
+                io.println(m.remove("a"), m, {:}, {1: "one", true: "yes"});
+
+                let s = {3, 1, 3,};
+                io.println(s, s.insert(2), s.insert(1), s.contains(2), s.remove(3), s, s.len());
+                io.println(collections.set(), collections.set([1, 1, 2]).to_list(), s == {2, 1});
+            }
+        "#;
+
+        assert_eq!(
+            output(code),
+            "[3, 1, 2] 4 3 [1, 2] [2, 4] [3] 4 [3, 1, 2]\n\
+             [10, 6, 2] [20, 12, 4] [10, 6, 2] 18 118\n\
+             nil nil true false 1\n\
+             [Point { x: 5 }, Point { x: 2 }, Point { x: 1 }]\n\
+             [7, 0] 1\n\
+             { \"b\": 2, \"a\": [2], \"c\": 3 } [2] nil [\"b\", \"a\", \"c\"] [2, [2], 3] true 3\n\
+             [2] { \"b\": 2, \"c\": 3 } {} { 1: \"one\", true: \"yes\" }\n\
+             { 1, 2 } true false true true { 1, 2 } 2\n\
+             set() [1, 2] true\n"
+        );
+
+        assert_eq!(
+            error("fn main() { [1, 2][2] }"),
+            "index 2 is out of range for a list of 2 items"
+        );
+        assert_eq!(
+            error("fn main() { [1, 2][1..3] }"),
+            "slice 1..3 is out of range for a list of 2 items"
+        );
+        assert_eq!(
+            error(r#"fn main() { [1]["a"] }"#),
+            "a list can only be indexed with an `int` or a `range`, found `string`"
+        );
+        assert_eq!(
+            error("fn main() { {1.5: 1} }"),
+            "`float` can't be a key, only `bool`, `int` and `string` can"
+        );
+        assert_eq!(
+            error("fn same(a, b) { a == b } fn main() { [2, 1].sort_by(same) }"),
+            "the function passed to `sort_by` must return `int`, found `bool`"
+        );
+    }
+
+    #[test]
+    fn iterates_with_for_and_lazy_iterators() {
+        let code = r#"
+            using kai.io;
+            using kai.iter;
+
+            class Naturals {
+                n: int;
+
+                fn next(self) {
+                    self.n += 1;
+                    self.n
+                }
+            }
+
+            class Countdown {
+                n: int;
+
+                fn next(self) -> int? {
+                    if self.n == 0 { nil } else { self.n -= 1; self.n + 1 }
+                }
+            }
+
+            fn square(x) { x * x }
+            fn is_odd(x) { x % 2 == 1 }
+            fn numbers() -> generator<int> {
+                let mut i = 0;
+                loop { yield i; i += 1; }
+            }
+
+            fn main() {
+                let mut sum = 0;
+                for i in 1..=10 { sum += i; }
+                io.println(sum);
+                for i in (0..10).step(4) { io.println(i); }
+
+                let xs = [1, 2];
+                for x in xs { if x < 4 { xs.push(x + 2); } }
+                io.println(xs);
+                for key in {"a": 1, "b": 2} { io.println(key); }
+                for item in {3, 1} { io.println(item); }
+                for c in "hé" { io.println(c); }
+                let countdown = Countdown { n: 2 };
+                for n in countdown { io.println(n * 10); }
+
+                let naturals = Naturals { n: 0 };
+                let odd_squares = iter.from(naturals).map(square).filter(is_odd).take(3);
+                io.println(odd_squares.collect(), naturals.n);
+                for pair in ["a", "b"].iter().enumerate() { io.println(pair); }
+                io.println((1..4).zip("xyz").collect(), iter.from(numbers()).take(3).collect());
+
+                let it = (1..3).iter();
+                io.println(it.next(), it.next(), it.next(), (0..0).map(square).collect());
+            }
+        "#;
+
+        assert_eq!(
+            output(code),
+            "55
+0
+4
+8
+[1, 2, 3, 4, 5]
+a
+b
+3
+1
+h
+é
+20
+10
+\
+             [1, 9, 25] 5
+[0, \"a\"]
+[1, \"b\"]
+\
+             [[1, \"x\"], [2, \"y\"], [3, \"z\"]] [0, 1, 2]
+\
+             1 2 nil []
+"
+        );
+
+        assert_eq!(
+            error("fn main() { (1..2).step(0) }"),
+            "the step must be positive, found 0"
+        );
+        assert_eq!(
+            error("fn main() { [1].iter().zip(2).collect() }"),
+            "`int` is not iterable"
+        );
+    }
+
+    #[test]
+    fn uses_math() {
+        let code = r#"
+            using kai.io;
+            using kai.math;
+
+            fn main() {
+                io.println(-7 / 2, -7 % 2, 7 % -2, 7 / 2.0, 1 + 0.5, 1.0 / 0, 2 == 2.0);
+                io.println(math.abs(-3), math.abs(-1.5), math.min(2, 3), math.max(2, 3.5));
+                io.println(math.pow(2, 10), math.pow(2, -1), math.pow(4, 0.5), math.sqrt(9));
+                io.println(math.floor(-1.5), math.ceil(1.2), math.round(2.5), math.floor(3));
+                io.println(math.sin(0), math.cos(math.pi), math.atan2(1, 1) * 4 == math.pi);
+                io.println(math.log(math.e), math.log(8, 2), math.is_nan(math.nan), math.max_int);
+
+                let a = math.random(42);
+                let b = math.random(42);
+                let rolls = [a.int(1..=6), a.int(1..=6), a.int(1..=6)];
+                io.println(rolls == [b.int(1..=6), b.int(1..=6), b.int(1..=6)]);
+                let mut in_range = true;
+                for i in 0..100 {
+                    let roll = a.int(-2..3);
+                    let x = a.float();
+                    in_range = in_range && roll >= -2 && roll < 3 && x >= 0.0 && x < 1.0;
+                }
+                let xs = [1, 2, 3, 4];
+                a.shuffle(xs);
+                io.println(in_range, xs.len(), xs.contains(4), a.choice([7]), a.choice([]));
+            }
+        "#;
+
+        assert_eq!(
+            output(code),
+            "-3 -1 1 3.5 1.5 inf true\n\
+             3 1.5 2 3.5\n\
+             1024 0.5 2.0 3.0\n\
+             -2 2 3 3\n\
+             0.0 -1.0 true\n\
+             1.0 3.0 true 9223372036854775807\n\
+             true\n\
+             true 4 true 7 nil\n"
+        );
+
+        assert_eq!(
+            error("using kai.math; fn main() { math.pow(2, 63) }"),
+            "attempt to raise to a power with overflow"
+        );
+        assert_eq!(
+            error("using kai.math; fn main() { math.floor(math.inf) }"),
+            "cannot convert inf to `int`"
+        );
+        assert_eq!(
+            error("using kai.math; fn main() { math.random(1).int(3..3) }"),
+            "cannot pick a number from the empty range 3..3"
+        );
+    }
+
+    #[test]
+    fn uses_time() {
+        let code = r#"
+            using kai.io;
+            using kai.task;
+            using kai.time;
+            using kai.time.DateTime;
+            using kai.time.Duration;
+
+            async fn late(order) {
+                await time.sleep(Duration.ms(20));
+                order.push("late");
+            }
+
+            async fn main() {
+                let start = time.now();
+                let order = [];
+                let waiting = task.spawn(late(order));
+                await time.sleep(1);
+                order.push("early");
+                await waiting;
+                io.println(order, time.elapsed(start).as_ms() >= 20, start.elapsed().as_secs() > 0.0);
+
+                let d = Duration.secs(1.5);
+                io.println(d, d.as_ms(), Duration.mins(2).plus(d), d.minus(Duration.secs(2)), d.times(2));
+                io.println(d == Duration.ms(1500), d.compare(Duration.hours(1)), Duration.ms(0));
+
+                let t = time.parse("2024-02-29T23:30:05.25+02:00");
+                io.println(t, t.year(), t.month(), t.day(), t.hour(), t.weekday(), t.unix());
+                io.println(t.to_utc(), t == t.to_utc(), t.plus(Duration.hours(1)));
+                io.println(t.minus(DateTime.parse("2024-02-29T21:00:00Z")), t.minus(Duration.secs(5.25)));
+                io.println(t.format("%a %d %b %Y %H:%M %z"), DateTime.from_unix(0), time.parse("soon"));
+                io.println(time.utc().offset(), time.utc().year() >= 2024);
+            }
+        "#;
+
+        assert_eq!(
+            output(code),
+            "[\"early\", \"late\"] true true\n\
+             1.5s 1500 121.5s -500ms 3s\n\
+             true -1 0ns\n\
+             2024-02-29T23:30:05.25+02:00 2024 2 29 23 4 1709242205\n\
+             2024-02-29T21:30:05.25Z true 2024-03-01T00:30:05.25+02:00\n\
+             1805.25s 2024-02-29T23:30:00+02:00\n\
+             Thu 29 Feb 2024 23:30 +0200 1970-01-01T00:00:00Z `soon` is not an ISO 8601 date\n\
+             0 true\n"
+        );
+
+        assert_eq!(
+            error("using kai.time; fn main() { time.sleep(-5) }"),
+            "cannot sleep for -5ms"
+        );
+        assert_eq!(
+            error("using kai.time; fn main() { time.utc().format(\"%q\") }"),
+            "unknown format `%q`"
+        );
+    }
+
+    #[test]
+    fn uses_regex() {
+        let code = r#"
+            using kai.io;
+            using kai.regex;
+
+            fn main() {
+                let date = regex.compile("(?<year>\\d{4})-(?<month>\\d\\d)(-(\\d\\d))?");
+                io.println(date.is_match("on 2024-03"), date.is_match("on 24-3"));
+                let found = date.find("from 2024-03-01 to 2025-12");
+                io.println(found.text, found.start, found.end, date.find("never"));
+                io.println(date.find_all("2024-03-01, 2025-12").map(text));
+
+                let caps = date.captures("on 2024-03!");
+                io.println(caps["year"], caps["month"], caps[0], caps[4], date.captures("no"));
+                io.println(date.replace("2024-03-01 and 1999-12", "${month}/$1 ($$)"));
+
+                let words = regex.compile("\\s*,\\s*");
+                io.println(words.split("a , b,c,"), regex.compile("x*").split("axb"));
+                io.println(regex.escape("1+1=2?"), regex.compile(regex.escape("a.b")).is_match("axb"));
+                io.println(regex.compile("a(b"), regex.compile("[z-a]"));
+            }
+
+            fn text(m) { m.text }
+        "#;
+
+        assert_eq!(
+            output(code),
+            "true false\n\
+             2024-03-01 5 15 nil\n\
+             [\"2024-03-01\", \"2025-12\"]\n\
+             2024 03 2024-03 nil nil\n\
+             03/2024 ($) and 12/1999 ($)\n\
+             [\"a\", \"b\", \"c\", \"\"] [\"\", \"a\", \"\", \"b\", \"\"]\n\
+             1\\+1=2\\? false\n\
+             invalid regex `a(b`: unclosed group at position 1 \
+             invalid regex `[z-a]`: invalid class range at position 1\n"
+        );
+
+        assert_eq!(
+            error(r#"using kai.regex; fn main() { regex.compile("a").replace("a", "$2") }"#),
+            "no group `2` in the regex"
+        );
+    }
+
+    #[test]
+    fn runs_generators() {
+        let code = r#"
+            using kai.io;
+
+            fn numbers(limit: int) -> generator<int> {
+                let mut i = 0;
+                while i < limit {
+                    yield i * 10;
+                    i += 1;
+                }
+            }
+
+            fn mixed() -> generator<string> {
+                let base = 100;
+                io.println("sum", base + (yield "a") + (yield "b"));
+                for c in "xyz" {
+                    if c != "y" { yield c; }
+                }
+            }
+
+            fn main() {
+                for n in numbers(3) { io.println(n); }
+                let gen = numbers(1);
+                io.println(gen.next(), gen.next(), gen.done(), gen.next());
+                let m = mixed();
+                io.println(m.next(), m.send(10), m.send(20), m.next(), m.next(), m.done());
+            }
+        "#;
+
+        assert_eq!(
+            output(code),
+            "0\n10\n20\n0 nil true nil\nsum 130\na b x z nil true\n"
+        );
+        assert_eq!(
+            error("fn g() -> generator<int> { yield 1; } fn main() { g().send(1, 2) }"),
+            "`send` takes 1 argument but 2 were supplied"
+        );
+    }
+
+    #[test]
+    fn runs_async_tasks() {
+        let code = r#"
+            using kai.io;
+            using kai.task;
+
+            async fn worker(name: string, ms: int) -> string {
+                io.println(name, "start");
+                await task.sleep(ms);
+                io.println(name, "done");
+                name
+            }
+
+            async fn main() {
+                let slow = task.spawn(worker("slow", 40));
+                let fast = task.spawn(worker("fast", 1));
+                io.println("joined", await fast);
+                io.println("joined", await slow);
+                io.println("direct", await worker("inline", 0));
+                task.spawn(worker("detached", 1));
+            }
+        "#;
+
+        assert_eq!(
+            output(code),
+            "slow start\nfast start\nfast done\njoined fast\nslow done\njoined slow\n\
+             inline start\ninline done\ndirect inline\ndetached start\ndetached done\n"
+        );
+        assert_eq!(
+            error("async fn main() { await 1; }"),
+            "`int` cannot be awaited"
+        );
+        assert_eq!(
+            error("async fn f() {} async fn main() { let c = f(); await c; await c; }"),
+            "`f` already finished"
+        );
+        assert_eq!(
+            error("using kai.task; fn main() { task.spawn(1) }"),
+            "expected the call of an `async fn`, found `int`"
+        );
+    }
+
+    #[test]
+    fn runs_socket_io_concurrently() {
+        let code = r#"
+            using kai.io;
+            using kai.net;
+            using kai.task;
+
+            async fn serve(listener) {
+                let socket = await listener.accept();
+                let line = await socket.read(64);
+                await socket.write("echo " + line);
+                socket.close();
+            }
+
+            async fn main() {
+                let listener = net.listen("127.0.0.1:0");
+                let server = task.spawn(serve(listener));
+                let client = await net.connect(listener.addr());
+                await client.write("hi");
+                io.println(await client.read(64));
+                io.println(await client.read(64) == "");
+                await server;
+            }
+        "#;
+
+        assert_eq!(output(code), "echo hi\ntrue\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn runs_subprocesses_concurrently() {
+        let code = r#"
+            using kai.io;
+            using kai.process;
+            using kai.task;
+
+            async fn tick() {
+                await task.sleep(1);
+                io.println("tick");
+            }
+
+            async fn main() {
+                let child = process.output("sh", "-c", "sleep 0.1; echo child");
+                task.spawn(tick());
+                let out = await child;
+                io.println(out.status, out.stdout == "child\n");
+            }
+        "#;
+
+        assert_eq!(output(code), "tick\n0 true\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn runs_and_pipes_subprocesses() {
+        let code = r#"
+            using kai.io;
+            using kai.process;
+
+            fn main() {
+                let out = process.run("sh", json_list());
+                io.println(out.status, out.stdout, out.stderr);
+
+                let env = process.command("sh").arg("-c").arg("echo $KAI_GREETING; pwd; cat")
+                    .env("KAI_GREETING", "hello").cwd("/").input("from stdin").run();
+                io.println(env.stdout);
+
+                let producer = process.command("printf").arg("b\\na\\nc\\n").spawn();
+                let sorter = process.command("sort").stdin_from(producer).spawn();
+                for line in sorter.lines() { io.println("sorted", line); }
+                io.println(producer.wait(), sorter.wait(), producer.read_line());
+
+                let echo = process.command("cat").spawn();
+                echo.write("ping\n");
+                io.println(echo.read_line());
+                echo.close_stdin();
+                io.println(echo.read_line(), echo.wait());
+
+                io.println(process.run("/nonexistent/kai"));
+                io.println(process.args());
+                process.exit(3);
+                io.println("unreachable");
+            }
+
+            fn json_list() { kai.json.parse("[\"-c\", \"echo out; echo err >&2; exit 2\"]") }
+        "#;
+
+        let (result, printed) = run(code);
+        assert_eq!(
+            printed,
+            "2 out\n err\n\n\
+             hello\n/\nfrom stdin\n\
+             sorted a\nsorted b\nsorted c\n\
+             0 0 the stdout of the child was taken\n\
+             ping\n\
+             nil 0\n\
+             `/nonexistent/kai`: No such file or directory (os error 2)\n\
+             []\n"
+        );
+        assert_eq!(result.unwrap_err().kind, RuntimeErrorKind::Exit(3));
+    }
+
+    #[test]
+    fn passes_arguments_to_scripts() {
+        let mut engine = Engine::default();
+        engine.set_args(["a", "b c"]);
+        assert_eq!(
+            engine.eval::<String>(
+                "using kai.process; let args = process.args(); kai.json.stringify(args)"
+            ),
+            Ok(r#"["a","b c"]"#.into())
+        );
+    }
+
+    #[test]
+    fn lets_the_host_drive_tasks() {
+        for backend in [Backend::Ast, Backend::Vm] {
+            let output = Output::default();
+            let mut engine = Engine::default();
+            engine.set_stdout(output.clone());
+            engine.set_backend(backend);
+
+            engine
+                .eval::<()>(
+                    r#"
+                    using kai.io;
+                    using kai.task;
+
+                    async fn tick(n: int) {
+                        let mut i = 0;
+                        while i < n {
+                            io.println("tick", i);
+                            await task.sleep(1);
+                            i += 1;
+                        }
+                    }
+                    fn plain() {}
+                    "#,
+                )
+                .unwrap();
+
+            engine.spawn("tick", (2,)).unwrap();
+            let first = engine.poll().unwrap();
+            assert_eq!(first.pending, 1);
+            assert!(first.wake_at.is_some());
+
+            while let Some(at) = engine.poll().unwrap().wake_at {
+                std::thread::sleep(at.saturating_duration_since(std::time::Instant::now()));
+            }
+            assert_eq!(engine.poll().unwrap().pending, 0);
+            assert_eq!(
+                String::from_utf8(output.0.take()).unwrap(),
+                "tick 0\ntick 1\n"
+            );
+
+            assert_eq!(
+                engine.spawn("plain", ()).unwrap_err().to_string(),
+                "`plain` is not an async fn"
+            );
+        }
+    }
+
+    #[test]
+    fn embeds_in_rust() {
+        let script = r#"
+            fn greet(name: string) -> string { shout("hi " + name) }
+            fn halve(n: int) -> result<int> { let h = half(n)?; h }
+            fn moved(p) { p.shift(p.sum() + origin.x) }
+        "#;
+
+        for backend in [Backend::Ast, Backend::Vm] {
+            let mut engine = Engine::default();
+            engine.set_stdout(Output::default());
+            engine.set_backend(backend);
+            engine.register_fn("add", |a: i64, b: i64| a + b);
+            engine.register_fn("shout", |s: String| s.to_uppercase());
+            engine.register_fn("half", |n: i64| match n % 2 {
+                0 => Ok(n / 2),
+                _ => Err(format!("{n} is odd")),
+            });
+            engine.set_global("origin", Point { x: 10, y: 0 });
+
+            assert_eq!(engine.eval::<i64>("let a = add(1, 2); a * 2"), Ok(6));
+            assert_eq!(engine.eval::<()>(script), Ok(()));
+            assert_eq!(
+                engine.call::<String>("greet", ("bob",)),
+                Ok("HI BOB".into())
+            );
+            assert_eq!(engine.call::<i64>("halve", (4,)), Ok(2));
+            assert_eq!(
+                engine.call::<Point>("moved", (Point { x: 1, y: 2 },)),
+                Ok(Point { x: 14, y: 15 })
+            );
+
+            let message = |err: EvalError| match err {
+                EvalError::Runtime(err) => err.message,
+                err => panic!("expected a runtime error, found {err:?}"),
+            };
+            assert_eq!(
+                engine.call::<i64>("halve", (3,)).map_err(message),
+                Err("`halve` returned an error: 3 is odd".into())
+            );
+            assert_eq!(
+                engine.call::<i64>("nothing", ()).map_err(message),
+                Err("cannot find fn `nothing`".into())
+            );
+            assert_eq!(
+                engine.call::<bool>("greet", ("x",)),
+                Err(EvalError::Convert("expected `bool`, found `string`".into()))
+            );
+            // a script with errors never replaces the loaded one
+            assert!(matches!(
+                engine.eval::<i64>("1 +"),
+                Err(EvalError::Compile(_))
+            ));
+            assert_eq!(engine.call::<String>("greet", ("al",)), Ok("HI AL".into()));
+
+            // registered fns are type checked, arguments of unknown type when called
+            assert!(matches!(
+                engine.eval::<i64>(r#"add(1, "a")"#),
+                Err(EvalError::Compile(_))
+            ));
+            assert_eq!(
+                engine
+                    .eval::<i64>(r#"fn twice(x) { add(x, x) } twice("a")"#)
+                    .map_err(message),
+                Err("expected `int`, found `string`".into())
+            );
+        }
+    }
+
+    #[test]
+    fn registers_native_modules() {
+        let script = r#"
+            using myapp.db;
+            using myapp.db.{ Point, VERSION };
+
+            fn main() -> int {
+                let p = Point.at(db.count("users"));
+                p.sum() + VERSION
+            }
+        "#;
+
+        for backend in [Backend::Ast, Backend::Vm] {
+            let mut engine = Engine::default();
+            engine.set_backend(backend);
+            engine.register_module(
+                NativeModule::new("myapp.db")
+                    .function("count", |table: String| table.len() as i64)
+                    .constant("VERSION", 3)
+                    .class::<Point>(),
+            );
+
+            assert_eq!(engine.eval::<()>(script), Ok(()));
+            assert_eq!(engine.call::<i64>("main", ()), Ok(13));
+
+            let mut messages = |src| -> Vec<String> {
+                match engine.eval::<()>(src) {
+                    Err(EvalError::Compile(diagnostics)) => diagnostics
+                        .into_iter()
+                        .map(|diagnostic| diagnostic.message)
+                        .collect(),
+                    result => panic!("expected compile errors, found {result:?}"),
+                }
+            };
+            assert_eq!(
+                messages("using myapp.db; db.count(1)"),
+                vec!["mismatched types: expected `string`, found `int`"]
+            );
+            assert_eq!(
+                messages("using myapp.nope;"),
+                vec!["unresolved import `myapp.nope`"]
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic = "`kai.mine` is under `kai`"]
+    fn reserves_the_kai_modules() {
+        Engine::default().register_module(NativeModule::new("kai.mine"));
     }
 }
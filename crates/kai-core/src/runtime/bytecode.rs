@@ -0,0 +1,206 @@
+//! The instructions the compiler emits and the VM runs
+
+use ecow::EcoString;
+
+use super::value::Class;
+use crate::ast::span::Span;
+use crate::ast::{BinOp, CoroutineKind, NodeId, UnOp};
+
+/// One VM instruction, operands index the constant pool, the locals of the
+/// current function or its code
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instr {
+    // push a constant
+    Const(u32),
+    Nil,
+    True,
+    False,
+
+    // stack shuffling
+    Pop,
+    // drop `n` values
+    PopN(u16),
+    // drop the `n` values beneath the top one, leaving the top in place
+    Slide(u16),
+    Dup,
+    // duplicates the top two values, `xs[i] += 1` reads and writes the same element
+    Dup2,
+    Swap,
+    // moves the value beneath the top two to the top
+    Rot,
+
+    GetLocal(u16),
+    SetLocal(u16),
+    // a fn item or method
+    Fn(NodeId),
+    Class(NodeId),
+    // a module or native function, its full path is a string constant
+    Import(u32),
+
+    // the field name is a string constant
+    GetField(u32),
+    // pops the value then the receiver
+    SetField(u32),
+
+    Unary(UnOp),
+    Binary(BinOp),
+    // checks the top of the stack is an `int`, used by range bounds
+    ExpectInt,
+    // pops `start` and `end`, `true` for `..=`
+    Range(bool),
+
+    // pops `n` values into a new list
+    List(u16),
+    // pops `n` keys and values in turn into a new map
+    Map(u16),
+    // pops `n` values into a new set
+    Set(u16),
+    // pops the index then the value indexed into
+    GetIndex,
+    // pops the value, the index then the value indexed into
+    SetIndex,
+
+    // absolute offsets into the code of the function
+    Jump(u32),
+    // pops a `bool`, errors on anything else
+    JumpIfFalse(u32),
+    // jumps unless the top of the stack is an error, which the next instruction returns
+    Try(u32),
+
+    // callee and arguments are on the stack
+    Call(u16),
+    // the method name is a string constant, receiver and arguments are on the stack
+    CallMethod(u32, u16),
+    Return,
+
+    // pushes an instance with every field set to `nil`
+    NewInstance(NodeId),
+    // pops a value into the field of the instance below it
+    InitField(u16),
+
+    // pops a value and starts iterating it
+    IterInit,
+    // pushes the next item or jumps once the iterator is done
+    IterNext(u32),
+    IterDrop,
+
+    // `true` when a message is on the stack
+    Panic(bool),
+    // raises the error message found in the constant pool
+    Error(u32),
+    // `break` or `continue` reached the function boundary
+    Escape,
+
+    // pops a value and suspends the coroutine, which pushes what it is resumed with
+    Yield,
+    // pops what the task was resumed with and replaces the future or async call below by
+    // its value, suspending until it is ready
+    Await,
+}
+
+impl Instr {
+    /// How many values the instruction leaves on the stack minus how many it takes
+    pub fn stack_effect(&self) -> i32 {
+        match *self {
+            Instr::Const(_)
+            | Instr::Nil
+            | Instr::True
+            | Instr::False
+            | Instr::Dup
+            | Instr::GetLocal(_)
+            | Instr::Fn(_)
+            | Instr::Class(_)
+            | Instr::Import(_)
+            | Instr::NewInstance(_)
+            | Instr::IterNext(_) => 1,
+            Instr::Dup2 => 2,
+            Instr::Pop
+            | Instr::SetLocal(_)
+            | Instr::Binary(_)
+            | Instr::Range(_)
+            | Instr::JumpIfFalse(_)
+            | Instr::InitField(_)
+            | Instr::IterInit
+            | Instr::Return
+            | Instr::Await
+            | Instr::GetIndex => -1,
+            Instr::SetField(_) => -2,
+            Instr::SetIndex => -3,
+            Instr::List(n) | Instr::Set(n) => 1 - i32::from(n),
+            Instr::Map(n) => 1 - 2 * i32::from(n),
+            Instr::PopN(n) | Instr::Slide(n) => -i32::from(n),
+            Instr::Call(argc) | Instr::CallMethod(_, argc) => -i32::from(argc),
+            Instr::Panic(true) => -1,
+            Instr::Swap
+            | Instr::Rot
+            | Instr::GetField(_)
+            | Instr::Unary(_)
+            | Instr::ExpectInt
+            | Instr::Jump(_)
+            | Instr::Try(_)
+            | Instr::IterDrop
+            | Instr::Panic(false)
+            | Instr::Error(_)
+            | Instr::Escape
+            | Instr::Yield => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Int(i64),
+    Float(f64),
+    Str(EcoString),
+}
+
+/// The code of one function
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Chunk {
+    pub code: Vec<Instr>,
+    // the source span of every instruction, errors point here
+    pub spans: Vec<Span>,
+    pub consts: Vec<Constant>,
+}
+
+impl Chunk {
+    pub fn push(&mut self, instr: Instr, span: Span) -> usize {
+        self.code.push(instr);
+        self.spans.push(span);
+        self.code.len() - 1
+    }
+
+    /// The string constant at `idx`, names and paths are always strings
+    pub fn str(&self, idx: u32) -> &str {
+        match &self.consts[idx as usize] {
+            Constant::Str(value) => value,
+            constant => panic!("expected a string constant, found {constant:?}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub id: NodeId,
+    pub name: EcoString,
+    pub span: Span,
+    // parameters besides `self`
+    pub arity: u16,
+    pub has_self: bool,
+    pub has_body: bool,
+    // calls return a coroutine instead of running the body
+    pub coroutine: Option<CoroutineKind>,
+    // every local in slot order, `self` and the parameters come first
+    pub locals: Vec<EcoString>,
+    pub chunk: Chunk,
+}
+
+/// A compiled file, it no longer refers to the syntax tree
+#[derive(Debug)]
+pub struct Program {
+    pub functions: Vec<Function>,
+    pub classes: Vec<Class>,
+    pub main: Option<NodeId>,
+    // the whole file, where a missing `main` is reported
+    pub span: Span,
+}
@@ -0,0 +1,787 @@
+//! Compiles a resolved file to bytecode for the VM
+
+use std::collections::HashMap;
+
+use ecow::EcoString;
+
+use super::bytecode::{Chunk, Constant, Function, Instr, Program};
+use super::error::RuntimeError;
+use super::load;
+use super::stdlib;
+use super::value::Class;
+use crate::ast::span::Span;
+use crate::ast::*;
+use crate::resolve::{Res, Resolutions};
+
+/// Compiles every fn and method of the file. Errors the interpreter would only
+/// raise when running a piece of code compile to an `Error` instruction so both
+/// backends fail at the same point.
+pub fn compile(file: &File, res: &Resolutions) -> Result<Program, RuntimeError> {
+    let loaded = load::load(file, res);
+
+    let mut ids: Vec<NodeId> = loaded.fns.keys().copied().collect();
+    ids.sort();
+
+    let functions = ids
+        .into_iter()
+        .map(|id| {
+            let compiler = FnCompiler {
+                res,
+                fns: &loaded.fns,
+                classes: &loaded.classes,
+                chunk: Chunk::default(),
+                slots: HashMap::new(),
+                locals: Vec::new(),
+                depth: 0,
+                loops: Vec::new(),
+            };
+            compiler.compile_fn(id, loaded.fns[&id])
+        })
+        .collect::<Result<_, _>>()?;
+
+    let mut classes: Vec<Class> = loaded.classes.into_values().collect();
+    classes.sort_by_key(|class| class.id);
+
+    Ok(Program {
+        functions,
+        classes,
+        main: load::find_main(file).map(|item| item.id),
+        span: file.span,
+    })
+}
+
+// a loop being compiled, `break` and `continue` jump relative to it
+struct Loop {
+    // stack depth at the start of every iteration
+    depth: u32,
+    // where `continue` jumps to
+    start: usize,
+    // `break` jumps, patched once the end of the loop is known
+    breaks: Vec<usize>,
+    // only `loop` produces the value given to `break`
+    keeps_value: bool,
+}
+
+struct FnCompiler<'a> {
+    res: &'a Resolutions,
+    fns: &'a HashMap<NodeId, &'a ItemFn>,
+    classes: &'a HashMap<NodeId, Class>,
+    chunk: Chunk,
+    // slot of every local, keyed by the id of its pattern
+    slots: HashMap<NodeId, u16>,
+    locals: Vec<EcoString>,
+    // temporaries on the stack, `break` and `continue` drop the ones of the loop body
+    depth: u32,
+    loops: Vec<Loop>,
+}
+
+type Compiled = Result<(), RuntimeError>;
+
+impl FnCompiler<'_> {
+    fn compile_fn(mut self, id: NodeId, item_fn: &ItemFn) -> Result<Function, RuntimeError> {
+        // `self` takes the first slot even when the method is called without a receiver
+        for param in &item_fn.inputs {
+            self.declare(&param.pat)?;
+        }
+
+        let arity = item_fn.inputs.iter().filter(|p| !p.is_self()).count();
+
+        if let Some(body) = &item_fn.body {
+            self.compile_block(body)?;
+            self.emit(Instr::Return, body.span);
+        }
+
+        Ok(Function {
+            id,
+            name: item_fn.ident.name.as_str().into(),
+            span: item_fn.ident.span,
+            arity: operand(arity, "parameters", item_fn.ident.span)?,
+            has_self: item_fn.has_self(),
+            has_body: item_fn.body.is_some(),
+            coroutine: item_fn.coroutine(),
+            locals: self.locals,
+            chunk: self.chunk,
+        })
+    }
+
+    //BEGIN emitting
+
+    fn emit(&mut self, instr: Instr, span: Span) -> usize {
+        self.depth = self.depth.saturating_add_signed(instr.stack_effect());
+        self.chunk.push(instr, span)
+    }
+
+    fn constant(&mut self, constant: Constant, span: Span) -> Result<u32, RuntimeError> {
+        let idx = match self.chunk.consts.iter().position(|c| *c == constant) {
+            Some(idx) => idx,
+            None => {
+                self.chunk.consts.push(constant);
+                self.chunk.consts.len() - 1
+            }
+        };
+
+        operand(idx, "constants", span)
+    }
+
+    fn name(&mut self, name: &str, span: Span) -> Result<u32, RuntimeError> {
+        self.constant(Constant::Str(name.into()), span)
+    }
+
+    /// Raises `message` when reached, the expression it replaces still counts as a value
+    fn emit_error(&mut self, message: String, span: Span) -> Compiled {
+        let depth = self.depth;
+        let idx = self.name(&message, span)?;
+        self.emit(Instr::Error(idx), span);
+        self.depth = depth + 1;
+        Ok(())
+    }
+
+    fn here(&self) -> Result<u32, RuntimeError> {
+        operand(self.chunk.code.len(), "instructions", Span::default())
+    }
+
+    /// Points the jump at `idx` to the next instruction
+    fn patch(&mut self, idx: usize) -> Compiled {
+        let target = self.here()?;
+
+        self.chunk.code[idx] = match self.chunk.code[idx] {
+            Instr::Jump(_) => Instr::Jump(target),
+            Instr::JumpIfFalse(_) => Instr::JumpIfFalse(target),
+            Instr::IterNext(_) => Instr::IterNext(target),
+            Instr::Try(_) => Instr::Try(target),
+            instr => unreachable!("{instr:?} is not a jump"),
+        };
+        Ok(())
+    }
+
+    fn declare(&mut self, pat: &Pattern) -> Result<u16, RuntimeError> {
+        let name = match &pat.kind {
+            PatternKind::Ident { ident, .. } => ident.name.as_str(),
+            PatternKind::Wild => "_",
+        };
+        self.slot(pat.id, name, pat.span)
+    }
+
+    fn slot(&mut self, pat: NodeId, name: &str, span: Span) -> Result<u16, RuntimeError> {
+        if let Some(&slot) = self.slots.get(&pat) {
+            return Ok(slot);
+        }
+
+        let slot = operand(self.locals.len(), "locals", span)?;
+        self.locals.push(name.into());
+        self.slots.insert(pat, slot);
+        Ok(slot)
+    }
+
+    /// Stores the value on top of the stack in the local bound by `pat`
+    fn bind(&mut self, pat: &Pattern) -> Compiled {
+        match pat.kind {
+            PatternKind::Ident { .. } => {
+                let slot = self.declare(pat)?;
+                self.emit(Instr::SetLocal(slot), pat.span);
+            }
+            PatternKind::Wild => {
+                self.emit(Instr::Pop, pat.span);
+            }
+        }
+        Ok(())
+    }
+
+    //END emitting
+
+    //BEGIN expressions
+
+    fn compile_block(&mut self, block: &Block) -> Compiled {
+        let mut has_value = false;
+
+        for (i, stmt) in block.stmts.iter().enumerate() {
+            let is_last = i + 1 == block.stmts.len();
+
+            match &stmt.kind {
+                StmtKind::Let(local) => {
+                    if let Some(init) = &local.init {
+                        self.compile_expr(init)?;
+                        self.bind(&local.pat)?;
+                    }
+                }
+                StmtKind::Item(_) | StmtKind::Empty => {}
+                StmtKind::Semi(expr) => {
+                    self.compile_expr(expr)?;
+                    self.emit(Instr::Pop, stmt.span);
+                }
+                StmtKind::Expr(expr) => {
+                    self.compile_expr(expr)?;
+                    if is_last {
+                        has_value = true;
+                    } else {
+                        self.emit(Instr::Pop, stmt.span);
+                    }
+                }
+            }
+        }
+
+        if !has_value {
+            self.emit(Instr::Nil, block.span);
+        }
+        Ok(())
+    }
+
+    /// Leaves exactly one more value on the stack
+    fn compile_expr(&mut self, expr: &Expr) -> Compiled {
+        let depth = self.depth;
+        let span = expr.span;
+
+        match &expr.kind {
+            ExprKind::Lit(lit) => {
+                let constant = match &lit.kind {
+                    LitKind::Int(value) => Constant::Int(*value),
+                    LitKind::Float(value) => Constant::Float(*value),
+                    LitKind::Str(value) => Constant::Str(value.clone()),
+                    LitKind::Bool(true) => return self.emit_unit(Instr::True, span),
+                    LitKind::Bool(false) => return self.emit_unit(Instr::False, span),
+                    LitKind::Nil => return self.emit_unit(Instr::Nil, span),
+                };
+                let idx = self.constant(constant, span)?;
+                self.emit(Instr::Const(idx), span);
+            }
+            ExprKind::Path(path) => self.compile_path(expr.id, path)?,
+            ExprKind::Unary(op, operand) => {
+                self.compile_expr(operand)?;
+                self.emit(Instr::Unary(*op), span);
+            }
+            ExprKind::Binary(op @ (BinOp::And | BinOp::Or), lhs, rhs) => {
+                // both sides must be booleans, `JumpIfFalse` checks it
+                self.compile_expr(lhs)?;
+                let lhs_false = self.emit(Instr::JumpIfFalse(0), lhs.span);
+
+                let mut ends = Vec::new();
+
+                if *op == BinOp::Or {
+                    self.emit(Instr::True, span);
+                    ends.push(self.emit(Instr::Jump(0), span));
+                    self.depth = depth;
+                    self.patch(lhs_false)?;
+                }
+
+                self.compile_expr(rhs)?;
+                let rhs_false = self.emit(Instr::JumpIfFalse(0), rhs.span);
+                self.emit(Instr::True, span);
+                ends.push(self.emit(Instr::Jump(0), span));
+
+                self.depth = depth;
+                if *op == BinOp::And {
+                    self.patch(lhs_false)?;
+                }
+                self.patch(rhs_false)?;
+                self.emit(Instr::False, span);
+
+                for end in ends {
+                    self.patch(end)?;
+                }
+            }
+            ExprKind::Binary(op, lhs, rhs) => {
+                self.compile_expr(lhs)?;
+                self.compile_expr(rhs)?;
+                self.emit(Instr::Binary(*op), span);
+            }
+            ExprKind::Assign(lhs, rhs) => {
+                self.compile_expr(rhs)?;
+                self.compile_assign(lhs)?;
+                self.emit(Instr::Nil, span);
+            }
+            ExprKind::AssignOp(op, lhs, rhs) => {
+                match &lhs.kind {
+                    // the receiver is evaluated once, like the interpreter does
+                    ExprKind::Field(base, ident) => {
+                        let name = self.name(&ident.name, ident.span)?;
+                        self.compile_expr(base)?;
+                        self.emit(Instr::Dup, ident.span);
+                        self.emit(Instr::GetField(name), ident.span);
+                        self.compile_expr(rhs)?;
+                        self.emit(Instr::Binary(*op), span);
+                        self.emit(Instr::SetField(name), ident.span);
+                    }
+                    ExprKind::Index(base, index) => {
+                        self.compile_expr(base)?;
+                        self.compile_expr(index)?;
+                        self.emit(Instr::Dup2, lhs.span);
+                        self.emit(Instr::GetIndex, lhs.span);
+                        self.compile_expr(rhs)?;
+                        self.emit(Instr::Binary(*op), span);
+                        self.emit(Instr::SetIndex, lhs.span);
+                    }
+                    _ => {
+                        self.compile_expr(lhs)?;
+                        self.compile_expr(rhs)?;
+                        self.emit(Instr::Binary(*op), span);
+                        self.compile_assign(lhs)?;
+                    }
+                }
+                self.emit(Instr::Nil, span);
+            }
+            ExprKind::Call(callee, args) => {
+                self.compile_expr(callee)?;
+                for arg in args {
+                    self.compile_expr(arg)?;
+                }
+                let argc = operand(args.len(), "arguments", span)?;
+                self.emit(Instr::Call(argc), span);
+            }
+            ExprKind::MethodCall(call) => {
+                self.compile_expr(&call.receiver)?;
+                for arg in &call.args {
+                    self.compile_expr(arg)?;
+                }
+                let name = self.name(&call.method.name, call.method.span)?;
+                let argc = operand(call.args.len(), "arguments", call.span)?;
+                self.emit(Instr::CallMethod(name, argc), call.span);
+            }
+            ExprKind::Field(base, ident) => {
+                self.compile_expr(base)?;
+                let name = self.name(&ident.name, ident.span)?;
+                self.emit(Instr::GetField(name), ident.span);
+            }
+            ExprKind::ClassInit(init) => self.compile_class_init(expr, init)?,
+            ExprKind::List(items) => {
+                for item in items {
+                    self.compile_expr(item)?;
+                }
+                let len = operand(items.len(), "list elements", span)?;
+                self.emit(Instr::List(len), span);
+            }
+            ExprKind::Map(entries) => {
+                for (key, value) in entries {
+                    self.compile_expr(key)?;
+                    self.compile_expr(value)?;
+                }
+                let len = operand(entries.len(), "map entries", span)?;
+                self.emit(Instr::Map(len), span);
+            }
+            ExprKind::Set(items) => {
+                for item in items {
+                    self.compile_expr(item)?;
+                }
+                let len = operand(items.len(), "set elements", span)?;
+                self.emit(Instr::Set(len), span);
+            }
+            ExprKind::Index(base, index) => {
+                self.compile_expr(base)?;
+                self.compile_expr(index)?;
+                self.emit(Instr::GetIndex, span);
+            }
+            ExprKind::Range(start, end, limits) => {
+                for (bound, default) in [(start, 0), (end, i64::MAX)] {
+                    match bound {
+                        Some(bound) => {
+                            self.compile_expr(bound)?;
+                            self.emit(Instr::ExpectInt, bound.span);
+                        }
+                        None => {
+                            let idx = self.constant(Constant::Int(default), span)?;
+                            self.emit(Instr::Const(idx), span);
+                        }
+                    }
+                }
+                self.emit(Instr::Range(*limits == RangeLimits::Closed), span);
+            }
+            ExprKind::If(cond, then, otherwise) => {
+                self.compile_expr(cond)?;
+                let to_else = self.emit(Instr::JumpIfFalse(0), cond.span);
+                self.compile_block(then)?;
+                let to_end = self.emit(Instr::Jump(0), span);
+
+                self.depth = depth;
+                self.patch(to_else)?;
+                match otherwise {
+                    Some(otherwise) => self.compile_expr(otherwise)?,
+                    None => {
+                        self.emit(Instr::Nil, span);
+                    }
+                }
+                self.patch(to_end)?;
+            }
+            ExprKind::While(cond, body) => {
+                let start = self.chunk.code.len();
+                self.compile_expr(cond)?;
+                let to_end = self.emit(Instr::JumpIfFalse(0), cond.span);
+
+                let breaks = self.compile_loop_body(body, start, false)?;
+
+                self.patch(to_end)?;
+                for idx in breaks {
+                    self.patch(idx)?;
+                }
+                self.emit(Instr::Nil, span);
+            }
+            ExprKind::Loop(body) => {
+                let start = self.chunk.code.len();
+                let breaks = self.compile_loop_body(body, start, true)?;
+
+                for idx in breaks {
+                    self.patch(idx)?;
+                }
+                // `loop` only ends through `break`, which leaves its value
+                self.depth = depth + 1;
+            }
+            ExprKind::For(pat, iter, body) => {
+                self.compile_expr(iter)?;
+                self.emit(Instr::IterInit, iter.span);
+
+                let start = self.chunk.code.len();
+                let to_done = self.emit(Instr::IterNext(0), span);
+                self.bind(pat)?;
+
+                let breaks = self.compile_loop_body(body, start, false)?;
+
+                self.patch(to_done)?;
+                for idx in breaks {
+                    self.patch(idx)?;
+                }
+                self.emit(Instr::IterDrop, span);
+                self.emit(Instr::Nil, span);
+            }
+            ExprKind::Block(block) => self.compile_block(block)?,
+            ExprKind::Paren(inner) => self.compile_expr(inner)?,
+            ExprKind::Return(value) => {
+                self.compile_opt(value, span)?;
+                self.emit(Instr::Return, span);
+                self.depth = depth + 1;
+            }
+            ExprKind::Break(value) => {
+                self.compile_opt(value, span)?;
+                self.compile_break(span)?;
+                self.depth = depth + 1;
+            }
+            ExprKind::Continue => {
+                match self.loops.last() {
+                    Some(current) => {
+                        let (extra, start) = (self.depth - current.depth, current.start);
+                        self.pop_n(extra, span)?;
+                        let start = operand(start, "instructions", span)?;
+                        self.emit(Instr::Jump(start), span);
+                    }
+                    None => {
+                        self.emit(Instr::Escape, span);
+                    }
+                }
+                self.depth = depth + 1;
+            }
+            ExprKind::Panic(value) => {
+                if let Some(value) = value {
+                    self.compile_expr(value)?;
+                }
+                self.emit(Instr::Panic(value.is_some()), span);
+                self.depth = depth + 1;
+            }
+            ExprKind::Try(inner) => {
+                self.compile_expr(inner)?;
+                let to_ok = self.emit(Instr::Try(0), span);
+                self.emit(Instr::Return, span);
+                self.patch(to_ok)?;
+                self.depth = depth + 1;
+            }
+            ExprKind::Yield(value) => {
+                self.compile_opt(value, span)?;
+                self.emit(Instr::Yield, span);
+            }
+            // the `nil` is replaced by the value the task is resumed with
+            ExprKind::Await(inner) => {
+                self.compile_expr(inner)?;
+                self.emit(Instr::Nil, span);
+                self.emit(Instr::Await, span);
+            }
+            ExprKind::Todo => self.emit_error("not yet implemented".to_owned(), span)?,
+        }
+
+        debug_assert_eq!(self.depth, depth + 1, "{:?}", expr.kind);
+        Ok(())
+    }
+
+    fn emit_unit(&mut self, instr: Instr, span: Span) -> Compiled {
+        self.emit(instr, span);
+        Ok(())
+    }
+
+    fn compile_opt(&mut self, expr: &Option<Box<Expr>>, span: Span) -> Compiled {
+        match expr {
+            Some(expr) => self.compile_expr(expr),
+            None => self.emit_unit(Instr::Nil, span),
+        }
+    }
+
+    fn pop_n(&mut self, n: u32, span: Span) -> Compiled {
+        if n > 0 {
+            let n = operand(n as usize, "temporaries", span)?;
+            self.emit(Instr::PopN(n), span);
+        }
+        Ok(())
+    }
+
+    /// Compiles a loop body jumping back to `start`, returns the `break` jumps to patch
+    fn compile_loop_body(
+        &mut self,
+        body: &Block,
+        start: usize,
+        keeps_value: bool,
+    ) -> Result<Vec<usize>, RuntimeError> {
+        self.loops.push(Loop {
+            depth: self.depth,
+            start,
+            breaks: Vec::new(),
+            keeps_value,
+        });
+
+        self.compile_block(body)?;
+        self.emit(Instr::Pop, body.span);
+        let start = operand(start, "instructions", body.span)?;
+        self.emit(Instr::Jump(start), body.span);
+
+        let current = self.loops.pop().expect("loop should exist");
+        self.depth = current.depth;
+        Ok(current.breaks)
+    }
+
+    // the value given to `break` is on the stack
+    fn compile_break(&mut self, span: Span) -> Compiled {
+        let Some(current) = self.loops.last() else {
+            self.emit(Instr::Pop, span);
+            self.emit(Instr::Escape, span);
+            return Ok(());
+        };
+
+        let loop_depth = current.depth;
+
+        if current.keeps_value {
+            let extra = self.depth - 1 - loop_depth;
+            if extra > 0 {
+                let extra = operand(extra as usize, "temporaries", span)?;
+                self.emit(Instr::Slide(extra), span);
+            }
+        } else {
+            self.emit(Instr::Pop, span);
+            self.pop_n(self.depth - loop_depth, span)?;
+        }
+
+        let idx = self.emit(Instr::Jump(0), span);
+        self.loops
+            .last_mut()
+            .expect("loop should exist")
+            .breaks
+            .push(idx);
+        Ok(())
+    }
+
+    fn compile_path(&mut self, id: NodeId, path: &Path) -> Compiled {
+        let name = path.segments.first().map(|i| i.name.as_str()).unwrap_or("");
+        let span = path.span;
+
+        match self.res.get(id) {
+            Some(Res::Local(pat)) => {
+                let slot = self.slot(pat, name, span)?;
+                self.emit(Instr::GetLocal(slot), span);
+            }
+            Some(Res::Item(item)) => {
+                if self.fns.contains_key(&item) {
+                    self.emit(Instr::Fn(item), span);
+                } else if self.classes.contains_key(&item) {
+                    self.emit(Instr::Class(item), span);
+                } else {
+                    self.emit_error(format!("`{name}` is not a value"), span)?;
+                }
+            }
+            Some(Res::Import(tree)) => {
+                let import = self.res.import(tree).expect("import should be resolved");
+                let full = import.full_path(name);
+                self.compile_import(&full, format!("unresolved import `{full}`"), span)?;
+            }
+            Some(Res::Builtin) => {
+                self.compile_import(name, format!("cannot find module `{name}`"), span)?;
+            }
+            _ => self.emit_error(format!("cannot find `{name}` in this scope"), span)?,
+        }
+        Ok(())
+    }
+
+    // the standard library never changes, missing paths are known right away. Paths
+    // outside of `kai` belong to the host and are looked up when run.
+    fn compile_import(&mut self, path: &str, missing: String, span: Span) -> Compiled {
+        let in_stdlib = path == "kai" || path.starts_with("kai.");
+
+        if in_stdlib && stdlib::lookup(path).is_none() {
+            return self.emit_error(missing, span);
+        }
+
+        let idx = self.name(path, span)?;
+        self.emit(Instr::Import(idx), span);
+        Ok(())
+    }
+
+    fn compile_class_init(&mut self, expr: &Expr, init: &ClassInit) -> Compiled {
+        let name = init
+            .path
+            .segments
+            .last()
+            .map(|i| i.name.as_str())
+            .unwrap_or("");
+
+        let class = match self.res.get(expr.id) {
+            Some(Res::Item(id)) => self.classes.get(&id),
+            _ => None,
+        };
+
+        let Some(class) = class else {
+            return self.emit_error(format!("`{name}` is not a class"), init.path.span);
+        };
+
+        let indices: Vec<Option<usize>> = init
+            .fields
+            .iter()
+            .map(|field| class.field_index(&field.ident.name))
+            .collect();
+
+        self.emit(Instr::NewInstance(class.id), expr.span);
+
+        for (field, idx) in init.fields.iter().zip(indices) {
+            self.compile_expr(&field.expr)?;
+
+            match idx {
+                Some(idx) => {
+                    let idx = operand(idx, "fields", field.span)?;
+                    self.emit(Instr::InitField(idx), field.span);
+                }
+                None => {
+                    let message = format!("`{name}` has no field named `{}`", field.ident.name);
+                    self.emit_error(message, field.ident.span)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Stores the value on top of the stack into `lhs`
+    fn compile_assign(&mut self, lhs: &Expr) -> Compiled {
+        let depth = self.depth;
+
+        match &lhs.kind {
+            ExprKind::Path(path) => match self.res.get(lhs.id) {
+                Some(Res::Local(pat)) => {
+                    let name = path.segments.first().map(|i| i.name.as_str()).unwrap_or("");
+                    let slot = self.slot(pat, name, lhs.span)?;
+                    self.emit(Instr::SetLocal(slot), lhs.span);
+                }
+                _ => {
+                    let name = path.segments.first().map(|i| i.name.as_str()).unwrap_or("");
+                    self.emit_error(format!("cannot assign to `{name}`"), lhs.span)?;
+                }
+            },
+            ExprKind::Field(base, ident) => {
+                self.compile_expr(base)?;
+                self.emit(Instr::Swap, ident.span);
+                let name = self.name(&ident.name, ident.span)?;
+                self.emit(Instr::SetField(name), ident.span);
+            }
+            // the value to store is beneath the base and the index
+            ExprKind::Index(base, index) => {
+                self.compile_expr(base)?;
+                self.compile_expr(index)?;
+                self.emit(Instr::Rot, lhs.span);
+                self.emit(Instr::SetIndex, lhs.span);
+            }
+            ExprKind::Paren(inner) => return self.compile_assign(inner),
+            _ => {
+                let message = "invalid left-hand side of assignment".to_owned();
+                self.emit_error(message, lhs.span)?;
+            }
+        }
+
+        self.depth = depth - 1;
+        Ok(())
+    }
+
+    //END expressions
+}
+
+// operands are narrower than `usize`, huge functions are rejected instead of wrapping
+fn operand<T: TryFrom<usize>>(value: usize, what: &str, span: Span) -> Result<T, RuntimeError> {
+    T::try_from(value)
+        .map_err(|_| RuntimeError::new(format!("too many {what} in one function"), span))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::resolve::resolve;
+
+    fn compile_main(code: &str) -> Function {
+        let file = parse(code).unwrap();
+        let resolutions = resolve(&file);
+        let program = compile(&file, &resolutions).unwrap();
+        let main = program.main.unwrap();
+
+        program
+            .functions
+            .into_iter()
+            .find(|function| function.id == main)
+            .unwrap()
+    }
+
+    #[test]
+    fn compiles_locals_and_constants() {
+        let main = compile_main("fn main() { let a = 1; let b = a + 1; b }");
+
+        assert_eq!(main.locals, ["a", "b"]);
+        assert_eq!(main.chunk.consts, [Constant::Int(1)]);
+        assert_eq!(
+            main.chunk.code,
+            [
+                Instr::Const(0),
+                Instr::SetLocal(0),
+                Instr::GetLocal(0),
+                Instr::Const(0),
+                Instr::Binary(BinOp::Add),
+                Instr::SetLocal(1),
+                Instr::GetLocal(1),
+                Instr::Return,
+            ]
+        );
+    }
+
+    #[test]
+    fn compiles_loops_with_jumps() {
+        let main = compile_main("fn main() { let mut i = 0; while i < 3 { i += 1; } }");
+
+        assert_eq!(
+            main.chunk.code,
+            [
+                Instr::Const(0),
+                Instr::SetLocal(0),
+                // condition
+                Instr::GetLocal(0),
+                Instr::Const(1),
+                Instr::Binary(BinOp::Lt),
+                Instr::JumpIfFalse(15),
+                // body, `i += 1;` then the value of the block
+                Instr::GetLocal(0),
+                Instr::Const(2),
+                Instr::Binary(BinOp::Add),
+                Instr::SetLocal(0),
+                Instr::Nil,
+                Instr::Pop,
+                Instr::Nil,
+                Instr::Pop,
+                Instr::Jump(2),
+                // the value of the `while`
+                Instr::Nil,
+                Instr::Return,
+            ]
+        );
+    }
+
+    #[test]
+    fn drops_temporaries_on_break() {
+        let main = compile_main("fn main() { loop { 1 + { break 2; }; } }");
+
+        assert!(main.chunk.code.contains(&Instr::Slide(1)));
+    }
+}
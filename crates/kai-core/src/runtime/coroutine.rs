@@ -0,0 +1,194 @@
+//! Coroutines, what calling a generator or an `async fn` returns
+//!
+//! A coroutine holds a call which has not finished yet. Resuming it runs the call until
+//! it reaches a `yield`, or an `await` on something not ready yet, where the backend
+//! saves the state of the call back into the coroutine. Only the frame of the coroutine
+//! fn itself is saved, `yield` and `await` can't appear in the fns it calls.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use ecow::EcoString;
+
+use super::error::RuntimeError;
+use super::limits::Budget;
+use super::ops;
+use super::value::{NativeContext, Value};
+use super::{interp, vm};
+use crate::ast::span::Span;
+use crate::ast::CoroutineKind;
+
+pub struct Coroutine {
+    pub name: EcoString,
+    pub kind: CoroutineKind,
+    state: RefCell<CoroutineState>,
+}
+
+pub(crate) enum CoroutineState {
+    // calls which never ran are suspended at the start of their body
+    Ast(interp::Suspended),
+    Vm(vm::Suspended),
+    // being resumed, its frame is on the stack of the backend
+    Running,
+    Done,
+}
+
+impl Coroutine {
+    pub(crate) fn new(
+        name: impl Into<EcoString>,
+        kind: CoroutineKind,
+        state: CoroutineState,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            kind,
+            state: RefCell::new(state),
+        }
+    }
+
+    /// Whether the call returned, resuming it again is an error
+    pub fn is_done(&self) -> bool {
+        matches!(*self.state.borrow(), CoroutineState::Done)
+    }
+
+    /// Takes the saved call out to resume it, the coroutine is running until the backend
+    /// calls `suspend` or `finish`
+    pub(crate) fn take(&self, span: Span) -> Result<CoroutineState, RuntimeError> {
+        match self.state.replace(CoroutineState::Running) {
+            CoroutineState::Running => Err(RuntimeError::new(
+                format!("`{}` is already running", self.name),
+                span,
+            )),
+            CoroutineState::Done => {
+                self.finish();
+                Err(RuntimeError::new(
+                    format!("`{}` already finished", self.name),
+                    span,
+                ))
+            }
+            state => Ok(state),
+        }
+    }
+
+    pub(crate) fn suspend(&self, state: CoroutineState) {
+        self.state.replace(state);
+    }
+
+    pub(crate) fn finish(&self) {
+        self.state.replace(CoroutineState::Done);
+    }
+}
+
+impl std::fmt::Debug for Coroutine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Coroutine")
+            .field("name", &self.name)
+            .field("kind", &self.kind)
+            .finish()
+    }
+}
+
+/// Where a resumed coroutine stopped
+#[derive(Debug)]
+pub(crate) enum Resumed {
+    // the yielded value, or the future an async coroutine waits for
+    Yield(Value),
+    Return(Value),
+}
+
+/// What the executor and the generator methods need from a backend
+pub(crate) trait Resume: NativeContext {
+    /// Runs the coroutine until it suspends or returns, `input` is what the `yield` or
+    /// `await` it is suspended at evaluates to
+    fn resume(
+        &mut self,
+        coroutine: &Rc<Coroutine>,
+        input: Value,
+        span: Span,
+    ) -> Result<Resumed, RuntimeError>;
+
+    fn budget(&self) -> &Budget;
+}
+
+/// How far an `await` got
+pub(crate) enum Awaited {
+    Ready(Value),
+    // the future the awaiting coroutine suspends on
+    Pending(Value),
+}
+
+/// Polls the future or resumes the async call `awaited`, whose pending futures the
+/// awaiting coroutine passes on to the executor
+pub(crate) fn poll_await(
+    backend: &mut impl Resume,
+    awaited: &Value,
+    input: Value,
+    span: Span,
+) -> Result<Awaited, RuntimeError> {
+    match awaited {
+        Value::Future(future) => match future.poll() {
+            Some(value) => Ok(Awaited::Ready(value)),
+            None => Ok(Awaited::Pending(awaited.clone())),
+        },
+        Value::Coroutine(coroutine) if coroutine.kind == CoroutineKind::Async => {
+            match backend.resume(coroutine, input, span)? {
+                Resumed::Return(value) => Ok(Awaited::Ready(value)),
+                Resumed::Yield(pending) => Ok(Awaited::Pending(pending)),
+            }
+        }
+        value => Err(RuntimeError::new(
+            format!("`{}` cannot be awaited", value.type_name()),
+            span,
+        )),
+    }
+}
+
+/// Resumes a generator, `None` once it returned
+pub(crate) fn next(
+    backend: &mut impl Resume,
+    coroutine: &Rc<Coroutine>,
+    input: Value,
+    span: Span,
+) -> Result<Option<Value>, RuntimeError> {
+    if coroutine.is_done() {
+        return Ok(None);
+    }
+
+    match backend.resume(coroutine, input, span)? {
+        Resumed::Yield(value) => Ok(Some(value)),
+        Resumed::Return(_) => Ok(None),
+    }
+}
+
+/// `next()` and `send(value)` resume a generator and return what it yields next, `nil`
+/// once it is done
+pub(crate) fn call_method(
+    backend: &mut impl Resume,
+    coroutine: &Rc<Coroutine>,
+    name: &str,
+    args: Vec<Value>,
+    span: Span,
+) -> Result<Value, RuntimeError> {
+    let receiver = Value::Coroutine(coroutine.clone());
+
+    if coroutine.kind != CoroutineKind::Generator {
+        return Err(ops::no_method(name, &receiver, span));
+    }
+
+    let expected = match name {
+        "next" | "done" => 0,
+        "send" => 1,
+        _ => return Err(ops::no_method(name, &receiver, span)),
+    };
+    if args.len() != expected {
+        return Err(ops::wrong_arg_count(name, expected, args.len(), span));
+    }
+
+    match name {
+        "done" => Ok(Value::Bool(coroutine.is_done())),
+        _ => {
+            let input = args.into_iter().next().unwrap_or(Value::Nil);
+            Ok(next(backend, coroutine, input, span)?.unwrap_or(Value::Nil))
+        }
+    }
+}
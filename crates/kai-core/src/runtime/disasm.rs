@@ -0,0 +1,172 @@
+//! A readable listing of compiled programs, printed by `kai disasm`
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use super::bytecode::{Constant, Function, Instr, Program};
+use crate::ast::span::Span;
+use crate::ast::NodeId;
+use crate::diagnostic::line_col;
+
+/// Lists every class and function of the program. Instructions show where they come
+/// from as `line:col` when the source is given and as char offsets otherwise.
+pub fn disassemble(program: &Program, src: Option<&str>) -> String {
+    let mut out = String::new();
+
+    // names of functions and classes, methods are listed as `Class.method`
+    let mut names: HashMap<NodeId, String> = program
+        .functions
+        .iter()
+        .map(|function| (function.id, function.name.to_string()))
+        .collect();
+
+    for class in &program.classes {
+        names.insert(class.id, class.name.to_string());
+
+        for (name, id) in &class.methods {
+            names.insert(*id, format!("{}.{name}", class.name));
+        }
+    }
+
+    for class in &program.classes {
+        let _ = writeln!(out, "class {}", class.name);
+
+        let mut methods: Vec<&str> = class.methods.keys().map(|name| name.as_str()).collect();
+        methods.sort();
+        let builtins: Vec<&str> = class.builtins.iter().map(|b| b.name()).collect();
+
+        for (label, names) in [
+            ("fields", class.fields.iter().map(|f| f.as_str()).collect()),
+            ("methods", methods),
+            ("implements", builtins),
+        ] {
+            if !names.is_empty() {
+                let _ = writeln!(out, "  {label}: {}", names.join(", "));
+            }
+        }
+        out.push('\n');
+    }
+
+    for function in &program.functions {
+        write_function(&mut out, function, &names, src);
+    }
+
+    out
+}
+
+fn write_function(
+    out: &mut String,
+    function: &Function,
+    names: &HashMap<NodeId, String>,
+    src: Option<&str>,
+) {
+    let name = &names[&function.id];
+    let params = function.arity + u16::from(function.has_self);
+    let _ = writeln!(
+        out,
+        "fn {name} at {}, {params} params, locals: [{}]",
+        location(function.span, src),
+        function
+            .locals
+            .iter()
+            .map(|local| local.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    if !function.has_body {
+        let _ = writeln!(out, "  no body\n");
+        return;
+    }
+
+    let chunk = &function.chunk;
+    let constant = |idx: u32| match &chunk.consts[idx as usize] {
+        Constant::Int(value) => value.to_string(),
+        Constant::Float(value) => format!("{value:?}"),
+        Constant::Str(value) => format!("{value:?}"),
+    };
+    let local = |slot: u16| function.locals[usize::from(slot)].to_string();
+    let name_of = |id: NodeId| names.get(&id).cloned().unwrap_or_default();
+
+    for (ip, (instr, span)) in chunk.code.iter().zip(&chunk.spans).enumerate() {
+        let (op, note) = match *instr {
+            Instr::Const(idx) => (format!("Const {idx}"), constant(idx)),
+            Instr::PopN(n) => (format!("PopN {n}"), String::new()),
+            Instr::Slide(n) => (format!("Slide {n}"), String::new()),
+            Instr::GetLocal(slot) => (format!("GetLocal {slot}"), local(slot)),
+            Instr::SetLocal(slot) => (format!("SetLocal {slot}"), local(slot)),
+            Instr::Fn(id) => (format!("Fn #{}", id.0), name_of(id)),
+            Instr::Class(id) => (format!("Class #{}", id.0), name_of(id)),
+            Instr::NewInstance(id) => (format!("NewInstance #{}", id.0), name_of(id)),
+            Instr::Import(idx) => (format!("Import {idx}"), constant(idx)),
+            Instr::GetField(idx) => (format!("GetField {idx}"), constant(idx)),
+            Instr::SetField(idx) => (format!("SetField {idx}"), constant(idx)),
+            Instr::Unary(op) => (format!("Unary {op:?}"), String::new()),
+            Instr::Binary(op) => (format!("Binary {}", op.as_str()), String::new()),
+            Instr::Range(closed) => {
+                let op = if closed { "..=" } else { ".." };
+                (format!("Range {op}"), String::new())
+            }
+            Instr::Jump(target) => (format!("Jump {target:04}"), String::new()),
+            Instr::JumpIfFalse(target) => (format!("JumpIfFalse {target:04}"), String::new()),
+            Instr::IterNext(target) => (format!("IterNext {target:04}"), String::new()),
+            Instr::Try(target) => (format!("Try {target:04}"), String::new()),
+            Instr::Call(argc) => (format!("Call {argc}"), String::new()),
+            Instr::CallMethod(idx, argc) => (format!("CallMethod {idx} {argc}"), constant(idx)),
+            Instr::InitField(idx) => (format!("InitField {idx}"), String::new()),
+            Instr::List(n) => (format!("List {n}"), String::new()),
+            Instr::Map(n) => (format!("Map {n}"), String::new()),
+            Instr::Set(n) => (format!("Set {n}"), String::new()),
+            Instr::Panic(has_message) => (format!("Panic {has_message}"), String::new()),
+            Instr::Error(idx) => (format!("Error {idx}"), constant(idx)),
+            instr => (format!("{instr:?}"), String::new()),
+        };
+
+        let location = location(*span, src);
+        let line = match note.is_empty() {
+            true => format!("  {ip:04}  {location:<9} {op}"),
+            false => format!("  {ip:04}  {location:<9} {op:<20} ; {note}"),
+        };
+        let _ = writeln!(out, "{}", line.trim_end());
+    }
+
+    out.push('\n');
+}
+
+fn location(span: Span, src: Option<&str>) -> String {
+    match src {
+        Some(src) => {
+            let (line, col) = line_col(src, span.start);
+            format!("{line}:{col}")
+        }
+        None => format!("{}..{}", span.start, span.end),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::resolve::resolve;
+    use crate::runtime::compile;
+
+    #[test]
+    fn lists_instructions_with_locations() {
+        let src = "class A { x: int; }\nfn main() {\n    let a = A { x: 1 };\n    a.x\n}";
+        let file = parse(src).unwrap();
+        let program = compile(&file, &resolve(&file)).unwrap();
+
+        let listing = disassemble(&program, Some(src));
+        let main = listing.split("fn main").nth(1).unwrap();
+        assert!(listing.starts_with("class A\n  fields: x\n\n"));
+        assert!(main.starts_with(" at 2:4, 0 params, locals: [a]\n"));
+        assert!(main.contains("  0000  3:13      NewInstance #"));
+        assert!(main.contains("; A\n"));
+        assert!(main.contains("  0001  3:20      Const 0              ; 1\n"));
+        assert!(main.contains("  0004  4:5       GetLocal 0           ; a\n"));
+        assert!(main.contains("GetField 1           ; \"x\"\n"));
+
+        let offsets = disassemble(&program, None);
+        assert!(offsets.contains("  0001  51..52    Const 0"));
+    }
+}
@@ -0,0 +1,97 @@
+use std::fmt::Display;
+
+use crate::ast::span::Span;
+use crate::diagnostic::Diagnostic;
+pub use crate::diagnostic::TraceFrame;
+
+/// Why a program stopped, limits set on the engine get their own kinds so embedders can
+/// tell them apart from errors in the program
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RuntimeErrorKind {
+    #[default]
+    Error,
+    StackOverflow,
+    OutOfFuel,
+    OutOfMemory,
+    Timeout,
+    // a native needing a capability the engine denies
+    Denied,
+    // `kai.process.exit`, hosts running scripts as programs exit with the code
+    Exit(i32),
+}
+
+/// An error which stopped the program
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    pub message: String,
+    // the expression being evaluated when the error happened
+    pub span: Span,
+    // filled in by the backend once the error leaves the function it happened in
+    pub trace: Vec<TraceFrame>,
+}
+
+impl RuntimeError {
+    pub fn new(message: impl Into<String>, span: Span) -> Self {
+        Self::with_kind(RuntimeErrorKind::Error, message, span)
+    }
+
+    pub fn with_kind(kind: RuntimeErrorKind, message: impl Into<String>, span: Span) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            span,
+            trace: Vec::new(),
+        }
+    }
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+/// Why `Engine::eval` or `Engine::call` failed
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    /// The script did not parse, resolve or type check, warnings are left out
+    Compile(Vec<Diagnostic>),
+    Runtime(RuntimeError),
+    /// The result could not be converted to the rust type asked for
+    Convert(String),
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvalError::Compile(diagnostics) => {
+                for (i, diagnostic) in diagnostics.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}", diagnostic.message)?;
+                }
+                Ok(())
+            }
+            EvalError::Runtime(err) => write!(f, "{err}"),
+            EvalError::Convert(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+impl From<RuntimeError> for EvalError {
+    fn from(value: RuntimeError) -> Self {
+        EvalError::Runtime(value)
+    }
+}
+
+impl From<RuntimeError> for Diagnostic {
+    fn from(value: RuntimeError) -> Self {
+        Diagnostic::error(value.message, value.span).with_trace(value.trace)
+    }
+}
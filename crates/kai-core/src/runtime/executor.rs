@@ -0,0 +1,299 @@
+//! The single threaded executor running `async fn` calls as tasks
+//!
+//! A task is an async coroutine. The executor resumes every task whose future is ready,
+//! the task runs until it awaits a future which is not and suspends again. Once no task
+//! can go on the executor sleeps until the nearest deadline, or polls again shortly when
+//! a task waits on I/O. Futures are polled, never woken, so everything stays on the
+//! thread of the engine and I/O is done non-blocking or by helper threads.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use ecow::EcoString;
+
+use super::coroutine::{Coroutine, Resume, Resumed};
+use super::error::RuntimeError;
+use super::value::Value;
+use crate::ast::span::Span;
+use crate::ast::CoroutineKind;
+
+// how long the executor sleeps between polls while a task waits on I/O
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// A value which is not there yet, awaiting it suspends the task until it is
+pub struct Future {
+    pub name: EcoString,
+    state: RefCell<FutureState>,
+}
+
+enum FutureState {
+    Pending(Box<dyn Source>),
+    Ready(Value),
+}
+
+/// When a pending source can become ready
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wake {
+    /// Not before this instant, the executor sleeps until then
+    At(Instant),
+    /// Any time, the executor polls it while idle
+    Poll,
+    /// Only when another task runs, like the end of a spawned task
+    Task,
+}
+
+/// What a pending future waits for
+pub trait Source {
+    /// The value once it is available, failures are returned as error values
+    fn poll(&mut self) -> Option<Value>;
+
+    fn wake(&self) -> Wake {
+        Wake::Poll
+    }
+}
+
+impl Future {
+    pub fn new(name: impl Into<EcoString>, source: impl Source + 'static) -> Self {
+        Self {
+            name: name.into(),
+            state: RefCell::new(FutureState::Pending(Box::new(source))),
+        }
+    }
+
+    pub fn ready(name: impl Into<EcoString>, value: Value) -> Self {
+        Self {
+            name: name.into(),
+            state: RefCell::new(FutureState::Ready(value)),
+        }
+    }
+
+    /// The value if it is available, polling the source otherwise
+    pub fn poll(&self) -> Option<Value> {
+        let mut state = self.state.borrow_mut();
+
+        let value = match &mut *state {
+            FutureState::Ready(value) => return Some(value.clone()),
+            FutureState::Pending(source) => source.poll()?,
+        };
+
+        *state = FutureState::Ready(value.clone());
+        Some(value)
+    }
+
+    pub fn wake(&self) -> Option<Wake> {
+        match &*self.state.borrow() {
+            FutureState::Ready(_) => None,
+            FutureState::Pending(source) => Some(source.wake()),
+        }
+    }
+}
+
+impl std::fmt::Debug for Future {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Future").field("name", &self.name).finish()
+    }
+}
+
+/// Ready once `deadline` has passed
+pub(crate) struct Sleep {
+    pub deadline: Instant,
+}
+
+impl Source for Sleep {
+    fn poll(&mut self) -> Option<Value> {
+        (Instant::now() >= self.deadline).then_some(Value::Nil)
+    }
+
+    fn wake(&self) -> Wake {
+        Wake::At(self.deadline)
+    }
+}
+
+// the value a task returned, shared by the task and the future joining it
+type Output = Rc<RefCell<Option<Value>>>;
+
+struct Join(Output);
+
+impl Source for Join {
+    fn poll(&mut self) -> Option<Value> {
+        self.0.borrow().clone()
+    }
+
+    fn wake(&self) -> Wake {
+        Wake::Task
+    }
+}
+
+struct Task {
+    coroutine: Rc<Coroutine>,
+    // `None` until the task first ran
+    waiting: Option<Rc<Future>>,
+    output: Output,
+}
+
+/// The tasks of an engine
+#[derive(Default)]
+pub(crate) struct Executor {
+    tasks: Vec<Task>,
+}
+
+impl Executor {
+    /// Adds a task, the future it returns is ready once the task returns
+    pub fn spawn(&mut self, coroutine: Rc<Coroutine>) -> Rc<Future> {
+        let output = Output::default();
+        let name = coroutine.name.clone();
+        self.tasks.push(Task {
+            coroutine,
+            waiting: None,
+            output: output.clone(),
+        });
+        Rc::new(Future::new(name, Join(output)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.tasks.clear();
+    }
+
+    /// When one of the tasks can go on, `None` when every task waits for another one
+    pub fn next_wake(&self) -> Option<Instant> {
+        let now = Instant::now();
+
+        self.tasks
+            .iter()
+            .filter_map(|task| match &task.waiting {
+                None => Some(now),
+                Some(future) => match future.wake() {
+                    None => Some(now),
+                    Some(Wake::At(at)) => Some(at),
+                    Some(Wake::Poll) => Some(now + POLL_INTERVAL),
+                    Some(Wake::Task) => None,
+                },
+            })
+            .min()
+    }
+}
+
+/// Resumes every task which can go on once, returns whether any could
+pub(crate) fn step(backend: &mut impl Resume, span: Span) -> Result<bool, RuntimeError> {
+    // tasks spawned while these run are only resumed by the next step
+    let mut tasks = std::mem::take(&mut backend.executor().tasks).into_iter();
+    let mut pending = Vec::new();
+    let mut progress = false;
+
+    let result = loop {
+        let Some(mut task) = tasks.next() else {
+            break Ok(progress);
+        };
+
+        let input = match &task.waiting {
+            None => Value::Nil,
+            Some(future) => match future.poll() {
+                Some(value) => value,
+                None => {
+                    pending.push(task);
+                    continue;
+                }
+            },
+        };
+        progress = true;
+
+        match backend.resume(&task.coroutine, input, span) {
+            Ok(Resumed::Yield(Value::Future(future))) => {
+                task.waiting = Some(future);
+                pending.push(task);
+            }
+            Ok(Resumed::Yield(value)) => {
+                unreachable!("async fns only suspend on futures, found {value:?}")
+            }
+            Ok(Resumed::Return(value)) => *task.output.borrow_mut() = Some(value),
+            Err(err) => break Err(err),
+        }
+    };
+
+    let executor = backend.executor();
+    let spawned = std::mem::replace(&mut executor.tasks, pending);
+    executor.tasks.extend(tasks);
+    executor.tasks.extend(spawned);
+    result
+}
+
+/// Runs an async call to completion, any other value is returned as it is
+pub(crate) fn run_async(
+    backend: &mut impl Resume,
+    value: Value,
+    span: Span,
+) -> Result<Value, RuntimeError> {
+    match value {
+        Value::Coroutine(coroutine) if coroutine.kind == CoroutineKind::Async => {
+            let join = backend.executor().spawn(coroutine);
+            block_on(backend, &join, span)
+        }
+        value => Ok(value),
+    }
+}
+
+/// Runs tasks until `future` is ready
+pub(crate) fn block_on(
+    backend: &mut impl Resume,
+    future: &Future,
+    span: Span,
+) -> Result<Value, RuntimeError> {
+    loop {
+        if let Some(value) = future.poll() {
+            return Ok(value);
+        }
+        if !step(backend, span)? {
+            park(backend, span)?;
+        }
+    }
+}
+
+/// What is left after `Engine::poll`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaskStatus {
+    /// Tasks which have not returned yet
+    pub pending: usize,
+    /// When to poll again, `None` if no task is left or every task waits for another
+    pub wake_at: Option<Instant>,
+}
+
+/// Runs tasks until none can go on without waiting
+pub(crate) fn poll(backend: &mut impl Resume, span: Span) -> Result<TaskStatus, RuntimeError> {
+    while step(backend, span)? {}
+
+    let executor = backend.executor();
+    Ok(TaskStatus {
+        pending: executor.len(),
+        wake_at: executor.next_wake(),
+    })
+}
+
+/// Runs tasks until every one of them returned
+pub(crate) fn run_all(backend: &mut impl Resume, span: Span) -> Result<(), RuntimeError> {
+    while backend.executor().len() > 0 {
+        if !step(backend, span)? {
+            park(backend, span)?;
+        }
+    }
+    Ok(())
+}
+
+// sleeps until a task may be able to go on, or the run times out
+fn park(backend: &mut impl Resume, span: Span) -> Result<(), RuntimeError> {
+    let Some(wake) = backend.executor().next_wake() else {
+        let message = "deadlock, every task is waiting for another task";
+        return Err(RuntimeError::new(message, span));
+    };
+
+    let budget = backend.budget();
+    let until = budget
+        .deadline()
+        .map_or(wake, |deadline| deadline.min(wake));
+    std::thread::sleep(until.saturating_duration_since(Instant::now()));
+    budget.check_time(span)
+}
@@ -0,0 +1,580 @@
+//! A tracing collector for the cycles reference counting cannot free
+//!
+//! Values are reference counted, most garbage is freed as soon as the last value
+//! referring to it goes away. Objects referring to each other, like an instance stored
+//! in one of its own fields, keep each other alive forever. To find them the collector
+//! subtracts the references heap objects hold to each other from their reference
+//! counts. What is left comes from outside the heap (locals, the stack, values held by
+//! the backend) and roots the mark phase. Objects left unmarked are only reachable from
+//! garbage, the sweep empties them which breaks the cycles.
+//!
+//! Instances, lists, maps and sets are the objects on the heap, every other value either
+//! can't refer to them or is counted as a reference from outside.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+use super::value::{Class, Instance, Key, Map, Set, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GcConfig {
+    /// Allocations between two collections, the live objects of the last collection
+    /// are counted too so big heaps are not traced over and over
+    pub threshold: usize,
+    /// Collects before every allocation, slow but finds objects freed too early
+    pub stress: bool,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 10_000,
+            stress: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GcStats {
+    pub collections: u64,
+    pub allocations: u64,
+    // objects freed by collections, reference counting frees the rest
+    pub freed: u64,
+    pub live: usize,
+}
+
+/// Every object the collector traces
+#[derive(Debug, Default)]
+pub struct Heap {
+    objects: Vec<Tracked>,
+    config: GcConfig,
+    stats: GcStats,
+    since_collection: usize,
+    // objects that survived the last collection
+    survivors: usize,
+    // see `Limits::heap_bytes`
+    limit: Option<usize>,
+    // bytes of the survivors and everything allocated since, objects freed by reference
+    // counting are only subtracted by the next collection
+    bytes: usize,
+}
+
+impl Heap {
+    pub fn new(config: GcConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    pub fn set_config(&mut self, config: GcConfig) {
+        self.config = config;
+    }
+
+    pub fn set_limit(&mut self, limit: Option<usize>) {
+        self.limit = limit;
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    /// Whether a single object of `bytes` fits under the limit at all
+    pub fn fits(&self, bytes: usize) -> bool {
+        self.limit.is_none_or(|limit| bytes <= limit)
+    }
+
+    pub fn stats(&self) -> GcStats {
+        GcStats {
+            live: self.objects.iter().filter(|o| o.is_live()).count(),
+            ..self.stats
+        }
+    }
+
+    /// Creates an instance, collecting first once enough objects were allocated. Returns
+    /// `None` when the heap limit is reached even after collecting.
+    pub fn alloc(&mut self, class: Rc<Class>, fields: Vec<Value>) -> Option<Rc<Instance>> {
+        let size = object_size(&fields);
+        if !self.make_room(size, true) {
+            return None;
+        }
+
+        let instance = Rc::new(Instance {
+            class,
+            fields: RefCell::new(fields),
+        });
+        self.track_object(Tracked::Instance(Rc::downgrade(&instance)), size);
+        Some(instance)
+    }
+
+    /// Puts a new list, map or set on the heap like `alloc`, other values are returned as
+    /// they are. Returns `None` when it doesn't fit under the limit.
+    pub fn track(&mut self, value: Value) -> Option<Value> {
+        let Some(object) = GcObject::from_value(&value) else {
+            return Some(value);
+        };
+        let size = object.size();
+        if !self.make_room(size, true) {
+            return None;
+        }
+
+        self.track_object(object.downgrade(), size);
+        Some(value)
+    }
+
+    /// Charges `additional` more elements of a list, map or set to the limit, along with
+    /// the `strings` bytes of the strings among them, see `string_bytes`. Collects first
+    /// when they don't fit, returns `false` when they don't fit even after collecting.
+    ///
+    /// Must be called before the collection is borrowed, the collector looks inside it.
+    pub fn reserve(&mut self, collection: &Value, additional: usize, strings: usize) -> bool {
+        let size = match collection {
+            Value::List(_) => std::mem::size_of::<Value>(),
+            Value::Map(_) | Value::Set(_) => ENTRY_SIZE,
+            _ => 0,
+        } * additional
+            + strings;
+
+        if !self.make_room(size, false) {
+            return false;
+        }
+        self.bytes += size;
+        true
+    }
+
+    // collects when `size` more bytes go over the limit, and for new objects once enough
+    // were allocated, returns whether they fit
+    fn make_room(&mut self, size: usize, new: bool) -> bool {
+        let over_limit = |heap: &Self| heap.limit.is_some_and(|limit| heap.bytes + size > limit);
+
+        if over_limit(self)
+            || new
+                && (self.config.stress
+                    || self.since_collection >= self.config.threshold.max(self.survivors))
+        {
+            self.collect();
+            return !over_limit(self);
+        }
+        true
+    }
+
+    fn track_object(&mut self, object: Tracked, size: usize) {
+        self.objects.push(object);
+        self.since_collection += 1;
+        self.stats.allocations += 1;
+        self.bytes += size;
+    }
+
+    /// Frees every cycle no longer reachable, returns how many objects were freed
+    pub fn collect(&mut self) -> usize {
+        // objects already freed by reference counting are forgotten here
+        let objects: Vec<GcObject> = self.objects.iter().filter_map(Tracked::upgrade).collect();
+        let index: HashMap<*const (), usize> = objects
+            .iter()
+            .enumerate()
+            .map(|(idx, object)| (object.as_ptr(), idx))
+            .collect();
+
+        // references from outside the heap, `objects` itself holds one
+        let mut outside: Vec<usize> = objects.iter().map(|o| o.strong_count() - 1).collect();
+
+        for object in &objects {
+            object.for_each_child(|child| {
+                if let Some(&idx) = index.get(&child) {
+                    outside[idx] -= 1;
+                }
+            });
+        }
+
+        let mut marked = vec![false; objects.len()];
+        let mut pending: Vec<usize> = (0..objects.len()).filter(|&i| outside[i] > 0).collect();
+
+        while let Some(idx) = pending.pop() {
+            if std::mem::replace(&mut marked[idx], true) {
+                continue;
+            }
+
+            objects[idx].for_each_child(|child| {
+                if let Some(&child) = index.get(&child) {
+                    if !marked[child] {
+                        pending.push(child);
+                    }
+                }
+            });
+        }
+
+        // emptied after the sweep so nothing is dropped while others are borrowed
+        let mut garbage = Vec::new();
+        self.objects.clear();
+        self.bytes = 0;
+
+        for (object, marked) in objects.into_iter().zip(marked) {
+            if marked {
+                self.bytes += object.size();
+                self.objects.push(object.downgrade());
+            } else {
+                garbage.push(object);
+            }
+        }
+
+        for object in &garbage {
+            object.clear();
+        }
+        let freed = garbage.len();
+        drop(garbage);
+
+        self.stats.collections += 1;
+        self.stats.freed += freed as u64;
+        self.since_collection = 0;
+        self.survivors = self.objects.len();
+        freed
+    }
+}
+
+// what an entry of a map or set takes up
+const ENTRY_SIZE: usize = std::mem::size_of::<(Key, Value)>();
+
+// what an instance takes up, strings and other values fields point to are not counted
+fn object_size(fields: &[Value]) -> usize {
+    std::mem::size_of::<Instance>() + std::mem::size_of_val(fields)
+}
+
+/// The bytes of a string stored in a list, map or set, which count towards the size of
+/// the collection. Collections can be filled with strings far bigger than their slots.
+pub(crate) fn string_bytes(value: &Value) -> usize {
+    match value {
+        Value::Str(value) => value.len(),
+        _ => 0,
+    }
+}
+
+fn key_bytes(key: &Key) -> usize {
+    match key {
+        Key::Str(key) => key.len(),
+        _ => 0,
+    }
+}
+
+/// An object on the heap, held weakly so reference counting still frees it
+#[derive(Debug)]
+enum Tracked {
+    Instance(Weak<Instance>),
+    List(Weak<RefCell<Vec<Value>>>),
+    Map(Weak<RefCell<Map>>),
+    Set(Weak<RefCell<Set>>),
+}
+
+impl Tracked {
+    fn upgrade(&self) -> Option<GcObject> {
+        Some(match self {
+            Tracked::Instance(object) => GcObject::Instance(object.upgrade()?),
+            Tracked::List(object) => GcObject::List(object.upgrade()?),
+            Tracked::Map(object) => GcObject::Map(object.upgrade()?),
+            Tracked::Set(object) => GcObject::Set(object.upgrade()?),
+        })
+    }
+
+    fn is_live(&self) -> bool {
+        match self {
+            Tracked::Instance(object) => object.strong_count() > 0,
+            Tracked::List(object) => object.strong_count() > 0,
+            Tracked::Map(object) => object.strong_count() > 0,
+            Tracked::Set(object) => object.strong_count() > 0,
+        }
+    }
+}
+
+/// An object on the heap kept alive during a collection
+enum GcObject {
+    Instance(Rc<Instance>),
+    List(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<Map>>),
+    Set(Rc<RefCell<Set>>),
+}
+
+impl GcObject {
+    fn from_value(value: &Value) -> Option<Self> {
+        Some(match value {
+            Value::Instance(object) => GcObject::Instance(object.clone()),
+            Value::List(object) => GcObject::List(object.clone()),
+            Value::Map(object) => GcObject::Map(object.clone()),
+            Value::Set(object) => GcObject::Set(object.clone()),
+            _ => return None,
+        })
+    }
+
+    fn downgrade(&self) -> Tracked {
+        match self {
+            GcObject::Instance(object) => Tracked::Instance(Rc::downgrade(object)),
+            GcObject::List(object) => Tracked::List(Rc::downgrade(object)),
+            GcObject::Map(object) => Tracked::Map(Rc::downgrade(object)),
+            GcObject::Set(object) => Tracked::Set(Rc::downgrade(object)),
+        }
+    }
+
+    // identifies the object, the address its values point to
+    fn as_ptr(&self) -> *const () {
+        match self {
+            GcObject::Instance(object) => Rc::as_ptr(object).cast(),
+            GcObject::List(object) => Rc::as_ptr(object).cast(),
+            GcObject::Map(object) => Rc::as_ptr(object).cast(),
+            GcObject::Set(object) => Rc::as_ptr(object).cast(),
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            GcObject::Instance(object) => Rc::strong_count(object),
+            GcObject::List(object) => Rc::strong_count(object),
+            GcObject::Map(object) => Rc::strong_count(object),
+            GcObject::Set(object) => Rc::strong_count(object),
+        }
+    }
+
+    // the elements of collections are counted like the fields of instances
+    fn size(&self) -> usize {
+        match self {
+            GcObject::Instance(object) => object_size(&object.fields.borrow()),
+            GcObject::List(items) => {
+                let items = items.borrow();
+                std::mem::size_of::<RefCell<Vec<Value>>>()
+                    + std::mem::size_of_val(items.as_slice())
+                    + items.iter().map(string_bytes).sum::<usize>()
+            }
+            GcObject::Map(map) => {
+                let map = map.borrow();
+                let strings: usize = map
+                    .iter()
+                    .map(|(key, value)| key_bytes(key) + string_bytes(value))
+                    .sum();
+                std::mem::size_of::<RefCell<Map>>() + map.len() * ENTRY_SIZE + strings
+            }
+            GcObject::Set(set) => {
+                let set = set.borrow();
+                let strings: usize = set.iter().map(key_bytes).sum();
+                std::mem::size_of::<RefCell<Set>>() + set.len() * ENTRY_SIZE + strings
+            }
+        }
+    }
+
+    // calls `f` with the objects the values inside point to, keys of maps and sets are
+    // never objects
+    fn for_each_child(&self, mut f: impl FnMut(*const ())) {
+        let mut visit = |value: &Value| match value {
+            Value::Instance(child) => f(Rc::as_ptr(child).cast()),
+            Value::List(child) => f(Rc::as_ptr(child).cast()),
+            Value::Map(child) => f(Rc::as_ptr(child).cast()),
+            Value::Set(child) => f(Rc::as_ptr(child).cast()),
+            _ => {}
+        };
+
+        match self {
+            GcObject::Instance(object) => object.fields.borrow().iter().for_each(visit),
+            GcObject::List(items) => items.borrow().iter().for_each(visit),
+            GcObject::Map(map) => map.borrow().iter().for_each(|(_, value)| visit(value)),
+            GcObject::Set(_) => {}
+        }
+    }
+
+    // breaks the cycles through the object, what it held is dropped once the borrow ends
+    fn clear(&self) {
+        match self {
+            GcObject::Instance(object) => {
+                let fields = std::mem::take(&mut *object.fields.borrow_mut());
+                drop(fields);
+            }
+            GcObject::List(items) => {
+                let items = std::mem::take(&mut *items.borrow_mut());
+                drop(items);
+            }
+            GcObject::Map(map) => {
+                let map = std::mem::take(&mut *map.borrow_mut());
+                drop(map);
+            }
+            GcObject::Set(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::NodeId;
+
+    fn node_class() -> Rc<Class> {
+        Rc::new(Class {
+            id: NodeId(0),
+            name: "Node".into(),
+            fields: vec!["next".into()],
+            methods: HashMap::new(),
+            builtins: Vec::new(),
+            natives: HashMap::new(),
+        })
+    }
+
+    fn link(from: &Rc<Instance>, to: &Rc<Instance>) {
+        from.fields.borrow_mut()[0] = Value::Instance(to.clone());
+    }
+
+    #[test]
+    fn frees_unreachable_cycles() {
+        let mut heap = Heap::default();
+        let class = node_class();
+
+        let a = heap.alloc(class.clone(), vec![Value::Nil]).unwrap();
+        let b = heap.alloc(class.clone(), vec![Value::Nil]).unwrap();
+        link(&a, &b);
+        link(&b, &a);
+        let weak = Rc::downgrade(&a);
+
+        // held by a local, the cycle survives
+        assert_eq!(heap.collect(), 0);
+        assert_eq!(heap.stats().live, 2);
+
+        drop((a, b));
+        assert!(weak.upgrade().is_some());
+
+        assert_eq!(heap.collect(), 2);
+        assert!(weak.upgrade().is_none());
+        assert_eq!(
+            heap.stats(),
+            GcStats {
+                collections: 2,
+                allocations: 2,
+                freed: 2,
+                live: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn keeps_objects_reachable_from_roots() {
+        let mut heap = Heap::default();
+        let class = node_class();
+
+        let root = heap.alloc(class.clone(), vec![Value::Nil]).unwrap();
+        let child = heap.alloc(class.clone(), vec![Value::Nil]).unwrap();
+        link(&root, &child);
+        link(&child, &child);
+        drop(child);
+
+        assert_eq!(heap.collect(), 0);
+        assert_eq!(heap.stats().live, 2);
+    }
+
+    fn list(heap: &mut Heap) -> Rc<RefCell<Vec<Value>>> {
+        match heap.track(Value::list(Vec::new())) {
+            Some(Value::List(items)) => items,
+            _ => unreachable!("lists fit without a limit"),
+        }
+    }
+
+    #[test]
+    fn frees_cycles_through_collections() {
+        let mut heap = Heap::default();
+
+        let items = list(&mut heap);
+        items.borrow_mut().push(Value::List(items.clone()));
+        let weak_list = Rc::downgrade(&items);
+
+        // a map holding a node holding the map
+        let node = heap.alloc(node_class(), vec![Value::Nil]).unwrap();
+        let Some(Value::Map(map)) = heap.track(Value::map(Map::new())) else {
+            unreachable!("maps fit without a limit")
+        };
+        map.borrow_mut()
+            .insert(Key::Int(0), Value::Instance(node.clone()));
+        node.fields.borrow_mut()[0] = Value::Map(map.clone());
+        let weak_map = Rc::downgrade(&map);
+
+        let _set = heap.track(Value::set(Set::new())).unwrap();
+        assert_eq!(heap.collect(), 0);
+        assert_eq!(heap.stats().live, 4);
+
+        drop((items, node, map));
+        assert_eq!(heap.collect(), 3);
+        assert!(weak_list.upgrade().is_none());
+        assert!(weak_map.upgrade().is_none());
+        assert_eq!(heap.stats().live, 1);
+    }
+
+    #[test]
+    fn charges_growing_collections() {
+        let mut heap = Heap::default();
+        let items = list(&mut heap);
+        heap.set_limit(Some(heap.bytes + 2 * std::mem::size_of::<Value>()));
+
+        let list = Value::List(items.clone());
+        assert!(heap.reserve(&list, 2, 0));
+        items.borrow_mut().extend([Value::Nil, Value::Nil]);
+        assert!(!heap.reserve(&list, 1, 0));
+    }
+
+    #[test]
+    fn counts_strings_in_collections() {
+        let mut heap = Heap::default();
+        let empty = list(&mut heap);
+        let bytes = heap.bytes;
+
+        let text = Value::Str("a".repeat(1000).into());
+        let items = Value::list(vec![text.clone(), text]);
+        heap.track(items.clone()).unwrap();
+        let slots = 2 * std::mem::size_of::<Value>();
+        assert_eq!(heap.bytes, 2 * bytes + slots + 2000);
+
+        heap.set_limit(Some(heap.bytes + 500));
+        assert!(!heap.reserve(&Value::List(empty), 1, 1000));
+        assert!(heap.reserve(&items, 1, string_bytes(&Value::Str("b".into()))));
+    }
+
+    #[test]
+    fn collects_before_reaching_the_limit() {
+        let mut heap = Heap::default();
+        let class = node_class();
+        heap.set_limit(Some(3 * object_size(&[Value::Nil])));
+
+        let kept: Vec<Rc<Instance>> = (0..2)
+            .map(|_| heap.alloc(class.clone(), vec![Value::Nil]).unwrap())
+            .collect();
+
+        // the cycles are freed to make room
+        for _ in 0..5 {
+            let node = heap.alloc(class.clone(), vec![Value::Nil]).unwrap();
+            link(&node, &node);
+        }
+
+        let _last = heap.alloc(class.clone(), vec![Value::Nil]).unwrap();
+        assert!(heap.alloc(class.clone(), vec![Value::Nil]).is_none());
+        assert_eq!(kept.len(), 2);
+    }
+
+    #[test]
+    fn collects_on_threshold_and_in_stress_mode() {
+        let class = node_class();
+        let cycle = |heap: &mut Heap| {
+            let node = heap.alloc(class.clone(), vec![Value::Nil]).unwrap();
+            link(&node, &node);
+        };
+
+        let mut heap = Heap::new(GcConfig {
+            threshold: 3,
+            stress: false,
+        });
+        for _ in 0..7 {
+            cycle(&mut heap);
+        }
+        assert_eq!(heap.stats().collections, 2);
+        assert_eq!(heap.stats().freed, 6);
+
+        let mut heap = Heap::new(GcConfig {
+            threshold: 3,
+            stress: true,
+        });
+        for _ in 0..7 {
+            cycle(&mut heap);
+        }
+        assert_eq!(heap.stats().collections, 7);
+        assert_eq!(heap.stats().live, 1);
+    }
+}
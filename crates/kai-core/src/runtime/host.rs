@@ -0,0 +1,625 @@
+//! Calling rust from kai and kai from rust
+//!
+//! Closures registered on an engine become native functions, their arguments are
+//! converted with [`FromValue`] and their result with [`IntoValue`]. Rust structs
+//! implementing [`HostClass`] are passed to kai as instances of a class with the
+//! struct's fields and the methods it registers.
+
+use std::any::TypeId;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+use ecow::{eco_format, EcoString};
+
+use super::stdlib;
+use super::value::{Class, Instance, NativeContext, NativeFn, NativeMethod, Value};
+use crate::ast::NodeId;
+use crate::typeck::{FnSig, Type};
+
+/// A rust value kai values can be converted to
+pub trait FromValue: Sized {
+    /// The type the checker expects kai code to pass
+    fn ty() -> Type;
+
+    /// Named when a value of another type is passed
+    fn type_name() -> EcoString {
+        eco_format!("{}", Self::ty())
+    }
+
+    /// `None` if the value has another type
+    fn from_value(value: Value) -> Option<Self>;
+}
+
+/// A rust value which can be passed to kai
+pub trait IntoValue {
+    /// The type the checker gives the value
+    fn ty() -> Type;
+
+    fn into_value(self) -> Value;
+}
+
+/// Converts `value`, the error names the type expected and the one found
+pub(crate) fn convert<T: FromValue>(value: Value) -> Result<T, String> {
+    let (expected, found) = (T::type_name(), value.type_name());
+
+    match T::from_value(value.clone()) {
+        Some(value) => Ok(value),
+        // the right kai type holding something the rust type can't, like `300` for a `u8`
+        None if found == expected => Err(format!(
+            "`{value}` does not fit in `{}`",
+            std::any::type_name::<T>()
+        )),
+        None => Err(format!("expected `{expected}`, found `{found}`")),
+    }
+}
+
+impl FromValue for Value {
+    fn ty() -> Type {
+        Type::Unknown
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        Some(value)
+    }
+}
+
+impl IntoValue for Value {
+    fn ty() -> Type {
+        Type::Unknown
+    }
+
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+impl FromValue for () {
+    fn ty() -> Type {
+        Type::Nil
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        matches!(value, Value::Nil).then_some(())
+    }
+}
+
+impl IntoValue for () {
+    fn ty() -> Type {
+        Type::Nil
+    }
+
+    fn into_value(self) -> Value {
+        Value::Nil
+    }
+}
+
+impl FromValue for bool {
+    fn ty() -> Type {
+        Type::Bool
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl IntoValue for bool {
+    fn ty() -> Type {
+        Type::Bool
+    }
+
+    fn into_value(self) -> Value {
+        Value::Bool(self)
+    }
+}
+
+// every integer fitting in an `int` without loss
+macro_rules! impl_int {
+    ($($ty:ty),*) => {
+        $(
+            impl FromValue for $ty {
+                fn ty() -> Type {
+                    Type::Int
+                }
+
+                fn from_value(value: Value) -> Option<Self> {
+                    match value {
+                        Value::Int(value) => <$ty>::try_from(value).ok(),
+                        _ => None,
+                    }
+                }
+            }
+
+            impl IntoValue for $ty {
+                fn ty() -> Type {
+                    Type::Int
+                }
+
+                fn into_value(self) -> Value {
+                    Value::Int(i64::from(self))
+                }
+            }
+        )*
+    };
+}
+
+impl_int!(i8, i16, i32, i64, u8, u16, u32);
+
+impl FromValue for f64 {
+    fn ty() -> Type {
+        Type::Float
+    }
+
+    // ints are accepted too, like the arithmetic operators do
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Float(value) => Some(value),
+            Value::Int(value) => Some(value as f64),
+            _ => None,
+        }
+    }
+}
+
+impl IntoValue for f64 {
+    fn ty() -> Type {
+        Type::Float
+    }
+
+    fn into_value(self) -> Value {
+        Value::Float(self)
+    }
+}
+
+impl FromValue for EcoString {
+    fn ty() -> Type {
+        Type::String
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Str(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl IntoValue for EcoString {
+    fn ty() -> Type {
+        Type::String
+    }
+
+    fn into_value(self) -> Value {
+        Value::Str(self)
+    }
+}
+
+impl FromValue for String {
+    fn ty() -> Type {
+        Type::String
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        EcoString::from_value(value).map(Into::into)
+    }
+}
+
+impl IntoValue for String {
+    fn ty() -> Type {
+        Type::String
+    }
+
+    fn into_value(self) -> Value {
+        Value::Str(self.into())
+    }
+}
+
+impl IntoValue for &str {
+    fn ty() -> Type {
+        Type::String
+    }
+
+    fn into_value(self) -> Value {
+        Value::Str(self.into())
+    }
+}
+
+/// `nil` is `None`
+impl<T: FromValue> FromValue for Option<T> {
+    // the checker has no optional types
+    fn ty() -> Type {
+        Type::Unknown
+    }
+
+    fn type_name() -> EcoString {
+        T::type_name()
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Nil => Some(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn ty() -> Type {
+        Type::Unknown
+    }
+
+    fn into_value(self) -> Value {
+        self.map_or(Value::Nil, IntoValue::into_value)
+    }
+}
+
+/// Errors become error values, kai code handles them with `?`
+impl<T: IntoValue, E: Display> IntoValue for Result<T, E> {
+    fn ty() -> Type {
+        Type::Result(Box::new(T::ty()))
+    }
+
+    fn into_value(self) -> Value {
+        match self {
+            Ok(value) => value.into_value(),
+            Err(err) => Value::error(err.to_string()),
+        }
+    }
+}
+
+/// A closure which can be called from kai, `Args` are its parameter types
+pub trait IntoNative<Args> {
+    /// The signature the checker gives the function
+    fn ty() -> Type;
+
+    fn into_native(self, name: &str) -> NativeFn;
+}
+
+macro_rules! impl_into_native {
+    ($count:literal $(, $arg:ident)*) => {
+        impl<F, R, $($arg),*> IntoNative<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + 'static,
+            R: IntoValue,
+            $($arg: FromValue,)*
+        {
+            fn ty() -> Type {
+                Type::Fn(FnSig {
+                    params: vec![$(<$arg as FromValue>::ty()),*],
+                    ret: Box::new(<R as IntoValue>::ty()),
+                })
+            }
+
+            #[allow(non_snake_case)]
+            fn into_native(self, name: &str) -> NativeFn {
+                let fn_name = EcoString::from(name);
+
+                let func = move |cx: &mut dyn NativeContext, args: Vec<Value>| {
+                    let [$($arg),*] = stdlib::args::<$count>(cx, &fn_name, args)?;
+                    $(let $arg = convert::<$arg>($arg).map_err(|message| cx.error(message))?;)*
+                    Ok(self($($arg),*).into_value())
+                };
+
+                NativeFn {
+                    name: name.into(),
+                    func: Rc::new(func),
+                    capability: None,
+                }
+            }
+        }
+    };
+}
+
+impl_into_native!(0);
+impl_into_native!(1, A);
+impl_into_native!(2, A, B);
+impl_into_native!(3, A, B, C);
+impl_into_native!(4, A, B, C, D);
+impl_into_native!(5, A, B, C, D, E);
+impl_into_native!(6, A, B, C, D, E, G);
+
+/// Arguments for `Engine::call`, a tuple of convertible values or the values themselves
+pub trait IntoArgs {
+    fn into_args(self) -> Vec<Value>;
+}
+
+impl IntoArgs for Vec<Value> {
+    fn into_args(self) -> Vec<Value> {
+        self
+    }
+}
+
+macro_rules! impl_into_args {
+    ($($arg:ident),*) => {
+        impl<$($arg: IntoValue),*> IntoArgs for ($($arg,)*) {
+            #[allow(non_snake_case)]
+            fn into_args(self) -> Vec<Value> {
+                let ($($arg,)*) = self;
+                vec![$($arg.into_value()),*]
+            }
+        }
+    };
+}
+
+impl_into_args!();
+impl_into_args!(A);
+impl_into_args!(A, B);
+impl_into_args!(A, B, C);
+impl_into_args!(A, B, C, D);
+impl_into_args!(A, B, C, D, E);
+impl_into_args!(A, B, C, D, E, G);
+
+/// A rust struct passed to kai as an instance of a class
+///
+/// Values are copied field by field, changes kai makes to an instance are only seen
+/// by rust once it is converted back. Instances made from rust values are not traced
+/// by the collector, reference counting alone frees them.
+pub trait HostClass: Sized + 'static {
+    const NAME: &'static str;
+    const FIELDS: &'static [&'static str];
+
+    /// The value of every field in the order of `FIELDS`
+    fn into_fields(self) -> Vec<Value>;
+
+    /// `None` if a field has the wrong type
+    fn from_fields(fields: Vec<Value>) -> Option<Self>;
+
+    fn methods(_methods: &mut Methods<Self>) {}
+}
+
+/// The methods of a [`HostClass`]
+pub struct Methods<T> {
+    natives: HashMap<EcoString, NativeMethod>,
+    class: PhantomData<T>,
+}
+
+impl<T: HostClass> Methods<T> {
+    /// Adds a method, `f` gets the instance it is called on as its first argument
+    pub fn add<Args>(&mut self, name: &str, f: impl IntoNative<Args>) -> &mut Self {
+        self.insert(name, f.into_native(name), true)
+    }
+
+    /// Adds a method called on the class itself, eg. `Point.origin()`
+    pub fn add_static<Args>(&mut self, name: &str, f: impl IntoNative<Args>) -> &mut Self {
+        self.insert(name, f.into_native(name), false)
+    }
+
+    fn insert(&mut self, name: &str, native: NativeFn, has_self: bool) -> &mut Self {
+        let method = NativeMethod {
+            native: Rc::new(native),
+            has_self,
+        };
+        self.natives.insert(name.into(), method);
+        self
+    }
+}
+
+thread_local! {
+    // one class per struct so instances keep the same class on every trip through rust
+    static CLASSES: RefCell<HashMap<TypeId, Rc<Class>>> = RefCell::default();
+}
+
+fn host_class<T: HostClass>() -> Rc<Class> {
+    let id = TypeId::of::<T>();
+
+    if let Some(class) = CLASSES.with(|classes| classes.borrow().get(&id).cloned()) {
+        return class;
+    }
+
+    let mut methods = Methods {
+        natives: HashMap::new(),
+        class: PhantomData,
+    };
+    T::methods(&mut methods);
+
+    let class = Rc::new(Class {
+        id: NodeId::DUMMY,
+        name: T::NAME.into(),
+        fields: T::FIELDS.iter().map(|&field| field.into()).collect(),
+        methods: HashMap::new(),
+        builtins: Vec::new(),
+        natives: methods.natives,
+    });
+
+    CLASSES.with(|classes| classes.borrow_mut().insert(id, class.clone()));
+    class
+}
+
+// host classes are not known to the checker
+impl<T: HostClass> FromValue for T {
+    fn ty() -> Type {
+        Type::Unknown
+    }
+
+    fn type_name() -> EcoString {
+        T::NAME.into()
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Instance(instance) if Rc::ptr_eq(&instance.class, &host_class::<T>()) => {
+                T::from_fields(instance.fields.borrow().clone())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<T: HostClass> IntoValue for T {
+    fn ty() -> Type {
+        Type::Unknown
+    }
+
+    fn into_value(self) -> Value {
+        let fields = self.into_fields();
+        debug_assert_eq!(
+            fields.len(),
+            T::FIELDS.len(),
+            "{} has wrong fields",
+            T::NAME
+        );
+
+        Value::Instance(Rc::new(Instance {
+            class: host_class::<T>(),
+            fields: RefCell::new(fields),
+        }))
+    }
+}
+
+/// A module of functions, constants and classes registered by the embedder, kai code
+/// imports it like a standard library module, eg. `using myapp.db;`
+pub struct NativeModule {
+    path: EcoString,
+    members: Vec<(EcoString, Value, Type)>,
+}
+
+impl NativeModule {
+    /// A module with a dotted path, paths under `kai` are left to the standard library
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.into(),
+            members: Vec::new(),
+        }
+    }
+
+    pub fn function<Args, F: IntoNative<Args>>(mut self, name: &str, f: F) -> Self {
+        let native = Value::Native(Rc::new(f.into_native(name)));
+        self.members.push((name.into(), native, F::ty()));
+        self
+    }
+
+    pub fn constant<V: IntoValue>(mut self, name: &str, value: V) -> Self {
+        self.members
+            .push((name.into(), value.into_value(), V::ty()));
+        self
+    }
+
+    /// Adds the class of `T` under its name, for its static methods
+    pub fn class<T: HostClass>(mut self) -> Self {
+        let class = Value::Class(host_class::<T>());
+        self.members.push((T::NAME.into(), class, Type::Unknown));
+        self
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+/// Functions, values and modules registered by the embedder, looked up before the
+/// standard library
+#[derive(Default)]
+pub(crate) struct Host {
+    // full path -> value, modules and every module above them are `Value::Module`
+    values: HashMap<EcoString, Value>,
+    // full path -> type of every value which is not a module
+    types: HashMap<EcoString, Type>,
+    // returned by `kai.process.args`
+    args: Vec<EcoString>,
+}
+
+impl Host {
+    pub fn define(&mut self, path: &str, value: Value, ty: Type) {
+        self.values.insert(path.into(), value);
+        self.types.insert(path.into(), ty);
+    }
+
+    pub fn add_module(&mut self, module: NativeModule) {
+        let mut prefix = EcoString::new();
+
+        for segment in module.path.split('.') {
+            if !prefix.is_empty() {
+                prefix.push('.');
+            }
+            prefix.push_str(segment);
+            self.values
+                .insert(prefix.clone(), Value::Module(prefix.clone()));
+        }
+
+        for (name, value, ty) in module.members {
+            self.define(&eco_format!("{}.{name}", module.path), value, ty);
+        }
+    }
+
+    pub fn set_args(&mut self, args: Vec<EcoString>) {
+        self.args = args;
+    }
+
+    pub fn args(&self) -> &[EcoString] {
+        &self.args
+    }
+
+    /// Every registered path, modules included
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.values.keys().map(EcoString::as_str)
+    }
+
+    /// The type of every registered value
+    pub fn types(&self) -> impl Iterator<Item = (&str, &Type)> {
+        self.types.iter().map(|(path, ty)| (path.as_str(), ty))
+    }
+
+    /// Finds a registered value, a standard library module or a native function by its
+    /// full path
+    pub fn lookup(&self, path: &str) -> Option<Value> {
+        match self.values.get(path) {
+            Some(value) => Some(value.clone()),
+            None => stdlib::lookup(path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Point {
+        x: i64,
+        y: i64,
+    }
+
+    impl HostClass for Point {
+        const NAME: &'static str = "Point";
+        const FIELDS: &'static [&'static str] = &["x", "y"];
+
+        fn into_fields(self) -> Vec<Value> {
+            vec![self.x.into_value(), self.y.into_value()]
+        }
+
+        fn from_fields(fields: Vec<Value>) -> Option<Self> {
+            let [x, y] = fields.try_into().ok()?;
+            Some(Point {
+                x: i64::from_value(x)?,
+                y: i64::from_value(y)?,
+            })
+        }
+    }
+
+    #[test]
+    fn converts_values() {
+        assert_eq!(convert::<i64>(Value::Int(3)), Ok(3));
+        assert_eq!(convert::<f64>(Value::Int(3)), Ok(3.0));
+        assert_eq!(convert::<Option<String>>(Value::Nil), Ok(None));
+        assert_eq!(
+            convert::<u8>(Value::Int(300)),
+            Err("`300` does not fit in `u8`".into())
+        );
+        assert_eq!(
+            convert::<bool>(Value::Str("yes".into())),
+            Err("expected `bool`, found `string`".into())
+        );
+
+        let err: Result<i64, &str> = Err("gone");
+        assert_eq!(err.into_value(), Value::error("gone"));
+
+        let point = Point { x: 1, y: 2 }.into_value();
+        assert_eq!(point.to_string(), "Point { x: 1, y: 2 }");
+        assert_eq!(convert::<Point>(point), Ok(Point { x: 1, y: 2 }));
+    }
+}
@@ -0,0 +1,1054 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::rc::Rc;
+
+use ecow::EcoString;
+
+use super::coroutine::{self, Awaited, Coroutine, CoroutineState, Resume, Resumed};
+use super::error::RuntimeError;
+use super::executor::{self, Executor};
+use super::gc::Heap;
+use super::host::Host;
+use super::limits::{self, Budget, Capabilities, Limits};
+use super::load;
+use super::ops::{self, Iter};
+use super::value::{Class, NativeContext, Value};
+use crate::ast::span::Span;
+use crate::ast::*;
+use crate::resolve::{Res, Resolutions};
+
+/// Why evaluation stopped before producing a value
+enum Unwind {
+    Error(RuntimeError),
+    Return(Value),
+    Break(Value),
+    Continue,
+    // a coroutine suspending, the expressions it leaves push their state to `saved`
+    Yield(Value),
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(value: RuntimeError) -> Self {
+        Unwind::Error(value)
+    }
+}
+
+type Eval = Result<Value, Unwind>;
+
+/// A coroutine call suspended on the tree walker
+pub(crate) struct Suspended {
+    id: NodeId,
+    locals: HashMap<NodeId, Value>,
+    saved: Vec<Saved>,
+}
+
+/// How far an expression got when its coroutine suspended
+enum Saved {
+    // the operands evaluated so far
+    Operands(Vec<Value>),
+    // the index of the statement in a block
+    Stmt(usize),
+    // a `for` loop suspended in its body
+    Iter(Iter),
+    Yield,
+    // what the `await` waits on
+    Await(Value),
+}
+
+/// A tree walking interpreter over a resolved file
+pub(crate) struct Interp<'a> {
+    res: &'a Resolutions,
+    fns: HashMap<NodeId, &'a ItemFn>,
+    classes: HashMap<NodeId, Rc<Class>>,
+    // locals of every active call, keyed by the id of their pattern
+    frames: Vec<HashMap<NodeId, Value>>,
+    // name of every active call and where it was called from, for stack traces
+    calls: Vec<(&'a str, Span)>,
+    stdout: &'a mut dyn Write,
+    heap: &'a mut Heap,
+    limits: &'a Limits,
+    host: &'a Host,
+    budget: Budget,
+    // span of the native call being run, errors raised by natives point here
+    native_span: Span,
+    executor: &'a mut Executor,
+    // state of the expressions the running coroutine suspended in, innermost first
+    saved: Vec<Saved>,
+    // set while a resumed coroutine finds its way back to where it suspended, every
+    // expression on the way takes its state from `saved`
+    resuming: bool,
+    // what the `yield` or `await` being resumed evaluates to
+    input: Value,
+}
+
+impl<'a> Interp<'a> {
+    pub fn new(
+        file: &'a File,
+        res: &'a Resolutions,
+        stdout: &'a mut dyn Write,
+        heap: &'a mut Heap,
+        limits: &'a Limits,
+        host: &'a Host,
+        executor: &'a mut Executor,
+    ) -> Self {
+        let loaded = load::load(file, res);
+
+        Self {
+            res,
+            fns: loaded.fns,
+            classes: loaded
+                .classes
+                .into_iter()
+                .map(|(id, class)| (id, Rc::new(class)))
+                .collect(),
+            frames: Vec::new(),
+            calls: Vec::new(),
+            stdout,
+            heap,
+            limits,
+            host,
+            budget: Budget::new(limits),
+            native_span: Span::default(),
+            executor,
+            saved: Vec::new(),
+            resuming: false,
+            input: Value::Nil,
+        }
+    }
+
+    /// Calls the top level `main` function
+    pub fn run_main(&mut self, file: &File) -> Result<Value, RuntimeError> {
+        match load::find_main(file) {
+            Some(item) => {
+                let value = self.run_fn(item, Vec::new())?;
+                self.run_tasks(item.ident.span)?;
+                Ok(value)
+            }
+            None => Err(RuntimeError::new("no `main` function found", file.span)),
+        }
+    }
+
+    /// Calls a fn item from outside of kai
+    pub fn run_fn(&mut self, item: &Item, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        self.budget = Budget::new(self.limits);
+        let value = self.call_fn(item.id, None, args, item.ident.span)?;
+        let value = executor::run_async(self, value, item.ident.span)?;
+        ops::uncaught_error(value, &item.ident.name, item.ident.span)
+    }
+
+    /// Calls an `async fn` item as a new task of the executor
+    pub fn spawn_fn(&mut self, item: &Item, args: Vec<Value>) -> Result<(), RuntimeError> {
+        self.budget = Budget::new(self.limits);
+        match self.call_fn(item.id, None, args, item.ident.span)? {
+            Value::Coroutine(coroutine) if coroutine.kind == CoroutineKind::Async => {
+                self.executor.spawn(coroutine);
+                Ok(())
+            }
+            _ => Err(RuntimeError::new(
+                format!("`{}` is not an async fn", item.ident.name),
+                item.ident.span,
+            )),
+        }
+    }
+
+    /// Calls a `@test` fn, the tasks it spawned have to return too
+    pub fn run_test(&mut self, item: &Item) -> Result<Value, RuntimeError> {
+        let result = self.run_fn(item, Vec::new()).and_then(|value| {
+            self.run_tasks(item.ident.span)?;
+            Ok(value)
+        });
+        // the tasks of a failed test don't run on in the next one
+        self.executor.clear();
+        result
+    }
+
+    /// Runs the tasks left by a call until every one returned
+    pub fn run_tasks(&mut self, span: Span) -> Result<(), RuntimeError> {
+        executor::run_all(self, span)
+    }
+
+    /// Runs the tasks until they all wait, see `Engine::poll`
+    pub fn poll_tasks(&mut self, span: Span) -> Result<executor::TaskStatus, RuntimeError> {
+        self.budget = Budget::new(self.limits);
+        executor::poll(self, span)
+    }
+
+    //BEGIN calls
+
+    fn call_fn(
+        &mut self,
+        id: NodeId,
+        this: Option<Value>,
+        args: Vec<Value>,
+        span: Span,
+    ) -> Result<Value, RuntimeError> {
+        let item_fn = self.fns[&id];
+        let name = &item_fn.ident.name;
+
+        let Some(body) = item_fn.body.as_deref() else {
+            return Err(RuntimeError::new(format!("`{name}` has no body"), span));
+        };
+
+        let params: Vec<&Param> = item_fn.inputs.iter().filter(|p| !p.is_self()).collect();
+
+        if params.len() != args.len() {
+            return Err(ops::wrong_arg_count(name, params.len(), args.len(), span));
+        }
+
+        let mut frame = HashMap::new();
+
+        if let (Some(param), Some(this)) = (item_fn.inputs.first(), this) {
+            if param.is_self() {
+                frame.insert(param.pat.id, this);
+            }
+        }
+
+        for (param, arg) in params.iter().zip(args) {
+            if let PatternKind::Ident { .. } = param.pat.kind {
+                frame.insert(param.pat.id, arg);
+            }
+        }
+
+        // the body only runs once the coroutine is resumed
+        if let Some(kind) = item_fn.coroutine() {
+            let state = CoroutineState::Ast(Suspended {
+                id,
+                locals: frame,
+                saved: Vec::new(),
+            });
+            let coroutine = Coroutine::new(name.as_str(), kind, state);
+            return Ok(Value::Coroutine(Rc::new(coroutine)));
+        }
+
+        if self.calls.len() == self.limits.call_depth {
+            return Err(limits::stack_overflow(self.limits.call_depth, span));
+        }
+
+        self.frames.push(frame);
+        self.calls.push((name, span));
+
+        let result = limits::grow(|| self.eval_block(body));
+        let result = self.returned(result, span);
+
+        self.calls.pop();
+        self.frames.pop();
+        result
+    }
+
+    // what the body of the innermost call evaluated to
+    fn returned(&self, result: Eval, span: Span) -> Result<Value, RuntimeError> {
+        let result = match result {
+            Ok(value) | Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Error(err)) => Err(err),
+            Err(Unwind::Break(_) | Unwind::Continue) => Err(RuntimeError::new(
+                "`break` or `continue` outside of a loop",
+                span,
+            )),
+            Err(Unwind::Yield(_)) => unreachable!("only coroutines suspend"),
+        };
+
+        // the innermost call records the whole stack, outer ones pass it on
+        result.map_err(|mut err| {
+            if err.trace.is_empty() {
+                err.trace = ops::trace(self.calls.iter().copied(), err.span);
+            }
+            err
+        })
+    }
+
+    fn call_value(
+        &mut self,
+        callee: Value,
+        args: Vec<Value>,
+        span: Span,
+    ) -> Result<Value, RuntimeError> {
+        match callee {
+            Value::Fn(id) => self.call_fn(id, None, args, span),
+            Value::Native(native) => {
+                limits::check_capability(self.limits.capabilities, &native, span)?;
+                let prev = std::mem::replace(&mut self.native_span, span);
+                // strings natives return are held against the heap limit like operator results
+                let result = (native.func)(self, args)
+                    .and_then(|value| limits::check_string(self.heap, value, span));
+                self.native_span = prev;
+                result
+            }
+            callee => Err(RuntimeError::new(
+                format!("`{}` is not callable", callee.type_name()),
+                span,
+            )),
+        }
+    }
+
+    /// Calls a method, looking it up on the class of the receiver at runtime
+    fn call_method(
+        &mut self,
+        receiver: Value,
+        name: &str,
+        args: Vec<Value>,
+        span: Span,
+    ) -> Result<Value, RuntimeError> {
+        match &receiver {
+            Value::Instance(instance) => {
+                let class = &instance.class;
+                let Some(&id) = class.methods.get(name) else {
+                    let (native, args) = ops::native_method(&receiver, name, args, span)?;
+                    return self.call_value(native, args, span);
+                };
+
+                if !self.fns[&id].has_self() {
+                    return Err(RuntimeError::new(
+                        format!(
+                            "`{name}` is a static method, call it as `{}.{name}(..)`",
+                            class.name
+                        ),
+                        span,
+                    ));
+                }
+
+                self.call_fn(id, Some(receiver.clone()), args, span)
+            }
+            Value::Class(class) => {
+                let Some(&id) = class.methods.get(name) else {
+                    let (native, args) = ops::native_method(&receiver, name, args, span)?;
+                    return self.call_value(native, args, span);
+                };
+
+                if self.fns[&id].has_self() {
+                    return Err(RuntimeError::new(
+                        format!(
+                            "`{name}` takes `self`, call it on an instance of `{}`",
+                            class.name
+                        ),
+                        span,
+                    ));
+                }
+
+                self.call_fn(id, None, args, span)
+            }
+            Value::Module(path) => {
+                let member = ops::module_member(self.host, path, name, span)?;
+                self.call_value(member, args, span)
+            }
+            Value::Handle(_)
+            | Value::Str(_)
+            | Value::List(_)
+            | Value::Map(_)
+            | Value::Set(_)
+            | Value::Range(..)
+            | Value::Error(_) => {
+                let (native, args) = ops::native_method(&receiver, name, args, span)?;
+                self.call_value(native, args, span)
+            }
+            Value::Coroutine(coroutine) => {
+                coroutine::call_method(self, coroutine, name, args, span)
+            }
+            receiver => Err(ops::no_method(name, receiver, span)),
+        }
+    }
+
+    //END calls
+
+    //BEGIN evaluation
+
+    fn eval_block(&mut self, block: &Block) -> Eval {
+        let start = match self.restore_saved() {
+            Some(Saved::Stmt(idx)) => idx,
+            None => 0,
+            Some(_) => unreachable!("a block saves the statement it suspended in"),
+        };
+        let mut value = Value::Nil;
+
+        for (i, stmt) in block.stmts.iter().enumerate().skip(start) {
+            let is_last = i + 1 == block.stmts.len();
+
+            match self.eval_stmt(stmt) {
+                Ok(stmt_value) => {
+                    if is_last {
+                        value = stmt_value;
+                    }
+                }
+                Err(Unwind::Yield(yielded)) => {
+                    self.saved.push(Saved::Stmt(i));
+                    return Err(Unwind::Yield(yielded));
+                }
+                Err(unwind) => return Err(unwind),
+            }
+        }
+
+        Ok(value)
+    }
+
+    // only expression statements have a value
+    fn eval_stmt(&mut self, stmt: &Stmt) -> Eval {
+        match &stmt.kind {
+            StmtKind::Let(local) => {
+                if let Some(init) = &local.init {
+                    let init = self.eval_expr(init)?;
+                    self.bind_pattern(&local.pat, init);
+                }
+                Ok(Value::Nil)
+            }
+            StmtKind::Item(_) | StmtKind::Empty => Ok(Value::Nil),
+            StmtKind::Semi(expr) => {
+                self.eval_expr(expr)?;
+                Ok(Value::Nil)
+            }
+            StmtKind::Expr(expr) => self.eval_expr(expr),
+        }
+    }
+
+    fn bind_pattern(&mut self, pat: &Pattern, value: Value) {
+        if let PatternKind::Ident { .. } = pat.kind {
+            let frame = self.frames.last_mut().expect("frame should exist");
+            frame.insert(pat.id, value);
+        }
+    }
+
+    // Expressions with operands keep the values evaluated so far in a `Vec` and evaluate
+    // each operand through `operand`. When a coroutine suspends in one of them the `Vec`
+    // is saved, resuming restores it and only the operand it suspended in runs again.
+    fn eval_expr(&mut self, expr: &Expr) -> Eval {
+        self.budget.tick(expr.span)?;
+
+        let value = match &expr.kind {
+            ExprKind::Lit(lit) => match &lit.kind {
+                LitKind::Int(value) => Value::Int(*value),
+                LitKind::Float(value) => Value::Float(*value),
+                LitKind::Str(value) => Value::Str(value.clone()),
+                LitKind::Bool(value) => Value::Bool(*value),
+                LitKind::Nil => Value::Nil,
+            },
+            ExprKind::Path(path) => self.eval_path(expr.id, path)?,
+            ExprKind::Unary(op, operand) => {
+                let mut values = self.restore();
+                let value = self.operand(&mut values, 0, operand)?;
+                ops::unary(*op, value, expr.span)?
+            }
+            ExprKind::Binary(op @ (BinOp::And | BinOp::Or), lhs, rhs) => {
+                let mut values = self.restore();
+                let lhs = expect_bool(self.operand(&mut values, 0, lhs)?, lhs.span)?;
+
+                // the right hand side only runs when it decides the result
+                match (op, lhs) {
+                    (BinOp::And, false) => Value::Bool(false),
+                    (BinOp::Or, true) => Value::Bool(true),
+                    _ => Value::Bool(expect_bool(self.operand(&mut values, 1, rhs)?, rhs.span)?),
+                }
+            }
+            ExprKind::Binary(op, lhs, rhs) => {
+                let mut values = self.restore();
+                let lhs = self.operand(&mut values, 0, lhs)?;
+                let rhs = self.operand(&mut values, 1, rhs)?;
+                self.binary(*op, lhs, rhs, expr.span)?
+            }
+            ExprKind::Assign(lhs, rhs) => {
+                let mut values = self.restore();
+                let value = self.operand(&mut values, 0, rhs)?;
+                self.assign(lhs, value, &mut values, 1)?;
+                Value::Nil
+            }
+            ExprKind::AssignOp(op, lhs, rhs) => {
+                let mut values = self.restore();
+
+                match &lhs.kind {
+                    // the receiver is evaluated once, `next().count += 1` calls `next` once
+                    ExprKind::Field(base, ident) => {
+                        let base = self.operand(&mut values, 0, base)?;
+                        if values.len() == 1 {
+                            let current =
+                                ops::get_field(self.host, &base, &ident.name, ident.span)?;
+                            values.push(current);
+                        }
+                        let current = values[1].clone();
+                        let rhs = self.operand(&mut values, 2, rhs)?;
+                        let value = self.binary(*op, current, rhs, expr.span)?;
+                        ops::set_field(&base, &ident.name, value, ident.span)?;
+                    }
+                    // `xs[next()] += 1` calls `next` once too
+                    ExprKind::Index(base, index) => {
+                        let base = self.operand(&mut values, 0, base)?;
+                        let index = self.operand(&mut values, 1, index)?;
+                        if values.len() == 2 {
+                            values.push(ops::index(self.heap, &base, &index, lhs.span)?);
+                        }
+                        let current = values[2].clone();
+                        let rhs = self.operand(&mut values, 3, rhs)?;
+                        let value = self.binary(*op, current, rhs, expr.span)?;
+                        ops::set_index(self.heap, &base, &index, value, lhs.span)?;
+                    }
+                    _ => {
+                        let current = self.operand(&mut values, 0, lhs)?;
+                        let rhs = self.operand(&mut values, 1, rhs)?;
+                        let value = self.binary(*op, current, rhs, expr.span)?;
+                        self.assign(lhs, value, &mut values, 2)?;
+                    }
+                }
+                Value::Nil
+            }
+            ExprKind::Call(callee, args) => {
+                let mut values = self.restore();
+                self.operands(&mut values, std::iter::once(&**callee).chain(args))?;
+                let callee = values.remove(0);
+                self.call_value(callee, values, expr.span)?
+            }
+            ExprKind::MethodCall(call) => {
+                let mut values = self.restore();
+                self.operands(
+                    &mut values,
+                    std::iter::once(&call.receiver).chain(&call.args),
+                )?;
+                let receiver = values.remove(0);
+                self.call_method(receiver, &call.method.name, values, call.span)?
+            }
+            ExprKind::Field(base, ident) => {
+                let mut values = self.restore();
+                let base = self.operand(&mut values, 0, base)?;
+                ops::get_field(self.host, &base, &ident.name, ident.span)?
+            }
+            ExprKind::ClassInit(init) => self.eval_class_init(expr, init)?,
+            ExprKind::List(items) => {
+                let mut values = self.restore();
+                self.operands(&mut values, items)?;
+                limits::track(self.heap, Value::list(values), expr.span)?
+            }
+            ExprKind::Map(entries) => {
+                let mut values = self.restore();
+                let exprs = entries.iter().flat_map(|(key, value)| [key, value]);
+                self.operands(&mut values, exprs)?;
+                ops::map(self.heap, values, expr.span)?
+            }
+            ExprKind::Set(items) => {
+                let mut values = self.restore();
+                self.operands(&mut values, items)?;
+                ops::set(self.heap, values, expr.span)?
+            }
+            ExprKind::Index(base, index) => {
+                let mut values = self.restore();
+                let base = self.operand(&mut values, 0, base)?;
+                let index = self.operand(&mut values, 1, index)?;
+                ops::index(self.heap, &base, &index, expr.span)?
+            }
+            ExprKind::Range(start, end, limits) => {
+                let mut values = self.restore();
+                let mut bounds = [0, i64::MAX];
+                let mut idx = 0;
+
+                for (bound, slot) in [start, end].into_iter().zip(&mut bounds) {
+                    if let Some(bound) = bound {
+                        *slot = expect_int(self.operand(&mut values, idx, bound)?, bound.span)?;
+                        idx += 1;
+                    }
+                }
+
+                ops::range(bounds[0], bounds[1], *limits == RangeLimits::Closed)
+            }
+            ExprKind::If(cond, then, otherwise) => {
+                let mut values = self.restore();
+                let cond = expect_bool(self.operand(&mut values, 0, cond)?, cond.span)?;
+
+                let result = if cond {
+                    self.eval_block(then)
+                } else if let Some(otherwise) = otherwise {
+                    self.eval_expr(otherwise)
+                } else {
+                    Ok(Value::Nil)
+                };
+                self.save(&mut values, result)?
+            }
+            ExprKind::While(cond, body) => {
+                // holds the condition while the body runs
+                let mut values = self.restore();
+
+                loop {
+                    if !expect_bool(self.operand(&mut values, 0, cond)?, cond.span)? {
+                        break;
+                    }
+
+                    let result = self.eval_block(body);
+                    match self.save(&mut values, result) {
+                        Ok(_) | Err(Unwind::Continue) => {}
+                        Err(Unwind::Break(_)) => break,
+                        Err(unwind) => return Err(unwind),
+                    }
+                    values.clear();
+                }
+                Value::Nil
+            }
+            ExprKind::Loop(body) => {
+                let mut values = self.restore();
+
+                loop {
+                    // an empty body evaluates no expressions, the loop still spends fuel
+                    self.budget.tick(expr.span)?;
+
+                    let result = self.eval_block(body);
+                    match self.save(&mut values, result) {
+                        Ok(_) | Err(Unwind::Continue) => {}
+                        Err(Unwind::Break(value)) => break value,
+                        Err(unwind) => return Err(unwind),
+                    }
+                }
+            }
+            ExprKind::For(pat, iter, body) => {
+                // a loop resumed in its body skips to the rest of the body
+                let (mut iter, mut resumed) = match self.restore_saved() {
+                    Some(Saved::Iter(iter)) => (iter, true),
+                    saved => {
+                        let mut values = operands(saved);
+                        let iterable = self.operand(&mut values, 0, iter)?;
+                        (Iter::new(iterable, iter.span)?, false)
+                    }
+                };
+
+                loop {
+                    if !std::mem::take(&mut resumed) {
+                        let Some(item) = self.next_item(&mut iter, expr.span)? else {
+                            break;
+                        };
+                        self.budget.tick(expr.span)?;
+                        self.bind_pattern(pat, item);
+                    }
+
+                    match self.eval_block(body) {
+                        Ok(_) | Err(Unwind::Continue) => {}
+                        Err(Unwind::Break(_)) => break,
+                        Err(Unwind::Yield(value)) => {
+                            self.saved.push(Saved::Iter(iter));
+                            return Err(Unwind::Yield(value));
+                        }
+                        Err(unwind) => return Err(unwind),
+                    }
+                }
+                Value::Nil
+            }
+            ExprKind::Block(block) => self.eval_block(block)?,
+            ExprKind::Paren(inner) => self.eval_expr(inner)?,
+            ExprKind::Try(inner) => {
+                let mut values = self.restore();
+                match self.operand(&mut values, 0, inner)? {
+                    value @ Value::Error(_) => return Err(Unwind::Return(value)),
+                    value => value,
+                }
+            }
+            ExprKind::Return(value) => {
+                let mut values = self.restore();
+                return Err(Unwind::Return(self.opt_operand(&mut values, value)?));
+            }
+            ExprKind::Break(value) => {
+                let mut values = self.restore();
+                return Err(Unwind::Break(self.opt_operand(&mut values, value)?));
+            }
+            ExprKind::Continue => return Err(Unwind::Continue),
+            ExprKind::Panic(value) => {
+                let mut values = self.restore();
+                let message = match value {
+                    Some(value) => {
+                        let value = self.operand(&mut values, 0, value)?;
+                        Some(self.display_at(&value, expr.span)?)
+                    }
+                    None => None,
+                };
+                let message = ops::panic_message(message);
+                return Err(RuntimeError::new(message, expr.span).into());
+            }
+            ExprKind::Yield(value) => match self.restore_saved() {
+                Some(Saved::Yield) => {
+                    self.resuming = false;
+                    std::mem::replace(&mut self.input, Value::Nil)
+                }
+                saved => {
+                    let mut values = operands(saved);
+                    let value = self.opt_operand(&mut values, value)?;
+                    self.saved.push(Saved::Yield);
+                    return Err(Unwind::Yield(value));
+                }
+            },
+            ExprKind::Await(inner) => {
+                let (awaited, input) = match self.restore_saved() {
+                    Some(Saved::Await(awaited)) => {
+                        self.resuming = false;
+                        (awaited, std::mem::replace(&mut self.input, Value::Nil))
+                    }
+                    saved => {
+                        let mut values = operands(saved);
+                        (self.operand(&mut values, 0, inner)?, Value::Nil)
+                    }
+                };
+
+                match coroutine::poll_await(self, &awaited, input, expr.span)? {
+                    Awaited::Ready(value) => value,
+                    Awaited::Pending(future) => {
+                        self.saved.push(Saved::Await(awaited));
+                        return Err(Unwind::Yield(future));
+                    }
+                }
+            }
+            ExprKind::Todo => {
+                return Err(RuntimeError::new("not yet implemented", expr.span).into());
+            }
+        };
+
+        Ok(value)
+    }
+
+    // the state the expression being resumed saved, `None` unless resuming
+    fn restore_saved(&mut self) -> Option<Saved> {
+        if !self.resuming {
+            return None;
+        }
+        let saved = self.saved.pop();
+        Some(saved.expect("every expression a coroutine suspended in saves its state"))
+    }
+
+    fn restore(&mut self) -> Vec<Value> {
+        operands(self.restore_saved())
+    }
+
+    // the operand at `idx`, evaluated unless it is in `values` already
+    fn operand(&mut self, values: &mut Vec<Value>, idx: usize, expr: &Expr) -> Eval {
+        if let Some(value) = values.get(idx) {
+            return Ok(value.clone());
+        }
+
+        let result = self.eval_expr(expr);
+        let value = self.save(values, result)?;
+        values.push(value.clone());
+        Ok(value)
+    }
+
+    fn opt_operand(&mut self, values: &mut Vec<Value>, expr: &Option<Box<Expr>>) -> Eval {
+        match expr {
+            Some(expr) => self.operand(values, 0, expr),
+            None => Ok(Value::Nil),
+        }
+    }
+
+    // evaluates the operands missing from `values`
+    fn operands<'e>(
+        &mut self,
+        values: &mut Vec<Value>,
+        exprs: impl IntoIterator<Item = &'e Expr>,
+    ) -> Result<(), Unwind> {
+        for expr in exprs.into_iter().skip(values.len()) {
+            let idx = values.len();
+            self.operand(values, idx, expr)?;
+        }
+        Ok(())
+    }
+
+    // saves `values` if `result` suspends the coroutine
+    fn save(&mut self, values: &mut Vec<Value>, result: Eval) -> Eval {
+        if let Err(Unwind::Yield(_)) = result {
+            self.saved.push(Saved::Operands(std::mem::take(values)));
+        }
+        result
+    }
+
+    fn eval_path(&mut self, id: NodeId, path: &Path) -> Result<Value, RuntimeError> {
+        let name = path.segments.first().map(|i| i.name.as_str()).unwrap_or("");
+
+        match self.res.get(id) {
+            Some(Res::Local(pat)) => {
+                let frame = self.frames.last().expect("frame should exist");
+                frame.get(&pat).cloned().ok_or_else(|| {
+                    RuntimeError::new(format!("`{name}` is used before being assigned"), path.span)
+                })
+            }
+            Some(Res::Item(item)) => {
+                if self.fns.contains_key(&item) {
+                    Ok(Value::Fn(item))
+                } else if let Some(class) = self.classes.get(&item) {
+                    Ok(Value::Class(class.clone()))
+                } else {
+                    Err(RuntimeError::new(
+                        format!("`{name}` is not a value"),
+                        path.span,
+                    ))
+                }
+            }
+            Some(Res::Import(tree)) => {
+                let import = self.res.import(tree).expect("import should be resolved");
+                let full = import.full_path(name);
+
+                self.host.lookup(&full).ok_or_else(|| {
+                    RuntimeError::new(format!("unresolved import `{full}`"), path.span)
+                })
+            }
+            Some(Res::Builtin) => self.host.lookup(name).ok_or_else(|| {
+                RuntimeError::new(format!("cannot find module `{name}`"), path.span)
+            }),
+            _ => Err(RuntimeError::new(
+                format!("cannot find `{name}` in this scope"),
+                path.span,
+            )),
+        }
+    }
+
+    fn eval_class_init(&mut self, expr: &Expr, init: &ClassInit) -> Eval {
+        let name = init
+            .path
+            .segments
+            .last()
+            .map(|i| i.name.as_str())
+            .unwrap_or("");
+
+        let class = match self.res.get(expr.id) {
+            Some(Res::Item(id)) => self.classes.get(&id).cloned(),
+            _ => None,
+        };
+
+        let Some(class) = class else {
+            let message = format!("`{name}` is not a class");
+            return Err(RuntimeError::new(message, init.path.span).into());
+        };
+
+        let mut values = self.restore();
+        self.operands(&mut values, init.fields.iter().map(|field| &field.expr))?;
+        let mut fields = vec![Value::Nil; class.fields.len()];
+
+        for (field, value) in init.fields.iter().zip(values) {
+            match class.field_index(&field.ident.name) {
+                Some(idx) => fields[idx] = value,
+                None => {
+                    let message = format!("`{name}` has no field named `{}`", field.ident.name);
+                    return Err(RuntimeError::new(message, field.ident.span).into());
+                }
+            }
+        }
+
+        match self.heap.alloc(class, fields) {
+            Some(instance) => Ok(Value::Instance(instance)),
+            None => Err(limits::out_of_memory(self.heap.limit(), expr.span).into()),
+        }
+    }
+
+    // a field base or an indexed value is the operand at `idx` of the assignment, the index
+    // the one after it
+    fn assign(
+        &mut self,
+        lhs: &Expr,
+        value: Value,
+        values: &mut Vec<Value>,
+        idx: usize,
+    ) -> Result<(), Unwind> {
+        match &lhs.kind {
+            ExprKind::Path(path) => match self.res.get(lhs.id) {
+                Some(Res::Local(pat)) => {
+                    let frame = self.frames.last_mut().expect("frame should exist");
+                    frame.insert(pat, value);
+                    Ok(())
+                }
+                _ => {
+                    let name = path.segments.first().map(|i| i.name.as_str()).unwrap_or("");
+                    let message = format!("cannot assign to `{name}`");
+                    Err(RuntimeError::new(message, lhs.span).into())
+                }
+            },
+            ExprKind::Field(base, ident) => {
+                let base = self.operand(values, idx, base)?;
+                Ok(ops::set_field(&base, &ident.name, value, ident.span)?)
+            }
+            ExprKind::Index(base, index) => {
+                let base = self.operand(values, idx, base)?;
+                let index = self.operand(values, idx + 1, index)?;
+                Ok(ops::set_index(self.heap, &base, &index, value, lhs.span)?)
+            }
+            ExprKind::Paren(inner) => self.assign(inner, value, values, idx),
+            _ => {
+                let message = "invalid left-hand side of assignment";
+                Err(RuntimeError::new(message, lhs.span).into())
+            }
+        }
+    }
+
+    //END evaluation
+
+    //BEGIN operators
+
+    fn binary(
+        &mut self,
+        op: BinOp,
+        lhs: Value,
+        rhs: Value,
+        span: Span,
+    ) -> Result<Value, RuntimeError> {
+        match op {
+            BinOp::Eq => Ok(Value::Bool(self.values_eq(&lhs, &rhs, span)?)),
+            BinOp::NotEq => Ok(Value::Bool(!self.values_eq(&lhs, &rhs, span)?)),
+            _ => {
+                let value = ops::binary(op, &lhs, &rhs, span)?;
+                limits::check_string(self.heap, value, span)
+            }
+        }
+    }
+
+    /// `==`, calling `eq` on instances implementing `Eq`
+    fn values_eq(&mut self, lhs: &Value, rhs: &Value, span: Span) -> Result<bool, RuntimeError> {
+        if ops::has_user_eq(lhs) {
+            let result = self.call_method(lhs.clone(), "eq", vec![rhs.clone()], span)?;
+            return ops::expect_eq_result(result, span);
+        }
+
+        Ok(lhs == rhs)
+    }
+
+    fn display_at(&mut self, value: &Value, span: Span) -> Result<String, RuntimeError> {
+        if ops::has_user_display(value) {
+            let result = self.call_method(value.clone(), "to_string", Vec::new(), span)?;
+            return Ok(result.to_string());
+        }
+
+        Ok(value.to_string())
+    }
+
+    fn next_item(&mut self, iter: &mut Iter, span: Span) -> Result<Option<Value>, RuntimeError> {
+        match iter {
+            Iter::Items(items) => Ok(items.next()),
+            // `nil` ends the loop
+            Iter::Object(value) => {
+                match self.call_method(value.clone(), "next", Vec::new(), span)? {
+                    Value::Nil => Ok(None),
+                    item => Ok(Some(item)),
+                }
+            }
+            Iter::Coroutine(coroutine) => coroutine::next(self, coroutine, Value::Nil, span),
+        }
+    }
+
+    //END operators
+}
+
+impl NativeContext for Interp<'_> {
+    fn stdout(&mut self) -> &mut dyn Write {
+        self.stdout
+    }
+
+    fn heap(&mut self) -> &mut Heap {
+        self.heap
+    }
+
+    fn error(&self, message: String) -> RuntimeError {
+        RuntimeError::new(message, self.native_span)
+    }
+
+    fn display(&mut self, value: &Value) -> Result<String, RuntimeError> {
+        self.display_at(value, self.native_span)
+    }
+
+    fn executor(&mut self) -> &mut Executor {
+        self.executor
+    }
+
+    fn args(&self) -> &[EcoString] {
+        self.host.args()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.limits.capabilities
+    }
+
+    fn tick(&mut self) -> Result<(), RuntimeError> {
+        self.budget.tick(self.native_span)
+    }
+
+    fn call(
+        &mut self,
+        receiver: Value,
+        method: &str,
+        args: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        self.call_method(receiver, method, args, self.native_span)
+    }
+
+    fn apply(&mut self, callee: Value, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        self.call_value(callee, args, self.native_span)
+    }
+}
+
+impl Resume for Interp<'_> {
+    fn resume(
+        &mut self,
+        coroutine: &Rc<Coroutine>,
+        input: Value,
+        span: Span,
+    ) -> Result<Resumed, RuntimeError> {
+        let suspended = match coroutine.take(span)? {
+            CoroutineState::Ast(suspended) => suspended,
+            state => {
+                coroutine.suspend(state);
+                let message = format!("`{}` was created by another backend", coroutine.name);
+                return Err(RuntimeError::new(message, span));
+            }
+        };
+        let Suspended { id, locals, saved } = suspended;
+        let item_fn = self.fns[&id];
+        let body = item_fn.body.as_deref().expect("coroutine fns have a body");
+
+        if self.calls.len() == self.limits.call_depth {
+            coroutine.suspend(CoroutineState::Ast(Suspended { id, locals, saved }));
+            return Err(limits::stack_overflow(self.limits.call_depth, span));
+        }
+
+        self.frames.push(locals);
+        self.calls.push((&item_fn.ident.name, span));
+        let resuming = std::mem::replace(&mut self.resuming, !saved.is_empty());
+        let outer = std::mem::replace(&mut self.saved, saved);
+        let outer_input = std::mem::replace(&mut self.input, input);
+
+        let result = limits::grow(|| self.eval_block(body));
+
+        self.resuming = resuming;
+        let saved = std::mem::replace(&mut self.saved, outer);
+        self.input = outer_input;
+
+        let resumed = match result {
+            Err(Unwind::Yield(value)) => Ok(Resumed::Yield(value)),
+            result => self.returned(result, span).map(Resumed::Return),
+        };
+
+        self.calls.pop();
+        let locals = self.frames.pop().expect("frame should exist");
+
+        match resumed {
+            Ok(Resumed::Yield(_)) => {
+                coroutine.suspend(CoroutineState::Ast(Suspended { id, locals, saved }))
+            }
+            _ => coroutine.finish(),
+        }
+        resumed
+    }
+
+    fn budget(&self) -> &Budget {
+        &self.budget
+    }
+}
+
+// the operands saved by an expression, if it saved any
+fn operands(saved: Option<Saved>) -> Vec<Value> {
+    match saved {
+        None => Vec::new(),
+        Some(Saved::Operands(values)) => values,
+        Some(_) => unreachable!("expected the operands of an expression"),
+    }
+}
+
+fn expect_bool(value: Value, span: Span) -> Result<bool, RuntimeError> {
+    match value {
+        Value::Bool(value) => Ok(value),
+        value => {
+            let message = format!("expected `bool`, found `{}`", value.type_name());
+            Err(RuntimeError::new(message, span))
+        }
+    }
+}
+
+fn expect_int(value: Value, span: Span) -> Result<i64, RuntimeError> {
+    match value {
+        Value::Int(value) => Ok(value),
+        value => {
+            let message = format!("expected `int`, found `{}`", value.type_name());
+            Err(RuntimeError::new(message, span))
+        }
+    }
+}
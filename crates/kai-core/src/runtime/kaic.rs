@@ -0,0 +1,732 @@
+//! The `.kaic` file format, a compiled program saved to disk
+//!
+//! ```text
+//! magic       b"KAIC"
+//! version     u16, bumped whenever the layout or the instruction set changes
+//! source hash u64, see `source_hash`
+//! checksum    u64, FNV-1a of the payload
+//! payload     the program, integers are little endian
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+
+use ecow::EcoString;
+
+use super::bytecode::{Chunk, Constant, Function, Instr, Program};
+use super::value::Class;
+use crate::ast::span::Span;
+use crate::ast::{BinOp, CoroutineKind, NodeId, UnOp};
+use crate::resolve::BuiltinInterface;
+
+pub const MAGIC: &[u8; 4] = b"KAIC";
+pub const VERSION: u16 = 4;
+pub const EXTENSION: &str = "kaic";
+
+const HEADER_LEN: usize = MAGIC.len() + 2 + 8 + 8;
+
+/// Why a `.kaic` file could not be read
+#[derive(Debug, Clone, PartialEq)]
+pub enum KaicError {
+    NotKaic,
+    Version(u16),
+    Checksum,
+    // the payload ended early or holds something no compiler writes
+    Malformed(&'static str),
+}
+
+impl Display for KaicError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KaicError::NotKaic => write!(f, "not a compiled kai file"),
+            KaicError::Version(found) => write!(
+                f,
+                "compiled file has format version {found} but this kai reads version {VERSION}"
+            ),
+            KaicError::Checksum => {
+                write!(f, "compiled file is corrupted, its checksum does not match")
+            }
+            KaicError::Malformed(what) => write!(f, "compiled file is malformed: {what}"),
+        }
+    }
+}
+
+impl std::error::Error for KaicError {}
+
+/// A decoded `.kaic` file
+#[derive(Debug)]
+pub struct Kaic {
+    pub source_hash: u64,
+    pub program: Program,
+}
+
+/// The key of a compiled file, a different kai version never reuses it
+pub fn source_hash(src: &str) -> u64 {
+    let mut hash = fnv1a(FNV_OFFSET, env!("CARGO_PKG_VERSION").as_bytes());
+    hash = fnv1a(hash, &[0]);
+    fnv1a(hash, src.as_bytes())
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+pub fn encode(program: &Program, source_hash: u64) -> Vec<u8> {
+    let mut payload = Writer::default();
+    payload.program(program);
+
+    let mut out = Vec::with_capacity(HEADER_LEN + payload.0.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.extend_from_slice(&source_hash.to_le_bytes());
+    out.extend_from_slice(&fnv1a(FNV_OFFSET, &payload.0).to_le_bytes());
+    out.extend_from_slice(&payload.0);
+    out
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Kaic, KaicError> {
+    if bytes.len() < HEADER_LEN || !bytes.starts_with(MAGIC) {
+        return Err(KaicError::NotKaic);
+    }
+
+    let mut header = Reader {
+        bytes: &bytes[MAGIC.len()..HEADER_LEN],
+    };
+    let version = header.u16()?;
+    if version != VERSION {
+        return Err(KaicError::Version(version));
+    }
+    let source_hash = header.u64()?;
+    let checksum = header.u64()?;
+
+    let payload = &bytes[HEADER_LEN..];
+    if fnv1a(FNV_OFFSET, payload) != checksum {
+        return Err(KaicError::Checksum);
+    }
+
+    let mut reader = Reader { bytes: payload };
+    let program = reader.program()?;
+
+    if !reader.bytes.is_empty() {
+        return Err(KaicError::Malformed("trailing bytes"));
+    }
+    validate(&program)?;
+
+    Ok(Kaic {
+        source_hash,
+        program,
+    })
+}
+
+//BEGIN writing
+
+#[derive(Default)]
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn len(&mut self, len: usize) {
+        self.u32(u32::try_from(len).expect("programs have fewer than 2^32 parts"));
+    }
+
+    fn str(&mut self, value: &str) {
+        self.len(value.len());
+        self.0.extend_from_slice(value.as_bytes());
+    }
+
+    fn span(&mut self, span: Span) {
+        self.u64(span.start as u64);
+        self.u64(span.end as u64);
+    }
+
+    fn program(&mut self, program: &Program) {
+        self.span(program.span);
+
+        match program.main {
+            Some(id) => {
+                self.u8(1);
+                self.u32(id.0);
+            }
+            None => self.u8(0),
+        }
+
+        self.len(program.classes.len());
+        for class in &program.classes {
+            self.class(class);
+        }
+
+        self.len(program.functions.len());
+        for function in &program.functions {
+            self.function(function);
+        }
+    }
+
+    fn class(&mut self, class: &Class) {
+        self.u32(class.id.0);
+        self.str(&class.name);
+
+        self.len(class.fields.len());
+        for field in &class.fields {
+            self.str(field);
+        }
+
+        // sorted so the same program always gives the same bytes
+        let mut methods: Vec<_> = class.methods.iter().collect();
+        methods.sort();
+        self.len(methods.len());
+        for (name, id) in methods {
+            self.str(name);
+            self.u32(id.0);
+        }
+
+        self.len(class.builtins.len());
+        for builtin in &class.builtins {
+            self.str(builtin.name());
+        }
+    }
+
+    fn function(&mut self, function: &Function) {
+        self.u32(function.id.0);
+        self.str(&function.name);
+        self.span(function.span);
+        self.u16(function.arity);
+        self.u8(u8::from(function.has_self));
+        self.u8(u8::from(function.has_body));
+        self.u8(match function.coroutine {
+            None => 0,
+            Some(CoroutineKind::Generator) => 1,
+            Some(CoroutineKind::Async) => 2,
+        });
+
+        self.len(function.locals.len());
+        for local in &function.locals {
+            self.str(local);
+        }
+
+        let chunk = &function.chunk;
+
+        self.len(chunk.consts.len());
+        for constant in &chunk.consts {
+            match constant {
+                Constant::Int(value) => {
+                    self.u8(0);
+                    self.u64(*value as u64);
+                }
+                Constant::Float(value) => {
+                    self.u8(1);
+                    self.u64(value.to_bits());
+                }
+                Constant::Str(value) => {
+                    self.u8(2);
+                    self.str(value);
+                }
+            }
+        }
+
+        self.len(chunk.code.len());
+        for (instr, span) in chunk.code.iter().zip(&chunk.spans) {
+            self.instr(*instr);
+            self.span(*span);
+        }
+    }
+
+    fn instr(&mut self, instr: Instr) {
+        self.u8(opcode(&instr));
+
+        match instr {
+            Instr::Const(idx)
+            | Instr::Import(idx)
+            | Instr::GetField(idx)
+            | Instr::SetField(idx)
+            | Instr::Jump(idx)
+            | Instr::JumpIfFalse(idx)
+            | Instr::IterNext(idx)
+            | Instr::Try(idx)
+            | Instr::Error(idx) => self.u32(idx),
+            Instr::PopN(n)
+            | Instr::Slide(n)
+            | Instr::GetLocal(n)
+            | Instr::SetLocal(n)
+            | Instr::Call(n)
+            | Instr::InitField(n)
+            | Instr::List(n)
+            | Instr::Map(n)
+            | Instr::Set(n) => self.u16(n),
+            Instr::Fn(id) | Instr::Class(id) | Instr::NewInstance(id) => self.u32(id.0),
+            Instr::CallMethod(name, argc) => {
+                self.u32(name);
+                self.u16(argc);
+            }
+            Instr::Unary(op) => self.u8(UN_OPS.iter().position(|o| *o == op).unwrap() as u8),
+            Instr::Binary(op) => self.u8(BIN_OPS.iter().position(|o| *o == op).unwrap() as u8),
+            Instr::Range(flag) | Instr::Panic(flag) => self.u8(u8::from(flag)),
+            Instr::Nil
+            | Instr::True
+            | Instr::False
+            | Instr::Pop
+            | Instr::Dup
+            | Instr::Dup2
+            | Instr::Swap
+            | Instr::Rot
+            | Instr::GetIndex
+            | Instr::SetIndex
+            | Instr::ExpectInt
+            | Instr::Return
+            | Instr::IterInit
+            | Instr::IterDrop
+            | Instr::Escape
+            | Instr::Yield
+            | Instr::Await => {}
+        }
+    }
+}
+
+const UN_OPS: [UnOp; 2] = [UnOp::Neg, UnOp::Not];
+
+const BIN_OPS: [BinOp; 13] = [
+    BinOp::Add,
+    BinOp::Sub,
+    BinOp::Mul,
+    BinOp::Div,
+    BinOp::Rem,
+    BinOp::And,
+    BinOp::Or,
+    BinOp::Eq,
+    BinOp::NotEq,
+    BinOp::Lt,
+    BinOp::LtEq,
+    BinOp::Gt,
+    BinOp::GtEq,
+];
+
+// the numbering is part of the format, append new instructions and bump `VERSION`
+fn opcode(instr: &Instr) -> u8 {
+    match instr {
+        Instr::Const(_) => 0,
+        Instr::Nil => 1,
+        Instr::True => 2,
+        Instr::False => 3,
+        Instr::Pop => 4,
+        Instr::PopN(_) => 5,
+        Instr::Slide(_) => 6,
+        Instr::Dup => 7,
+        Instr::Swap => 8,
+        Instr::GetLocal(_) => 9,
+        Instr::SetLocal(_) => 10,
+        Instr::Fn(_) => 11,
+        Instr::Class(_) => 12,
+        Instr::Import(_) => 13,
+        Instr::GetField(_) => 14,
+        Instr::SetField(_) => 15,
+        Instr::Unary(_) => 16,
+        Instr::Binary(_) => 17,
+        Instr::ExpectInt => 18,
+        Instr::Range(_) => 19,
+        Instr::Jump(_) => 20,
+        Instr::JumpIfFalse(_) => 21,
+        Instr::Call(_) => 22,
+        Instr::CallMethod(..) => 23,
+        Instr::Return => 24,
+        Instr::NewInstance(_) => 25,
+        Instr::InitField(_) => 26,
+        Instr::IterInit => 27,
+        Instr::IterNext(_) => 28,
+        Instr::IterDrop => 29,
+        Instr::Panic(_) => 30,
+        Instr::Error(_) => 31,
+        Instr::Escape => 32,
+        Instr::Try(_) => 33,
+        Instr::Yield => 34,
+        Instr::Await => 35,
+        Instr::Dup2 => 36,
+        Instr::Rot => 37,
+        Instr::List(_) => 38,
+        Instr::Map(_) => 39,
+        Instr::Set(_) => 40,
+        Instr::GetIndex => 41,
+        Instr::SetIndex => 42,
+    }
+}
+
+//END writing
+
+//BEGIN reading
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+type Read<T> = Result<T, KaicError>;
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Read<[u8; N]> {
+        if self.bytes.len() < N {
+            return Err(KaicError::Malformed("unexpected end of file"));
+        }
+
+        let (head, rest) = self.bytes.split_at(N);
+        self.bytes = rest;
+        Ok(head.try_into().expect("length was checked"))
+    }
+
+    fn u8(&mut self) -> Read<u8> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Read<u16> {
+        Ok(u16::from_le_bytes(self.take()?))
+    }
+
+    fn u32(&mut self) -> Read<u32> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    fn u64(&mut self) -> Read<u64> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    fn bool(&mut self) -> Read<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(KaicError::Malformed("invalid boolean")),
+        }
+    }
+
+    fn len(&mut self) -> Read<usize> {
+        let len = self.u32()? as usize;
+
+        // every part takes at least a byte, this rejects absurd lengths before allocating
+        if len > self.bytes.len() {
+            return Err(KaicError::Malformed("length past the end of file"));
+        }
+        Ok(len)
+    }
+
+    fn str(&mut self) -> Read<EcoString> {
+        let len = self.len()?;
+        let (head, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+
+        std::str::from_utf8(head)
+            .map(EcoString::from)
+            .map_err(|_| KaicError::Malformed("invalid utf-8 in a string"))
+    }
+
+    fn span(&mut self) -> Read<Span> {
+        let start = self.u64()?;
+        let end = self.u64()?;
+        Ok(Span::new(start as usize, end as usize))
+    }
+
+    fn node_id(&mut self) -> Read<NodeId> {
+        Ok(NodeId(self.u32()?))
+    }
+
+    fn program(&mut self) -> Read<Program> {
+        let span = self.span()?;
+        let main = match self.bool()? {
+            true => Some(self.node_id()?),
+            false => None,
+        };
+
+        let classes = (0..self.len()?)
+            .map(|_| self.class())
+            .collect::<Read<Vec<_>>>()?;
+        let functions = (0..self.len()?)
+            .map(|_| self.function())
+            .collect::<Read<Vec<_>>>()?;
+
+        Ok(Program {
+            functions,
+            classes,
+            main,
+            span,
+        })
+    }
+
+    fn class(&mut self) -> Read<Class> {
+        let id = self.node_id()?;
+        let name = self.str()?;
+        let fields = (0..self.len()?)
+            .map(|_| self.str())
+            .collect::<Read<Vec<_>>>()?;
+
+        let mut methods = HashMap::new();
+        for _ in 0..self.len()? {
+            let name = self.str()?;
+            methods.insert(name, self.node_id()?);
+        }
+
+        let builtins = (0..self.len()?)
+            .map(|_| {
+                BuiltinInterface::from_name(&self.str()?)
+                    .ok_or(KaicError::Malformed("unknown builtin interface"))
+            })
+            .collect::<Read<Vec<_>>>()?;
+
+        Ok(Class {
+            id,
+            name,
+            fields,
+            methods,
+            builtins,
+            natives: HashMap::new(),
+        })
+    }
+
+    fn function(&mut self) -> Read<Function> {
+        let id = self.node_id()?;
+        let name = self.str()?;
+        let span = self.span()?;
+        let arity = self.u16()?;
+        let has_self = self.bool()?;
+        let has_body = self.bool()?;
+        let coroutine = match self.u8()? {
+            0 => None,
+            1 => Some(CoroutineKind::Generator),
+            2 => Some(CoroutineKind::Async),
+            _ => return Err(KaicError::Malformed("unknown coroutine kind")),
+        };
+        let locals = (0..self.len()?)
+            .map(|_| self.str())
+            .collect::<Read<Vec<_>>>()?;
+
+        let mut chunk = Chunk::default();
+
+        for _ in 0..self.len()? {
+            let constant = match self.u8()? {
+                0 => Constant::Int(self.u64()? as i64),
+                1 => Constant::Float(f64::from_bits(self.u64()?)),
+                2 => Constant::Str(self.str()?),
+                _ => return Err(KaicError::Malformed("unknown constant kind")),
+            };
+            chunk.consts.push(constant);
+        }
+
+        for _ in 0..self.len()? {
+            let instr = self.instr()?;
+            let span = self.span()?;
+            chunk.push(instr, span);
+        }
+
+        Ok(Function {
+            id,
+            name,
+            span,
+            arity,
+            has_self,
+            has_body,
+            coroutine,
+            locals,
+            chunk,
+        })
+    }
+
+    fn instr(&mut self) -> Read<Instr> {
+        let instr = match self.u8()? {
+            0 => Instr::Const(self.u32()?),
+            1 => Instr::Nil,
+            2 => Instr::True,
+            3 => Instr::False,
+            4 => Instr::Pop,
+            5 => Instr::PopN(self.u16()?),
+            6 => Instr::Slide(self.u16()?),
+            7 => Instr::Dup,
+            8 => Instr::Swap,
+            9 => Instr::GetLocal(self.u16()?),
+            10 => Instr::SetLocal(self.u16()?),
+            11 => Instr::Fn(self.node_id()?),
+            12 => Instr::Class(self.node_id()?),
+            13 => Instr::Import(self.u32()?),
+            14 => Instr::GetField(self.u32()?),
+            15 => Instr::SetField(self.u32()?),
+            16 => Instr::Unary(
+                *UN_OPS
+                    .get(usize::from(self.u8()?))
+                    .ok_or(KaicError::Malformed("unknown unary operator"))?,
+            ),
+            17 => Instr::Binary(
+                *BIN_OPS
+                    .get(usize::from(self.u8()?))
+                    .ok_or(KaicError::Malformed("unknown binary operator"))?,
+            ),
+            18 => Instr::ExpectInt,
+            19 => Instr::Range(self.bool()?),
+            20 => Instr::Jump(self.u32()?),
+            21 => Instr::JumpIfFalse(self.u32()?),
+            22 => Instr::Call(self.u16()?),
+            23 => Instr::CallMethod(self.u32()?, self.u16()?),
+            24 => Instr::Return,
+            25 => Instr::NewInstance(self.node_id()?),
+            26 => Instr::InitField(self.u16()?),
+            27 => Instr::IterInit,
+            28 => Instr::IterNext(self.u32()?),
+            29 => Instr::IterDrop,
+            30 => Instr::Panic(self.bool()?),
+            31 => Instr::Error(self.u32()?),
+            32 => Instr::Escape,
+            33 => Instr::Try(self.u32()?),
+            34 => Instr::Yield,
+            35 => Instr::Await,
+            36 => Instr::Dup2,
+            37 => Instr::Rot,
+            38 => Instr::List(self.u16()?),
+            39 => Instr::Map(self.u16()?),
+            40 => Instr::Set(self.u16()?),
+            41 => Instr::GetIndex,
+            42 => Instr::SetIndex,
+            _ => return Err(KaicError::Malformed("unknown instruction")),
+        };
+
+        Ok(instr)
+    }
+}
+
+// the VM looks functions, classes, constants and locals up without checking,
+// a file passing the checksum can still be handcrafted
+fn validate(program: &Program) -> Read<()> {
+    let fns: HashSet<NodeId> = program.functions.iter().map(|f| f.id).collect();
+    let classes: HashSet<NodeId> = program.classes.iter().map(|c| c.id).collect();
+
+    let valid_main = program.main.is_none_or(|id| fns.contains(&id));
+    let valid_methods = program
+        .classes
+        .iter()
+        .all(|class| class.methods.values().all(|id| fns.contains(id)));
+
+    if !valid_main || !valid_methods {
+        return Err(KaicError::Malformed("reference to a missing function"));
+    }
+
+    for function in &program.functions {
+        let chunk = &function.chunk;
+        let is_str = |idx: u32| matches!(chunk.consts.get(idx as usize), Some(Constant::Str(_)));
+
+        for instr in &chunk.code {
+            let valid = match *instr {
+                Instr::Const(idx) => (idx as usize) < chunk.consts.len(),
+                Instr::Import(idx)
+                | Instr::GetField(idx)
+                | Instr::SetField(idx)
+                | Instr::CallMethod(idx, _)
+                | Instr::Error(idx) => is_str(idx),
+                Instr::GetLocal(slot) | Instr::SetLocal(slot) => {
+                    usize::from(slot) < function.locals.len()
+                }
+                Instr::Jump(target)
+                | Instr::JumpIfFalse(target)
+                | Instr::IterNext(target)
+                | Instr::Try(target) => (target as usize) < chunk.code.len(),
+                Instr::Fn(id) => fns.contains(&id),
+                Instr::Class(id) | Instr::NewInstance(id) => classes.contains(&id),
+                _ => true,
+            };
+
+            if !valid {
+                return Err(KaicError::Malformed("operand out of range"));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+//END reading
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::resolve::resolve;
+    use crate::runtime::compile;
+
+    fn program(code: &str) -> Program {
+        let file = parse(code).unwrap();
+        compile(&file, &resolve(&file)).unwrap()
+    }
+
+    const CODE: &str = r#"
+        using kai.io;
+
+        class Point { x: float; y: float; }
+
+        impl Display for Point {
+            fn to_string(self) -> string { "point" }
+        }
+
+        fn main() {
+            let p = Point { x: 1.5, y: -2.0 };
+            for i in 0..3 { io.print(i, p, "done"); }
+        }
+
+        fn count() -> generator<int> { yield 1; }
+        async fn wait(f) { await f; }
+    "#;
+
+    #[test]
+    fn round_trips_programs() {
+        let program = program(CODE);
+        let bytes = encode(&program, 42);
+        let decoded = decode(&bytes).unwrap();
+
+        assert_eq!(decoded.source_hash, 42);
+        assert_eq!(decoded.program.main, program.main);
+        assert_eq!(decoded.program.functions, program.functions);
+
+        let class = &decoded.program.classes[0];
+        assert_eq!(class.fields, program.classes[0].fields);
+        assert_eq!(class.methods, program.classes[0].methods);
+        assert_eq!(class.builtins, [BuiltinInterface::Display]);
+
+        // the same program always encodes the same way
+        assert_eq!(encode(&decoded.program, 42), bytes);
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        let bytes = encode(&program(CODE), 0);
+
+        assert_eq!(decode(b"fn main() {}").unwrap_err(), KaicError::NotKaic);
+
+        let mut old = bytes.clone();
+        old[4..6].copy_from_slice(&0u16.to_le_bytes());
+        assert_eq!(decode(&old).unwrap_err(), KaicError::Version(0));
+
+        let mut corrupted = bytes.clone();
+        *corrupted.last_mut().unwrap() ^= 1;
+        assert_eq!(decode(&corrupted).unwrap_err(), KaicError::Checksum);
+
+        let mut truncated = bytes[..bytes.len() - 4].to_vec();
+        let checksum = fnv1a(FNV_OFFSET, &truncated[HEADER_LEN..]);
+        truncated[14..22].copy_from_slice(&checksum.to_le_bytes());
+        assert!(matches!(decode(&truncated), Err(KaicError::Malformed(_))));
+    }
+
+    #[test]
+    fn hashes_sources() {
+        assert_eq!(source_hash("fn main() {}"), source_hash("fn main() {}"));
+        assert_ne!(source_hash("fn main() {}"), source_hash("fn main() { }"));
+    }
+}
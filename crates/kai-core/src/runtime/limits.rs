@@ -0,0 +1,262 @@
+//! Limits embedders put on the programs an engine runs
+
+use std::time::{Duration, Instant};
+
+use super::error::{RuntimeError, RuntimeErrorKind};
+use super::gc::Heap;
+use super::value::{NativeFn, Value};
+use crate::ast::span::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Steps a run may take, the VM spends one per instruction and the tree walker one
+    /// per expression so the same budget goes further on the VM
+    pub fuel: Option<u64>,
+    /// Bytes of heap objects alive at once, with the strings stored in lists, maps and
+    /// sets. Also caps the length of a single string.
+    pub heap_bytes: Option<usize>,
+    /// Calls which can be active at once before the run stops with a stack overflow
+    pub call_depth: usize,
+    /// Wall clock time a run may take
+    pub timeout: Option<Duration>,
+    pub capabilities: Capabilities,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            fuel: None,
+            heap_bytes: None,
+            call_depth: 1024,
+            timeout: None,
+            capabilities: Capabilities::ALL,
+        }
+    }
+}
+
+/// Access to the outside world a standard library module needs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    Fs,
+    Process,
+    Env,
+    Net,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 4] = [
+        Capability::Fs,
+        Capability::Process,
+        Capability::Env,
+        Capability::Net,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|cap| cap.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Capability::Fs => "fs",
+            Capability::Process => "process",
+            Capability::Env => "env",
+            Capability::Net => "net",
+        }
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// The capabilities an engine grants, natives of denied modules fail when called
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities(u8);
+
+impl Capabilities {
+    pub const ALL: Capabilities = Capabilities(0b1111);
+    pub const NONE: Capabilities = Capabilities(0);
+
+    pub fn allow(self, cap: Capability) -> Self {
+        Self(self.0 | cap.bit())
+    }
+
+    pub fn deny(self, cap: Capability) -> Self {
+        Self(self.0 & !cap.bit())
+    }
+
+    pub fn allows(self, cap: Capability) -> bool {
+        self.0 & cap.bit() != 0
+    }
+}
+
+// the clock is only read every this many steps
+const CLOCK_INTERVAL: u32 = 1024;
+
+/// Fuel and time left for the current run
+#[derive(Debug)]
+pub(crate) struct Budget {
+    fuel: Option<u64>,
+    spent: u64,
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    until_clock: u32,
+}
+
+impl Budget {
+    pub fn new(limits: &Limits) -> Self {
+        Self {
+            fuel: limits.fuel,
+            spent: 0,
+            timeout: limits.timeout,
+            deadline: limits.timeout.map(|timeout| Instant::now() + timeout),
+            until_clock: CLOCK_INTERVAL,
+        }
+    }
+
+    /// Spends one step, `span` is where the run stops once nothing is left
+    pub fn tick(&mut self, span: Span) -> Result<(), RuntimeError> {
+        if self.fuel.is_some_and(|fuel| self.spent == fuel) {
+            let message = format!("ran out of fuel after {} steps", self.spent);
+            return Err(RuntimeError::with_kind(
+                RuntimeErrorKind::OutOfFuel,
+                message,
+                span,
+            ));
+        }
+        self.spent += 1;
+
+        if self.deadline.is_some() {
+            self.until_clock -= 1;
+
+            if self.until_clock == 0 {
+                self.until_clock = CLOCK_INTERVAL;
+                self.check_time(span)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// When the run times out
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Reads the clock right away, for the executor which sleeps without taking steps
+    pub fn check_time(&self, span: Span) -> Result<(), RuntimeError> {
+        if let (Some(deadline), Some(timeout)) = (self.deadline, self.timeout) {
+            if Instant::now() >= deadline {
+                let message = format!("timed out after {timeout:?}");
+                return Err(RuntimeError::with_kind(
+                    RuntimeErrorKind::Timeout,
+                    message,
+                    span,
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+// space left on the native stack before a recursion point moves to a new segment, and
+// the size of those segments
+const RED_ZONE: usize = 256 * 1024;
+const STACK_SEGMENT: usize = 2 * 1024 * 1024;
+
+/// Runs `f` where the backends recurse, growing the native stack so deep kai calls hit
+/// `call_depth` instead of overflowing the Rust stack
+pub(crate) fn grow<R>(f: impl FnOnce() -> R) -> R {
+    stacker::maybe_grow(RED_ZONE, STACK_SEGMENT, f)
+}
+
+pub(crate) fn stack_overflow(depth: usize, span: Span) -> RuntimeError {
+    let message = format!("stack overflow, more than {depth} nested calls");
+    RuntimeError::with_kind(RuntimeErrorKind::StackOverflow, message, span)
+}
+
+pub(crate) fn out_of_memory(limit: Option<usize>, span: Span) -> RuntimeError {
+    let message = format!("heap limit of {} bytes exceeded", limit.unwrap_or_default());
+    RuntimeError::with_kind(RuntimeErrorKind::OutOfMemory, message, span)
+}
+
+/// Strings live outside the heap, the length of new ones is held against its limit and
+/// collections count the strings they store, see `gc::string_bytes`
+pub(crate) fn check_string(heap: &Heap, value: Value, span: Span) -> Result<Value, RuntimeError> {
+    match &value {
+        Value::Str(value) if !heap.fits(value.len()) => Err(out_of_memory(heap.limit(), span)),
+        _ => Ok(value),
+    }
+}
+
+/// A new list, map or set on the heap, see `Heap::track`
+pub(crate) fn track(heap: &mut Heap, value: Value, span: Span) -> Result<Value, RuntimeError> {
+    heap.track(value)
+        .ok_or_else(|| out_of_memory(heap.limit(), span))
+}
+
+/// Charges elements added to a list, map or set to the heap, see `Heap::reserve`
+pub(crate) fn reserve(
+    heap: &mut Heap,
+    collection: &Value,
+    additional: usize,
+    strings: usize,
+    span: Span,
+) -> Result<(), RuntimeError> {
+    match heap.reserve(collection, additional, strings) {
+        true => Ok(()),
+        false => Err(out_of_memory(heap.limit(), span)),
+    }
+}
+
+pub(crate) fn check_capability(
+    caps: Capabilities,
+    native: &NativeFn,
+    span: Span,
+) -> Result<(), RuntimeError> {
+    match native.capability {
+        Some(cap) if !caps.allows(cap) => {
+            let message = format!(
+                "`{}` needs the `{}` capability, which this engine denies",
+                native.name,
+                cap.name()
+            );
+            Err(RuntimeError::with_kind(
+                RuntimeErrorKind::Denied,
+                message,
+                span,
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grants_and_denies_capabilities() {
+        let caps = Capabilities::ALL.deny(Capability::Fs);
+        assert!(!caps.allows(Capability::Fs));
+        assert!(caps.allows(Capability::Env));
+        assert!(Capabilities::NONE
+            .allow(Capability::Process)
+            .allows(Capability::Process));
+        assert_eq!(Capability::from_name("env"), Some(Capability::Env));
+    }
+
+    #[test]
+    fn spends_fuel() {
+        let mut budget = Budget::new(&Limits {
+            fuel: Some(2),
+            ..Limits::default()
+        });
+
+        assert!(budget.tick(Span::default()).is_ok());
+        assert!(budget.tick(Span::default()).is_ok());
+        let err = budget.tick(Span::default()).unwrap_err();
+        assert_eq!(err.kind, RuntimeErrorKind::OutOfFuel);
+        assert_eq!(err.message, "ran out of fuel after 2 steps");
+    }
+}
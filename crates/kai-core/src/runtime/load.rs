@@ -0,0 +1,114 @@
+//! What both backends read from a resolved file before running it
+
+use std::collections::HashMap;
+
+use super::value::Class;
+use crate::ast::*;
+use crate::resolve::{Res, Resolutions};
+
+/// Every fn, method and class of a file, keyed by node id
+pub(crate) struct Loaded<'a> {
+    pub fns: HashMap<NodeId, &'a ItemFn>,
+    pub classes: HashMap<NodeId, Class>,
+}
+
+pub(crate) fn load<'a>(file: &'a File, res: &Resolutions) -> Loaded<'a> {
+    let items = file.all_items();
+    let mut loaded = Loaded {
+        fns: HashMap::new(),
+        classes: HashMap::new(),
+    };
+    let mut interfaces: HashMap<NodeId, &ItemInterface> = HashMap::new();
+
+    for item in &items {
+        match &item.kind {
+            ItemKind::Fn(item_fn) => {
+                loaded.fns.insert(item.id, item_fn);
+            }
+            ItemKind::Use(_) => {}
+            ItemKind::Class(item_class) => {
+                let mut class = Class {
+                    id: item.id,
+                    name: item_class.ident.name.as_str().into(),
+                    fields: item_class
+                        .fields
+                        .iter()
+                        .map(|field| field.ident.name.as_str().into())
+                        .collect(),
+                    methods: HashMap::new(),
+                    builtins: Vec::new(),
+                    natives: HashMap::new(),
+                };
+                add_methods(&mut class, &item_class.methods);
+                loaded.classes.insert(item.id, class);
+            }
+            ItemKind::Interface(item_interface) => {
+                interfaces.insert(item.id, item_interface);
+            }
+            ItemKind::Impl(_) => {}
+        }
+
+        for method in item.kind.methods() {
+            if let ItemKind::Fn(item_fn) = &method.kind {
+                loaded.fns.insert(method.id, item_fn);
+            }
+        }
+    }
+
+    let impls: Vec<(NodeId, &ItemImpl)> = items
+        .iter()
+        .filter_map(|item| match &item.kind {
+            ItemKind::Impl(item_impl) => match res.get(item_impl.class.id) {
+                Some(Res::Item(class)) => Some((class, &**item_impl)),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+
+    for (class, item_impl) in &impls {
+        if let Some(class) = loaded.classes.get_mut(class) {
+            add_methods(class, &item_impl.methods);
+        }
+    }
+
+    // defaults go last so they never replace a method from an impl
+    for (class, item_impl) in &impls {
+        let Some(class) = loaded.classes.get_mut(class) else {
+            continue;
+        };
+
+        match res.get(item_impl.interface.id) {
+            Some(Res::BuiltinInterface(builtin)) => class.builtins.push(builtin),
+            Some(Res::Item(id)) => {
+                if let Some(item_interface) = interfaces.get(&id) {
+                    add_methods(class, &item_interface.methods);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    loaded
+}
+
+// methods already on the class win, so own methods shadow the ones from impls
+fn add_methods(class: &mut Class, methods: &[Item]) {
+    for method in methods {
+        if let ItemKind::Fn(item_fn) = &method.kind {
+            if item_fn.body.is_some() {
+                class
+                    .methods
+                    .entry(item_fn.ident.name.as_str().into())
+                    .or_insert(method.id);
+            }
+        }
+    }
+}
+
+/// The top level `main` function
+pub(crate) fn find_main(file: &File) -> Option<&Item> {
+    file.items
+        .iter()
+        .find(|item| matches!(&item.kind, ItemKind::Fn(item_fn) if item_fn.ident.name == "main"))
+}
@@ -0,0 +1,562 @@
+//! Operators, iteration and error helpers shared by both backends
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::coroutine::Coroutine;
+use super::error::{RuntimeError, TraceFrame};
+use super::gc::{self, Heap};
+use super::host::Host;
+use super::limits;
+use super::stdlib;
+use super::value::{Class, Key, Map, Set, Value};
+use crate::ast::span::Span;
+use crate::ast::{BinOp, CoroutineKind, UnOp};
+use crate::resolve::BuiltinInterface;
+
+pub(crate) fn unary(op: UnOp, value: Value, span: Span) -> Result<Value, RuntimeError> {
+    match (op, value) {
+        (UnOp::Neg, Value::Int(value)) => value
+            .checked_neg()
+            .map(Value::Int)
+            .ok_or_else(|| RuntimeError::new("attempt to negate with overflow", span)),
+        (UnOp::Neg, Value::Float(value)) => Ok(Value::Float(-value)),
+        (UnOp::Not, Value::Bool(value)) => Ok(Value::Bool(!value)),
+        (UnOp::Neg, value) => Err(RuntimeError::new(
+            format!("cannot negate `{}`", value.type_name()),
+            span,
+        )),
+        (UnOp::Not, value) => Err(RuntimeError::new(
+            format!("cannot apply `!` to `{}`", value.type_name()),
+            span,
+        )),
+    }
+}
+
+/// Every binary operator but `==`, `!=`, `&&` and `||`, which need the backend
+///
+/// Arithmetic on two `int`s stays an `int` and stops the program on overflow instead of
+/// wrapping. `/` rounds toward zero and `%` takes the sign of the left side, so `-7 / 2` is
+/// `-3` and `-7 % 2` is `-1`. With a `float` on either side both are turned into floats,
+/// which follow IEEE 754 and never stop the program, `1.0 / 0` is `inf`.
+pub(crate) fn binary(
+    op: BinOp,
+    lhs: &Value,
+    rhs: &Value,
+    span: Span,
+) -> Result<Value, RuntimeError> {
+    let value = match (lhs, rhs) {
+        (Value::Int(a), Value::Int(b)) => {
+            int_binary(op, *a, *b).map_err(|message| RuntimeError::new(message, span))?
+        }
+        (Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => {
+            float_binary(op, as_float(lhs), as_float(rhs))
+        }
+        (Value::Str(a), Value::Str(b)) => match op {
+            BinOp::Add => {
+                let mut joined = a.clone();
+                joined.push_str(b);
+                Some(Value::Str(joined))
+            }
+            _ => compare(op, a, b),
+        },
+        _ => None,
+    };
+
+    value.ok_or_else(|| {
+        let message = format!(
+            "cannot apply `{}` to `{}` and `{}`",
+            op.as_str(),
+            lhs.type_name(),
+            rhs.type_name()
+        );
+        RuntimeError::new(message, span)
+    })
+}
+
+fn as_float(value: &Value) -> f64 {
+    match value {
+        Value::Int(value) => *value as f64,
+        Value::Float(value) => *value,
+        _ => f64::NAN,
+    }
+}
+
+fn int_binary(op: BinOp, a: i64, b: i64) -> Result<Option<Value>, &'static str> {
+    let value = match op {
+        BinOp::Add => a.checked_add(b).ok_or("attempt to add with overflow")?,
+        BinOp::Sub => a
+            .checked_sub(b)
+            .ok_or("attempt to subtract with overflow")?,
+        BinOp::Mul => a
+            .checked_mul(b)
+            .ok_or("attempt to multiply with overflow")?,
+        BinOp::Div if b == 0 => return Err("attempt to divide by zero"),
+        BinOp::Div => a.checked_div(b).ok_or("attempt to divide with overflow")?,
+        BinOp::Rem if b == 0 => {
+            return Err("attempt to calculate the remainder with a divisor of zero")
+        }
+        BinOp::Rem => a
+            .checked_rem(b)
+            .ok_or("attempt to calculate the remainder with overflow")?,
+        _ => return Ok(compare(op, &a, &b)),
+    };
+
+    Ok(Some(Value::Int(value)))
+}
+
+fn float_binary(op: BinOp, a: f64, b: f64) -> Option<Value> {
+    let value = match op {
+        BinOp::Add => a + b,
+        BinOp::Sub => a - b,
+        BinOp::Mul => a * b,
+        BinOp::Div => a / b,
+        BinOp::Rem => a % b,
+        _ => return compare(op, &a, &b),
+    };
+
+    Some(Value::Float(value))
+}
+
+fn compare<T: PartialOrd + ?Sized>(op: BinOp, a: &T, b: &T) -> Option<Value> {
+    let value = match op {
+        BinOp::Lt => a < b,
+        BinOp::LtEq => a <= b,
+        BinOp::Gt => a > b,
+        BinOp::GtEq => a >= b,
+        _ => return None,
+    };
+
+    Some(Value::Bool(value))
+}
+
+/// Whether `==` on `value` calls its `eq` method
+pub(crate) fn has_user_eq(value: &Value) -> bool {
+    matches!(value, Value::Instance(instance) if instance.class.implements(BuiltinInterface::Eq))
+}
+
+/// Whether printing `value` calls its `to_string` method
+pub(crate) fn has_user_display(value: &Value) -> bool {
+    matches!(value, Value::Instance(instance) if instance.class.implements(BuiltinInterface::Display))
+}
+
+/// The result of a user `eq` method
+pub(crate) fn expect_eq_result(value: Value, span: Span) -> Result<bool, RuntimeError> {
+    match value {
+        Value::Bool(value) => Ok(value),
+        value => Err(RuntimeError::new(
+            format!("`eq` must return a `bool`, found `{}`", value.type_name()),
+            span,
+        )),
+    }
+}
+
+/// The state of a `for` loop
+#[derive(Debug)]
+pub(crate) enum Iter {
+    // ranges, strings, lists, maps and sets, which need nothing from the backend
+    Items(Items),
+    // an instance or a handle with a `next` method, which the backend calls
+    Object(Value),
+    // a generator, resumed for every item
+    Coroutine(Rc<Coroutine>),
+}
+
+impl Iter {
+    pub fn new(value: Value, span: Span) -> Result<Iter, RuntimeError> {
+        if let Some(items) = Items::new(&value) {
+            return Ok(Iter::Items(items));
+        }
+
+        match value {
+            // classes only need a `next` method, implementing `Iterator` is optional
+            Value::Instance(ref instance) if has_next(&instance.class) => Ok(Iter::Object(value)),
+            // handles with a `next` method, like files and lazy iterators
+            Value::Handle(ref handle) if handle.method("next").is_some() => Ok(Iter::Object(value)),
+            Value::Coroutine(coroutine) if coroutine.kind == CoroutineKind::Generator => {
+                Ok(Iter::Coroutine(coroutine))
+            }
+            value => Err(RuntimeError::new(
+                format!("`{}` is not iterable", value.type_name()),
+                span,
+            )),
+        }
+    }
+}
+
+fn has_next(class: &Class) -> bool {
+    class.implements(BuiltinInterface::Iterator)
+        || class.methods.contains_key("next")
+        || class.natives.contains_key("next")
+}
+
+/// The items of the values which can be iterated without calling back into kai code
+#[derive(Debug)]
+pub(crate) enum Items {
+    Range(std::ops::Range<i64>),
+    Chars(std::vec::IntoIter<char>),
+    // the list is read as the loop goes, items pushed while iterating are visited too
+    List(Rc<RefCell<Vec<Value>>>, usize),
+    // the keys of a map or the elements of a set, copied when the loop starts
+    Keys(std::vec::IntoIter<Value>),
+}
+
+impl Items {
+    /// The items of a range, string, list or set, or the keys of a map
+    pub fn new(value: &Value) -> Option<Items> {
+        let items = match value {
+            Value::Range(start, end, _) => Items::Range(*start..*end),
+            Value::Str(value) => Items::Chars(value.chars().collect::<Vec<_>>().into_iter()),
+            Value::List(items) => Items::List(items.clone(), 0),
+            Value::Map(map) => {
+                let keys: Vec<Value> = map.borrow().iter().map(|(key, _)| key.to_value()).collect();
+                Items::Keys(keys.into_iter())
+            }
+            Value::Set(set) => {
+                let keys: Vec<Value> = set.borrow().iter().map(Key::to_value).collect();
+                Items::Keys(keys.into_iter())
+            }
+            _ => return None,
+        };
+        Some(items)
+    }
+}
+
+impl Iterator for Items {
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Value> {
+        match self {
+            Items::Range(range) => range.next().map(Value::Int),
+            Items::Chars(chars) => chars.next().map(|c| Value::Str(c.into())),
+            Items::List(items, idx) => {
+                let item = items.borrow().get(*idx).cloned()?;
+                *idx += 1;
+                Some(item)
+            }
+            Items::Keys(keys) => keys.next(),
+        }
+    }
+}
+
+/// `start..end` and `start..=end`, missing bounds are filled in by the caller
+pub(crate) fn range(start: i64, end: i64, closed: bool) -> Value {
+    match closed {
+        true => Value::Range(start, end.saturating_add(1), true),
+        false => Value::Range(start, end, false),
+    }
+}
+
+/// What `value` is stored under in a map or a set
+pub(crate) fn key(value: &Value, span: Span) -> Result<Key, RuntimeError> {
+    Key::from_value(value).ok_or_else(|| {
+        let message = format!(
+            "`{}` can't be a key, only `bool`, `int` and `string` can",
+            value.type_name()
+        );
+        RuntimeError::new(message, span)
+    })
+}
+
+/// `{a: b, c: d}` from its keys and values in turn
+pub(crate) fn map(heap: &mut Heap, values: Vec<Value>, span: Span) -> Result<Value, RuntimeError> {
+    let mut map = Map::new();
+    let mut values = values.into_iter();
+
+    while let (Some(key), Some(value)) = (values.next(), values.next()) {
+        map.insert(self::key(&key, span)?, value);
+    }
+    limits::track(heap, Value::map(map), span)
+}
+
+/// `{a, b}`, repeated elements are kept once
+pub(crate) fn set(heap: &mut Heap, items: Vec<Value>, span: Span) -> Result<Value, RuntimeError> {
+    let set = items
+        .iter()
+        .map(|item| key(item, span))
+        .collect::<Result<Set, _>>()?;
+    limits::track(heap, Value::set(set), span)
+}
+
+/// `base[index]`, the element of a list, a slice of it when `index` is a range, or the
+/// value of a key in a map which is `nil` when the key is missing
+pub(crate) fn index(
+    heap: &mut Heap,
+    base: &Value,
+    index: &Value,
+    span: Span,
+) -> Result<Value, RuntimeError> {
+    match (base, index) {
+        (Value::List(items), Value::Int(idx)) => {
+            let items = items.borrow();
+            let idx = list_index(*idx, items.len(), span)?;
+            Ok(items[idx].clone())
+        }
+        (Value::List(items), Value::Range(start, end, _)) => {
+            let slice = {
+                let items = items.borrow();
+                let range = slice_range(*start, *end, items.len(), span)?;
+                items[range].to_vec()
+            };
+            limits::track(heap, Value::list(slice), span)
+        }
+        (Value::Map(map), key) => {
+            let key = self::key(key, span)?;
+            Ok(map.borrow().get(&key).cloned().unwrap_or(Value::Nil))
+        }
+        _ => Err(index_error(base, index, span)),
+    }
+}
+
+/// `base[index] = value`, replacing an element of a list or setting a key of a map
+pub(crate) fn set_index(
+    heap: &mut Heap,
+    base: &Value,
+    index: &Value,
+    value: Value,
+    span: Span,
+) -> Result<(), RuntimeError> {
+    match (base, index) {
+        (Value::List(items), Value::Int(idx)) => {
+            let idx = list_index(*idx, items.borrow().len(), span)?;
+            limits::reserve(heap, base, 0, gc::string_bytes(&value), span)?;
+            items.borrow_mut()[idx] = value;
+            Ok(())
+        }
+        (Value::Map(map), index) => {
+            let key = self::key(index, span)?;
+            // a new key takes an entry, and its string is held too
+            let (entries, mut strings) = match map.borrow().get(&key) {
+                Some(_) => (0, 0),
+                None => (1, gc::string_bytes(index)),
+            };
+            strings += gc::string_bytes(&value);
+            limits::reserve(heap, base, entries, strings, span)?;
+            map.borrow_mut().insert(key, value);
+            Ok(())
+        }
+        _ => Err(index_error(base, index, span)),
+    }
+}
+
+fn list_index(idx: i64, len: usize, span: Span) -> Result<usize, RuntimeError> {
+    match usize::try_from(idx) {
+        Ok(idx) if idx < len => Ok(idx),
+        _ => Err(RuntimeError::new(
+            format!("index {idx} is out of range for a list of {len} items"),
+            span,
+        )),
+    }
+}
+
+// an open end is `i64::MAX`, see `range`
+fn slice_range(
+    start: i64,
+    end: i64,
+    len: usize,
+    span: Span,
+) -> Result<std::ops::Range<usize>, RuntimeError> {
+    let end = if end == i64::MAX { len as i64 } else { end };
+
+    match (usize::try_from(start), usize::try_from(end)) {
+        (Ok(start), Ok(end)) if start <= end && end <= len => Ok(start..end),
+        _ => Err(RuntimeError::new(
+            format!("slice {start}..{end} is out of range for a list of {len} items"),
+            span,
+        )),
+    }
+}
+
+fn index_error(base: &Value, index: &Value, span: Span) -> RuntimeError {
+    let message = match base {
+        Value::List(_) => format!(
+            "a list can only be indexed with an `int` or a `range`, found `{}`",
+            index.type_name()
+        ),
+        base => format!("cannot index into `{}`", base.type_name()),
+    };
+    RuntimeError::new(message, span)
+}
+
+pub(crate) fn module_member(
+    host: &Host,
+    path: &str,
+    name: &str,
+    span: Span,
+) -> Result<Value, RuntimeError> {
+    host.lookup(&format!("{path}.{name}"))
+        .ok_or_else(|| RuntimeError::new(format!("cannot find `{name}` in `{path}`"), span))
+}
+
+/// A method of a class registered by the host, of a handle or of a builtin value and the
+/// arguments to call it with, the receiver is passed first to methods taking `self`
+pub(crate) fn native_method(
+    receiver: &Value,
+    name: &str,
+    args: Vec<Value>,
+    span: Span,
+) -> Result<(Value, Vec<Value>), RuntimeError> {
+    let (class, on_instance) = match receiver {
+        Value::Instance(instance) => (&instance.class, true),
+        Value::Class(class) => (class, false),
+        Value::Handle(handle) => {
+            let Some(native) = handle.method(name) else {
+                return Err(no_method(name, receiver, span));
+            };
+            let args = std::iter::once(receiver.clone()).chain(args).collect();
+            return Ok((Value::Native(Rc::new(native)), args));
+        }
+        Value::Str(_)
+        | Value::List(_)
+        | Value::Map(_)
+        | Value::Set(_)
+        | Value::Range(..)
+        | Value::Error(_) => {
+            let Some(native) = stdlib::value_method(receiver, name) else {
+                return Err(no_method(name, receiver, span));
+            };
+            let args = std::iter::once(receiver.clone()).chain(args).collect();
+            return Ok((Value::Native(Rc::new(native)), args));
+        }
+        _ => return Err(no_method(name, receiver, span)),
+    };
+
+    let Some(method) = class.natives.get(name) else {
+        return Err(no_method(name, receiver, span));
+    };
+    let native = Value::Native(method.native.clone());
+
+    match (method.has_self, on_instance) {
+        (true, true) => Ok((
+            native,
+            std::iter::once(receiver.clone()).chain(args).collect(),
+        )),
+        (false, false) => Ok((native, args)),
+        (true, false) => Err(RuntimeError::new(
+            format!(
+                "`{name}` takes `self`, call it on an instance of `{}`",
+                class.name
+            ),
+            span,
+        )),
+        (false, true) => Err(RuntimeError::new(
+            format!(
+                "`{name}` is a static method, call it as `{}.{name}(..)`",
+                class.name
+            ),
+            span,
+        )),
+    }
+}
+
+pub(crate) fn get_field(
+    host: &Host,
+    base: &Value,
+    name: &str,
+    span: Span,
+) -> Result<Value, RuntimeError> {
+    let field = match base {
+        Value::Instance(instance) => instance.field(name),
+        Value::Module(path) => return module_member(host, path, name, span),
+        // `Person.new` without calling it
+        Value::Class(class) => match class.methods.get(name) {
+            Some(id) => Some(Value::Fn(*id)),
+            None => class
+                .natives
+                .get(name)
+                .map(|method| Value::Native(method.native.clone())),
+        },
+        Value::Error(error) => match name {
+            "message" => Some(Value::Str(error.message.clone())),
+            "cause" => Some(error.cause.clone().map_or(Value::Nil, Value::Error)),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    field.ok_or_else(|| {
+        let message = format!("no field `{name}` on `{}`", base.type_name());
+        RuntimeError::new(message, span)
+    })
+}
+
+pub(crate) fn set_field(
+    base: &Value,
+    name: &str,
+    value: Value,
+    span: Span,
+) -> Result<(), RuntimeError> {
+    if let Value::Instance(instance) = base {
+        if let Some(idx) = instance.class.field_index(name) {
+            instance.fields.borrow_mut()[idx] = value;
+            return Ok(());
+        }
+    }
+
+    let message = format!("no field `{name}` on `{}`", base.type_name());
+    Err(RuntimeError::new(message, span))
+}
+
+pub(crate) fn no_method(name: &str, receiver: &Value, span: Span) -> RuntimeError {
+    let ty = match receiver {
+        Value::Instance(instance) => instance.class.name.clone(),
+        Value::Class(class) => class.name.clone(),
+        value => value.type_name(),
+    };
+
+    RuntimeError::new(format!("no method `{name}` on `{ty}`"), span)
+}
+
+pub(crate) fn wrong_arg_count(
+    name: &str,
+    expected: usize,
+    found: usize,
+    span: Span,
+) -> RuntimeError {
+    RuntimeError::new(arg_count_message(name, expected, found), span)
+}
+
+pub(crate) fn arg_count_message(name: &str, expected: usize, found: usize) -> String {
+    format!(
+        "`{name}` takes {expected} argument{} but {found} {} supplied",
+        if expected == 1 { "" } else { "s" },
+        if found == 1 { "was" } else { "were" },
+    )
+}
+
+/// An error returned from `main` or a test stops the program like a panic would
+pub(crate) fn uncaught_error(value: Value, name: &str, span: Span) -> Result<Value, RuntimeError> {
+    match value {
+        Value::Error(error) => Err(RuntimeError::new(
+            format!("`{name}` returned an error: {error}"),
+            span,
+        )),
+        value => Ok(value),
+    }
+}
+
+pub(crate) fn panic_message(message: Option<String>) -> String {
+    match message {
+        Some(message) => format!("panicked: {message}"),
+        None => "explicit panic".to_owned(),
+    }
+}
+
+/// The trace of an error at `span`, `calls` are the active calls outermost first, each
+/// with the span it was called from
+pub(crate) fn trace<'a>(
+    calls: impl DoubleEndedIterator<Item = (&'a str, Span)>,
+    mut span: Span,
+) -> Vec<TraceFrame> {
+    calls
+        .rev()
+        .map(|(function, call)| {
+            let frame = TraceFrame {
+                function: function.to_owned(),
+                span,
+            };
+            span = call;
+            frame
+        })
+        .collect()
+}
@@ -0,0 +1,555 @@
+//! Native modules available under `kai`
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use ecow::EcoString;
+
+use super::error::RuntimeError;
+use super::executor::{Future, Source};
+use super::gc::string_bytes;
+use super::limits::{self, Capability};
+use super::ops;
+use super::value::{Class, Instance, NativeContext, NativeFn, NativeFnPtr, NativeMethod, Value};
+use crate::ast::NodeId;
+use crate::resolve::BuiltinInterface;
+use crate::typeck::{FnSig, Type};
+
+mod collections;
+mod env;
+mod error;
+mod fs;
+mod gc;
+mod io;
+mod iter;
+mod json;
+mod math;
+mod net;
+mod path;
+mod process;
+mod regex;
+mod string;
+mod task;
+mod time;
+
+// the name, the function and its signature for the checker. Signatures are written like
+// `(string, int?) -> result<string>`, a `?` marks a parameter which can be left out and `..`
+// takes any number of arguments after the others, on the return type it makes it optional.
+// `num` is an `int` or a `float`, it and `any`, `fn` and classes like `Path` are not checked.
+// Methods name the types inside their receiver, like `T` in `(list<T>, T) -> nil`.
+type Fns = &'static [(&'static str, NativeFnPtr, &'static str)];
+
+// functions of a module needing the same capability
+type Group = (Option<Capability>, Fns);
+
+// every module with its functions
+const MODULES: &[(&str, &[Group])] = &[
+    ("kai", &[]),
+    ("kai.collections", &[(None, collections::FNS)]),
+    ("kai.env", &[(Some(Capability::Env), env::FNS)]),
+    ("kai.error", &[(None, error::FNS)]),
+    ("kai.fs", &[(Some(Capability::Fs), fs::FNS)]),
+    ("kai.gc", &[(None, gc::FNS)]),
+    (
+        "kai.io",
+        &[(None, io::FNS), (Some(Capability::Fs), io::FILE_FNS)],
+    ),
+    ("kai.iter", &[(None, iter::FNS)]),
+    ("kai.json", &[(None, json::FNS)]),
+    ("kai.math", &[(None, math::FNS)]),
+    ("kai.net", &[(Some(Capability::Net), net::FNS)]),
+    ("kai.path", &[(None, path::FNS)]),
+    (
+        "kai.process",
+        &[
+            (None, process::SCRIPT_FNS),
+            (Some(Capability::Process), process::FNS),
+        ],
+    ),
+    ("kai.regex", &[(None, regex::FNS)]),
+    ("kai.string", &[(None, string::FNS)]),
+    ("kai.task", &[(None, task::FNS)]),
+    ("kai.time", &[(None, time::FNS)]),
+];
+
+// the values of modules which aren't functions
+const CONSTANTS: &[(&str, &[(&str, Value)])] = &[("kai.math", math::CONSTANTS)];
+
+// the classes of modules by their full path, made on first use
+type ClassFn = fn() -> Rc<Class>;
+
+const CLASSES: &[(&str, ClassFn)] = &[
+    ("kai.path.Path", path::class),
+    ("kai.time.Duration", time::duration_class),
+    ("kai.time.DateTime", time::date_time_class),
+];
+
+/// Finds a module, a native function, a constant or a class by its full path, eg. `kai.io` or
+/// `kai.io.print`
+pub(crate) fn lookup(path: &str) -> Option<Value> {
+    if MODULES.iter().any(|(module, _)| *module == path) {
+        return Some(Value::Module(path.into()));
+    }
+    if let Some((_, class)) = CLASSES.iter().find(|(class, _)| *class == path) {
+        return Some(Value::Class(class()));
+    }
+
+    let (module, name) = path.rsplit_once('.')?;
+    if let Some((_, constants)) = CONSTANTS.iter().find(|(path, _)| *path == module) {
+        if let Some((_, value)) = constants.iter().find(|(constant, _)| *constant == name) {
+            return Some(value.clone());
+        }
+    }
+    let (_, groups) = MODULES.iter().find(|(path, _)| *path == module)?;
+
+    groups.iter().find_map(|(capability, fns)| {
+        let (name, func, _) = fns.iter().find(|(fn_name, ..)| *fn_name == name)?;
+        Some(Value::Native(Rc::new(NativeFn {
+            name: (*name).into(),
+            func: Rc::new(*func),
+            capability: *capability,
+        })))
+    })
+}
+
+/// Whether the path is a module like `kai.io`
+pub(crate) fn is_module(path: &str) -> bool {
+    MODULES.iter().any(|(module, _)| *module == path)
+}
+
+/// What the checker knows of a function of a module
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct NativeSig {
+    pub params: Vec<Type>,
+    // the params after these can be left out
+    pub required: usize,
+    // any number of arguments can follow the params
+    pub variadic: bool,
+    pub ret: Type,
+}
+
+impl NativeSig {
+    /// The type of the function as a value, `unknown` when it takes a varying number of
+    /// arguments
+    pub fn fn_ty(&self) -> Type {
+        if self.variadic || self.required < self.params.len() {
+            return Type::Unknown;
+        }
+        Type::Fn(FnSig {
+            params: self.params.clone(),
+            ret: Box::new(self.ret.clone()),
+        })
+    }
+}
+
+/// The signature of a function by its full path, eg. `kai.math.sqrt`
+pub(crate) fn signature(path: &str) -> Option<NativeSig> {
+    let (module, name) = path.rsplit_once('.')?;
+    let (_, groups) = MODULES.iter().find(|(path, _)| *path == module)?;
+    let (_, _, sig) = groups
+        .iter()
+        .flat_map(|(_, fns)| fns.iter())
+        .find(|(fn_name, ..)| *fn_name == name)?;
+    Some(parse_sig(sig, &[]))
+}
+
+// the methods of values of a type and what their signatures call the types inside it
+fn methods(receiver: &Type) -> Option<(Fns, Vec<(&'static str, Type)>)> {
+    Some(match receiver {
+        // the functions of `kai.string` all take the string first
+        Type::String => (string::FNS, Vec::new()),
+        Type::List(elem) => (collections::LIST_METHODS, vec![("T", (**elem).clone())]),
+        Type::Map(key, value) => (
+            collections::MAP_METHODS,
+            vec![("K", (**key).clone()), ("V", (**value).clone())],
+        ),
+        Type::Set(elem) => (collections::SET_METHODS, vec![("T", (**elem).clone())]),
+        Type::Range => (iter::RANGE_METHODS, Vec::new()),
+        Type::Error => (error::ERROR_METHODS, Vec::new()),
+        _ => return None,
+    })
+}
+
+/// Whether values of the type have the methods of `value_method`
+pub(crate) fn has_methods(receiver: &Type) -> bool {
+    methods(receiver).is_some()
+}
+
+/// The signature of a method of `value_method` without the receiver, eg. `push` of a
+/// `list<int>` takes an `int`
+pub(crate) fn method_signature(receiver: &Type, name: &str) -> Option<NativeSig> {
+    let (fns, inner) = methods(receiver)?;
+    let (_, _, sig) = fns.iter().find(|(method, ..)| *method == name)?;
+
+    let mut sig = parse_sig(sig, &inner);
+    sig.params.remove(0);
+    sig.required -= 1;
+    Some(sig)
+}
+
+/// The type of a function, a constant, a class or a module of `kai` by its full path
+pub(crate) fn member_ty(path: &str) -> Option<Type> {
+    if let Some(sig) = signature(path) {
+        return Some(sig.fn_ty());
+    }
+    Some(match lookup(path)? {
+        Value::Int(_) => Type::Int,
+        Value::Float(_) => Type::Float,
+        _ => Type::Unknown,
+    })
+}
+
+// the signatures are part of the tables, a malformed one is a bug the tests catch. `inner`
+// are the types inside the receiver of a method.
+fn parse_sig(sig: &str, inner: &[(&str, Type)]) -> NativeSig {
+    let (params, ret) = sig
+        .strip_prefix('(')
+        .and_then(|sig| sig.split_once(") -> "))
+        .unwrap_or_else(|| panic!("malformed signature `{sig}`"));
+
+    let mut out = NativeSig {
+        params: Vec::new(),
+        required: 0,
+        variadic: false,
+        ret: parse_sig_ty(ret, inner),
+    };
+    for param in split_sig_list(params) {
+        if param == ".." {
+            out.variadic = true;
+        } else if let Some(param) = param.strip_suffix('?') {
+            out.params.push(parse_sig_ty(param, inner));
+        } else {
+            assert_eq!(
+                out.required,
+                out.params.len(),
+                "`{sig}` requires a param after an optional one"
+            );
+            out.params.push(parse_sig_ty(param, inner));
+            out.required += 1;
+        }
+    }
+    out
+}
+
+fn parse_sig_ty(ty: &str, inner: &[(&str, Type)]) -> Type {
+    if let Some(ty) = ty.strip_suffix('?') {
+        return parse_sig_ty(ty, inner).optional();
+    }
+    if let Some((_, inner)) = inner.iter().find(|(name, _)| *name == ty) {
+        return inner.clone();
+    }
+    if let Some((name, args)) = ty.strip_suffix('>').and_then(|ty| ty.split_once('<')) {
+        let mut args = split_sig_list(args)
+            .into_iter()
+            .map(|arg| parse_sig_ty(arg, inner));
+        let mut arg = || Box::new(args.next().unwrap_or(Type::Unknown));
+        return match name {
+            "list" => Type::List(arg()),
+            "set" => Type::Set(arg()),
+            "map" => Type::Map(arg(), arg()),
+            "result" => Type::Result(arg()),
+            "future" => Type::Future(arg()),
+            _ => panic!("unknown generic type `{ty}` in a signature"),
+        };
+    }
+
+    match ty {
+        "int" => Type::Int,
+        "float" => Type::Float,
+        "string" => Type::String,
+        "bool" => Type::Bool,
+        "nil" => Type::Nil,
+        "range" => Type::Range,
+        "error" => Type::Error,
+        "list" => Type::List(Box::new(Type::Unknown)),
+        "set" => Type::Set(Box::new(Type::Unknown)),
+        "map" => Type::Map(Box::new(Type::Unknown), Box::new(Type::Unknown)),
+        "num" | "any" | "fn" => Type::Unknown,
+        // classes of modules, the checker only knows the ones of the script
+        class if class.starts_with(char::is_uppercase) => Type::Unknown,
+        _ => panic!("unknown type `{ty}` in a signature"),
+    }
+}
+
+// splits at the commas which aren't inside `<..>`
+fn split_sig_list(list: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (idx, c) in list.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(list[start..idx].trim());
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+    let last = list[start..].trim();
+    if !last.is_empty() {
+        parts.push(last);
+    }
+    parts
+}
+
+/// A method of a string, list, map, set, range or error, the receiver is passed as the first argument
+pub(crate) fn value_method(receiver: &Value, name: &str) -> Option<NativeFn> {
+    let methods = match receiver {
+        Value::Str(_) => string::FNS,
+        Value::List(_) => collections::LIST_METHODS,
+        Value::Map(_) => collections::MAP_METHODS,
+        Value::Set(_) => collections::SET_METHODS,
+        Value::Range(..) => iter::RANGE_METHODS,
+        Value::Error(_) => error::ERROR_METHODS,
+        _ => return None,
+    };
+    let (name, func, _) = methods.iter().find(|(method, ..)| *method == name)?;
+    Some(NativeFn {
+        name: (*name).into(),
+        func: Rc::new(*func),
+        capability: None,
+    })
+}
+
+/// The arguments of a native function taking exactly `N` of them
+pub(super) fn args<const N: usize>(
+    cx: &dyn NativeContext,
+    name: &str,
+    args: Vec<Value>,
+) -> Result<[Value; N], RuntimeError> {
+    let found = args.len();
+    args.try_into()
+        .map_err(|_| cx.error(ops::arg_count_message(name, N, found)))
+}
+
+/// The arguments of a native function taking `required` to `N` of them, the ones left out
+/// are `None`
+pub(super) fn opt_args<const N: usize>(
+    cx: &dyn NativeContext,
+    name: &str,
+    required: usize,
+    args: Vec<Value>,
+) -> Result<[Option<Value>; N], RuntimeError> {
+    let found = args.len();
+    if !(required..=N).contains(&found) {
+        let supplied = if found == 1 { "was" } else { "were" };
+        let message =
+            format!("`{name}` takes {required} to {N} arguments but {found} {supplied} supplied");
+        return Err(cx.error(message));
+    }
+
+    let mut args = args.into_iter();
+    Ok(std::array::from_fn(|_| args.next()))
+}
+
+fn expect_str(cx: &dyn NativeContext, value: Value) -> Result<EcoString, RuntimeError> {
+    match value {
+        Value::Str(value) => Ok(value),
+        value => Err(cx.error(format!("expected `string`, found `{}`", value.type_name()))),
+    }
+}
+
+fn expect_bool(cx: &dyn NativeContext, value: Value) -> Result<bool, RuntimeError> {
+    match value {
+        Value::Bool(value) => Ok(value),
+        value => Err(cx.error(format!("expected `bool`, found `{}`", value.type_name()))),
+    }
+}
+
+/// An instance on the heap of the engine, failing like the backends do once the heap limit
+/// is reached
+fn alloc(
+    cx: &mut dyn NativeContext,
+    class: Rc<Class>,
+    fields: Vec<Value>,
+) -> Result<Value, RuntimeError> {
+    match cx.heap().alloc(class, fields) {
+        Some(instance) => Ok(Value::Instance(instance)),
+        None => {
+            let span = cx.error(String::new()).span;
+            Err(limits::out_of_memory(cx.heap().limit(), span))
+        }
+    }
+}
+
+/// A new list, map or set on the heap of the engine, see `alloc`
+fn track(cx: &mut dyn NativeContext, value: Value) -> Result<Value, RuntimeError> {
+    let span = cx.error(String::new()).span;
+    limits::track(cx.heap(), value, span)
+}
+
+/// Fails like the backends do when a string of `bytes` can't fit under the heap limit,
+/// checked before building it
+fn fits(cx: &mut dyn NativeContext, bytes: usize) -> Result<(), RuntimeError> {
+    match cx.heap().fits(bytes) {
+        true => Ok(()),
+        false => {
+            let span = cx.error(String::new()).span;
+            Err(limits::out_of_memory(cx.heap().limit(), span))
+        }
+    }
+}
+
+/// Charges an element about to be added to a list or set to the heap of the engine
+fn reserve(
+    cx: &mut dyn NativeContext,
+    collection: &Value,
+    item: &Value,
+) -> Result<(), RuntimeError> {
+    let span = cx.error(String::new()).span;
+    let strings = string_bytes(item);
+    limits::reserve(cx.heap(), collection, 1, strings, span)
+}
+
+/// A list of `items` on the heap of the engine. A step is spent and the heap limit checked
+/// for every item, so natives making long lists stop at the limits before they are built.
+fn list(
+    cx: &mut dyn NativeContext,
+    items: impl IntoIterator<Item = Value>,
+) -> Result<Value, RuntimeError> {
+    let list = track(cx, Value::list(Vec::new()))?;
+    for item in items {
+        cx.tick()?;
+        reserve(cx, &list, &item)?;
+        if let Value::List(list) = &list {
+            list.borrow_mut().push(item);
+        }
+    }
+    Ok(list)
+}
+
+fn future(name: &'static str, source: impl Source + 'static) -> Value {
+    Value::Future(Rc::new(Future::new(name, source)))
+}
+
+/// An instance of a class without methods, for results made of several values. It is not
+/// traced by the collector, its fields must not refer back to it.
+fn object<const N: usize>(name: &str, fields: [(&str, Value); N]) -> Value {
+    let class = Class {
+        id: NodeId::DUMMY,
+        name: name.into(),
+        fields: fields.iter().map(|(name, _)| (*name).into()).collect(),
+        methods: HashMap::new(),
+        builtins: Vec::new(),
+        natives: HashMap::new(),
+    };
+
+    Value::Instance(Rc::new(Instance {
+        class: Rc::new(class),
+        fields: RefCell::new(fields.into_iter().map(|(_, value)| value).collect()),
+    }))
+}
+
+/// A class of a module like `Path`, its methods are natives and the ones taking `self` get
+/// the instance first
+fn native_class(
+    name: &str,
+    fields: &[&str],
+    builtins: Vec<BuiltinInterface>,
+    methods: &[(&str, NativeFnPtr, bool)],
+) -> Class {
+    Class {
+        id: NodeId::DUMMY,
+        name: name.into(),
+        fields: fields.iter().map(|&field| field.into()).collect(),
+        methods: HashMap::new(),
+        builtins,
+        natives: methods
+            .iter()
+            .map(|&(name, func, has_self)| {
+                let native = NativeFn {
+                    name: name.into(),
+                    func: Rc::new(func),
+                    capability: None,
+                };
+                let method = NativeMethod {
+                    native: Rc::new(native),
+                    has_self,
+                };
+                (name.into(), method)
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn looks_up_modules_and_functions() {
+        assert_eq!(lookup("kai.io"), Some(Value::Module("kai.io".into())));
+        assert!(
+            matches!(lookup("kai.io.print"), Some(Value::Native(native)) if native.name == "print")
+        );
+        assert_eq!(lookup("kai.io.nothing"), None);
+        assert_eq!(lookup("kai.nothing"), None);
+        assert_eq!(lookup("kai.math.max_int"), Some(Value::Int(i64::MAX)));
+        assert!(matches!(
+            lookup("kai.env.get"),
+            Some(Value::Native(native)) if native.capability == Some(Capability::Env)
+        ));
+        assert!(matches!(
+            lookup("kai.io.open"),
+            Some(Value::Native(native)) if native.capability == Some(Capability::Fs)
+        ));
+    }
+
+    #[test]
+    fn parses_every_signature() {
+        for (module, groups) in MODULES {
+            for (_, fns) in groups.iter() {
+                for (name, ..) in fns.iter() {
+                    signature(&format!("{module}.{name}"));
+                }
+            }
+        }
+        let receivers = [
+            Type::String,
+            Type::List(Box::new(Type::Int)),
+            Type::Map(Box::new(Type::String), Box::new(Type::Int)),
+            Type::Set(Box::new(Type::Int)),
+            Type::Range,
+            Type::Error,
+        ];
+        for receiver in &receivers {
+            let (fns, _) = methods(receiver).unwrap();
+            for (name, ..) in fns.iter() {
+                method_signature(receiver, name);
+            }
+        }
+
+        assert_eq!(
+            signature("kai.string.slice"),
+            Some(NativeSig {
+                params: vec![Type::String, Type::Int, Type::Int],
+                required: 2,
+                variadic: false,
+                ret: Type::String,
+            })
+        );
+        assert_eq!(
+            parse_sig("(string, ..) -> result<map<string, list<int>>>", &[]).ret,
+            Type::Result(Box::new(Type::Map(
+                Box::new(Type::String),
+                Box::new(Type::List(Box::new(Type::Int)))
+            )))
+        );
+        assert!(parse_sig("(..) -> nil", &[]).variadic);
+        assert_eq!(signature("kai.io.nothing"), None);
+
+        let map = Type::Map(Box::new(Type::String), Box::new(Type::Int));
+        assert_eq!(
+            method_signature(&map, "remove"),
+            Some(NativeSig {
+                params: vec![Type::String],
+                required: 1,
+                variadic: false,
+                ret: Type::Int.optional(),
+            })
+        );
+        assert_eq!(method_signature(&map, "push"), None);
+    }
+}
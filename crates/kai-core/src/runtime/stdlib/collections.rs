@@ -0,0 +1,342 @@
+//! Methods of lists, maps and sets, and `collections.set` for making sets
+//!
+//! Methods taking a function call it with each element, `xs.map(double)`. The elements
+//! are copied out first, so the function may change the collection without affecting
+//! the call.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::runtime::error::RuntimeError;
+use crate::runtime::ops;
+use crate::runtime::value::{Key, Map, NativeContext, NativeFnPtr, Set, Value};
+
+use super::{args, iter, opt_args, reserve, track};
+
+pub(super) const FNS: &[(&str, NativeFnPtr, &str)] = &[("set", set, "(list?) -> set")];
+
+// every method takes the collection first, `T` is the type of the elements and `K` and `V`
+// the ones of the keys and values
+pub(super) const LIST_METHODS: &[(&str, NativeFnPtr, &str)] = &[
+    ("len", list_len, "(list<T>) -> int"),
+    ("is_empty", list_is_empty, "(list<T>) -> bool"),
+    ("push", push, "(list<T>, T) -> nil"),
+    ("pop", pop, "(list<T>) -> T?"),
+    ("contains", list_contains, "(list<T>, T) -> bool"),
+    ("map", map, "(list<T>, fn) -> list"),
+    ("filter", filter, "(list<T>, fn) -> list<T>"),
+    ("reduce", reduce, "(list<T>, fn, any?) -> any"),
+    ("sort_by", sort_by, "(list<T>, fn) -> nil"),
+    ("iter", iter::from, "(list<T>) -> any"),
+];
+
+pub(super) const MAP_METHODS: &[(&str, NativeFnPtr, &str)] = &[
+    ("len", map_len, "(map<K, V>) -> int"),
+    ("is_empty", map_is_empty, "(map<K, V>) -> bool"),
+    ("contains", map_contains, "(map<K, V>, K) -> bool"),
+    ("keys", keys, "(map<K, V>) -> list<K>"),
+    ("values", values, "(map<K, V>) -> list<V>"),
+    ("remove", map_remove, "(map<K, V>, K) -> V?"),
+    ("iter", iter::from, "(map<K, V>) -> any"),
+];
+
+pub(super) const SET_METHODS: &[(&str, NativeFnPtr, &str)] = &[
+    ("len", set_len, "(set<T>) -> int"),
+    ("is_empty", set_is_empty, "(set<T>) -> bool"),
+    ("contains", set_contains, "(set<T>, T) -> bool"),
+    ("insert", insert, "(set<T>, T) -> bool"),
+    ("remove", set_remove, "(set<T>, T) -> bool"),
+    ("to_list", to_list, "(set<T>) -> list<T>"),
+    ("iter", iter::from, "(set<T>) -> any"),
+];
+
+// `set()` or `set(list)`, `{}` is an empty map so empty sets are made with this
+fn set(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [items] = opt_args(cx, "set", 0, args)?;
+    let items = match items {
+        Some(items) => expect_list(cx, items)?.borrow().clone(),
+        None => Vec::new(),
+    };
+
+    let set = items
+        .iter()
+        .map(|item| key(cx, item))
+        .collect::<Result<Set, _>>()?;
+    track(cx, Value::set(set))
+}
+
+fn list_len(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [list] = self::args(cx, "len", args)?;
+    Ok(Value::Int(expect_list(cx, list)?.borrow().len() as i64))
+}
+
+fn list_is_empty(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [list] = self::args(cx, "is_empty", args)?;
+    Ok(Value::Bool(expect_list(cx, list)?.borrow().is_empty()))
+}
+
+fn push(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [list, item] = self::args(cx, "push", args)?;
+    reserve(cx, &list, &item)?;
+    expect_list(cx, list)?.borrow_mut().push(item);
+    Ok(Value::Nil)
+}
+
+// removes the last element and returns it, `nil` when the list is empty
+fn pop(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [list] = self::args(cx, "pop", args)?;
+    Ok(expect_list(cx, list)?
+        .borrow_mut()
+        .pop()
+        .unwrap_or(Value::Nil))
+}
+
+// compares with `==`, so instances implementing `Eq` are compared by their `eq`
+fn list_contains(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [list, needle] = self::args(cx, "contains", args)?;
+    let items = expect_list(cx, list)?.borrow().clone();
+
+    for item in items {
+        let equal = match ops::has_user_eq(&item) {
+            true => {
+                let result = cx.call(item, "eq", vec![needle.clone()])?;
+                ops::expect_eq_result(result, cx.error(String::new()).span)?
+            }
+            false => item == needle,
+        };
+        if equal {
+            return Ok(Value::Bool(true));
+        }
+    }
+    Ok(Value::Bool(false))
+}
+
+// a new list with `f(item)` for every item
+fn map(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [list, f] = self::args(cx, "map", args)?;
+    let items = expect_list(cx, list)?.borrow().clone();
+
+    let mapped = items
+        .into_iter()
+        .map(|item| cx.apply(f.clone(), vec![item]))
+        .collect::<Result<_, _>>()?;
+    track(cx, Value::list(mapped))
+}
+
+// a new list with the items `f` returns `true` for
+fn filter(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [list, f] = self::args(cx, "filter", args)?;
+    let items = expect_list(cx, list)?.borrow().clone();
+
+    let mut kept = Vec::new();
+    for item in items {
+        match cx.apply(f.clone(), vec![item.clone()])? {
+            Value::Bool(true) => kept.push(item),
+            Value::Bool(false) => {}
+            value => return Err(returned(cx, "filter", "bool", &value)),
+        }
+    }
+    track(cx, Value::list(kept))
+}
+
+// `reduce(f)` or `reduce(f, initial)`, folds the items into one value with
+// `acc = f(acc, item)`. Without `initial` the first item starts, an empty list gives `nil`.
+fn reduce(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [list, f, initial] = opt_args(cx, "reduce", 2, args)?;
+    let items = expect_list(cx, list.unwrap_or(Value::Nil))?
+        .borrow()
+        .clone();
+    let f = f.unwrap_or(Value::Nil);
+
+    let mut items = items.into_iter();
+    let Some(mut acc) = initial.or_else(|| items.next()) else {
+        return Ok(Value::Nil);
+    };
+    for item in items {
+        acc = cx.apply(f.clone(), vec![acc, item])?;
+    }
+    Ok(acc)
+}
+
+// sorts the list in place with `f(a, b)` returning a negative `int` when `a` goes before
+// `b`, a positive one when it goes after and `0` to keep their order
+fn sort_by(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [list, f] = self::args(cx, "sort_by", args)?;
+    let list = expect_list(cx, list)?;
+    let items = list.borrow().clone();
+
+    let mut before = |cx: &mut dyn NativeContext, a: &Value, b: &Value| match cx
+        .apply(f.clone(), vec![a.clone(), b.clone()])?
+    {
+        Value::Int(ordering) => Ok(ordering < 0),
+        value => Err(returned(cx, "sort_by", "int", &value)),
+    };
+    let sorted = merge_sort(cx, items, &mut before)?;
+
+    *list.borrow_mut() = sorted;
+    Ok(Value::Nil)
+}
+
+type Before<'a> =
+    dyn FnMut(&mut dyn NativeContext, &Value, &Value) -> Result<bool, RuntimeError> + 'a;
+
+// a stable sort which stops at the first error, the comparison of `slice::sort_by` can't
+// fail and may panic when the order is inconsistent
+fn merge_sort(
+    cx: &mut dyn NativeContext,
+    mut items: Vec<Value>,
+    before: &mut Before,
+) -> Result<Vec<Value>, RuntimeError> {
+    if items.len() < 2 {
+        return Ok(items);
+    }
+
+    let right = items.split_off(items.len() / 2);
+    let left = merge_sort(cx, items, before)?;
+    let right = merge_sort(cx, right, before)?;
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        let next = match before(cx, b, a)? {
+            true => right.next(),
+            false => left.next(),
+        };
+        merged.extend(next);
+    }
+    merged.extend(left.chain(right));
+    Ok(merged)
+}
+
+fn map_len(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [map] = self::args(cx, "len", args)?;
+    Ok(Value::Int(expect_map(cx, map)?.borrow().len() as i64))
+}
+
+fn map_is_empty(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [map] = self::args(cx, "is_empty", args)?;
+    Ok(Value::Bool(expect_map(cx, map)?.borrow().is_empty()))
+}
+
+// whether the map has the key
+fn map_contains(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [map, key] = self::args(cx, "contains", args)?;
+    let map = expect_map(cx, map)?;
+    let key = self::key(cx, &key)?;
+    let found = map.borrow().get(&key).is_some();
+    Ok(Value::Bool(found))
+}
+
+// the keys in insertion order
+fn keys(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [map] = self::args(cx, "keys", args)?;
+    let map = expect_map(cx, map)?;
+    let keys = map.borrow().iter().map(|(key, _)| key.to_value()).collect();
+    track(cx, Value::list(keys))
+}
+
+// the values in the order of their keys
+fn values(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [map] = self::args(cx, "values", args)?;
+    let map = expect_map(cx, map)?;
+    let values = map
+        .borrow()
+        .iter()
+        .map(|(_, value)| value.clone())
+        .collect();
+    track(cx, Value::list(values))
+}
+
+// removes the key and returns its value, `nil` when it was missing
+fn map_remove(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [map, key] = self::args(cx, "remove", args)?;
+    let map = expect_map(cx, map)?;
+    let key = self::key(cx, &key)?;
+    let removed = map.borrow_mut().remove(&key);
+    Ok(removed.unwrap_or(Value::Nil))
+}
+
+fn set_len(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [set] = self::args(cx, "len", args)?;
+    Ok(Value::Int(expect_set(cx, set)?.borrow().len() as i64))
+}
+
+fn set_is_empty(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [set] = self::args(cx, "is_empty", args)?;
+    Ok(Value::Bool(expect_set(cx, set)?.borrow().is_empty()))
+}
+
+fn set_contains(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [set, item] = self::args(cx, "contains", args)?;
+    let set = expect_set(cx, set)?;
+    let item = key(cx, &item)?;
+    let found = set.borrow().contains(&item);
+    Ok(Value::Bool(found))
+}
+
+// adds the element, returns whether it wasn't in the set yet
+fn insert(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [value, item] = self::args(cx, "insert", args)?;
+    let set = expect_set(cx, value.clone())?;
+    let key = self::key(cx, &item)?;
+    if !set.borrow().contains(&key) {
+        reserve(cx, &value, &item)?;
+    }
+    let inserted = set.borrow_mut().insert(key);
+    Ok(Value::Bool(inserted))
+}
+
+// removes the element, returns whether it was in the set
+fn set_remove(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [set, item] = self::args(cx, "remove", args)?;
+    let set = expect_set(cx, set)?;
+    let item = key(cx, &item)?;
+    let removed = set.borrow_mut().remove(&item);
+    Ok(Value::Bool(removed))
+}
+
+// the elements in insertion order
+fn to_list(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [set] = self::args(cx, "to_list", args)?;
+    let set = expect_set(cx, set)?;
+    let items = set.borrow().iter().map(Key::to_value).collect();
+    track(cx, Value::list(items))
+}
+
+pub(super) fn expect_list(
+    cx: &dyn NativeContext,
+    value: Value,
+) -> Result<Rc<RefCell<Vec<Value>>>, RuntimeError> {
+    match value {
+        Value::List(items) => Ok(items),
+        value => Err(cx.error(format!("expected `list`, found `{}`", value.type_name()))),
+    }
+}
+
+fn expect_map(cx: &dyn NativeContext, value: Value) -> Result<Rc<RefCell<Map>>, RuntimeError> {
+    match value {
+        Value::Map(map) => Ok(map),
+        value => Err(cx.error(format!("expected `map`, found `{}`", value.type_name()))),
+    }
+}
+
+fn expect_set(cx: &dyn NativeContext, value: Value) -> Result<Rc<RefCell<Set>>, RuntimeError> {
+    match value {
+        Value::Set(set) => Ok(set),
+        value => Err(cx.error(format!("expected `set`, found `{}`", value.type_name()))),
+    }
+}
+
+fn key(cx: &dyn NativeContext, value: &Value) -> Result<Key, RuntimeError> {
+    ops::key(value, cx.error(String::new()).span)
+}
+
+// the function passed to `method` returned the wrong kind of value
+fn returned(cx: &dyn NativeContext, method: &str, expected: &str, value: &Value) -> RuntimeError {
+    cx.error(format!(
+        "the function passed to `{method}` must return `{expected}`, found `{}`",
+        value.type_name()
+    ))
+}
@@ -0,0 +1,17 @@
+use crate::runtime::error::RuntimeError;
+use crate::runtime::value::{NativeContext, NativeFnPtr, Value};
+
+use super::{args, expect_str};
+
+pub(super) const FNS: &[(&str, NativeFnPtr, &str)] = &[("get", get, "(string) -> result<string>")];
+
+// the value of an environment variable, an error if it is not set
+fn get(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [name] = self::args(cx, "get", args)?;
+    let name = expect_str(cx, name)?;
+
+    match std::env::var(name.as_str()) {
+        Ok(value) => Ok(Value::Str(value.into())),
+        Err(err) => Ok(Value::error(format!("`{name}`: {err}"))),
+    }
+}
@@ -0,0 +1,43 @@
+//! Error values, `kai.error`
+//!
+//! `is_error` and `wrap` are methods of errors too, `err.wrap("loading")`. `err.is_error()` only
+//! works on errors, `error.is_error(v)` takes any value.
+
+use crate::runtime::error::RuntimeError;
+use crate::runtime::value::{NativeContext, NativeFnPtr, Value};
+
+use super::{args, expect_str};
+
+pub(super) const FNS: &[(&str, NativeFnPtr, &str)] = &[
+    ("new", new, "(string) -> error"),
+    ("wrap", wrap, "(error, string) -> error"),
+    ("is_error", is_error, "(any) -> bool"),
+];
+
+// the error is passed first
+pub(super) const ERROR_METHODS: &[(&str, NativeFnPtr, &str)] = &[
+    ("wrap", wrap, "(error, string) -> error"),
+    ("is_error", is_error, "(error) -> bool"),
+];
+
+// an error without a cause
+fn new(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [message] = self::args(cx, "new", args)?;
+    Ok(Value::error(expect_str(cx, message)?))
+}
+
+// adds context to an error, `wrap(err, "reading config")`
+fn wrap(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [cause, message] = self::args(cx, "wrap", args)?;
+    let message = expect_str(cx, message)?;
+
+    match cause {
+        Value::Error(cause) => Ok(Value::wrap_error(cause, message)),
+        cause => Err(cx.error(format!("expected `error`, found `{}`", cause.type_name()))),
+    }
+}
+
+fn is_error(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [value] = self::args(cx, "is_error", args)?;
+    Ok(Value::Bool(matches!(value, Value::Error(_))))
+}
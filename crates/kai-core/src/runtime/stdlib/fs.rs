@@ -0,0 +1,336 @@
+//! Directories and file metadata, everything here needs the `fs` capability
+//!
+//! Paths can be strings or `Path` instances, paths returned are strings. Failures are
+//! returned as errors naming the path, like the file functions of `kai.io`.
+
+use std::fs::{self, Metadata};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use crate::runtime::error::RuntimeError;
+use crate::runtime::value::{NativeContext, NativeFnPtr, Value};
+
+use super::io::path_error;
+use super::path::expect_path;
+use super::{args, expect_bool, expect_str, object, opt_args, track};
+
+pub(super) const FNS: &[(&str, NativeFnPtr, &str)] = &[
+    ("list_dir", list_dir, "(any) -> result<list<string>>"),
+    ("walk", walk, "(any, string?) -> result<list<string>>"),
+    ("create_dir", create_dir, "(any) -> result<nil>"),
+    ("remove", remove, "(any) -> result<nil>"),
+    ("remove_all", remove_all, "(any) -> result<nil>"),
+    ("rename", rename, "(any, any) -> result<nil>"),
+    ("copy", copy, "(any, any) -> result<int>"),
+    ("metadata", metadata, "(any) -> result<Metadata>"),
+    ("set_readonly", set_readonly, "(any, bool) -> result<nil>"),
+    ("temp_dir", temp_dir, "() -> result<string>"),
+];
+
+// the names of the entries of a directory, sorted so scripts behave the same everywhere
+fn list_dir(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [path] = self::args(cx, "list_dir", args)?;
+    let path = expect_path(cx, path)?;
+
+    let names = fs::read_dir(path.as_str()).and_then(|entries| {
+        entries
+            .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+            .collect::<std::io::Result<Vec<_>>>()
+    });
+    Ok(match names {
+        Ok(mut names) => {
+            names.sort();
+            let names = names.into_iter().map(|name| Value::Str(name.into()));
+            track(cx, Value::list(names.collect()))?
+        }
+        Err(err) => path_error(&path, err),
+    })
+}
+
+// `walk(root)` or `walk(root, glob)`, the paths of everything below `root` depth first in
+// sorted order. The glob is matched against the path relative to `root`, see `glob_match`.
+// Symlinks to directories are listed but not followed.
+fn walk(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [root, glob] = opt_args(cx, "walk", 1, args)?;
+    let root = expect_path(cx, root.unwrap_or(Value::Nil))?;
+    let glob = glob.map(|glob| expect_str(cx, glob)).transpose()?;
+
+    let mut found = Vec::new();
+    if let Err((path, err)) = walk_dir(Path::new(root.as_str()), Path::new(""), &mut found) {
+        return Ok(path_error(&path, err));
+    }
+
+    let paths = found
+        .into_iter()
+        .filter(|relative| {
+            let relative = relative.to_string_lossy().replace('\\', "/");
+            glob.as_ref().is_none_or(|glob| glob_match(glob, &relative))
+        })
+        .map(|relative| {
+            Value::Str(
+                Path::new(root.as_str())
+                    .join(relative)
+                    .to_string_lossy()
+                    .as_ref()
+                    .into(),
+            )
+        })
+        .collect();
+    track(cx, Value::list(paths))
+}
+
+// pushes the paths below `root.join(dir)` relative to `root`, or fails with the path which
+// couldn't be read
+fn walk_dir(
+    root: &Path,
+    dir: &Path,
+    found: &mut Vec<std::path::PathBuf>,
+) -> Result<(), (String, std::io::Error)> {
+    let full = root.join(dir);
+    let failed = |err| (full.to_string_lossy().into_owned(), err);
+
+    let mut entries = fs::read_dir(&full)
+        .and_then(|entries| entries.collect::<std::io::Result<Vec<_>>>())
+        .map_err(failed)?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let relative = dir.join(entry.file_name());
+        let is_dir = entry.file_type().map_err(failed)?.is_dir();
+
+        found.push(relative.clone());
+        if is_dir {
+            walk_dir(root, &relative, found)?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether `path` matches `glob`, where `*` matches within a part of the path, `**`
+/// matches any number of parts and `?` one character. `**/*.kai` matches `a.kai` and
+/// `src/a.kai`.
+fn glob_match(glob: &str, path: &str) -> bool {
+    let glob: Vec<&str> = glob.split('/').collect();
+    let path: Vec<&str> = path.split('/').collect();
+    wildcard(
+        &glob,
+        &path,
+        |part| *part == "**",
+        |part, name| {
+            let part: Vec<char> = part.chars().collect();
+            let name: Vec<char> = name.chars().collect();
+            wildcard(&part, &name, |c| *c == '*', |c, n| *c == '?' || c == n)
+        },
+    )
+}
+
+// matches `items` against `glob`, where a star takes any number of items. On a mismatch
+// only the last star takes one more item, the ones before it never need to be retried, so
+// this takes at most the product of the lengths instead of backtracking into every star.
+fn wildcard<G, I>(
+    glob: &[G],
+    items: &[I],
+    is_star: impl Fn(&G) -> bool,
+    matches: impl Fn(&G, &I) -> bool,
+) -> bool {
+    let (mut g, mut i) = (0, 0);
+    // the glob after the last star and the first item it hasn't taken yet
+    let mut star = None;
+
+    while i < items.len() {
+        if g < glob.len() && is_star(&glob[g]) {
+            g += 1;
+            star = Some((g, i));
+        } else if g < glob.len() && matches(&glob[g], &items[i]) {
+            g += 1;
+            i += 1;
+        } else if let Some((after, taken)) = star {
+            g = after;
+            i = taken + 1;
+            star = Some((after, i));
+        } else {
+            return false;
+        }
+    }
+    glob[g..].iter().all(is_star)
+}
+
+// creates a directory and the ones above it which are missing
+fn create_dir(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [path] = self::args(cx, "create_dir", args)?;
+    let path = expect_path(cx, path)?;
+    Ok(done(&path, fs::create_dir_all(path.as_str())))
+}
+
+// removes a file or an empty directory
+fn remove(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [path] = self::args(cx, "remove", args)?;
+    let path = expect_path(cx, path)?;
+
+    let removed = match fs::symlink_metadata(path.as_str()) {
+        Ok(meta) if meta.is_dir() => fs::remove_dir(path.as_str()),
+        _ => fs::remove_file(path.as_str()),
+    };
+    Ok(done(&path, removed))
+}
+
+// removes a directory with everything in it, or a file
+fn remove_all(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [path] = self::args(cx, "remove_all", args)?;
+    let path = expect_path(cx, path)?;
+
+    let removed = match fs::symlink_metadata(path.as_str()) {
+        Ok(meta) if meta.is_dir() => fs::remove_dir_all(path.as_str()),
+        _ => fs::remove_file(path.as_str()),
+    };
+    Ok(done(&path, removed))
+}
+
+fn rename(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [from, to] = self::args(cx, "rename", args)?;
+    let from = expect_path(cx, from)?;
+    let to = expect_path(cx, to)?;
+    Ok(done(&from, fs::rename(from.as_str(), to.as_str())))
+}
+
+// copies a file, replacing `to` if it exists, returns the number of bytes copied
+fn copy(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [from, to] = self::args(cx, "copy", args)?;
+    let from = expect_path(cx, from)?;
+    let to = expect_path(cx, to)?;
+
+    Ok(match fs::copy(from.as_str(), to.as_str()) {
+        Ok(bytes) => Value::Int(bytes as i64),
+        Err(err) => path_error(&from, err),
+    })
+}
+
+// `Metadata { size, modified, is_file, is_dir, is_symlink, readonly, mode }`, `modified`
+// is in seconds since the unix epoch and `mode` holds the unix permission bits, `nil`
+// elsewhere. Symlinks are followed.
+fn metadata(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [path] = self::args(cx, "metadata", args)?;
+    let path = expect_path(cx, path)?;
+
+    let is_symlink = fs::symlink_metadata(path.as_str()).is_ok_and(|meta| meta.is_symlink());
+    Ok(match fs::metadata(path.as_str()) {
+        Ok(meta) => metadata_value(&meta, is_symlink),
+        Err(err) => path_error(&path, err),
+    })
+}
+
+fn metadata_value(meta: &Metadata, is_symlink: bool) -> Value {
+    let modified = meta
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(Value::Nil, |since| Value::Float(since.as_secs_f64()));
+
+    object(
+        "Metadata",
+        [
+            ("size", Value::Int(meta.len() as i64)),
+            ("modified", modified),
+            ("is_file", Value::Bool(meta.is_file())),
+            ("is_dir", Value::Bool(meta.is_dir())),
+            ("is_symlink", Value::Bool(is_symlink)),
+            ("readonly", Value::Bool(meta.permissions().readonly())),
+            ("mode", mode(meta)),
+        ],
+    )
+}
+
+#[cfg(unix)]
+fn mode(meta: &Metadata) -> Value {
+    use std::os::unix::fs::PermissionsExt;
+    Value::Int((meta.permissions().mode() & 0o7777).into())
+}
+
+#[cfg(not(unix))]
+fn mode(_: &Metadata) -> Value {
+    Value::Nil
+}
+
+fn set_readonly(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [path, readonly] = self::args(cx, "set_readonly", args)?;
+    let path = expect_path(cx, path)?;
+    let readonly = expect_bool(cx, readonly)?;
+
+    let changed = fs::metadata(path.as_str()).and_then(|meta| {
+        let mut permissions = meta.permissions();
+        permissions.set_readonly(readonly);
+        fs::set_permissions(path.as_str(), permissions)
+    });
+    Ok(done(&path, changed))
+}
+
+// creates a new empty directory under the temp directory of the system and returns its
+// path, scripts remove it with `remove_all` once done
+fn temp_dir(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [] = self::args(cx, "temp_dir", args)?;
+    let base = std::env::temp_dir();
+    let nanos = std::time::SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.subsec_nanos());
+
+    // another process may have taken the name, the next one is tried then
+    for attempt in 0u32..100 {
+        let dir = base.join(format!("kai-{}-{nanos}-{attempt}", std::process::id()));
+        match fs::create_dir(&dir) {
+            Ok(()) => return Ok(Value::Str(dir.to_string_lossy().as_ref().into())),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Ok(path_error(&base.to_string_lossy(), err)),
+        }
+    }
+    Ok(Value::error(
+        "could not find a free name for a temp directory",
+    ))
+}
+
+fn done(path: &str, result: std::io::Result<()>) -> Value {
+    match result {
+        Ok(()) => Value::Nil,
+        Err(err) => path_error(path, err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_globs() {
+        let cases = [
+            ("*.kai", "a.kai", true),
+            ("*.kai", "src/a.kai", false),
+            ("**/*.kai", "a.kai", true),
+            ("**/*.kai", "src/lib/a.kai", true),
+            ("src/**", "src/lib/a.kai", true),
+            ("src/*/a.?ai", "src/lib/a.kai", true),
+            ("src/*/a.?ai", "src/a.kai", false),
+            ("a*b*c", "abxbc", true),
+            ("a*b*c", "abxb", false),
+            ("**", "", true),
+            ("*", "", true),
+            ("**/a", "a", true),
+            ("a/**/b/**/c", "a/x/b/y/z/c", true),
+            ("a/**/b/**/c", "a/x/c/b", false),
+        ];
+
+        for (glob, path, matches) in cases {
+            assert_eq!(glob_match(glob, path), matches, "{glob:?} {path:?}");
+        }
+    }
+
+    #[test]
+    fn matches_pathological_globs_quickly() {
+        let name = "a".repeat(1000);
+        let glob = format!("{}b", "*a".repeat(20));
+        assert!(!glob_match(&glob, &name));
+        assert!(glob_match(&format!("{}*", "*a".repeat(20)), &name));
+
+        let path = vec!["a"; 200].join("/");
+        let glob = format!("{}b", "**/a/".repeat(20));
+        assert!(!glob_match(&glob, &path));
+    }
+}
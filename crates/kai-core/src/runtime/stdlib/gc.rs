@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::ast::NodeId;
+use crate::runtime::error::RuntimeError;
+use crate::runtime::value::{Class, NativeContext, NativeFnPtr, Value};
+
+pub(super) const FNS: &[(&str, NativeFnPtr, &str)] = &[
+    ("collect", collect, "() -> int"),
+    ("stats", stats, "() -> GcStats"),
+];
+
+// runs a collection and returns how many objects it freed
+fn collect(cx: &mut dyn NativeContext, _args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let freed = cx.heap().collect();
+    Ok(Value::Int(freed as i64))
+}
+
+// the counters of the collector as a `GcStats` instance
+fn stats(cx: &mut dyn NativeContext, _args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let stats = cx.heap().stats();
+
+    let class = Rc::new(Class {
+        id: NodeId::DUMMY,
+        name: "GcStats".into(),
+        fields: ["collections", "allocations", "freed", "live"]
+            .into_iter()
+            .map(Into::into)
+            .collect(),
+        methods: HashMap::new(),
+        builtins: Vec::new(),
+        natives: HashMap::new(),
+    });
+    let fields = [
+        stats.collections,
+        stats.allocations,
+        stats.freed,
+        stats.live as u64,
+    ]
+    .into_iter()
+    .map(|count| Value::Int(count as i64))
+    .collect();
+
+    match cx.heap().alloc(class, fields) {
+        Some(instance) => Ok(Value::Instance(instance)),
+        None => Err(cx.error("heap limit exceeded".to_owned())),
+    }
+}
@@ -0,0 +1,310 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::rc::Rc;
+
+use crate::runtime::error::RuntimeError;
+use crate::runtime::value::{Handle, NativeContext, NativeFnPtr, Value};
+
+use super::path::expect_path;
+use super::{args, expect_str};
+
+pub(super) const FNS: &[(&str, NativeFnPtr, &str)] = &[
+    ("print", print, "(..) -> result<nil>"),
+    ("println", println, "(..) -> result<nil>"),
+    ("eprint", eprint, "(..) -> result<nil>"),
+    ("eprintln", eprintln, "(..) -> result<nil>"),
+    ("read_line", read_line, "() -> any"),
+    ("read_to_string", read_to_string, "() -> result<string>"),
+];
+
+// everything touching the file system, these need the `fs` capability
+pub(super) const FILE_FNS: &[(&str, NativeFnPtr, &str)] = &[
+    ("open", open, "(any) -> result<any>"),
+    ("create", create, "(any) -> result<any>"),
+    ("append", append, "(any) -> result<any>"),
+    ("read_file", read_file, "(any) -> result<string>"),
+    ("write_file", write_file, "(any, string) -> result<nil>"),
+    ("append_file", append_file, "(any, string) -> result<nil>"),
+    ("exists", exists, "(any) -> bool"),
+];
+
+// a file opened for reading, iterating it goes through its lines
+const READER_METHODS: &[(&str, NativeFnPtr)] = &[
+    ("read_line", file_read_line),
+    ("read_to_string", file_read_to_string),
+    ("lines", lines),
+    ("next", file_read_line),
+    ("close", close_reader),
+];
+
+const WRITER_METHODS: &[(&str, NativeFnPtr)] = &[
+    ("write", file_write),
+    ("write_line", file_write_line),
+    ("flush", flush),
+    ("close", close_writer),
+];
+
+// prints every argument separated by a space without ending the line, returns an error
+// if writing fails
+fn print(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let text = join(cx, &args)?;
+    written(write!(cx.stdout(), "{text}"))
+}
+
+// like `print` and ends the line
+fn println(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let line = join(cx, &args)?;
+    written(writeln!(cx.stdout(), "{line}"))
+}
+
+// like `print` but to stderr, which the engine does not redirect
+fn eprint(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let text = join(cx, &args)?;
+    written(write!(std::io::stderr(), "{text}"))
+}
+
+fn eprintln(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let line = join(cx, &args)?;
+    written(writeln!(std::io::stderr(), "{line}"))
+}
+
+fn written(result: std::io::Result<()>) -> Result<Value, RuntimeError> {
+    match result {
+        Ok(()) => Ok(Value::Nil),
+        Err(err) => Ok(Value::error(err.to_string())),
+    }
+}
+
+fn join(cx: &mut dyn NativeContext, args: &[Value]) -> Result<String, RuntimeError> {
+    let mut line = String::new();
+
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+            line.push(' ');
+        }
+        line.push_str(&cx.display(arg)?);
+    }
+    Ok(line)
+}
+
+// the next line of stdin without its line break, `nil` at the end of the input
+fn read_line(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [] = self::args(cx, "read_line", args)?;
+    // a prompt printed before is shown before waiting for input
+    let _ = cx.stdout().flush();
+    Ok(next_line(&mut std::io::stdin().lock()))
+}
+
+// the rest of stdin
+fn read_to_string(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [] = self::args(cx, "read_to_string", args)?;
+    let _ = cx.stdout().flush();
+    Ok(rest(&mut std::io::stdin().lock()))
+}
+
+pub(super) fn next_line(reader: &mut impl BufRead) -> Value {
+    let mut line = String::new();
+
+    match reader.read_line(&mut line) {
+        Ok(0) => Value::Nil,
+        Ok(_) => {
+            let len = line.trim_end_matches(['\n', '\r']).len();
+            line.truncate(len);
+            Value::Str(line.into())
+        }
+        Err(err) => Value::error(err.to_string()),
+    }
+}
+
+pub(super) fn rest(reader: &mut impl Read) -> Value {
+    let mut text = String::new();
+
+    match reader.read_to_string(&mut text) {
+        Ok(_) => Value::Str(text.into()),
+        Err(err) => Value::error(err.to_string()),
+    }
+}
+
+// `open(path)` opens a file for reading
+fn open(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [path] = self::args(cx, "open", args)?;
+    let path = expect_path(cx, path)?;
+
+    Ok(match File::open(path.as_str()) {
+        Ok(file) => file_handle(BufReader::new(file), READER_METHODS),
+        Err(err) => path_error(&path, err),
+    })
+}
+
+// `create(path)` opens a file for writing, emptying it if it exists
+fn create(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [path] = self::args(cx, "create", args)?;
+    let path = expect_path(cx, path)?;
+
+    Ok(match File::create(path.as_str()) {
+        Ok(file) => file_handle(BufWriter::new(file), WRITER_METHODS),
+        Err(err) => path_error(&path, err),
+    })
+}
+
+// `append(path)` opens a file for writing at its end, creating it if needed
+fn append(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [path] = self::args(cx, "append", args)?;
+    let path = expect_path(cx, path)?;
+
+    let file = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path.as_str());
+    Ok(match file {
+        Ok(file) => file_handle(BufWriter::new(file), WRITER_METHODS),
+        Err(err) => path_error(&path, err),
+    })
+}
+
+fn read_file(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [path] = self::args(cx, "read_file", args)?;
+    let path = expect_path(cx, path)?;
+
+    Ok(match std::fs::read_to_string(path.as_str()) {
+        Ok(text) => Value::Str(text.into()),
+        Err(err) => path_error(&path, err),
+    })
+}
+
+fn write_file(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [path, text] = self::args(cx, "write_file", args)?;
+    let path = expect_path(cx, path)?;
+    let text = expect_str(cx, text)?;
+
+    Ok(match std::fs::write(path.as_str(), text.as_bytes()) {
+        Ok(()) => Value::Nil,
+        Err(err) => path_error(&path, err),
+    })
+}
+
+fn append_file(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [path, text] = self::args(cx, "append_file", args)?;
+    let path = expect_path(cx, path)?;
+    let text = expect_str(cx, text)?;
+
+    let written = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path.as_str())
+        .and_then(|mut file| file.write_all(text.as_bytes()));
+    Ok(match written {
+        Ok(()) => Value::Nil,
+        Err(err) => path_error(&path, err),
+    })
+}
+
+fn exists(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [path] = self::args(cx, "exists", args)?;
+    let path = expect_path(cx, path)?;
+    Ok(Value::Bool(std::path::Path::new(path.as_str()).exists()))
+}
+
+pub(super) fn path_error(path: &str, err: std::io::Error) -> Value {
+    Value::error(format!("`{path}`: {err}"))
+}
+
+// `close` takes the file out, later calls return an error
+fn file_handle<T: 'static>(file: T, methods: &'static [(&'static str, NativeFnPtr)]) -> Value {
+    Value::Handle(Rc::new(Handle::new("file", Some(file), methods)))
+}
+
+// runs `f` on the file of a handle, or returns an error if it was closed
+fn with_file<T: 'static>(handle: &Value, f: impl FnOnce(&mut T) -> Value) -> Value {
+    let Value::Handle(handle) = handle else {
+        unreachable!("file methods are called on handles");
+    };
+    let mut file = handle.get::<Option<T>>().expect("handle of the method");
+
+    match file.as_mut() {
+        Some(file) => f(file),
+        None => Value::error("the file is closed"),
+    }
+}
+
+// the next line without its line break, `nil` at the end of the file
+fn file_read_line(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [file] = self::args(cx, "read_line", args)?;
+    Ok(with_file(&file, |reader: &mut BufReader<File>| {
+        next_line(reader)
+    }))
+}
+
+fn file_read_to_string(
+    cx: &mut dyn NativeContext,
+    args: Vec<Value>,
+) -> Result<Value, RuntimeError> {
+    let [file] = self::args(cx, "read_to_string", args)?;
+    Ok(with_file(&file, |reader: &mut BufReader<File>| {
+        rest(reader)
+    }))
+}
+
+// `for line in file.lines()`, the file is its own iterator
+fn lines(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [file] = self::args(cx, "lines", args)?;
+    Ok(file)
+}
+
+// writes a value the way `print` shows it
+fn file_write(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [file, value] = self::args(cx, "write", args)?;
+    let text = cx.display(&value)?;
+    Ok(write_text(&file, &text))
+}
+
+fn file_write_line(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [file, value] = self::args(cx, "write_line", args)?;
+    let text = cx.display(&value)? + "\n";
+    Ok(write_text(&file, &text))
+}
+
+fn write_text(file: &Value, text: &str) -> Value {
+    with_file(file, |writer: &mut BufWriter<File>| {
+        match writer.write_all(text.as_bytes()) {
+            Ok(()) => Value::Nil,
+            Err(err) => Value::error(err.to_string()),
+        }
+    })
+}
+
+fn flush(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [file] = self::args(cx, "flush", args)?;
+    Ok(with_file(
+        &file,
+        |writer: &mut BufWriter<File>| match writer.flush() {
+            Ok(()) => Value::Nil,
+            Err(err) => Value::error(err.to_string()),
+        },
+    ))
+}
+
+fn close_reader(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [file] = self::args(cx, "close", args)?;
+    take_file::<BufReader<File>>(&file);
+    Ok(Value::Nil)
+}
+
+// writes still buffered are flushed before closing
+fn close_writer(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [file] = self::args(cx, "close", args)?;
+
+    Ok(
+        match take_file::<BufWriter<File>>(&file).map(|mut file| file.flush()) {
+            Some(Err(err)) => Value::error(err.to_string()),
+            _ => Value::Nil,
+        },
+    )
+}
+
+fn take_file<T: 'static>(handle: &Value) -> Option<T> {
+    let Value::Handle(handle) = handle else {
+        unreachable!("file methods are called on handles");
+    };
+    handle.get::<Option<T>>()?.take()
+}
@@ -0,0 +1,230 @@
+//! Lazy iterators, `iter.from(xs)` and the adapters on ranges and iterators
+//!
+//! An adapter makes a new iterator pulling from the one it was called on, nothing runs
+//! until `next`, `collect` or a `for` loop asks for an item. Like every iterator `next`
+//! returns `nil` at the end.
+
+use std::cell::RefMut;
+use std::rc::Rc;
+
+use crate::runtime::error::RuntimeError;
+use crate::runtime::ops::{Items, Iter};
+use crate::runtime::value::{Handle, NativeContext, NativeFnPtr, Value};
+
+use super::{args, reserve, track};
+
+pub(super) const FNS: &[(&str, NativeFnPtr, &str)] = &[("from", from, "(any) -> any")];
+
+// `(1..10).map(f)` is `iter.from(1..10).map(f)`
+pub(super) const RANGE_METHODS: &[(&str, NativeFnPtr, &str)] = &[
+    ("iter", from, "(range) -> any"),
+    ("step", step, "(range, int) -> any"),
+    ("map", map, "(range, fn) -> any"),
+    ("filter", filter, "(range, fn) -> any"),
+    ("zip", zip, "(range, any) -> any"),
+    ("enumerate", enumerate, "(range) -> any"),
+    ("take", take, "(range, int) -> any"),
+    ("collect", collect, "(range) -> list<int>"),
+];
+
+pub(super) const ITERATOR_METHODS: &[(&str, NativeFnPtr)] = &[
+    ("next", next),
+    ("step", step),
+    ("map", map),
+    ("filter", filter),
+    ("zip", zip),
+    ("enumerate", enumerate),
+    ("take", take),
+    ("collect", collect),
+];
+
+#[derive(Debug)]
+enum Lazy {
+    Items(Items),
+    // an instance, a handle or a generator, its `next` is called for every item
+    Next(Value),
+    // every `n`th item starting with the first
+    Step(Value, usize),
+    Map(Value, Value),
+    Filter(Value, Value),
+    // `[a, b]` lists until either side ends
+    Zip(Value, Value),
+    // `[index, item]` lists
+    Enumerate(Value, i64),
+    // the items left to take
+    Take(Value, usize),
+    // taken out of the handle while it makes an item, see `advance`
+    Running,
+}
+
+impl Lazy {
+    // every item takes a step, ranges give out items without running any code
+    fn next(&mut self, cx: &mut dyn NativeContext) -> Result<Option<Value>, RuntimeError> {
+        cx.tick()?;
+        match self {
+            Lazy::Items(items) => Ok(items.next()),
+            Lazy::Next(value) => match cx.call(value.clone(), "next", Vec::new())? {
+                Value::Nil => Ok(None),
+                item => Ok(Some(item)),
+            },
+            Lazy::Step(inner, n) => {
+                let Some(item) = advance(cx, inner)? else {
+                    return Ok(None);
+                };
+                for _ in 1..*n {
+                    if advance(cx, inner)?.is_none() {
+                        break;
+                    }
+                }
+                Ok(Some(item))
+            }
+            Lazy::Map(inner, f) => match advance(cx, inner)? {
+                Some(item) => cx.apply(f.clone(), vec![item]).map(Some),
+                None => Ok(None),
+            },
+            Lazy::Filter(inner, f) => {
+                while let Some(item) = advance(cx, inner)? {
+                    match cx.apply(f.clone(), vec![item.clone()])? {
+                        Value::Bool(true) => return Ok(Some(item)),
+                        Value::Bool(false) => {}
+                        value => {
+                            let message = format!(
+                                "the function passed to `filter` must return `bool`, found `{}`",
+                                value.type_name()
+                            );
+                            return Err(cx.error(message));
+                        }
+                    }
+                }
+                Ok(None)
+            }
+            Lazy::Zip(a, b) => {
+                let (Some(a), Some(b)) = (advance(cx, a)?, advance(cx, b)?) else {
+                    return Ok(None);
+                };
+                Ok(Some(track(cx, Value::list(vec![a, b]))?))
+            }
+            Lazy::Enumerate(inner, idx) => {
+                let Some(item) = advance(cx, inner)? else {
+                    return Ok(None);
+                };
+                *idx += 1;
+                let pair = vec![Value::Int(*idx - 1), item];
+                Ok(Some(track(cx, Value::list(pair))?))
+            }
+            Lazy::Take(_, 0) => Ok(None),
+            Lazy::Take(inner, left) => {
+                *left -= 1;
+                advance(cx, inner)
+            }
+            Lazy::Running => Err(cx.error("the iterator is already running".into())),
+        }
+    }
+}
+
+// the next item of an iterator handle. The state is taken out while the item is made so
+// functions of the adapters can't borrow it again, using the iterator inside its own
+// `map` function is an error instead.
+fn advance(cx: &mut dyn NativeContext, iter: &Value) -> Result<Option<Value>, RuntimeError> {
+    let mut lazy = std::mem::replace(&mut *state(iter), Lazy::Running);
+    let item = lazy.next(cx);
+    *state(iter) = lazy;
+    item
+}
+
+fn state(iter: &Value) -> RefMut<'_, Lazy> {
+    match iter {
+        Value::Handle(handle) => handle.get::<Lazy>().expect("handle of the method"),
+        _ => unreachable!("iterator methods are called on handles"),
+    }
+}
+
+fn iterator(lazy: Lazy) -> Value {
+    Value::Handle(Rc::new(Handle::new("iterator", lazy, ITERATOR_METHODS)))
+}
+
+// an iterator over anything a `for` loop accepts, iterators are returned as they are
+pub(super) fn from(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [value] = self::args(cx, "from", args)?;
+    lazy(cx, value)
+}
+
+fn lazy(cx: &dyn NativeContext, value: Value) -> Result<Value, RuntimeError> {
+    if let Value::Handle(handle) = &value {
+        if handle.get::<Lazy>().is_some() {
+            return Ok(value);
+        }
+    }
+
+    let span = cx.error(String::new()).span;
+    Ok(iterator(match Iter::new(value.clone(), span)? {
+        Iter::Items(items) => Lazy::Items(items),
+        Iter::Object(_) | Iter::Coroutine(_) => Lazy::Next(value),
+    }))
+}
+
+// the next item, `nil` at the end
+fn next(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [iter] = self::args(cx, "next", args)?;
+    Ok(advance(cx, &iter)?.unwrap_or(Value::Nil))
+}
+
+// every `n`th item starting with the first, `(0..10).step(3)` gives 0, 3, 6 and 9
+fn step(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [iter, n] = self::args(cx, "step", args)?;
+    let iter = lazy(cx, iter)?;
+    match n {
+        Value::Int(n) if n > 0 => Ok(iterator(Lazy::Step(iter, n as usize))),
+        Value::Int(n) => Err(cx.error(format!("the step must be positive, found {n}"))),
+        n => Err(cx.error(format!("expected `int`, found `{}`", n.type_name()))),
+    }
+}
+
+fn map(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [iter, f] = self::args(cx, "map", args)?;
+    Ok(iterator(Lazy::Map(lazy(cx, iter)?, f)))
+}
+
+// the items `f` returns `true` for
+fn filter(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [iter, f] = self::args(cx, "filter", args)?;
+    Ok(iterator(Lazy::Filter(lazy(cx, iter)?, f)))
+}
+
+// pairs of items from both sides, the other side can be anything a `for` loop accepts
+fn zip(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [iter, other] = self::args(cx, "zip", args)?;
+    Ok(iterator(Lazy::Zip(lazy(cx, iter)?, lazy(cx, other)?)))
+}
+
+// pairs of the index and the item, starting at `0`
+fn enumerate(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [iter] = self::args(cx, "enumerate", args)?;
+    Ok(iterator(Lazy::Enumerate(lazy(cx, iter)?, 0)))
+}
+
+// at most `n` items, so infinite iterators can be collected
+fn take(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [iter, n] = self::args(cx, "take", args)?;
+    let iter = lazy(cx, iter)?;
+    match n {
+        Value::Int(n) => Ok(iterator(Lazy::Take(iter, n.max(0) as usize))),
+        n => Err(cx.error(format!("expected `int`, found `{}`", n.type_name()))),
+    }
+}
+
+// the items left as a list
+fn collect(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [iter] = self::args(cx, "collect", args)?;
+    let iter = lazy(cx, iter)?;
+
+    // charged item by item, infinite iterators stop at the heap limit
+    let list = track(cx, Value::list(Vec::new()))?;
+    while let Some(item) = advance(cx, &iter)? {
+        reserve(cx, &list, &item)?;
+        if let Value::List(items) = &list {
+            items.borrow_mut().push(item);
+        }
+    }
+    Ok(list)
+}
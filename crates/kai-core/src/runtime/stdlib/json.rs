@@ -0,0 +1,600 @@
+//! JSON encoding and decoding
+//!
+//! Objects decode to maps keeping the order of their keys, numbers to ints unless they
+//! have a fraction or an exponent. Instances encode as objects of their fields, or as
+//! whatever their `to_json` returns when their class implements `Serialize`. Decoding
+//! into a class calls its static `from_json` if it has one and fills in its fields by
+//! name otherwise.
+
+use std::fmt::Write;
+use std::rc::Rc;
+
+use ecow::EcoString;
+
+use crate::resolve::BuiltinInterface;
+use crate::runtime::error::RuntimeError;
+use crate::runtime::value::{Class, Key, Map, NativeContext, NativeFnPtr, Value};
+
+use super::{alloc, expect_bool, expect_str, opt_args, track};
+
+pub(super) const FNS: &[(&str, NativeFnPtr, &str)] = &[
+    ("parse", parse, "(string, any?) -> result<any>"),
+    (
+        "stringify",
+        stringify,
+        "(any, bool?, bool?) -> result<string>",
+    ),
+];
+
+// deeper documents are rejected instead of overflowing the stack, this also stops
+// encoding a list containing itself
+const MAX_DEPTH: usize = 512;
+
+// `parse(text)` or `parse(text, Class)`, malformed input is an error with its line and
+// column
+fn parse(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [text, class] = opt_args(cx, "parse", 1, args)?;
+    let text = expect_str(cx, text.unwrap_or(Value::Nil))?;
+
+    let value = match Parser::new(&text).parse() {
+        Ok(value) => value,
+        Err(err) => return Ok(Value::error(err)),
+    };
+    track_document(cx, &value)?;
+
+    match class {
+        None => Ok(value),
+        Some(Value::Class(class)) => decode(cx, &class, value),
+        Some(class) => Err(cx.error(format!("expected a class, found `{}`", class.type_name()))),
+    }
+}
+
+// `stringify(value, pretty, sorted)`, keys are written in insertion order unless `sorted`
+// is set
+fn stringify(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [value, pretty, sorted] = opt_args(cx, "stringify", 1, args)?;
+    let flag = |cx: &mut dyn NativeContext, flag: Option<Value>| {
+        flag.map_or(Ok(false), |flag| expect_bool(cx, flag))
+    };
+
+    let mut encoder = Encoder {
+        pretty: flag(cx, pretty)?,
+        sorted: flag(cx, sorted)?,
+        cx,
+        out: String::new(),
+    };
+    match encoder.encode(&value.unwrap_or(Value::Nil), 0) {
+        Ok(()) => Ok(Value::Str(encoder.out.into())),
+        Err(Fail::Json(message)) => Ok(Value::error(message)),
+        Err(Fail::Runtime(err)) => Err(err),
+    }
+}
+
+// puts the lists and maps of a parsed document on the heap, all of them are new
+fn track_document(cx: &mut dyn NativeContext, value: &Value) -> Result<(), RuntimeError> {
+    match value {
+        Value::List(items) => {
+            for item in items.borrow().iter() {
+                track_document(cx, item)?;
+            }
+        }
+        Value::Map(map) => {
+            for (_, value) in map.borrow().iter() {
+                track_document(cx, value)?;
+            }
+        }
+        _ => return Ok(()),
+    }
+    track(cx, value.clone()).map(drop)
+}
+
+fn decode(
+    cx: &mut dyn NativeContext,
+    class: &Rc<Class>,
+    value: Value,
+) -> Result<Value, RuntimeError> {
+    if class.methods.contains_key("from_json") {
+        return cx.call(Value::Class(class.clone()), "from_json", vec![value]);
+    }
+
+    let Value::Map(map) = value else {
+        let message = format!(
+            "expected an object for `{}`, found `{}`",
+            class.name,
+            value.type_name()
+        );
+        return Ok(Value::error(message));
+    };
+
+    let mut fields = Vec::with_capacity(class.fields.len());
+    for field in &class.fields {
+        match map.borrow().get(&Key::Str(field.clone())) {
+            Some(value) => fields.push(value.clone()),
+            None => {
+                let message = format!("missing field `{field}` of `{}`", class.name);
+                return Ok(Value::error(message));
+            }
+        }
+    }
+
+    alloc(cx, class.clone(), fields)
+}
+
+// why encoding stopped, values which can't be encoded are reported to the program
+enum Fail {
+    Json(String),
+    Runtime(RuntimeError),
+}
+
+impl From<RuntimeError> for Fail {
+    fn from(value: RuntimeError) -> Self {
+        Fail::Runtime(value)
+    }
+}
+
+struct Encoder<'a> {
+    cx: &'a mut dyn NativeContext,
+    out: String,
+    pretty: bool,
+    sorted: bool,
+}
+
+impl Encoder<'_> {
+    fn encode(&mut self, value: &Value, depth: usize) -> Result<(), Fail> {
+        if depth > MAX_DEPTH {
+            let message = format!("nesting deeper than {MAX_DEPTH} levels, is a value in itself?");
+            return Err(Fail::Json(message));
+        }
+
+        match value {
+            Value::Nil => self.out.push_str("null"),
+            Value::Bool(value) => write!(self.out, "{value}").unwrap(),
+            Value::Int(value) => write!(self.out, "{value}").unwrap(),
+            Value::Float(value) if !value.is_finite() => {
+                return Err(Fail::Json(format!("`{value}` can't be encoded as JSON")));
+            }
+            // written like kai prints it so `1.0` decodes as a float again
+            Value::Float(_) => write!(self.out, "{value}").unwrap(),
+            Value::Str(value) => write_str(&mut self.out, value),
+            Value::List(items) => {
+                let items = items.borrow().clone();
+                self.out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    self.separate(i, depth);
+                    self.encode(item, depth + 1)?;
+                }
+                self.close(']', items.is_empty(), depth);
+            }
+            Value::Map(map) => {
+                let entries = map
+                    .borrow()
+                    .iter()
+                    .map(|(key, value)| (key_string(key), value.clone()))
+                    .collect();
+                self.object(entries, depth)?;
+            }
+            Value::Instance(instance) if instance.class.implements(BuiltinInterface::Serialize) => {
+                let value = self.cx.call(value.clone(), "to_json", Vec::new())?;
+                self.encode(&value, depth + 1)?;
+            }
+            Value::Instance(instance) => {
+                let entries = instance
+                    .class
+                    .fields
+                    .iter()
+                    .cloned()
+                    .zip(instance.fields.borrow().iter().cloned())
+                    .collect();
+                self.object(entries, depth)?;
+            }
+            value => {
+                let message = format!("`{}` can't be encoded as JSON", value.type_name());
+                return Err(Fail::Json(message));
+            }
+        }
+        Ok(())
+    }
+
+    fn object(&mut self, mut entries: Vec<(EcoString, Value)>, depth: usize) -> Result<(), Fail> {
+        if self.sorted {
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        }
+
+        self.out.push('{');
+        for (i, (key, value)) in entries.iter().enumerate() {
+            self.separate(i, depth);
+            write_str(&mut self.out, key);
+            self.out.push_str(if self.pretty { ": " } else { ":" });
+            self.encode(value, depth + 1)?;
+        }
+        self.close('}', entries.is_empty(), depth);
+        Ok(())
+    }
+
+    // goes before the item at `idx` of a list or object
+    fn separate(&mut self, idx: usize, depth: usize) {
+        if idx > 0 {
+            self.out.push(',');
+        }
+        if self.pretty {
+            self.newline(depth + 1);
+        }
+    }
+
+    fn close(&mut self, bracket: char, empty: bool, depth: usize) {
+        if self.pretty && !empty {
+            self.newline(depth);
+        }
+        self.out.push(bracket);
+    }
+
+    fn newline(&mut self, depth: usize) {
+        self.out.push('\n');
+        for _ in 0..depth {
+            self.out.push_str("  ");
+        }
+    }
+}
+
+// object keys are strings, ints and bools are written like kai prints them
+fn key_string(key: &Key) -> EcoString {
+    match key {
+        Key::Str(key) => key.clone(),
+        key => key.to_value().to_string().into(),
+    }
+}
+
+fn write_str(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    // byte offset into `text`
+    pos: usize,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            pos: 0,
+            depth: 0,
+        }
+    }
+
+    fn parse(mut self) -> Result<Value, String> {
+        let value = self.value()?;
+        self.skip_whitespace();
+
+        match self.peek() {
+            None => Ok(value),
+            Some(_) => Err(self.error("expected the end of the input")),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+
+        match self.peek() {
+            Some('{') => self.nested(Self::object),
+            Some('[') => self.nested(Self::list),
+            Some('"') => Ok(Value::Str(self.string()?.into())),
+            Some('-' | '0'..='9') => self.number(),
+            Some('t') => self.keyword("true", Value::Bool(true)),
+            Some('f') => self.keyword("false", Value::Bool(false)),
+            Some('n') => self.keyword("null", Value::Nil),
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("expected a value, found the end of the input")),
+        }
+    }
+
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Value, String>) -> Result<Value, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error(&format!("nesting deeper than {MAX_DEPTH} levels")));
+        }
+
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.pos += 1;
+        let mut map = Map::new();
+
+        self.skip_whitespace();
+        if self.eat('}') {
+            return Ok(Value::map(map));
+        }
+
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err(self.error("expected a string key"));
+            }
+            let key = self.string()?;
+
+            self.skip_whitespace();
+            if !self.eat(':') {
+                return Err(self.error("expected `:` after the key"));
+            }
+
+            let value = self.value()?;
+            map.insert(Key::Str(key.into()), value);
+
+            self.skip_whitespace();
+            if self.eat('}') {
+                return Ok(Value::map(map));
+            }
+            if !self.eat(',') {
+                return Err(self.error("expected `,` or `}`"));
+            }
+        }
+    }
+
+    fn list(&mut self) -> Result<Value, String> {
+        self.pos += 1;
+        let mut items = Vec::new();
+
+        self.skip_whitespace();
+        if self.eat(']') {
+            return Ok(Value::list(items));
+        }
+
+        loop {
+            items.push(self.value()?);
+
+            self.skip_whitespace();
+            if self.eat(']') {
+                return Ok(Value::list(items));
+            }
+            if !self.eat(',') {
+                return Err(self.error("expected `,` or `]`"));
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.pos += 1;
+        let mut value = String::new();
+
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(self.error("unterminated string"));
+            };
+
+            match c {
+                '"' => {
+                    self.pos += 1;
+                    return Ok(value);
+                }
+                '\\' => {
+                    self.pos += 1;
+                    value.push(self.escape()?);
+                }
+                c if (c as u32) < 0x20 => {
+                    return Err(self.error("control characters must be escaped in strings"));
+                }
+                c => {
+                    self.pos += c.len_utf8();
+                    value.push(c);
+                }
+            }
+        }
+    }
+
+    // the character of the escape after a `\`
+    fn escape(&mut self) -> Result<char, String> {
+        let c = match self.peek() {
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some('/') => '/',
+            Some('b') => '\u{8}',
+            Some('f') => '\u{c}',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('u') => {
+                let start = self.pos - 1;
+                self.pos += 1;
+                let high = self.hex()?;
+
+                // characters outside the basic plane are written as two surrogates
+                let code = if (0xd800..0xdc00).contains(&high) {
+                    if !self.text[self.pos..].starts_with("\\u") {
+                        return Err(self.error_at(start, "unpaired surrogate in `\\u` escape"));
+                    }
+                    self.pos += 2;
+                    let low = self.hex()?;
+                    if !(0xdc00..0xe000).contains(&low) {
+                        return Err(self.error_at(start, "unpaired surrogate in `\\u` escape"));
+                    }
+                    0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+                } else {
+                    high
+                };
+
+                return char::from_u32(code)
+                    .ok_or_else(|| self.error_at(start, "unpaired surrogate in `\\u` escape"));
+            }
+            _ => return Err(self.error("unknown escape")),
+        };
+
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn hex(&mut self) -> Result<u32, String> {
+        let digits = self.text.get(self.pos..self.pos + 4);
+        let Some(digits) = digits.filter(|d| d.bytes().all(|b| b.is_ascii_hexdigit())) else {
+            return Err(self.error("expected 4 hex digits"));
+        };
+
+        self.pos += 4;
+        Ok(u32::from_str_radix(digits, 16).expect("the digits should be hex"))
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        self.eat('-');
+
+        match self.peek() {
+            Some('0') => self.pos += 1,
+            Some('1'..='9') => self.digits(),
+            _ => return Err(self.error("expected a digit")),
+        }
+
+        let mut is_float = false;
+        if self.eat('.') {
+            if !self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                return Err(self.error("expected a digit after `.`"));
+            }
+            self.digits();
+            is_float = true;
+        }
+        if self.eat('e') || self.eat('E') {
+            if !self.eat('+') {
+                self.eat('-');
+            }
+            if !self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                return Err(self.error("expected a digit in the exponent"));
+            }
+            self.digits();
+            is_float = true;
+        }
+
+        let number = &self.text[start..self.pos];
+        // ints too big for an `int` become floats
+        match number.parse() {
+            Ok(value) if !is_float => Ok(Value::Int(value)),
+            _ => Ok(Value::Float(
+                number.parse().expect("the number should be valid"),
+            )),
+        }
+    }
+
+    fn digits(&mut self) {
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str, value: Value) -> Result<Value, String> {
+        if !self.text[self.pos..].starts_with(keyword) {
+            return Err(self.error("expected a value"));
+        }
+        self.pos += keyword.len();
+        Ok(value)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ' | '\t' | '\n' | '\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += c.len_utf8();
+        }
+        found
+    }
+
+    fn error(&self, message: &str) -> String {
+        self.error_at(self.pos, message)
+    }
+
+    // `invalid JSON at 2:5: expected a value`, lines and columns count from 1
+    fn error_at(&self, pos: usize, message: &str) -> String {
+        let before = &self.text[..pos];
+        let line = before.matches('\n').count() + 1;
+        let column = before[before.rfind('\n').map_or(0, |i| i + 1)..]
+            .chars()
+            .count()
+            + 1;
+        format!("invalid JSON at {line}:{column}: {message}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<String, String> {
+        Parser::new(text).parse().map(|value| value.to_string())
+    }
+
+    #[test]
+    fn parses_documents() {
+        assert_eq!(
+            parse(r#" {"b": [1, -2.5, 1e3, true, null], "a": {}} "#).unwrap(),
+            r#"{ "b": [1, -2.5, 1000.0, true, nil], "a": {} }"#
+        );
+        assert_eq!(
+            parse(r#""tab\t\"q\" \u00e9 \ud83d\ude00""#).unwrap(),
+            "tab\t\"q\" é 😀"
+        );
+        assert_eq!(parse("[]").unwrap(), "[]");
+        assert_eq!(
+            parse("99999999999999999999").unwrap(),
+            "100000000000000000000.0"
+        );
+    }
+
+    #[test]
+    fn reports_where_input_is_malformed() {
+        let cases = [
+            (
+                "",
+                "invalid JSON at 1:1: expected a value, found the end of the input",
+            ),
+            ("[1,\n  2,\n  ]", "invalid JSON at 3:3: expected a value"),
+            (
+                "{\"a\" 1}",
+                "invalid JSON at 1:6: expected `:` after the key",
+            ),
+            (
+                "{\"é\": 1 \"b\"}",
+                "invalid JSON at 1:9: expected `,` or `}`",
+            ),
+            ("[01]", "invalid JSON at 1:3: expected `,` or `]`"),
+            ("\"abc", "invalid JSON at 1:5: unterminated string"),
+            (
+                "\"\\ud800\"",
+                "invalid JSON at 1:2: unpaired surrogate in `\\u` escape",
+            ),
+            ("1.", "invalid JSON at 1:3: expected a digit after `.`"),
+            ("nul", "invalid JSON at 1:1: expected a value"),
+            ("1 2", "invalid JSON at 1:3: expected the end of the input"),
+        ];
+
+        for (text, message) in cases {
+            assert_eq!(parse(text), Err(message.to_owned()), "{text:?}");
+        }
+
+        let deep = "[".repeat(MAX_DEPTH + 1);
+        assert!(parse(&deep)
+            .unwrap_err()
+            .contains("nesting deeper than 512 levels"));
+    }
+}
@@ -0,0 +1,303 @@
+//! Numbers, `kai.math`
+//!
+//! Functions taking a `float` accept an `int` too. The ones which can keep an `int`, like
+//! `abs`, `min` or `pow` with a positive exponent, do so and stop the program on overflow
+//! like the operators. `floor`, `ceil` and `round` give an `int`.
+
+use std::cell::RefMut;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::runtime::error::RuntimeError;
+use crate::runtime::value::{Handle, NativeContext, NativeFnPtr, Value};
+
+use super::collections::expect_list;
+use super::{args, opt_args};
+
+pub(super) const CONSTANTS: &[(&str, Value)] = &[
+    ("pi", Value::Float(std::f64::consts::PI)),
+    ("tau", Value::Float(std::f64::consts::TAU)),
+    ("e", Value::Float(std::f64::consts::E)),
+    ("inf", Value::Float(f64::INFINITY)),
+    ("nan", Value::Float(f64::NAN)),
+    ("max_int", Value::Int(i64::MAX)),
+    ("min_int", Value::Int(i64::MIN)),
+];
+
+pub(super) const FNS: &[(&str, NativeFnPtr, &str)] = &[
+    ("abs", abs, "(num) -> num"),
+    ("min", min, "(num, num) -> num"),
+    ("max", max, "(num, num) -> num"),
+    ("pow", pow, "(num, num) -> num"),
+    ("sqrt", sqrt, "(num) -> float"),
+    ("exp", exp, "(num) -> float"),
+    ("log", log, "(num, num?) -> float"),
+    ("sin", sin, "(num) -> float"),
+    ("cos", cos, "(num) -> float"),
+    ("tan", tan, "(num) -> float"),
+    ("asin", asin, "(num) -> float"),
+    ("acos", acos, "(num) -> float"),
+    ("atan", atan, "(num) -> float"),
+    ("atan2", atan2, "(num, num) -> float"),
+    ("floor", floor, "(num) -> int"),
+    ("ceil", ceil, "(num) -> int"),
+    ("round", round, "(num) -> int"),
+    ("is_nan", is_nan, "(num) -> bool"),
+    ("random", random, "(int?) -> any"),
+];
+
+const RANDOM_METHODS: &[(&str, NativeFnPtr)] = &[
+    ("int", random_int),
+    ("float", random_float),
+    ("choice", choice),
+    ("shuffle", shuffle),
+];
+
+enum Num {
+    Int(i64),
+    Float(f64),
+}
+
+impl Num {
+    fn to_f64(&self) -> f64 {
+        match *self {
+            Num::Int(n) => n as f64,
+            Num::Float(n) => n,
+        }
+    }
+}
+
+fn expect_num(cx: &dyn NativeContext, value: Value) -> Result<Num, RuntimeError> {
+    match value {
+        Value::Int(n) => Ok(Num::Int(n)),
+        Value::Float(n) => Ok(Num::Float(n)),
+        value => Err(cx.error(format!(
+            "expected `int` or `float`, found `{}`",
+            value.type_name()
+        ))),
+    }
+}
+
+fn expect_float(cx: &dyn NativeContext, value: Value) -> Result<f64, RuntimeError> {
+    Ok(expect_num(cx, value)?.to_f64())
+}
+
+fn expect_int(cx: &dyn NativeContext, value: Value) -> Result<i64, RuntimeError> {
+    match value {
+        Value::Int(n) => Ok(n),
+        value => Err(cx.error(format!("expected `int`, found `{}`", value.type_name()))),
+    }
+}
+
+fn abs(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [n] = self::args(cx, "abs", args)?;
+    match expect_num(cx, n)? {
+        Num::Int(n) => n
+            .checked_abs()
+            .map(Value::Int)
+            .ok_or_else(|| cx.error("attempt to negate with overflow".into())),
+        Num::Float(n) => Ok(Value::Float(n.abs())),
+    }
+}
+
+// the smaller of two numbers, an `int` when both are
+fn min(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [a, b] = self::args(cx, "min", args)?;
+    Ok(match (expect_num(cx, a)?, expect_num(cx, b)?) {
+        (Num::Int(a), Num::Int(b)) => Value::Int(a.min(b)),
+        (a, b) => Value::Float(a.to_f64().min(b.to_f64())),
+    })
+}
+
+// the larger of two numbers, an `int` when both are
+fn max(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [a, b] = self::args(cx, "max", args)?;
+    Ok(match (expect_num(cx, a)?, expect_num(cx, b)?) {
+        (Num::Int(a), Num::Int(b)) => Value::Int(a.max(b)),
+        (a, b) => Value::Float(a.to_f64().max(b.to_f64())),
+    })
+}
+
+// `base` to the power of `exp`, an `int` when both are and `exp` isn't negative
+fn pow(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [base, exp] = self::args(cx, "pow", args)?;
+    match (expect_num(cx, base)?, expect_num(cx, exp)?) {
+        (Num::Int(base), Num::Int(exp)) if exp >= 0 => u32::try_from(exp)
+            .ok()
+            .and_then(|exp| base.checked_pow(exp))
+            .map(Value::Int)
+            .ok_or_else(|| cx.error("attempt to raise to a power with overflow".into())),
+        (base, exp) => Ok(Value::Float(base.to_f64().powf(exp.to_f64()))),
+    }
+}
+
+// functions of one `float` giving a `float`
+macro_rules! float_fns {
+    ($($name:ident => $f:expr,)*) => {
+        $(
+            fn $name(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+                let [n] = self::args(cx, stringify!($name), args)?;
+                let f: fn(f64) -> f64 = $f;
+                Ok(Value::Float(f(expect_float(cx, n)?)))
+            }
+        )*
+    };
+}
+
+float_fns! {
+    sqrt => f64::sqrt,
+    exp => f64::exp,
+    sin => f64::sin,
+    cos => f64::cos,
+    tan => f64::tan,
+    asin => f64::asin,
+    acos => f64::acos,
+    atan => f64::atan,
+}
+
+// `log(x)` is the natural logarithm, `log(x, base)` the one of `base`
+fn log(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [n, base] = opt_args(cx, "log", 1, args)?;
+    let n = expect_float(cx, n.unwrap_or(Value::Nil))?;
+    Ok(Value::Float(match base {
+        Some(base) => n.log(expect_float(cx, base)?),
+        None => n.ln(),
+    }))
+}
+
+// the angle of the point `(x, y)`, `atan2(y, x)`
+fn atan2(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [y, x] = self::args(cx, "atan2", args)?;
+    let y = expect_float(cx, y)?;
+    Ok(Value::Float(y.atan2(expect_float(cx, x)?)))
+}
+
+fn floor(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [n] = self::args(cx, "floor", args)?;
+    to_int(cx, expect_num(cx, n)?, f64::floor)
+}
+
+fn ceil(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [n] = self::args(cx, "ceil", args)?;
+    to_int(cx, expect_num(cx, n)?, f64::ceil)
+}
+
+// halfway cases round away from zero, `round(2.5)` is `3`
+fn round(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [n] = self::args(cx, "round", args)?;
+    to_int(cx, expect_num(cx, n)?, f64::round)
+}
+
+// the rounded float as an `int`, `nan` and floats out of the range of `int` stop the program
+fn to_int(cx: &dyn NativeContext, n: Num, round: fn(f64) -> f64) -> Result<Value, RuntimeError> {
+    let n = match n {
+        Num::Int(n) => return Ok(Value::Int(n)),
+        Num::Float(n) => round(n),
+    };
+
+    // `i64::MAX as f64` rounds up to 2^63, which is out of range
+    if n >= i64::MIN as f64 && n < i64::MAX as f64 {
+        Ok(Value::Int(n as i64))
+    } else {
+        Err(cx.error(format!("cannot convert {} to `int`", Value::Float(n))))
+    }
+}
+
+fn is_nan(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [n] = self::args(cx, "is_nan", args)?;
+    Ok(Value::Bool(expect_float(cx, n)?.is_nan()))
+}
+
+/// A splitmix64 generator, small and fast but not for secrets
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // a number below `bound`, which must not be zero
+    fn below(&mut self, bound: u64) -> u64 {
+        ((self.next() as u128 * bound as u128) >> 64) as u64
+    }
+}
+
+// `random(seed)` always gives the same numbers, `random()` is seeded from the clock
+fn random(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [seed] = opt_args(cx, "random", 0, args)?;
+    let seed = match seed {
+        Some(seed) => expect_int(cx, seed)? as u64,
+        None => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64),
+    };
+    Ok(Value::Handle(Rc::new(Handle::new(
+        "random",
+        Random(seed),
+        RANDOM_METHODS,
+    ))))
+}
+
+fn generator(random: &Value) -> RefMut<'_, Random> {
+    match random {
+        Value::Handle(handle) => handle.get::<Random>().expect("handle of the method"),
+        _ => unreachable!("random methods are called on handles"),
+    }
+}
+
+// an `int` in a range, `rng.int(1..=6)`
+fn random_int(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [random, range] = self::args(cx, "int", args)?;
+    let (start, end) = match range {
+        Value::Range(start, end, _) => (start, end),
+        value => {
+            let message = format!("expected `range`, found `{}`", value.type_name());
+            return Err(cx.error(message));
+        }
+    };
+    if start >= end {
+        return Err(cx.error(format!(
+            "cannot pick a number from the empty range {start}..{end}"
+        )));
+    }
+
+    let span = end.wrapping_sub(start) as u64;
+    let offset = generator(&random).below(span);
+    Ok(Value::Int(start.wrapping_add(offset as i64)))
+}
+
+// a `float` from `0.0` up to but without `1.0`
+fn random_float(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [random] = self::args(cx, "float", args)?;
+    let bits = generator(&random).next() >> 11;
+    Ok(Value::Float(bits as f64 / (1u64 << 53) as f64))
+}
+
+// an item of the list, `nil` when it is empty
+fn choice(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [random, list] = self::args(cx, "choice", args)?;
+    let items = expect_list(cx, list)?;
+    let items = items.borrow();
+    if items.is_empty() {
+        return Ok(Value::Nil);
+    }
+    let idx = generator(&random).below(items.len() as u64);
+    Ok(items[idx as usize].clone())
+}
+
+// puts the items of the list in a random order
+fn shuffle(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [random, list] = self::args(cx, "shuffle", args)?;
+    let items = expect_list(cx, list)?;
+    let mut generator = generator(&random);
+    let mut items = items.borrow_mut();
+    for i in (1..items.len()).rev() {
+        let j = generator.below(i as u64 + 1) as usize;
+        items.swap(i, j);
+    }
+    Ok(Value::Nil)
+}
@@ -0,0 +1,210 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+
+use crate::runtime::error::RuntimeError;
+use crate::runtime::executor::Source;
+use crate::runtime::value::{Handle, NativeContext, NativeFnPtr, Value};
+
+use super::{args, expect_str, future};
+
+pub(super) const FNS: &[(&str, NativeFnPtr, &str)] = &[
+    ("connect", connect, "(string) -> future<any>"),
+    ("listen", listen, "(string) -> result<any>"),
+];
+
+const LISTENER_METHODS: &[(&str, NativeFnPtr)] = &[("accept", accept), ("addr", addr)];
+
+const SOCKET_METHODS: &[(&str, NativeFnPtr)] = &[
+    ("read", read),
+    ("write", write),
+    ("close", close),
+    ("peer_addr", peer_addr),
+];
+
+// `connect("host:port")`, the future is ready with a socket or an error
+fn connect(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [addr] = self::args(cx, "connect", args)?;
+    let addr = expect_str(cx, addr)?.to_string();
+
+    // resolving and connecting block, a thread does it for the executor
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || sender.send(TcpStream::connect(addr)));
+
+    Ok(future("connect", Connecting(receiver)))
+}
+
+struct Connecting(Receiver<std::io::Result<TcpStream>>);
+
+impl Source for Connecting {
+    fn poll(&mut self) -> Option<Value> {
+        match self.0.try_recv() {
+            Ok(Ok(stream)) => Some(socket(stream)),
+            Ok(Err(err)) => Some(Value::error(err.to_string())),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Value::error("the connection was lost")),
+        }
+    }
+}
+
+fn socket(stream: TcpStream) -> Value {
+    match stream.set_nonblocking(true) {
+        Ok(()) => Value::Handle(Rc::new(Handle::new(
+            "socket",
+            Rc::new(stream),
+            SOCKET_METHODS,
+        ))),
+        Err(err) => Value::error(err.to_string()),
+    }
+}
+
+// `listen("127.0.0.1:8080")` binds right away, port 0 picks a free one
+fn listen(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [addr] = self::args(cx, "listen", args)?;
+    let addr = expect_str(cx, addr)?;
+
+    let listener = TcpListener::bind(addr.as_str()).and_then(|listener| {
+        listener.set_nonblocking(true)?;
+        Ok(listener)
+    });
+
+    match listener {
+        Ok(listener) => Ok(Value::Handle(Rc::new(Handle::new(
+            "listener",
+            Rc::new(listener),
+            LISTENER_METHODS,
+        )))),
+        Err(err) => Ok(Value::error(format!("`{addr}`: {err}"))),
+    }
+}
+
+// the object of a handle, methods are only ever called on handles of their own kind
+fn object<T: 'static>(handle: &Value) -> Rc<T> {
+    match handle {
+        Value::Handle(handle) => handle.get::<Rc<T>>().expect("handle of the method").clone(),
+        _ => unreachable!("handle methods are called on handles"),
+    }
+}
+
+// a future ready with the next connection
+fn accept(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [listener] = self::args(cx, "accept", args)?;
+    Ok(future("accept", Accept(object(&listener))))
+}
+
+struct Accept(Rc<TcpListener>);
+
+impl Source for Accept {
+    fn poll(&mut self) -> Option<Value> {
+        match self.0.accept() {
+            Ok((stream, _)) => Some(socket(stream)),
+            Err(err) if would_block(&err) => None,
+            Err(err) => Some(Value::error(err.to_string())),
+        }
+    }
+}
+
+// the address the listener is bound to, with the port picked for port 0
+fn addr(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [listener] = self::args(cx, "addr", args)?;
+
+    match object::<TcpListener>(&listener).local_addr() {
+        Ok(addr) => Ok(Value::Str(addr.to_string().into())),
+        Err(err) => Ok(Value::error(err.to_string())),
+    }
+}
+
+fn peer_addr(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [socket] = self::args(cx, "peer_addr", args)?;
+
+    match object::<TcpStream>(&socket).peer_addr() {
+        Ok(addr) => Ok(Value::Str(addr.to_string().into())),
+        Err(err) => Ok(Value::error(err.to_string())),
+    }
+}
+
+// `read(max)`, a future ready with up to `max` bytes once some arrived and `""` once the
+// peer closed the connection. Bytes are read as UTF-8, invalid sequences are replaced.
+fn read(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [socket, max] = self::args(cx, "read", args)?;
+
+    let max = match max {
+        Value::Int(max) if max > 0 => max as usize,
+        Value::Int(max) => return Err(cx.error(format!("cannot read {max} bytes"))),
+        max => return Err(cx.error(format!("expected `int`, found `{}`", max.type_name()))),
+    };
+
+    Ok(future(
+        "read",
+        Reading {
+            socket: object(&socket),
+            buf: vec![0; max],
+        },
+    ))
+}
+
+struct Reading {
+    socket: Rc<TcpStream>,
+    buf: Vec<u8>,
+}
+
+impl Source for Reading {
+    fn poll(&mut self) -> Option<Value> {
+        match (&*self.socket).read(&mut self.buf) {
+            Ok(len) => Some(Value::Str(String::from_utf8_lossy(&self.buf[..len]).into())),
+            Err(err) if would_block(&err) => None,
+            Err(err) => Some(Value::error(err.to_string())),
+        }
+    }
+}
+
+// a future ready with `nil` once all of the string was sent
+fn write(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [socket, data] = self::args(cx, "write", args)?;
+    let data = expect_str(cx, data)?;
+
+    Ok(future(
+        "write",
+        Writing {
+            socket: object(&socket),
+            data: data.as_bytes().to_vec(),
+            written: 0,
+        },
+    ))
+}
+
+struct Writing {
+    socket: Rc<TcpStream>,
+    data: Vec<u8>,
+    written: usize,
+}
+
+impl Source for Writing {
+    fn poll(&mut self) -> Option<Value> {
+        while self.written < self.data.len() {
+            match (&*self.socket).write(&self.data[self.written..]) {
+                Ok(0) => return Some(Value::error("the connection was closed")),
+                Ok(len) => self.written += len,
+                Err(err) if would_block(&err) => return None,
+                Err(err) => return Some(Value::error(err.to_string())),
+            }
+        }
+        Some(Value::Nil)
+    }
+}
+
+// shuts the connection down in both directions, the peer reads `""` from then on
+fn close(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [socket] = self::args(cx, "close", args)?;
+
+    match object::<TcpStream>(&socket).shutdown(std::net::Shutdown::Both) {
+        Ok(()) => Ok(Value::Nil),
+        Err(err) if err.kind() == ErrorKind::NotConnected => Ok(Value::Nil),
+        Err(err) => Ok(Value::error(err.to_string())),
+    }
+}
+
+fn would_block(err: &std::io::Error) -> bool {
+    matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted)
+}
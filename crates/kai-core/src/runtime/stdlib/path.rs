@@ -0,0 +1,227 @@
+//! Paths as text, nothing here touches the file system
+//!
+//! The functions take strings or `Path` instances and return strings, the methods of
+//! `Path` return paths again. Parts which don't exist, like the extension of `a/b`, are
+//! `nil`.
+
+use std::cell::RefCell;
+use std::path::{Component, PathBuf};
+use std::rc::Rc;
+
+use ecow::EcoString;
+
+use crate::resolve::BuiltinInterface;
+use crate::runtime::error::RuntimeError;
+use crate::runtime::value::{Class, Instance, NativeContext, NativeFnPtr, Value};
+
+use super::{args, native_class};
+
+pub(super) const FNS: &[(&str, NativeFnPtr, &str)] = &[
+    ("join", join, "(any, ..) -> any"),
+    ("parent", parent, "(any) -> any"),
+    ("file_name", file_name, "(any) -> any"),
+    ("stem", stem, "(any) -> any"),
+    ("extension", extension, "(any) -> any"),
+    ("normalize", normalize, "(any) -> any"),
+    ("is_absolute", is_absolute, "(any) -> bool"),
+];
+
+// `Path.new(text)` and the methods of its instances, which take the path first like the
+// functions do
+const METHODS: &[(&str, NativeFnPtr, bool)] = &[
+    ("new", new, false),
+    ("join", join, true),
+    ("parent", parent, true),
+    ("file_name", file_name, true),
+    ("stem", stem, true),
+    ("extension", extension, true),
+    ("normalize", normalize, true),
+    ("is_absolute", is_absolute, true),
+    ("to_string", to_string, true),
+    ("eq", eq, true),
+];
+
+thread_local! {
+    // one class so instances made by different calls are of the same class
+    static CLASS: Rc<Class> = Rc::new(native_class(
+        "Path",
+        &["text"],
+        vec![BuiltinInterface::Display, BuiltinInterface::Eq],
+        METHODS,
+    ));
+}
+
+/// The `Path` class, `kai.path.Path`
+pub(super) fn class() -> Rc<Class> {
+    CLASS.with(Rc::clone)
+}
+
+/// The text of a string or a `Path`, for natives taking either
+pub(super) fn expect_path(cx: &dyn NativeContext, value: Value) -> Result<EcoString, RuntimeError> {
+    match value {
+        Value::Str(text) => Ok(text),
+        Value::Instance(instance) if Rc::ptr_eq(&instance.class, &class()) => {
+            match instance.field("text") {
+                Some(Value::Str(text)) => Ok(text),
+                _ => Err(cx.error("the text of a `Path` must be a `string`".to_owned())),
+            }
+        }
+        value => Err(cx.error(format!(
+            "expected `string` or `Path`, found `{}`",
+            value.type_name()
+        ))),
+    }
+}
+
+// it only holds a string, so it is left out of the heap like the objects of `object`
+fn path(text: impl Into<EcoString>) -> Value {
+    Value::Instance(Rc::new(Instance {
+        class: class(),
+        fields: RefCell::new(vec![Value::Str(text.into())]),
+    }))
+}
+
+// paths stay paths and strings stay strings
+fn same_kind(receiver: &Value, text: impl Into<EcoString>) -> Value {
+    match receiver {
+        Value::Instance(_) => path(text),
+        _ => Value::Str(text.into()),
+    }
+}
+
+fn new(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [text] = self::args(cx, "new", args)?;
+    Ok(path(expect_path(cx, text)?))
+}
+
+// `join(base, ..parts)`, an absolute part replaces everything before it
+fn join(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let mut args = args.into_iter();
+    let Some(base) = args.next() else {
+        return Err(cx.error("`join` takes a path and the parts to add to it".to_owned()));
+    };
+
+    let mut path = PathBuf::from(expect_path(cx, base.clone())?.as_str());
+    for part in args {
+        path.push(expect_path(cx, part)?.as_str());
+    }
+    Ok(same_kind(&base, path.to_string_lossy().as_ref()))
+}
+
+// the path without its last part, `nil` for a root or a single part
+fn parent(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [path] = self::args(cx, "parent", args)?;
+    let text = expect_path(cx, path.clone())?;
+
+    Ok(match std::path::Path::new(text.as_str()).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => {
+            same_kind(&path, parent.to_string_lossy().as_ref())
+        }
+        _ => Value::Nil,
+    })
+}
+
+fn file_name(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    part(cx, "file_name", args, std::path::Path::file_name)
+}
+
+// the file name without its extension
+fn stem(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    part(cx, "stem", args, std::path::Path::file_stem)
+}
+
+// the extension without the dot
+fn extension(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    part(cx, "extension", args, std::path::Path::extension)
+}
+
+fn part(
+    cx: &mut dyn NativeContext,
+    name: &str,
+    args: Vec<Value>,
+    get: fn(&std::path::Path) -> Option<&std::ffi::OsStr>,
+) -> Result<Value, RuntimeError> {
+    let [path] = self::args(cx, name, args)?;
+    let text = expect_path(cx, path)?;
+
+    Ok(match get(std::path::Path::new(text.as_str())) {
+        Some(part) => Value::Str(part.to_string_lossy().as_ref().into()),
+        None => Value::Nil,
+    })
+}
+
+// removes `.` and folds `..` into the part before it without looking at the file
+// system, `a/./b/../c` is `a/c`
+fn normalize(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [path] = self::args(cx, "normalize", args)?;
+    let text = expect_path(cx, path.clone())?;
+    Ok(same_kind(&path, normalized(&text)))
+}
+
+fn normalized(text: &str) -> String {
+    let mut parts: Vec<Component> = Vec::new();
+
+    for component in std::path::Path::new(text).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match parts.last() {
+                Some(Component::Normal(_)) => {
+                    parts.pop();
+                }
+                // nothing is above the root
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => parts.push(component),
+            },
+            component => parts.push(component),
+        }
+    }
+
+    match parts.iter().collect::<PathBuf>() {
+        path if path.as_os_str().is_empty() => ".".to_owned(),
+        path => path.to_string_lossy().into_owned(),
+    }
+}
+
+fn is_absolute(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [path] = self::args(cx, "is_absolute", args)?;
+    let text = expect_path(cx, path)?;
+    Ok(Value::Bool(
+        std::path::Path::new(text.as_str()).is_absolute(),
+    ))
+}
+
+fn to_string(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [path] = self::args(cx, "to_string", args)?;
+    Ok(Value::Str(expect_path(cx, path)?))
+}
+
+// paths are equal when their text is, `a/./b` and `a/b` are not
+fn eq(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [path, other] = self::args(cx, "eq", args)?;
+    let text = expect_path(cx, path)?;
+    Ok(Value::Bool(match other {
+        Value::Nil => false,
+        other => expect_path(cx, other)? == text,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_paths() {
+        let cases = [
+            ("a/./b/../c", "a/c"),
+            ("./a/", "a"),
+            ("a/..", "."),
+            ("../../a/../b", "../../b"),
+            ("/../a/./b/..", "/a"),
+            ("", "."),
+        ];
+
+        for (text, normal) in cases {
+            assert_eq!(normalized(text), normal, "{text:?}");
+        }
+    }
+}
@@ -0,0 +1,437 @@
+use std::cell::RefMut;
+use std::io::{BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Output, Stdio};
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+
+use ecow::EcoString;
+
+use crate::runtime::error::{RuntimeError, RuntimeErrorKind};
+use crate::runtime::executor::Source;
+use crate::runtime::value::{Handle, NativeContext, NativeFnPtr, Value};
+
+use super::io::{next_line, rest};
+use super::path::expect_path;
+use super::{args, expect_str, future, object, opt_args, track};
+
+pub(super) const FNS: &[(&str, NativeFnPtr, &str)] = &[
+    ("output", output, "(string, ..) -> future<any>"),
+    ("run", run, "(string, list<string>?) -> result<Output>"),
+    ("command", command, "(string) -> any"),
+];
+
+// what the script itself was started with, these don't start anything
+pub(super) const SCRIPT_FNS: &[(&str, NativeFnPtr, &str)] = &[
+    ("args", script_args, "() -> list<string>"),
+    ("exit", exit, "(int) -> nil"),
+];
+
+// a command being set up, the setters return the command so calls can be chained
+const COMMAND_METHODS: &[(&str, NativeFnPtr)] = &[
+    ("arg", arg),
+    ("args", command_args),
+    ("env", env),
+    ("env_remove", env_remove),
+    ("env_clear", env_clear),
+    ("cwd", cwd),
+    ("input", input),
+    ("stdin_from", stdin_from),
+    ("run", command_run),
+    ("spawn", spawn),
+];
+
+// a running process, reading from it goes through its stdout line by line
+const CHILD_METHODS: &[(&str, NativeFnPtr)] = &[
+    ("write", child_write),
+    ("close_stdin", close_stdin),
+    ("read_line", child_read_line),
+    ("read_to_string", child_read_to_string),
+    ("lines", lines),
+    ("next", child_read_line),
+    ("wait", wait),
+    ("kill", kill),
+    ("pid", pid),
+];
+
+// `output(cmd, ..args)` runs a command without a shell, the future is ready with an
+// `Output { status, stdout, stderr }` once it exited, or an error if it didn't start
+fn output(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let mut args = args.into_iter();
+    let Some(cmd) = args.next() else {
+        return Err(cx.error("`output` takes a command and its arguments".to_owned()));
+    };
+
+    let mut command = Command::new(expect_str(cx, cmd)?.as_str());
+    for arg in args {
+        command.arg(expect_str(cx, arg)?.as_str());
+    }
+
+    // the command runs on a thread of its own so the executor is not blocked
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || sender.send(command.output()));
+
+    Ok(future("output", Exited(receiver)))
+}
+
+struct Exited(Receiver<std::io::Result<Output>>);
+
+impl Source for Exited {
+    fn poll(&mut self) -> Option<Value> {
+        match self.0.try_recv() {
+            Ok(Ok(output)) => Some(output_value(output)),
+            Ok(Err(err)) => Some(Value::error(err.to_string())),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Value::error("the command was lost")),
+        }
+    }
+}
+
+// `status` is -1 if the command was killed by a signal
+fn output_value(output: Output) -> Value {
+    let text = |bytes: Vec<u8>| Value::Str(String::from_utf8_lossy(&bytes).into());
+
+    object(
+        "Output",
+        [
+            ("status", status(output.status)),
+            ("stdout", text(output.stdout)),
+            ("stderr", text(output.stderr)),
+        ],
+    )
+}
+
+fn status(status: std::process::ExitStatus) -> Value {
+    Value::Int(status.code().unwrap_or(-1).into())
+}
+
+// `run(cmd)` or `run(cmd, args)` with a list of arguments, waits for the command and
+// returns its `Output` like `output` does
+fn run(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [cmd, cmd_args] = opt_args(cx, "run", 1, args)?;
+    let mut command = Command::new(expect_str(cx, cmd.unwrap_or(Value::Nil))?.as_str());
+    if let Some(cmd_args) = cmd_args {
+        command.args(strings(cx, cmd_args)?);
+    }
+
+    Ok(match command.output() {
+        Ok(output) => output_value(output),
+        Err(err) => start_error(&command, err),
+    })
+}
+
+fn strings(cx: &dyn NativeContext, list: Value) -> Result<Vec<String>, RuntimeError> {
+    let Value::List(items) = list else {
+        return Err(cx.error(format!("expected `list`, found `{}`", list.type_name())));
+    };
+
+    let items = items.borrow().clone();
+    items
+        .into_iter()
+        .map(|item| Ok(expect_str(cx, item)?.to_string()))
+        .collect()
+}
+
+fn start_error(command: &Command, err: std::io::Error) -> Value {
+    let program = command.get_program().to_string_lossy();
+    Value::error(format!("`{program}`: {err}"))
+}
+
+struct Builder {
+    command: Command,
+    // written to the stdin of the command by `run`
+    input: Option<EcoString>,
+    // set by `stdin_from`, the input is then read from another process
+    piped: bool,
+}
+
+// `command(cmd)` sets up a command to `run` or `spawn`
+fn command(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [cmd] = self::args(cx, "command", args)?;
+    let builder = Builder {
+        command: Command::new(expect_str(cx, cmd)?.as_str()),
+        input: None,
+        piped: false,
+    };
+    Ok(Value::Handle(Rc::new(Handle::new(
+        "command",
+        builder,
+        COMMAND_METHODS,
+    ))))
+}
+
+fn builder(command: &Value) -> RefMut<'_, Builder> {
+    match command {
+        Value::Handle(handle) => handle.get::<Builder>().expect("handle of the method"),
+        _ => unreachable!("command methods are called on handles"),
+    }
+}
+
+fn arg(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [command, arg] = self::args(cx, "arg", args)?;
+    let arg = expect_str(cx, arg)?;
+    builder(&command).command.arg(arg.as_str());
+    Ok(command)
+}
+
+// `args(list)` adds every argument of the list
+fn command_args(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [command, list] = self::args(cx, "args", args)?;
+    let list = strings(cx, list)?;
+    builder(&command).command.args(list);
+    Ok(command)
+}
+
+// `env(name, value)` sets a variable of the command, the others are inherited
+fn env(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [command, name, value] = self::args(cx, "env", args)?;
+    let name = expect_str(cx, name)?;
+    let value = expect_str(cx, value)?;
+    builder(&command).command.env(name.as_str(), value.as_str());
+    Ok(command)
+}
+
+fn env_remove(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [command, name] = self::args(cx, "env_remove", args)?;
+    let name = expect_str(cx, name)?;
+    builder(&command).command.env_remove(name.as_str());
+    Ok(command)
+}
+
+// nothing is inherited, only variables set with `env` afterwards are passed
+fn env_clear(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [command] = self::args(cx, "env_clear", args)?;
+    builder(&command).command.env_clear();
+    Ok(command)
+}
+
+// the working directory of the command, a string or a `Path`
+fn cwd(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [command, dir] = self::args(cx, "cwd", args)?;
+    let dir = expect_path(cx, dir)?;
+    builder(&command).command.current_dir(dir.as_str());
+    Ok(command)
+}
+
+// the text `run` writes to the stdin of the command
+fn input(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [command, text] = self::args(cx, "input", args)?;
+    builder(&command).input = Some(expect_str(cx, text)?);
+    Ok(command)
+}
+
+// `stdin_from(child)` connects the stdout of a spawned process to the stdin of the
+// command, like `a | b` in a shell. The child can't be read from afterwards.
+fn stdin_from(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [command, child] = self::args(cx, "stdin_from", args)?;
+
+    let stdout = match &child {
+        Value::Handle(handle) if handle.kind == "child" => handle
+            .get::<Running>()
+            .expect("handle of a child")
+            .stdout
+            .take(),
+        child => {
+            let message = format!("expected `child`, found `{}`", child.type_name());
+            return Err(cx.error(message));
+        }
+    };
+    let Some(stdout) = stdout else {
+        return Ok(Value::error("the stdout of the child was already taken"));
+    };
+
+    let mut builder = builder(&command);
+    builder.command.stdin(Stdio::from(stdout.into_inner()));
+    builder.piped = true;
+    drop(builder);
+    Ok(command)
+}
+
+// waits for the command and returns its `Output`, stdin is empty unless set with `input`
+// or `stdin_from`
+fn command_run(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [command] = self::args(cx, "run", args)?;
+    let mut builder = builder(&command);
+    let input = builder.input.clone();
+
+    if !builder.piped {
+        let stdin = if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        };
+        builder.command.stdin(stdin);
+    }
+    builder
+        .command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let mut child = match builder.command.spawn() {
+        Ok(child) => child,
+        Err(err) => return Ok(start_error(&builder.command, err)),
+    };
+
+    // written on a thread of its own, a command filling its stdout before reading all
+    // of its input would otherwise never finish
+    let writer = child.stdin.take().zip(input).map(|(mut stdin, input)| {
+        std::thread::spawn(move || {
+            let _ = stdin.write_all(input.as_bytes());
+        })
+    });
+    let output = child.wait_with_output();
+    if let Some(writer) = writer {
+        let _ = writer.join();
+    }
+
+    Ok(match output {
+        Ok(output) => output_value(output),
+        Err(err) => Value::error(err.to_string()),
+    })
+}
+
+struct Running {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    stdout: Option<BufReader<ChildStdout>>,
+}
+
+// starts the command with pipes to its stdin and from its stdout, stderr goes where the
+// stderr of the script goes
+fn spawn(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [command] = self::args(cx, "spawn", args)?;
+    let mut builder = builder(&command);
+
+    if !builder.piped {
+        builder.command.stdin(Stdio::piped());
+    }
+    builder
+        .command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit());
+
+    Ok(match builder.command.spawn() {
+        Ok(mut child) => {
+            let running = Running {
+                stdin: child.stdin.take(),
+                stdout: child.stdout.take().map(BufReader::new),
+                child,
+            };
+            Value::Handle(Rc::new(Handle::new("child", running, CHILD_METHODS)))
+        }
+        Err(err) => start_error(&builder.command, err),
+    })
+}
+
+fn running(child: &Value) -> RefMut<'_, Running> {
+    match child {
+        Value::Handle(handle) => handle.get::<Running>().expect("handle of the method"),
+        _ => unreachable!("child methods are called on handles"),
+    }
+}
+
+// writes a value the way `print` shows it to the stdin of the child
+fn child_write(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [child, value] = self::args(cx, "write", args)?;
+    let text = cx.display(&value)?;
+
+    let mut running = running(&child);
+
+    Ok(match running.stdin.as_mut() {
+        Some(stdin) => match stdin.write_all(text.as_bytes()) {
+            Ok(()) => Value::Nil,
+            Err(err) => Value::error(err.to_string()),
+        },
+        None => Value::error("the stdin of the child is closed"),
+    })
+}
+
+// the child reads the end of its input from then on
+fn close_stdin(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [child] = self::args(cx, "close_stdin", args)?;
+    running(&child).stdin = None;
+    Ok(Value::Nil)
+}
+
+// the next line of the stdout of the child, `nil` once it closed it
+fn child_read_line(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [child] = self::args(cx, "read_line", args)?;
+    let mut running = running(&child);
+
+    Ok(match running.stdout.as_mut() {
+        Some(stdout) => next_line(stdout),
+        None => Value::error("the stdout of the child was taken"),
+    })
+}
+
+fn child_read_to_string(
+    cx: &mut dyn NativeContext,
+    args: Vec<Value>,
+) -> Result<Value, RuntimeError> {
+    let [child] = self::args(cx, "read_to_string", args)?;
+    let mut running = running(&child);
+
+    Ok(match running.stdout.as_mut() {
+        Some(stdout) => rest(stdout),
+        None => Value::error("the stdout of the child was taken"),
+    })
+}
+
+// `for line in child.lines()`, the child is its own iterator
+fn lines(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [child] = self::args(cx, "lines", args)?;
+    Ok(child)
+}
+
+// closes the stdin of the child and waits for it to exit, returns its status
+fn wait(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [child] = self::args(cx, "wait", args)?;
+    let mut running = running(&child);
+    running.stdin = None;
+
+    Ok(match running.child.wait() {
+        Ok(exit) => status(exit),
+        Err(err) => Value::error(err.to_string()),
+    })
+}
+
+fn kill(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [child] = self::args(cx, "kill", args)?;
+    let killed = running(&child).child.kill();
+
+    Ok(match killed {
+        Ok(()) => Value::Nil,
+        Err(err) => Value::error(err.to_string()),
+    })
+}
+
+fn pid(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [child] = self::args(cx, "pid", args)?;
+    let id = running(&child).child.id();
+    Ok(Value::Int(id.into()))
+}
+
+// the arguments passed to the script after its path, see `Engine::set_args`
+fn script_args(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [] = self::args(cx, "args", args)?;
+    let args = cx.args().iter().cloned().map(Value::Str).collect();
+    track(cx, Value::list(args))
+}
+
+// `exit(code)` stops the script, the error it stops with tells the host which code to
+// exit with
+fn exit(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [code] = self::args(cx, "exit", args)?;
+    let code = match code {
+        Value::Int(code) => i32::try_from(code)
+            .map_err(|_| cx.error(format!("exit code `{code}` is out of range")))?,
+        code => return Err(cx.error(format!("expected `int`, found `{}`", code.type_name()))),
+    };
+
+    let span = cx.error(String::new()).span;
+    let message = format!("the script exited with code {code}");
+    Err(RuntimeError::with_kind(
+        RuntimeErrorKind::Exit(code),
+        message,
+        span,
+    ))
+}
@@ -0,0 +1,901 @@
+//! Regular expressions, `kai.regex`
+//!
+//! `compile(pattern)` returns a regex, or an error value with the position of the mistake.
+//! Patterns have literals, `.`, classes like `[a-z_]` and `[^,]`, `\d`, `\w`, `\s` and their
+//! negations, `^`, `$`, `\b`, groups `(..)`, `(?:..)` and `(?<name>..)`, `|` and the
+//! repetitions `*`, `+`, `?` and `{n,m}`, lazy with a `?` after them. `\d` is an ASCII digit,
+//! `\w` and `\s` follow Unicode. There are no backreferences, so matching takes time linear
+//! in the length of the text.
+//!
+//! Positions are char indices like the ones of `kai.string`. Matches are objects with the
+//! `text`, `start` and `end` of the match.
+
+use std::cell::RefMut;
+use std::rc::Rc;
+
+use ecow::EcoString;
+
+use crate::runtime::error::RuntimeError;
+use crate::runtime::value::{Handle, Key, Map, NativeContext, NativeFnPtr, Value};
+
+use super::{args, expect_str, object, track};
+
+pub(super) const FNS: &[(&str, NativeFnPtr, &str)] = &[
+    ("compile", compile, "(string) -> result<any>"),
+    ("escape", escape, "(string) -> string"),
+];
+
+// every method takes the regex first
+const REGEX_METHODS: &[(&str, NativeFnPtr)] = &[
+    ("is_match", is_match),
+    ("find", find),
+    ("find_all", find_all),
+    ("captures", captures),
+    ("replace", replace),
+    ("split", split),
+];
+
+// repetitions are unrolled, this keeps `(a{1000}){1000}` from taking all memory
+const MAX_REPEAT: u32 = 1000;
+const MAX_PROGRAM: usize = 100_000;
+
+fn compile(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [pattern] = self::args(cx, "compile", args)?;
+    let pattern = expect_str(cx, pattern)?;
+
+    Ok(match Regex::new(&pattern) {
+        Ok(regex) => Value::Handle(Rc::new(Handle::new("regex", regex, REGEX_METHODS))),
+        Err((message, pos)) => Value::error(format!(
+            "invalid regex `{pattern}`: {message} at position {pos}"
+        )),
+    })
+}
+
+// the text with every char which means something in a pattern escaped
+fn escape(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [text] = self::args(cx, "escape", args)?;
+    let text = expect_str(cx, text)?;
+
+    let mut escaped = EcoString::new();
+    for c in text.chars() {
+        if "\\.+*?()|[]{}^$-".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    Ok(Value::Str(escaped))
+}
+
+fn regex(regex: &Value) -> RefMut<'_, Regex> {
+    match regex {
+        Value::Handle(handle) => handle.get::<Regex>().expect("handle of the method"),
+        _ => unreachable!("regex methods are called on handles"),
+    }
+}
+
+// the text as chars, matching works on char indices
+fn expect_chars(cx: &dyn NativeContext, text: Value) -> Result<Vec<char>, RuntimeError> {
+    Ok(expect_str(cx, text)?.chars().collect())
+}
+
+fn text(chars: &[char], start: usize, end: usize) -> Value {
+    Value::Str(chars[start..end].iter().copied().collect())
+}
+
+fn match_object(chars: &[char], slots: &Slots) -> Value {
+    let (start, end) = slots.group(0).expect("a match has group 0");
+    object(
+        "Match",
+        [
+            ("text", text(chars, start, end)),
+            ("start", Value::Int(start as i64)),
+            ("end", Value::Int(end as i64)),
+        ],
+    )
+}
+
+fn is_match(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [regex, text] = self::args(cx, "is_match", args)?;
+    let chars = expect_chars(cx, text)?;
+    let found = self::regex(&regex).search(&chars, 0).is_some();
+    Ok(Value::Bool(found))
+}
+
+// the first match, `nil` when there is none
+fn find(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [regex, text] = self::args(cx, "find", args)?;
+    let chars = expect_chars(cx, text)?;
+    let found = self::regex(&regex).search(&chars, 0);
+    Ok(found.map_or(Value::Nil, |slots| match_object(&chars, &slots)))
+}
+
+// every match which doesn't overlap an earlier one
+fn find_all(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [regex, text] = self::args(cx, "find_all", args)?;
+    let chars = expect_chars(cx, text)?;
+    let matches = self::regex(&regex).search_all(&chars);
+    let matches = matches
+        .iter()
+        .map(|slots| match_object(&chars, slots))
+        .collect();
+    track(cx, Value::list(matches))
+}
+
+// the groups of the first match as a map from their number, and their name for named ones,
+// to their text. Group `0` is the whole match, groups which took no part are `nil`. `nil`
+// when there is no match.
+fn captures(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [regex, text] = self::args(cx, "captures", args)?;
+    let chars = expect_chars(cx, text)?;
+    let regex = self::regex(&regex);
+    let Some(slots) = regex.search(&chars, 0) else {
+        return Ok(Value::Nil);
+    };
+
+    let group = |idx| match slots.group(idx) {
+        Some((start, end)) => self::text(&chars, start, end),
+        None => Value::Nil,
+    };
+    let mut map = Map::new();
+    for idx in 0..=regex.groups {
+        map.insert(Key::Int(idx as i64), group(idx));
+    }
+    for (name, idx) in &regex.names {
+        map.insert(Key::Str(name.clone()), group(*idx));
+    }
+    track(cx, Value::map(map))
+}
+
+// replaces every match, `$1` or `${name}` in the replacement are the text of a group and
+// `$$` is a `$`
+fn replace(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [regex, text, replacement] = self::args(cx, "replace", args)?;
+    let chars = expect_chars(cx, text)?;
+    let replacement = expect_str(cx, replacement)?;
+    let regex = self::regex(&regex);
+    let parts = regex
+        .replacement(&replacement)
+        .map_err(|message| cx.error(message))?;
+
+    let mut replaced = EcoString::new();
+    let mut last = 0;
+    for slots in regex.search_all(&chars) {
+        let (start, end) = slots.group(0).expect("a match has group 0");
+        replaced.extend(chars[last..start].iter().copied());
+        for part in &parts {
+            match part {
+                Part::Text(text) => replaced.push_str(text),
+                Part::Group(idx) => {
+                    if let Some((start, end)) = slots.group(*idx) {
+                        replaced.extend(chars[start..end].iter().copied());
+                    }
+                }
+            }
+        }
+        last = end;
+    }
+    replaced.extend(chars[last..].iter().copied());
+    Ok(Value::Str(replaced))
+}
+
+// the text between the matches
+fn split(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [regex, text] = self::args(cx, "split", args)?;
+    let chars = expect_chars(cx, text)?;
+
+    let mut parts = Vec::new();
+    let mut last = 0;
+    for slots in self::regex(&regex).search_all(&chars) {
+        let (start, end) = slots.group(0).expect("a match has group 0");
+        parts.push(self::text(&chars, last, start));
+        last = end;
+    }
+    parts.push(self::text(&chars, last, chars.len()));
+    track(cx, Value::list(parts))
+}
+
+/// A compiled pattern
+struct Regex {
+    program: Vec<Inst>,
+    // the number of groups, without group 0 which is the whole match
+    groups: usize,
+    names: Vec<(EcoString, usize)>,
+}
+
+// what went wrong and the char index in the pattern where it did
+type Fail = (String, usize);
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Char(char),
+    // any char but `\n`
+    Any,
+    Class(Class),
+    Assert(Assert),
+    // a group and its number, `None` for `(?:..)`
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Assert {
+    Start,
+    End,
+    WordBoundary,
+    NotWordBoundary,
+}
+
+impl Assert {
+    fn holds(self, text: &[char], pos: usize) -> bool {
+        let is_word = |c: Option<&char>| c.is_some_and(|&c| is_word(c));
+        let boundary =
+            || is_word(pos.checked_sub(1).and_then(|pos| text.get(pos))) != is_word(text.get(pos));
+        match self {
+            Assert::Start => pos == 0,
+            Assert::End => pos == text.len(),
+            Assert::WordBoundary => boundary(),
+            Assert::NotWordBoundary => !boundary(),
+        }
+    }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Class {
+    items: Vec<ClassItem>,
+    negated: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ClassItem {
+    Range(char, char),
+    // `\d`, `\w` or `\s`, negated for `\D`, `\W` and `\S`
+    Digit(bool),
+    Word(bool),
+    Space(bool),
+}
+
+impl Class {
+    fn matches(&self, c: char) -> bool {
+        let found = self.items.iter().any(|item| match *item {
+            ClassItem::Range(lo, hi) => (lo..=hi).contains(&c),
+            ClassItem::Digit(negated) => c.is_ascii_digit() != negated,
+            ClassItem::Word(negated) => is_word(c) != negated,
+            ClassItem::Space(negated) => c.is_whitespace() != negated,
+        });
+        found != self.negated
+    }
+}
+
+// what follows a `\`
+enum Escape {
+    Char(char),
+    Class(ClassItem),
+    Assert(Assert),
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    groups: usize,
+    names: Vec<(EcoString, usize)>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn alternation(&mut self) -> Result<Node, Fail> {
+        let mut alts = vec![self.concat()?];
+        while self.eat('|') {
+            alts.push(self.concat()?);
+        }
+        Ok(match alts.len() {
+            1 => alts.pop().expect("one alternative"),
+            _ => Node::Alt(alts),
+        })
+    }
+
+    fn concat(&mut self) -> Result<Node, Fail> {
+        let mut nodes = Vec::new();
+        while !matches!(self.peek(), None | Some('|' | ')')) {
+            let atom = self.atom()?;
+            nodes.push(self.repeat(atom)?);
+        }
+        Ok(Node::Concat(nodes))
+    }
+
+    fn atom(&mut self) -> Result<Node, Fail> {
+        let start = self.pos;
+        Ok(match self.next().expect("checked by `concat`") {
+            '.' => Node::Any,
+            '^' => Node::Assert(Assert::Start),
+            '$' => Node::Assert(Assert::End),
+            '(' => self.group(start)?,
+            '[' => Node::Class(self.class(start)?),
+            '\\' => match self.escape(start)? {
+                Escape::Char(c) => Node::Char(c),
+                Escape::Class(item) => Node::Class(Class {
+                    items: vec![item],
+                    negated: false,
+                }),
+                Escape::Assert(assert) => Node::Assert(assert),
+            },
+            '*' | '+' | '?' | '{' => return Err(("nothing to repeat".into(), start)),
+            c => Node::Char(c),
+        })
+    }
+
+    fn group(&mut self, start: usize) -> Result<Node, Fail> {
+        let idx = if self.eat('?') {
+            let flag = self.pos;
+            if self.eat(':') {
+                None
+            } else if self.eat('<') || (self.eat('P') && self.eat('<')) {
+                Some(self.group_name()?)
+            } else {
+                return Err(("unknown group flag".into(), flag));
+            }
+        } else {
+            self.groups += 1;
+            Some(self.groups)
+        };
+
+        let node = self.alternation()?;
+        if !self.eat(')') {
+            return Err(("unclosed group".into(), start));
+        }
+        Ok(Node::Group(Box::new(node), idx))
+    }
+
+    // the name of `(?<name>..)` after the `<`, numbered like every other group
+    fn group_name(&mut self) -> Result<usize, Fail> {
+        let start = self.pos;
+        while self.peek().is_some_and(is_word) {
+            self.pos += 1;
+        }
+        let name: EcoString = self.chars[start..self.pos].iter().copied().collect();
+        if name.is_empty() || !self.eat('>') {
+            return Err(("invalid group name".into(), start));
+        }
+        if self.names.iter().any(|(other, _)| *other == name) {
+            return Err((format!("duplicate group name `{name}`"), start));
+        }
+
+        self.groups += 1;
+        self.names.push((name, self.groups));
+        Ok(self.groups)
+    }
+
+    fn repeat(&mut self, node: Node) -> Result<Node, Fail> {
+        let start = self.pos;
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => self.counts(start)?,
+            _ => return Ok(node),
+        };
+        if self.chars[start] != '{' {
+            self.pos += 1;
+        }
+
+        let greedy = !self.eat('?');
+        Ok(Node::Repeat {
+            node: Box::new(node),
+            min,
+            max,
+            greedy,
+        })
+    }
+
+    // `{n}`, `{n,}` or `{n,m}`, leaves the parser after the `}`
+    fn counts(&mut self, start: usize) -> Result<(u32, Option<u32>), Fail> {
+        self.pos += 1;
+        let invalid = || ("invalid repetition".to_owned(), start);
+
+        let min = self.number().ok_or_else(invalid)?;
+        let max = match self.eat(',') {
+            true if self.peek() == Some('}') => None,
+            true => Some(self.number().ok_or_else(invalid)?),
+            false => Some(min),
+        };
+        if !self.eat('}') || max.is_some_and(|max| max < min) {
+            return Err(invalid());
+        }
+        if min.max(max.unwrap_or(0)) > MAX_REPEAT {
+            return Err(("repetition count is too large".into(), start));
+        }
+        Ok((min, max))
+    }
+
+    fn number(&mut self) -> Option<u32> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let digits: String = self.chars[start..self.pos].iter().collect();
+        // too many digits to parse is too large a count too
+        digits
+            .parse()
+            .ok()
+            .or((!digits.is_empty()).then_some(u32::MAX))
+    }
+
+    // `[..]` after the `[`, a `]` right at the start is a char of the class
+    fn class(&mut self, start: usize) -> Result<Class, Fail> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+
+        loop {
+            let item_start = self.pos;
+            let lo = match self.next() {
+                None => return Err(("unclosed character class".into(), start)),
+                Some(']') if self.pos - 1 > start + 1 + negated as usize => break,
+                Some('\\') => match self.escape(item_start)? {
+                    Escape::Char(c) => c,
+                    Escape::Class(item) => {
+                        items.push(item);
+                        continue;
+                    }
+                    Escape::Assert(_) => return Err(("unknown escape".into(), item_start)),
+                },
+                Some(c) => c,
+            };
+
+            // a `-` before the `]` is a char
+            let is_range = self.peek() == Some('-')
+                && !matches!(self.chars.get(self.pos + 1), None | Some(']'));
+            if !is_range {
+                items.push(ClassItem::Range(lo, lo));
+                continue;
+            }
+
+            self.pos += 1;
+            let hi_start = self.pos;
+            let hi = match self.next() {
+                Some('\\') => match self.escape(hi_start)? {
+                    Escape::Char(c) => c,
+                    _ => return Err(("invalid class range".into(), item_start)),
+                },
+                Some(c) => c,
+                None => unreachable!("checked before"),
+            };
+            if hi < lo {
+                return Err(("invalid class range".into(), item_start));
+            }
+            items.push(ClassItem::Range(lo, hi));
+        }
+
+        Ok(Class { items, negated })
+    }
+
+    // after a `\` at `start`
+    fn escape(&mut self, start: usize) -> Result<Escape, Fail> {
+        let Some(c) = self.next() else {
+            return Err(("trailing `\\`".into(), start));
+        };
+        Ok(match c {
+            'd' => Escape::Class(ClassItem::Digit(false)),
+            'D' => Escape::Class(ClassItem::Digit(true)),
+            'w' => Escape::Class(ClassItem::Word(false)),
+            'W' => Escape::Class(ClassItem::Word(true)),
+            's' => Escape::Class(ClassItem::Space(false)),
+            'S' => Escape::Class(ClassItem::Space(true)),
+            'b' => Escape::Assert(Assert::WordBoundary),
+            'B' => Escape::Assert(Assert::NotWordBoundary),
+            'n' => Escape::Char('\n'),
+            't' => Escape::Char('\t'),
+            'r' => Escape::Char('\r'),
+            c if c.is_ascii_punctuation() => Escape::Char(c),
+            c => return Err((format!("unknown escape `\\{c}`"), start)),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Assert(Assert),
+    // go on at both, the first is preferred
+    Split(usize, usize),
+    Jmp(usize),
+    // the position is stored in a slot, group `n` starts in `2n` and ends in `2n + 1`
+    Save(usize),
+    Match,
+}
+
+struct Compiler {
+    program: Vec<Inst>,
+}
+
+impl Compiler {
+    fn emit(&mut self, inst: Inst) -> Result<usize, Fail> {
+        if self.program.len() == MAX_PROGRAM {
+            return Err(("the regex is too big".into(), 0));
+        }
+        self.program.push(inst);
+        Ok(self.program.len() - 1)
+    }
+
+    // a split to `next` and `later`, swapped for lazy repetitions. Splits and jumps are
+    // emitted pointing nowhere and patched once their targets are known.
+    fn patch_split(&mut self, at: usize, next: usize, later: usize, greedy: bool) {
+        self.program[at] = match greedy {
+            true => Inst::Split(next, later),
+            false => Inst::Split(later, next),
+        };
+    }
+
+    fn node(&mut self, node: &Node) -> Result<(), Fail> {
+        match node {
+            Node::Char(c) => {
+                self.emit(Inst::Char(*c))?;
+            }
+            Node::Any => {
+                self.emit(Inst::Any)?;
+            }
+            Node::Class(class) => {
+                self.emit(Inst::Class(class.clone()))?;
+            }
+            Node::Assert(assert) => {
+                self.emit(Inst::Assert(*assert))?;
+            }
+            Node::Group(node, None) => self.node(node)?,
+            Node::Group(node, Some(idx)) => {
+                self.emit(Inst::Save(idx * 2))?;
+                self.node(node)?;
+                self.emit(Inst::Save(idx * 2 + 1))?;
+            }
+            Node::Concat(nodes) => {
+                for node in nodes {
+                    self.node(node)?;
+                }
+            }
+            Node::Alt(alts) => {
+                let (last, alts) = alts.split_last().expect("at least two alternatives");
+                let mut jumps = Vec::new();
+                for alt in alts {
+                    let split = self.emit(Inst::Split(0, 0))?;
+                    self.node(alt)?;
+                    jumps.push(self.emit(Inst::Jmp(0))?);
+                    self.patch_split(split, split + 1, self.program.len(), true);
+                }
+                self.node(last)?;
+                for jump in jumps {
+                    self.program[jump] = Inst::Jmp(self.program.len());
+                }
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                for _ in 0..*min {
+                    self.node(node)?;
+                }
+                match max {
+                    None => {
+                        let split = self.emit(Inst::Split(0, 0))?;
+                        self.node(node)?;
+                        self.emit(Inst::Jmp(split))?;
+                        self.patch_split(split, split + 1, self.program.len(), *greedy);
+                    }
+                    // each optional copy skips the ones after it too
+                    Some(max) => {
+                        let mut splits = Vec::new();
+                        for _ in *min..*max {
+                            splits.push(self.emit(Inst::Split(0, 0))?);
+                            self.node(node)?;
+                        }
+                        for split in splits {
+                            self.patch_split(split, split + 1, self.program.len(), *greedy);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The positions of the groups of a match
+#[derive(Debug, Clone)]
+struct Slots(Vec<Option<usize>>);
+
+impl Slots {
+    fn group(&self, idx: usize) -> Option<(usize, usize)> {
+        Some((self.0[idx * 2]?, self.0[idx * 2 + 1]?))
+    }
+}
+
+struct Thread {
+    pc: usize,
+    slots: Slots,
+}
+
+// a piece of a replacement
+enum Part {
+    Text(EcoString),
+    Group(usize),
+}
+
+impl Regex {
+    fn new(pattern: &str) -> Result<Regex, Fail> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+            groups: 0,
+            names: Vec::new(),
+        };
+        let node = parser.alternation()?;
+        if parser.pos < parser.chars.len() {
+            return Err(("unmatched `)`".into(), parser.pos));
+        }
+
+        let mut compiler = Compiler {
+            program: Vec::new(),
+        };
+        compiler.node(&Node::Group(Box::new(node), Some(0)))?;
+        compiler.emit(Inst::Match)?;
+
+        Ok(Regex {
+            program: compiler.program,
+            groups: parser.groups,
+            names: parser.names,
+        })
+    }
+
+    // the leftmost match starting at `from` or later, of the alternatives the first one
+    // written and of repetitions the longest or for lazy ones the shortest. Every thread of
+    // the program moves over the text in step, which is Pike's VM.
+    fn search(&self, text: &[char], from: usize) -> Option<Slots> {
+        let mut current = Vec::new();
+        let mut next = Vec::new();
+        // the position a thread at each instruction was last added for
+        let mut seen = vec![usize::MAX; self.program.len()];
+        let mut matched = None;
+
+        for pos in from..=text.len() {
+            // a match starting here is worse than the ones which started before
+            if matched.is_none() {
+                let slots = Slots(vec![None; (self.groups + 1) * 2]);
+                self.add(&mut current, &mut seen, text, pos, 0, slots);
+            }
+            if current.is_empty() && matched.is_some() {
+                break;
+            }
+
+            for thread in current.drain(..) {
+                let c = text.get(pos);
+                let step = match &self.program[thread.pc] {
+                    Inst::Char(expected) => c == Some(expected),
+                    Inst::Any => c.is_some_and(|&c| c != '\n'),
+                    Inst::Class(class) => c.is_some_and(|&c| class.matches(c)),
+                    Inst::Match => {
+                        // the threads after this one are worse
+                        matched = Some(thread.slots);
+                        break;
+                    }
+                    _ => unreachable!("only threads waiting for a char are kept"),
+                };
+                if step {
+                    self.add(
+                        &mut next,
+                        &mut seen,
+                        text,
+                        pos + 1,
+                        thread.pc + 1,
+                        thread.slots,
+                    );
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+        }
+
+        matched
+    }
+
+    // adds a thread at `pc` and the ones it reaches without reading a char, in the order of
+    // preference
+    fn add(
+        &self,
+        threads: &mut Vec<Thread>,
+        seen: &mut [usize],
+        text: &[char],
+        pos: usize,
+        pc: usize,
+        slots: Slots,
+    ) {
+        let mut stack = vec![(pc, slots)];
+        while let Some((pc, mut slots)) = stack.pop() {
+            if seen[pc] == pos {
+                continue;
+            }
+            seen[pc] = pos;
+
+            match &self.program[pc] {
+                Inst::Jmp(to) => stack.push((*to, slots)),
+                Inst::Split(first, second) => {
+                    stack.push((*second, slots.clone()));
+                    stack.push((*first, slots));
+                }
+                Inst::Save(slot) => {
+                    slots.0[*slot] = Some(pos);
+                    stack.push((pc + 1, slots));
+                }
+                Inst::Assert(assert) => {
+                    if assert.holds(text, pos) {
+                        stack.push((pc + 1, slots));
+                    }
+                }
+                _ => threads.push(Thread { pc, slots }),
+            }
+        }
+    }
+
+    // the matches which don't overlap, an empty match moves the search on by a char
+    fn search_all(&self, text: &[char]) -> Vec<Slots> {
+        let mut matches = Vec::new();
+        let mut from = 0;
+        while from <= text.len() {
+            let Some(slots) = self.search(text, from) else {
+                break;
+            };
+            let (start, end) = slots.group(0).expect("a match has group 0");
+            from = if start == end { end + 1 } else { end };
+            matches.push(slots);
+        }
+        matches
+    }
+
+    fn replacement(&self, replacement: &str) -> Result<Vec<Part>, String> {
+        let mut parts = Vec::new();
+        let mut text = EcoString::new();
+        let mut chars = replacement.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            if c != '$' {
+                text.push(c);
+                continue;
+            }
+
+            let name: String =
+                match chars.peek() {
+                    Some('$') => {
+                        chars.next();
+                        text.push('$');
+                        continue;
+                    }
+                    Some('{') => {
+                        chars.next();
+                        let name = chars.by_ref().take_while(|&c| c != '}').collect();
+                        name
+                    }
+                    Some(c) if c.is_ascii_digit() => {
+                        let mut digits = String::new();
+                        while let Some(c) = chars.next_if(char::is_ascii_digit) {
+                            digits.push(c);
+                        }
+                        digits
+                    }
+                    _ => return Err(
+                        "a `$` in a replacement must be followed by a group, use `$$` for a `$`"
+                            .into(),
+                    ),
+                };
+
+            let idx = match name.parse::<usize>() {
+                Ok(idx) if idx <= self.groups => Some(idx),
+                Ok(_) => None,
+                Err(_) => self
+                    .names
+                    .iter()
+                    .find(|(other, _)| *other == name)
+                    .map(|(_, idx)| *idx),
+            };
+            let Some(idx) = idx else {
+                return Err(format!("no group `{name}` in the regex"));
+            };
+
+            parts.push(Part::Text(std::mem::take(&mut text)));
+            parts.push(Part::Group(idx));
+        }
+        parts.push(Part::Text(text));
+        Ok(parts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(pattern: &str, text: &str) -> Option<String> {
+        let chars: Vec<char> = text.chars().collect();
+        let slots = Regex::new(pattern).unwrap().search(&chars, 0)?;
+        let (start, end) = slots.group(0)?;
+        Some(chars[start..end].iter().collect())
+    }
+
+    #[test]
+    fn matches_patterns() {
+        let cases = [
+            ("a+", "baaac", Some("aaa")),
+            ("a+?", "baaac", Some("a")),
+            ("a*", "baaac", Some("")),
+            ("colou?r", "my colour", Some("colour")),
+            ("cat|category", "category", Some("cat")),
+            ("^b", "ab", None),
+            ("b$", "ab", Some("b")),
+            (r"\bis\b", "this is it", Some("is")),
+            (r"\d{2,3}", "a1234", Some("123")),
+            (r"\d{2,}?", "a1234", Some("12")),
+            ("[^a-c]+", "abcdef", Some("def")),
+            ("[]a]+", "x]a]", Some("]a]")),
+            ("[a-]+", "x-a-", Some("-a-")),
+            (r"[\w.]+@\w+", "to: ann.b@example", Some("ann.b@example")),
+            ("(a|ab)(c|bcd)", "abcd", Some("abcd")),
+            ("(a*)*b", "aaab", Some("aaab")),
+            ("é.", "café!", Some("é!")),
+            (".", "\n", None),
+        ];
+
+        for (pattern, text, expected) in cases {
+            assert_eq!(
+                find(pattern, text).as_deref(),
+                expected,
+                "{pattern:?} {text:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn reports_where_patterns_are_malformed() {
+        let cases = [
+            ("(ab", "unclosed group", 0),
+            ("ab)", "unmatched `)`", 2),
+            ("a**", "nothing to repeat", 2),
+            ("[a-", "unclosed character class", 0),
+            ("x[z-a]", "invalid class range", 2),
+            (r"a\q", r"unknown escape `\q`", 1),
+            ("a{2,1}", "invalid repetition", 1),
+            ("a{5000}", "repetition count is too large", 1),
+            ("(?<a>x)(?<a>y)", "duplicate group name `a`", 10),
+            ("(?x)", "unknown group flag", 2),
+            ("a\\", "trailing `\\`", 1),
+        ];
+
+        for (pattern, message, pos) in cases {
+            let err = Regex::new(pattern).err();
+            assert_eq!(err, Some((message.to_owned(), pos)), "{pattern:?}");
+        }
+    }
+}
@@ -0,0 +1,402 @@
+//! Operations on strings, called as methods, `s.len()`, or through the module,
+//! `string.len(s)`
+//!
+//! Lengths and indices count chars, `byte_len` is the only one counting bytes. An index
+//! past the end of the string stops the program like it would for any other sequence.
+
+use std::fmt::Write;
+
+use ecow::EcoString;
+
+use crate::runtime::error::RuntimeError;
+use crate::runtime::value::{NativeContext, NativeFnPtr, Value};
+
+use super::{args, expect_str, fits, list, opt_args};
+
+// every function takes the string first, which is the receiver when called as a method
+pub(super) const FNS: &[(&str, NativeFnPtr, &str)] = &[
+    ("len", len, "(string) -> int"),
+    ("byte_len", byte_len, "(string) -> int"),
+    ("is_empty", is_empty, "(string) -> bool"),
+    ("slice", slice, "(string, int, int?) -> string"),
+    ("char_at", char_at, "(string, int) -> any"),
+    ("chars", chars, "(string) -> list<string>"),
+    ("graphemes", graphemes, "(string) -> list<string>"),
+    ("split", split, "(string, string) -> list<string>"),
+    ("lines", lines, "(string) -> list<string>"),
+    ("join", join, "(string, list) -> string"),
+    ("trim", trim, "(string) -> string"),
+    ("trim_start", trim_start, "(string) -> string"),
+    ("trim_end", trim_end, "(string) -> string"),
+    ("replace", replace, "(string, string, string) -> string"),
+    ("find", find, "(string, string) -> any"),
+    ("contains", contains, "(string, string) -> bool"),
+    ("starts_with", starts_with, "(string, string) -> bool"),
+    ("ends_with", ends_with, "(string, string) -> bool"),
+    ("to_upper", to_upper, "(string) -> string"),
+    ("to_lower", to_lower, "(string) -> string"),
+    ("repeat", repeat, "(string, int) -> string"),
+    ("reverse", reverse, "(string) -> string"),
+    ("format", format, "(string, ..) -> string"),
+];
+
+fn len(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [s] = self::args(cx, "len", args)?;
+    Ok(Value::Int(expect_str(cx, s)?.chars().count() as i64))
+}
+
+fn byte_len(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [s] = self::args(cx, "byte_len", args)?;
+    Ok(Value::Int(expect_str(cx, s)?.len() as i64))
+}
+
+fn is_empty(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [s] = self::args(cx, "is_empty", args)?;
+    Ok(Value::Bool(expect_str(cx, s)?.is_empty()))
+}
+
+// `slice(start)` or `slice(start, end)`, the chars from `start` up to but without `end`
+fn slice(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [s, start, end] = opt_args(cx, "slice", 2, args)?;
+    let s = expect_str(cx, s.unwrap_or(Value::Nil))?;
+    let count = s.chars().count();
+
+    let start = index(cx, start.unwrap_or(Value::Nil), count)?;
+    let end = match end {
+        Some(end) => index(cx, end, count)?,
+        None => count,
+    };
+    if start > end {
+        return Err(cx.error(format!("slice starts at {start} but ends at {end}")));
+    }
+
+    Ok(Value::Str(
+        s.chars().skip(start).take(end - start).collect(),
+    ))
+}
+
+// a char index from `0` to `count`, both included
+fn index(cx: &dyn NativeContext, value: Value, count: usize) -> Result<usize, RuntimeError> {
+    match value {
+        Value::Int(idx) if (0..=count as i64).contains(&idx) => Ok(idx as usize),
+        Value::Int(idx) => Err(cx.error(format!(
+            "char index {idx} is out of range for a string of {count} chars"
+        ))),
+        value => Err(cx.error(format!("expected `int`, found `{}`", value.type_name()))),
+    }
+}
+
+// the char at an index, `nil` past the end
+fn char_at(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [s, idx] = self::args(cx, "char_at", args)?;
+    let s = expect_str(cx, s)?;
+
+    let c = match idx {
+        Value::Int(idx) => usize::try_from(idx).ok().and_then(|idx| s.chars().nth(idx)),
+        idx => return Err(cx.error(format!("expected `int`, found `{}`", idx.type_name()))),
+    };
+    Ok(c.map_or(Value::Nil, |c| Value::Str(c.into())))
+}
+
+fn chars(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [s] = self::args(cx, "chars", args)?;
+    let s = expect_str(cx, s)?;
+    list(cx, s.chars().map(|c| Value::Str(c.into())))
+}
+
+// what readers see as single characters, like `é` written as `e` and an accent
+fn graphemes(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [s] = self::args(cx, "graphemes", args)?;
+    let s = expect_str(cx, s)?;
+    list(cx, clusters(&s).map(|g| Value::Str(g.into())))
+}
+
+// `split(sep)`, an empty separator splits between every char
+fn split(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [s, sep] = self::args(cx, "split", args)?;
+    let s = expect_str(cx, s)?;
+    let sep = expect_str(cx, sep)?;
+
+    match sep.as_str() {
+        "" => list(cx, s.chars().map(|c| Value::Str(c.into()))),
+        sep => list(cx, s.split(sep).map(|part| Value::Str(part.into()))),
+    }
+}
+
+// the lines without their line breaks
+fn lines(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [s] = self::args(cx, "lines", args)?;
+    let s = expect_str(cx, s)?;
+    list(cx, s.lines().map(|line| Value::Str(line.into())))
+}
+
+// `sep.join(list)`, the items are written the way `print` shows them
+fn join(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [sep, items] = self::args(cx, "join", args)?;
+    let sep = expect_str(cx, sep)?;
+    let Value::List(items) = items else {
+        return Err(cx.error(format!("expected `list`, found `{}`", items.type_name())));
+    };
+
+    let items = items.borrow().clone();
+    let mut joined = String::new();
+    for (i, item) in items.iter().enumerate() {
+        cx.tick()?;
+        if i > 0 {
+            joined.push_str(&sep);
+        }
+        joined.push_str(&cx.display(item)?);
+        fits(cx, joined.len())?;
+    }
+    Ok(Value::Str(joined.into()))
+}
+
+fn trim(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [s] = self::args(cx, "trim", args)?;
+    Ok(Value::Str(expect_str(cx, s)?.trim().into()))
+}
+
+fn trim_start(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [s] = self::args(cx, "trim_start", args)?;
+    Ok(Value::Str(expect_str(cx, s)?.trim_start().into()))
+}
+
+fn trim_end(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [s] = self::args(cx, "trim_end", args)?;
+    Ok(Value::Str(expect_str(cx, s)?.trim_end().into()))
+}
+
+// replaces every occurrence
+fn replace(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [s, from, to] = self::args(cx, "replace", args)?;
+    let s = expect_str(cx, s)?;
+    let from = expect_str(cx, from)?;
+    let to = expect_str(cx, to)?;
+
+    if from.is_empty() {
+        return Err(cx.error("cannot replace an empty string".to_owned()));
+    }
+    // every occurrence can grow the string, the result is checked before it is built
+    let count = s.matches(from.as_str()).count();
+    let len = (s.len() - count * from.len()).checked_add(count.saturating_mul(to.len()));
+    fits(cx, len.unwrap_or(usize::MAX))?;
+    Ok(Value::Str(s.replace(from.as_str(), &to)))
+}
+
+// the char index of the first occurrence, `nil` if there is none
+fn find(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [s, needle] = self::args(cx, "find", args)?;
+    let s = expect_str(cx, s)?;
+    let needle = expect_str(cx, needle)?;
+
+    Ok(match s.find(needle.as_str()) {
+        Some(byte) => Value::Int(s[..byte].chars().count() as i64),
+        None => Value::Nil,
+    })
+}
+
+fn contains(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [s, needle] = self::args(cx, "contains", args)?;
+    let s = expect_str(cx, s)?;
+    Ok(Value::Bool(s.contains(expect_str(cx, needle)?.as_str())))
+}
+
+fn starts_with(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [s, prefix] = self::args(cx, "starts_with", args)?;
+    let s = expect_str(cx, s)?;
+    Ok(Value::Bool(s.starts_with(expect_str(cx, prefix)?.as_str())))
+}
+
+fn ends_with(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [s, suffix] = self::args(cx, "ends_with", args)?;
+    let s = expect_str(cx, s)?;
+    Ok(Value::Bool(s.ends_with(expect_str(cx, suffix)?.as_str())))
+}
+
+fn to_upper(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [s] = self::args(cx, "to_upper", args)?;
+    Ok(Value::Str(expect_str(cx, s)?.to_uppercase()))
+}
+
+fn to_lower(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [s] = self::args(cx, "to_lower", args)?;
+    Ok(Value::Str(expect_str(cx, s)?.to_lowercase()))
+}
+
+fn repeat(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [s, times] = self::args(cx, "repeat", args)?;
+    let s = expect_str(cx, s)?;
+
+    let times = match times {
+        Value::Int(times) if times >= 0 => times,
+        Value::Int(times) => return Err(cx.error(format!("cannot repeat a string {times} times"))),
+        times => {
+            let message = format!("expected `int`, found `{}`", times.type_name());
+            return Err(cx.error(message));
+        }
+    };
+
+    // the length is checked before anything is allocated
+    let too_long = format!("cannot repeat a string {times} times, it would be too long");
+    let Some(len) = usize::try_from(times)
+        .ok()
+        .and_then(|times| s.len().checked_mul(times))
+    else {
+        return Err(cx.error(too_long));
+    };
+    fits(cx, len)?;
+    if len == 0 {
+        return Ok(Value::Str(EcoString::new()));
+    }
+
+    let mut repeated = String::new();
+    if repeated.try_reserve_exact(len).is_err() {
+        return Err(cx.error(too_long));
+    }
+    for _ in 0..times {
+        repeated.push_str(&s);
+    }
+    Ok(Value::Str(repeated.into()))
+}
+
+// reverses graphemes instead of chars so accents stay on their letter
+fn reverse(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [s] = self::args(cx, "reverse", args)?;
+    let s = expect_str(cx, s)?;
+    let mut clusters: Vec<&str> = clusters(&s).collect();
+    clusters.reverse();
+    Ok(Value::Str(clusters.concat().into()))
+}
+
+// `"{} is {}".format(name, age)`, every `{}` is replaced by the next argument shown the
+// way `print` shows it, `{{` and `}}` are literal braces
+fn format(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let mut args = args.into_iter();
+    let Some(template) = args.next() else {
+        return Err(cx.error("`format` takes a template and its arguments".to_owned()));
+    };
+    let template = expect_str(cx, template)?;
+
+    let mut out = EcoString::new();
+    let mut chars = template.chars().peekable();
+    let mut used = 0;
+
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                chars.next();
+                out.push(c);
+            }
+            ('{', Some('}')) => {
+                chars.next();
+                let Some(arg) = args.next() else {
+                    let message = format!("the template has more `{{}}` than the {used} arguments");
+                    return Err(cx.error(message));
+                };
+                used += 1;
+                let _ = write!(out, "{}", cx.display(&arg)?);
+            }
+            ('{' | '}', _) => {
+                let message = format!("unmatched `{c}` in template, write `{c}{c}` for a brace");
+                return Err(cx.error(message));
+            }
+            (c, _) => out.push(c),
+        }
+    }
+
+    let left = args.count();
+    if left > 0 {
+        let message = format!("{left} more arguments than `{{}}` in the template");
+        return Err(cx.error(message));
+    }
+    Ok(Value::Str(out))
+}
+
+/// Splits `s` into grapheme clusters. Covers combining marks, variation selectors,
+/// emoji modifiers and tags, sequences joined by a zero width joiner, flags and `\r\n`,
+/// which is what text in practice is made of.
+fn clusters(s: &str) -> impl Iterator<Item = &str> {
+    let mut rest = s;
+
+    std::iter::from_fn(move || {
+        let mut chars = rest.char_indices().peekable();
+        let (_, first) = chars.next()?;
+        let mut end = first.len_utf8();
+        let mut prev = first;
+        // a flag is two regional indicators, a third starts the next flag
+        let mut indicators = usize::from(is_regional_indicator(first));
+
+        while let Some(&(idx, c)) = chars.peek() {
+            let joins = match c {
+                '\n' => prev == '\r',
+                _ if prev == '\u{200d}' => true,
+                _ if is_regional_indicator(c) => indicators == 1,
+                _ => is_extend(c),
+            };
+            if !joins {
+                break;
+            }
+
+            indicators += usize::from(is_regional_indicator(c));
+            end = idx + c.len_utf8();
+            prev = c;
+            chars.next();
+        }
+
+        let (cluster, after) = rest.split_at(end);
+        rest = after;
+        Some(cluster)
+    })
+}
+
+// chars which never start a cluster
+fn is_extend(c: char) -> bool {
+    matches!(
+        c,
+        '\u{0300}'..='\u{036f}'
+            | '\u{0483}'..='\u{0489}'
+            | '\u{0591}'..='\u{05bd}'
+            | '\u{0610}'..='\u{061a}'
+            | '\u{064b}'..='\u{065f}'
+            | '\u{0900}'..='\u{0903}'
+            | '\u{093a}'..='\u{094f}'
+            | '\u{0e31}'
+            | '\u{0e34}'..='\u{0e3a}'
+            | '\u{1ab0}'..='\u{1aff}'
+            | '\u{1dc0}'..='\u{1dff}'
+            | '\u{200c}'..='\u{200d}'
+            | '\u{20d0}'..='\u{20ff}'
+            | '\u{fe00}'..='\u{fe0f}'
+            | '\u{fe20}'..='\u{fe2f}'
+            | '\u{1f3fb}'..='\u{1f3ff}'
+            | '\u{e0020}'..='\u{e007f}'
+            | '\u{e0100}'..='\u{e01ef}'
+    )
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    matches!(c, '\u{1f1e6}'..='\u{1f1ff}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_graphemes() {
+        let cases: [(&str, &[&str]); 5] = [
+            ("abc", &["a", "b", "c"]),
+            ("e\u{301}x", &["e\u{301}", "x"]),
+            ("a\r\nb", &["a", "\r\n", "b"]),
+            (
+                "👩\u{200d}👩\u{200d}👧👍\u{1f3fd}",
+                &["👩\u{200d}👩\u{200d}👧", "👍\u{1f3fd}"],
+            ),
+            ("🇫🇷🇩🇪🇮", &["🇫🇷", "🇩🇪", "🇮"]),
+        ];
+
+        for (s, expected) in cases {
+            assert_eq!(clusters(s).collect::<Vec<_>>(), expected, "{s:?}");
+        }
+    }
+}
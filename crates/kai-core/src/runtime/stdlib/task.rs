@@ -0,0 +1,43 @@
+use std::time::{Duration, Instant};
+
+use crate::ast::CoroutineKind;
+use crate::runtime::error::RuntimeError;
+use crate::runtime::executor::Sleep;
+use crate::runtime::value::{NativeContext, NativeFnPtr, Value};
+
+use super::{args, future};
+
+pub(super) const FNS: &[(&str, NativeFnPtr, &str)] = &[
+    ("sleep", sleep, "(int) -> future<nil>"),
+    ("spawn", spawn, "(any) -> future<any>"),
+];
+
+// a future ready after `ms` milliseconds, other tasks run while it is awaited
+fn sleep(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [ms] = self::args(cx, "sleep", args)?;
+
+    let ms = match ms {
+        Value::Int(ms) if ms >= 0 => ms as u64,
+        Value::Int(ms) => return Err(cx.error(format!("cannot sleep for {ms}ms"))),
+        ms => return Err(cx.error(format!("expected `int`, found `{}`", ms.type_name()))),
+    };
+
+    let deadline = Instant::now() + Duration::from_millis(ms);
+    Ok(future("sleep", Sleep { deadline }))
+}
+
+// runs an async call as a task of its own, the future it returns is ready with the value
+// the call returned
+fn spawn(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [call] = self::args(cx, "spawn", args)?;
+
+    match call {
+        Value::Coroutine(coroutine) if coroutine.kind == CoroutineKind::Async => {
+            Ok(Value::Future(cx.executor().spawn(coroutine)))
+        }
+        call => Err(cx.error(format!(
+            "expected the call of an `async fn`, found `{}`",
+            call.type_name()
+        ))),
+    }
+}
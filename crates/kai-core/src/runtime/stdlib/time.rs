@@ -0,0 +1,965 @@
+//! Clocks, durations and dates, `kai.time`
+//!
+//! `now()` reads a monotonic clock for measuring, `utc()` and `local()` read the wall clock.
+//! `Duration` and `DateTime` instances never change, their methods return new ones. Dates
+//! are proleptic Gregorian and print as ISO 8601, `2024-03-01T12:30:05Z`.
+//!
+//! The offset of local time comes from the zone file named by `TZ` or `/etc/localtime`.
+//! Past the last transition in the file its offset is kept, the daylight saving rule at
+//! the end of some files isn't applied. Without a zone file local time is UTC, as it is
+//! when the engine denies `fs`. `TZ` is only read when it grants `env`.
+
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::rc::Rc;
+use std::time::{Duration as StdDuration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::resolve::BuiltinInterface;
+use crate::runtime::error::RuntimeError;
+use crate::runtime::executor::Sleep;
+use crate::runtime::limits::{Capabilities, Capability};
+use crate::runtime::value::{Class, Handle, Instance, NativeContext, NativeFnPtr, Value};
+
+use super::{args, expect_str, future, native_class};
+
+const NANOS_PER_SEC: i64 = 1_000_000_000;
+
+pub(super) const FNS: &[(&str, NativeFnPtr, &str)] = &[
+    ("now", now, "() -> any"),
+    ("elapsed", elapsed, "(any) -> Duration"),
+    ("sleep", sleep, "(any) -> future<nil>"),
+    ("utc", utc, "() -> DateTime"),
+    ("local", local, "() -> DateTime"),
+    ("parse", parse, "(string) -> result<DateTime>"),
+];
+
+const INSTANT_METHODS: &[(&str, NativeFnPtr)] = &[("elapsed", elapsed), ("since", since)];
+
+// `Duration.ms(250)` and the others make durations, the methods take the duration first
+const DURATION_METHODS: &[(&str, NativeFnPtr, bool)] = &[
+    ("ms", ms, false),
+    ("secs", secs, false),
+    ("mins", mins, false),
+    ("hours", hours, false),
+    ("as_ms", as_ms, true),
+    ("as_secs", as_secs, true),
+    ("plus", duration_plus, true),
+    ("minus", duration_minus, true),
+    ("times", times, true),
+    ("compare", duration_compare, true),
+    ("to_string", duration_to_string, true),
+    ("eq", duration_eq, true),
+];
+
+const DATE_TIME_METHODS: &[(&str, NativeFnPtr, bool)] = &[
+    ("utc", utc, false),
+    ("local", local, false),
+    ("parse", parse, false),
+    ("from_unix", from_unix, false),
+    ("year", year, true),
+    ("month", month, true),
+    ("day", day, true),
+    ("hour", hour, true),
+    ("minute", minute, true),
+    ("second", second, true),
+    ("weekday", weekday, true),
+    ("offset", offset, true),
+    ("unix", unix, true),
+    ("to_utc", to_utc, true),
+    ("to_local", to_local, true),
+    ("plus", date_time_plus, true),
+    ("minus", date_time_minus, true),
+    ("format", format, true),
+    ("compare", date_time_compare, true),
+    ("to_string", date_time_to_string, true),
+    ("eq", date_time_eq, true),
+];
+
+thread_local! {
+    static DURATION: Rc<Class> = Rc::new(native_class(
+        "Duration",
+        &["nanos"],
+        vec![BuiltinInterface::Display, BuiltinInterface::Eq],
+        DURATION_METHODS,
+    ));
+    // the instant is kept in UTC, `offset` is only used to show it
+    static DATE_TIME: Rc<Class> = Rc::new(native_class(
+        "DateTime",
+        &["secs", "nanos", "offset"],
+        vec![BuiltinInterface::Display, BuiltinInterface::Eq],
+        DATE_TIME_METHODS,
+    ));
+}
+
+/// The `Duration` class, `kai.time.Duration`
+pub(super) fn duration_class() -> Rc<Class> {
+    DURATION.with(Rc::clone)
+}
+
+/// The `DateTime` class, `kai.time.DateTime`
+pub(super) fn date_time_class() -> Rc<Class> {
+    DATE_TIME.with(Rc::clone)
+}
+
+// instances only hold ints, so they are left out of the heap like the objects of `object`
+fn instance(class: Rc<Class>, fields: Vec<Value>) -> Value {
+    Value::Instance(Rc::new(Instance {
+        class,
+        fields: RefCell::new(fields),
+    }))
+}
+
+fn int_field(cx: &dyn NativeContext, instance: &Instance, name: &str) -> Result<i64, RuntimeError> {
+    match instance.field(name) {
+        Some(Value::Int(value)) => Ok(value),
+        _ => Err(cx.error(format!(
+            "the `{name}` of a `{}` must be an `int`",
+            instance.class.name
+        ))),
+    }
+}
+
+fn overflow(cx: &dyn NativeContext) -> RuntimeError {
+    cx.error("the time is too far away to be represented".to_owned())
+}
+
+fn duration(nanos: i64) -> Value {
+    instance(duration_class(), vec![Value::Int(nanos)])
+}
+
+// the nanoseconds of a `Duration`
+fn expect_duration(cx: &dyn NativeContext, value: Value) -> Result<i64, RuntimeError> {
+    match value {
+        Value::Instance(instance) if Rc::ptr_eq(&instance.class, &duration_class()) => {
+            int_field(cx, &instance, "nanos")
+        }
+        value => Err(cx.error(format!(
+            "expected `Duration`, found `{}`",
+            value.type_name()
+        ))),
+    }
+}
+
+fn from_std(cx: &dyn NativeContext, duration: StdDuration) -> Result<i64, RuntimeError> {
+    i64::try_from(duration.as_nanos()).map_err(|_| overflow(cx))
+}
+
+// `Duration.ms(n)` and the others, `n` can be a `float`
+fn of_unit(
+    cx: &dyn NativeContext,
+    name: &str,
+    args: Vec<Value>,
+    unit: i64,
+) -> Result<Value, RuntimeError> {
+    let [n] = self::args(cx, name, args)?;
+    let nanos = match n {
+        Value::Int(n) => n.checked_mul(unit),
+        Value::Float(n) => {
+            let nanos = (n * unit as f64).round();
+            (nanos >= i64::MIN as f64 && nanos < i64::MAX as f64).then_some(nanos as i64)
+        }
+        n => {
+            let message = format!("expected `int` or `float`, found `{}`", n.type_name());
+            return Err(cx.error(message));
+        }
+    };
+    nanos.map(duration).ok_or_else(|| overflow(cx))
+}
+
+fn ms(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    of_unit(cx, "ms", args, 1_000_000)
+}
+
+fn secs(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    of_unit(cx, "secs", args, NANOS_PER_SEC)
+}
+
+fn mins(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    of_unit(cx, "mins", args, 60 * NANOS_PER_SEC)
+}
+
+fn hours(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    of_unit(cx, "hours", args, 3600 * NANOS_PER_SEC)
+}
+
+// the whole milliseconds, rounded toward zero
+fn as_ms(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [duration] = self::args(cx, "as_ms", args)?;
+    Ok(Value::Int(expect_duration(cx, duration)? / 1_000_000))
+}
+
+fn as_secs(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [duration] = self::args(cx, "as_secs", args)?;
+    let nanos = expect_duration(cx, duration)?;
+    Ok(Value::Float(nanos as f64 / NANOS_PER_SEC as f64))
+}
+
+fn duration_plus(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [duration, other] = self::args(cx, "plus", args)?;
+    let nanos = expect_duration(cx, duration)?;
+    let other = expect_duration(cx, other)?;
+    nanos
+        .checked_add(other)
+        .map(self::duration)
+        .ok_or_else(|| overflow(cx))
+}
+
+// can be negative, `Duration.secs(1).minus(Duration.secs(2))` is `-1s`
+fn duration_minus(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [duration, other] = self::args(cx, "minus", args)?;
+    let nanos = expect_duration(cx, duration)?;
+    let other = expect_duration(cx, other)?;
+    nanos
+        .checked_sub(other)
+        .map(self::duration)
+        .ok_or_else(|| overflow(cx))
+}
+
+// scaled by an `int` or a `float`
+fn times(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [duration, n] = self::args(cx, "times", args)?;
+    let nanos = expect_duration(cx, duration)?;
+    of_unit(cx, "times", vec![n], nanos)
+}
+
+// `-1`, `0` or `1` as the duration is shorter, as long as or longer than the other, for
+// `sort_by`
+fn duration_compare(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [duration, other] = self::args(cx, "compare", args)?;
+    let nanos = expect_duration(cx, duration)?;
+    let other = expect_duration(cx, other)?;
+    Ok(Value::Int(nanos.cmp(&other) as i64))
+}
+
+// in the largest unit which keeps it short, `1.5s`, `250ms` or `-20ns`
+fn duration_to_string(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [duration] = self::args(cx, "to_string", args)?;
+    let nanos = expect_duration(cx, duration)?;
+    let sign = if nanos < 0 { "-" } else { "" };
+    let text = format!("{sign}{:?}", StdDuration::from_nanos(nanos.unsigned_abs()));
+    Ok(Value::Str(text.into()))
+}
+
+fn duration_eq(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [duration, other] = self::args(cx, "eq", args)?;
+    let nanos = expect_duration(cx, duration)?;
+    Ok(Value::Bool(match other {
+        Value::Nil => false,
+        other => expect_duration(cx, other)? == nanos,
+    }))
+}
+
+// a point on the monotonic clock, it only tells how much time passed since
+fn now(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [] = self::args(cx, "now", args)?;
+    let instant = Handle::new("instant", Instant::now(), INSTANT_METHODS);
+    Ok(Value::Handle(Rc::new(instant)))
+}
+
+fn expect_instant(cx: &dyn NativeContext, value: &Value) -> Result<Instant, RuntimeError> {
+    match value {
+        Value::Handle(handle) => match handle.get::<Instant>() {
+            Some(instant) => Ok(*instant),
+            None => Err(cx.error(format!("expected `instant`, found `{}`", handle.kind))),
+        },
+        value => Err(cx.error(format!("expected `instant`, found `{}`", value.type_name()))),
+    }
+}
+
+// the time since an instant of `now()`, as a `Duration`
+fn elapsed(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [instant] = self::args(cx, "elapsed", args)?;
+    let instant = expect_instant(cx, &instant)?;
+    Ok(duration(from_std(cx, instant.elapsed())?))
+}
+
+// the time from an earlier instant to this one, negative when it was later
+fn since(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [instant, earlier] = self::args(cx, "since", args)?;
+    let instant = expect_instant(cx, &instant)?;
+    let earlier = expect_instant(cx, &earlier)?;
+    Ok(duration(match instant.checked_duration_since(earlier) {
+        Some(after) => from_std(cx, after)?,
+        None => -from_std(cx, earlier.duration_since(instant))?,
+    }))
+}
+
+// a future ready after `ms` milliseconds or a `Duration`, other tasks run while it is
+// awaited like for `task.sleep`
+fn sleep(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [time] = self::args(cx, "sleep", args)?;
+    let nanos = match time {
+        Value::Int(ms) => ms.checked_mul(1_000_000).ok_or_else(|| overflow(cx))?,
+        time => expect_duration(cx, time)?,
+    };
+    if nanos < 0 {
+        let text = format!("-{:?}", StdDuration::from_nanos(nanos.unsigned_abs()));
+        return Err(cx.error(format!("cannot sleep for {text}")));
+    }
+
+    let deadline = Instant::now() + StdDuration::from_nanos(nanos as u64);
+    Ok(future("sleep", Sleep { deadline }))
+}
+
+/// An instant on the wall clock with the offset it is shown in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct DateTime {
+    // seconds since 1970-01-01T00:00:00Z
+    secs: i64,
+    nanos: i64,
+    // seconds east of UTC
+    offset: i64,
+}
+
+/// The parts of a date and time as they are shown
+#[derive(Debug, PartialEq, Eq)]
+struct Civil {
+    year: i64,
+    month: i64,
+    day: i64,
+    hour: i64,
+    minute: i64,
+    second: i64,
+}
+
+impl DateTime {
+    fn now(offset: impl FnOnce(i64) -> i64) -> DateTime {
+        let (secs, nanos) = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(after) => (after.as_secs() as i64, after.subsec_nanos() as i64),
+            // a clock set before 1970
+            Err(err) => {
+                let before = err.duration();
+                let nanos = -(before.subsec_nanos() as i64);
+                let secs = -(before.as_secs() as i64) + nanos.div_euclid(NANOS_PER_SEC);
+                (secs, nanos.rem_euclid(NANOS_PER_SEC))
+            }
+        };
+        DateTime {
+            secs,
+            nanos,
+            offset: offset(secs),
+        }
+    }
+
+    fn value(self) -> Value {
+        let fields = [self.secs, self.nanos, self.offset].map(Value::Int);
+        instance(date_time_class(), fields.into())
+    }
+
+    fn total_nanos(self) -> i128 {
+        self.secs as i128 * NANOS_PER_SEC as i128 + self.nanos as i128
+    }
+
+    // the same offset moved by `nanos`, `None` when it is out of range
+    fn plus(self, nanos: i128) -> Option<DateTime> {
+        let total = self.total_nanos().checked_add(nanos)?;
+        let secs = i64::try_from(total.div_euclid(NANOS_PER_SEC as i128)).ok()?;
+        // the days of years far outside of `int` can't be counted
+        (secs.checked_add(self.offset)?.unsigned_abs() < 1 << 52).then_some(DateTime {
+            secs,
+            nanos: total.rem_euclid(NANOS_PER_SEC as i128) as i64,
+            offset: self.offset,
+        })
+    }
+
+    fn civil(self) -> Civil {
+        let local = self.secs + self.offset;
+        let (year, month, day) = civil_from_days(local.div_euclid(86_400));
+        let secs = local.rem_euclid(86_400);
+        Civil {
+            year,
+            month,
+            day,
+            hour: secs / 3600,
+            minute: secs / 60 % 60,
+            second: secs % 60,
+        }
+    }
+
+    // from 1 for Monday to 7 for Sunday
+    fn weekday(self) -> i64 {
+        // 1970-01-01 was a Thursday
+        ((self.secs + self.offset).div_euclid(86_400) + 3).rem_euclid(7) + 1
+    }
+}
+
+// the days since 1970-01-01 of a date, from Howard Hinnant's `days_from_civil`
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_from_march = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_from_march + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+// the date of a day since 1970-01-01, the inverse of `days_from_civil`
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn expect_date_time(cx: &dyn NativeContext, value: Value) -> Result<DateTime, RuntimeError> {
+    match value {
+        Value::Instance(instance) if Rc::ptr_eq(&instance.class, &date_time_class()) => {
+            Ok(DateTime {
+                secs: int_field(cx, &instance, "secs")?,
+                nanos: int_field(cx, &instance, "nanos")?,
+                offset: int_field(cx, &instance, "offset")?,
+            })
+        }
+        value => Err(cx.error(format!(
+            "expected `DateTime`, found `{}`",
+            value.type_name()
+        ))),
+    }
+}
+
+// the current date and time in UTC
+fn utc(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [] = self::args(cx, "utc", args)?;
+    Ok(DateTime::now(|_| 0).value())
+}
+
+// the current date and time in the local time zone
+fn local(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [] = self::args(cx, "local", args)?;
+    let caps = cx.capabilities();
+    Ok(DateTime::now(|secs| local_offset(caps, secs)).value())
+}
+
+// a date and time written in ISO 8601, an error value when the text isn't one
+fn parse(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [text] = self::args(cx, "parse", args)?;
+    let text = expect_str(cx, text)?;
+    Ok(match parse_iso(&text) {
+        Some(date_time) => date_time.value(),
+        None => Value::error(format!("`{text}` is not an ISO 8601 date")),
+    })
+}
+
+// `from_unix(secs)` in UTC
+fn from_unix(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [secs] = self::args(cx, "from_unix", args)?;
+    let secs = match secs {
+        Value::Int(secs) => secs,
+        secs => {
+            let message = format!("expected `int`, found `{}`", secs.type_name());
+            return Err(cx.error(message));
+        }
+    };
+    let epoch = DateTime {
+        secs: 0,
+        nanos: 0,
+        offset: 0,
+    };
+    let date_time = epoch.plus(secs as i128 * NANOS_PER_SEC as i128);
+    date_time.map(DateTime::value).ok_or_else(|| overflow(cx))
+}
+
+// the parts of the date and time in its offset
+macro_rules! civil_fns {
+    ($($name:ident,)*) => {
+        $(
+            fn $name(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+                let [date_time] = self::args(cx, stringify!($name), args)?;
+                Ok(Value::Int(expect_date_time(cx, date_time)?.civil().$name))
+            }
+        )*
+    };
+}
+
+civil_fns! {
+    year,
+    month,
+    day,
+    hour,
+    minute,
+    second,
+}
+
+fn weekday(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [date_time] = self::args(cx, "weekday", args)?;
+    Ok(Value::Int(expect_date_time(cx, date_time)?.weekday()))
+}
+
+// the seconds east of UTC it is shown in
+fn offset(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [date_time] = self::args(cx, "offset", args)?;
+    Ok(Value::Int(expect_date_time(cx, date_time)?.offset))
+}
+
+// the seconds since 1970-01-01T00:00:00Z
+fn unix(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [date_time] = self::args(cx, "unix", args)?;
+    Ok(Value::Int(expect_date_time(cx, date_time)?.secs))
+}
+
+// the same instant shown in UTC
+fn to_utc(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [date_time] = self::args(cx, "to_utc", args)?;
+    let date_time = expect_date_time(cx, date_time)?;
+    Ok(DateTime {
+        offset: 0,
+        ..date_time
+    }
+    .value())
+}
+
+// the same instant shown in the local time zone
+fn to_local(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [date_time] = self::args(cx, "to_local", args)?;
+    let date_time = expect_date_time(cx, date_time)?;
+    Ok(DateTime {
+        offset: local_offset(cx.capabilities(), date_time.secs),
+        ..date_time
+    }
+    .value())
+}
+
+// moved by a `Duration`, the offset stays
+fn date_time_plus(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [date_time, duration] = self::args(cx, "plus", args)?;
+    let date_time = expect_date_time(cx, date_time)?;
+    let nanos = expect_duration(cx, duration)?;
+    let moved = date_time.plus(nanos as i128);
+    moved.map(DateTime::value).ok_or_else(|| overflow(cx))
+}
+
+// `minus(duration)` moves it back, `minus(other)` is the `Duration` from the other one
+fn date_time_minus(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [date_time, other] = self::args(cx, "minus", args)?;
+    let date_time = expect_date_time(cx, date_time)?;
+
+    match other {
+        Value::Instance(ref instance) if Rc::ptr_eq(&instance.class, &date_time_class()) => {
+            let other = expect_date_time(cx, other)?;
+            let nanos = date_time.total_nanos() - other.total_nanos();
+            let nanos = i64::try_from(nanos).map_err(|_| overflow(cx))?;
+            Ok(duration(nanos))
+        }
+        other => {
+            let nanos = expect_duration(cx, other)?;
+            let moved = date_time.plus(-(nanos as i128));
+            moved.map(DateTime::value).ok_or_else(|| overflow(cx))
+        }
+    }
+}
+
+// `-1`, `0` or `1` as it is before, at or after the other one, for `sort_by`
+fn date_time_compare(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [date_time, other] = self::args(cx, "compare", args)?;
+    let date_time = expect_date_time(cx, date_time)?;
+    let other = expect_date_time(cx, other)?;
+    let ordering = date_time.total_nanos().cmp(&other.total_nanos());
+    Ok(Value::Int(ordering as i64))
+}
+
+// ISO 8601 with the offset, `2024-03-01T12:30:05.25+02:00`
+fn date_time_to_string(
+    cx: &mut dyn NativeContext,
+    args: Vec<Value>,
+) -> Result<Value, RuntimeError> {
+    let [date_time] = self::args(cx, "to_string", args)?;
+    let date_time = expect_date_time(cx, date_time)?;
+    Ok(Value::Str(iso(date_time).into()))
+}
+
+// the same instant, shown in any offset
+fn date_time_eq(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [date_time, other] = self::args(cx, "eq", args)?;
+    let date_time = expect_date_time(cx, date_time)?;
+    Ok(Value::Bool(match other {
+        Value::Nil => false,
+        other => expect_date_time(cx, other)?.total_nanos() == date_time.total_nanos(),
+    }))
+}
+
+// `format(pattern)` replaces `%Y`, `%m`, `%d`, `%H`, `%M`, `%S`, `%f` (microseconds),
+// `%z` (`+0200`), `%a` (`Mon`), `%b` (`Jan`) and `%%`
+fn format(cx: &mut dyn NativeContext, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let [date_time, pattern] = self::args(cx, "format", args)?;
+    let date_time = expect_date_time(cx, date_time)?;
+    let pattern = expect_str(cx, pattern)?;
+
+    match format_date_time(date_time, &pattern) {
+        Ok(text) => Ok(Value::Str(text.into())),
+        Err(spec) => Err(cx.error(format!("unknown format `%{spec}`"))),
+    }
+}
+
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+// the text of `pattern` or the char after a `%` which isn't known, a `%` at the end is
+// `'%'` itself
+fn format_date_time(date_time: DateTime, pattern: &str) -> Result<String, char> {
+    let civil = date_time.civil();
+    let mut text = String::new();
+    let mut chars = pattern.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            text.push(c);
+            continue;
+        }
+        let _ = match chars.next().ok_or('%')? {
+            'Y' => write!(text, "{:04}", civil.year),
+            'm' => write!(text, "{:02}", civil.month),
+            'd' => write!(text, "{:02}", civil.day),
+            'H' => write!(text, "{:02}", civil.hour),
+            'M' => write!(text, "{:02}", civil.minute),
+            'S' => write!(text, "{:02}", civil.second),
+            'f' => write!(text, "{:06}", date_time.nanos / 1000),
+            'z' => write!(text, "{}", offset_text(date_time.offset, "")),
+            'a' => write!(text, "{}", WEEKDAYS[date_time.weekday() as usize - 1]),
+            'b' => write!(text, "{}", MONTHS[civil.month as usize - 1]),
+            '%' => write!(text, "%"),
+            spec => return Err(spec),
+        };
+    }
+    Ok(text)
+}
+
+// `+02:00` or `-0530` with `separator` between the hours and minutes
+fn offset_text(offset: i64, separator: &str) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    let minutes = offset.abs() / 60;
+    format!("{sign}{:02}{separator}{:02}", minutes / 60, minutes % 60)
+}
+
+fn iso(date_time: DateTime) -> String {
+    let civil = date_time.civil();
+    let mut text = format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        civil.year, civil.month, civil.day, civil.hour, civil.minute, civil.second
+    );
+    if date_time.nanos != 0 {
+        let fraction = format!("{:09}", date_time.nanos);
+        text.push('.');
+        text.push_str(fraction.trim_end_matches('0'));
+    }
+    match date_time.offset {
+        0 => text.push('Z'),
+        offset => text.push_str(&offset_text(offset, ":")),
+    }
+    text
+}
+
+// `2024-03-01`, `2024-03-01T12:30:05Z` or with a space for the `T`, a fraction of a second
+// and an offset, `2024-03-01 12:30:05.25+02:00`. Without an offset it is UTC.
+fn parse_iso(text: &str) -> Option<DateTime> {
+    let mut cursor = Cursor(text.as_bytes());
+
+    let year = cursor.number(4)?;
+    cursor.expect(b'-')?;
+    let month = cursor.number(2)?;
+    cursor.expect(b'-')?;
+    let day = cursor.number(2)?;
+    if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) {
+        return None;
+    }
+
+    let (mut hour, mut minute, mut second, mut nanos, mut offset) = (0, 0, 0, 0, 0);
+    if cursor.eat(b'T') || cursor.eat(b't') || cursor.eat(b' ') {
+        hour = cursor.number(2)?;
+        cursor.expect(b':')?;
+        minute = cursor.number(2)?;
+        cursor.expect(b':')?;
+        second = cursor.number(2)?;
+        if hour > 23 || minute > 59 || second > 59 {
+            return None;
+        }
+
+        if cursor.eat(b'.') {
+            let digits = cursor.digits();
+            if digits.is_empty() {
+                return None;
+            }
+            // digits past nanoseconds are dropped
+            for i in 0..9 {
+                let digit = digits.get(i).map_or(0, |digit| i64::from(digit - b'0'));
+                nanos = nanos * 10 + digit;
+            }
+        }
+
+        if !(cursor.eat(b'Z') || cursor.eat(b'z')) {
+            let sign = match cursor.0.first() {
+                Some(b'+') => 1,
+                Some(b'-') => -1,
+                _ => 0,
+            };
+            if sign != 0 {
+                cursor.0 = &cursor.0[1..];
+                let hours = cursor.number(2)?;
+                cursor.eat(b':');
+                let minutes = cursor.number(2)?;
+                if hours > 23 || minutes > 59 {
+                    return None;
+                }
+                offset = sign * (hours * 3600 + minutes * 60);
+            }
+        }
+    }
+    if !cursor.0.is_empty() {
+        return None;
+    }
+
+    let local = days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second;
+    Some(DateTime {
+        secs: local - offset,
+        nanos,
+        offset,
+    })
+}
+
+struct Cursor<'a>(&'a [u8]);
+
+impl<'a> Cursor<'a> {
+    fn eat(&mut self, byte: u8) -> bool {
+        match self.0.split_first() {
+            Some((first, rest)) if *first == byte => {
+                self.0 = rest;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Option<()> {
+        self.eat(byte).then_some(())
+    }
+
+    fn digits(&mut self) -> &'a [u8] {
+        let len = self.0.iter().take_while(|c| c.is_ascii_digit()).count();
+        let (digits, rest) = self.0.split_at(len);
+        self.0 = rest;
+        digits
+    }
+
+    // exactly `len` digits
+    fn number(&mut self, len: usize) -> Option<i64> {
+        let digits = self.0.get(..len)?;
+        if !digits.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        self.0 = &self.0[len..];
+        Some(
+            digits
+                .iter()
+                .fold(0, |n, digit| n * 10 + i64::from(digit - b'0')),
+        )
+    }
+}
+
+// the offset of local time at `secs` after the epoch, UTC when there is no zone file
+fn local_offset(caps: Capabilities, secs: i64) -> i64 {
+    zone_file(caps, || std::env::var("TZ").ok())
+        .and_then(|path| std::fs::read(path).ok())
+        .and_then(|data| zone_offset(&data, secs))
+        .unwrap_or(0)
+}
+
+// the path of the zone file, `None` without `fs`. `tz` reads `TZ` and is only called with
+// `env`.
+fn zone_file(caps: Capabilities, tz: impl FnOnce() -> Option<String>) -> Option<String> {
+    if !caps.allows(Capability::Fs) {
+        return None;
+    }
+
+    let tz = caps.allows(Capability::Env).then(tz).flatten();
+    Some(match tz {
+        Some(zone) if zone.starts_with('/') => zone,
+        Some(zone) if !zone.is_empty() => {
+            format!("/usr/share/zoneinfo/{}", zone.trim_start_matches(':'))
+        }
+        _ => "/etc/localtime".to_owned(),
+    })
+}
+
+// the offset at `secs` in a TZif zone file, see RFC 8536
+fn zone_offset(data: &[u8], secs: i64) -> Option<i64> {
+    const HEADER: usize = 44;
+
+    let counts = |data: &[u8]| -> Option<[usize; 6]> {
+        if data.get(..4)? != b"TZif" {
+            return None;
+        }
+        let mut counts = [0; 6];
+        for (i, count) in counts.iter_mut().enumerate() {
+            let at = 20 + i * 4;
+            *count = u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?) as usize;
+        }
+        Some(counts)
+    };
+
+    let [is_ut, is_std, leaps, times, types, chars] = counts(data)?;
+    let (data, time_len) = match data.get(4)? {
+        // version 1 has 32 bit times, later versions repeat the data with 64 bit times
+        0 => (data, 4),
+        _ => {
+            let v1 = HEADER + times * 5 + types * 6 + chars + leaps * 8 + is_std + is_ut;
+            (data.get(v1..)?, 8)
+        }
+    };
+
+    let [_, _, _, times, types, _] = counts(data)?;
+    let data = data.get(HEADER..)?;
+    let (transitions, data) = data.split_at_checked(times * time_len)?;
+    let (type_indices, data) = data.split_at_checked(times)?;
+    let infos = data.get(..types * 6)?;
+
+    // the first type is used before the first transition
+    let mut ty = 0;
+    for (at, idx) in transitions.chunks(time_len).zip(type_indices) {
+        let at = match *at {
+            [a, b, c, d] => i32::from_be_bytes([a, b, c, d]) as i64,
+            _ => i64::from_be_bytes(at.try_into().ok()?),
+        };
+        if at > secs {
+            break;
+        }
+        ty = *idx as usize;
+    }
+
+    let info = infos.get(ty * 6..ty * 6 + 4)?;
+    Some(i32::from_be_bytes(info.try_into().ok()?) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_dates_and_days() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+        assert_eq!(civil_from_days(11_017), (2000, 3, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+
+        for days in [-800_000, -1, 0, 59, 19_782, 2_932_896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+
+    #[test]
+    fn parses_and_prints_iso_dates() {
+        let date_time = parse_iso("2024-02-29T23:30:05.25+02:00").unwrap();
+        assert_eq!(date_time.offset, 7200);
+        assert_eq!(date_time.nanos, 250_000_000);
+        assert_eq!(iso(date_time), "2024-02-29T23:30:05.25+02:00");
+        assert_eq!(
+            iso(DateTime {
+                offset: 0,
+                ..date_time
+            }),
+            "2024-02-29T21:30:05.25Z"
+        );
+        assert_eq!(
+            date_time.civil(),
+            Civil {
+                year: 2024,
+                month: 2,
+                day: 29,
+                hour: 23,
+                minute: 30,
+                second: 5,
+            }
+        );
+
+        assert_eq!(parse_iso("1970-01-01").unwrap().secs, 0);
+        assert_eq!(parse_iso("1970-01-01 00:00:01-0100").unwrap().secs, 3601);
+        for text in [
+            "2023-02-29",
+            "2024-13-01",
+            "2024-01-01T24:00:00Z",
+            "2024-01-01x",
+            "24-1-1",
+        ] {
+            assert_eq!(parse_iso(text), None, "{text}");
+        }
+    }
+
+    #[test]
+    fn formats_date_times() {
+        let date_time = parse_iso("2024-03-04T05:06:07.000008-05:30").unwrap();
+        assert_eq!(
+            format_date_time(date_time, "%a %d %b %Y %H:%M:%S.%f %z %%").unwrap(),
+            "Mon 04 Mar 2024 05:06:07.000008 -0530 %"
+        );
+        assert_eq!(format_date_time(date_time, "%q"), Err('q'));
+        assert_eq!(format_date_time(date_time, "100%"), Err('%'));
+    }
+
+    #[test]
+    fn reads_offsets_of_zone_files() {
+        // a version 1 file with a transition from +01:00 to +02:00 at 1000
+        let mut data = b"TZif".to_vec();
+        data.extend([0; 16]);
+        for count in [0u32, 0, 0, 1, 2, 0] {
+            data.extend(count.to_be_bytes());
+        }
+        data.extend(1000i32.to_be_bytes());
+        data.push(1);
+        data.extend(3600i32.to_be_bytes());
+        data.extend([0, 0]);
+        data.extend(7200i32.to_be_bytes());
+        data.extend([1, 0]);
+
+        assert_eq!(zone_offset(&data, 999), Some(3600));
+        assert_eq!(zone_offset(&data, 1000), Some(7200));
+        assert_eq!(zone_offset(b"nope", 0), None);
+    }
+
+    #[test]
+    fn finds_zone_files_with_the_granted_capabilities() {
+        let tz = || Some("Europe/Paris".to_owned());
+        assert_eq!(
+            zone_file(Capabilities::ALL, tz).as_deref(),
+            Some("/usr/share/zoneinfo/Europe/Paris")
+        );
+        assert_eq!(
+            zone_file(Capabilities::ALL, || Some("/tmp/zone".to_owned())).as_deref(),
+            Some("/tmp/zone")
+        );
+        assert_eq!(
+            zone_file(Capabilities::ALL, || None).as_deref(),
+            Some("/etc/localtime")
+        );
+
+        let no_env = Capabilities::ALL.deny(Capability::Env);
+        assert_eq!(
+            zone_file(no_env, || unreachable!("`TZ` is read without `env`")).as_deref(),
+            Some("/etc/localtime")
+        );
+        assert_eq!(zone_file(Capabilities::ALL.deny(Capability::Fs), tz), None);
+        assert_eq!(local_offset(Capabilities::NONE, 0), 0);
+    }
+}
@@ -0,0 +1,703 @@
+use std::any::Any;
+use std::cell::{RefCell, RefMut};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::io::Write;
+use std::rc::Rc;
+
+use ecow::{eco_format, EcoString};
+
+use super::coroutine::Coroutine;
+use super::error::RuntimeError;
+use super::executor::{Executor, Future};
+use super::gc::Heap;
+use super::limits::{Capabilities, Capability};
+use crate::ast::{CoroutineKind, NodeId};
+use crate::resolve::BuiltinInterface;
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(EcoString),
+    // `start..end`, an inclusive range is stored with `end + 1` and `true` so it shows as
+    // written
+    Range(i64, i64, bool),
+    List(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<Map>>),
+    Set(Rc<RefCell<Set>>),
+    // a fn item
+    Fn(NodeId),
+    Native(Rc<NativeFn>),
+    // the class itself, eg. `Person` in `Person.new()`
+    Class(Rc<Class>),
+    Instance(Rc<Instance>),
+    // a standard library module like `kai.io`
+    Module(EcoString),
+    Error(Rc<ErrorValue>),
+    // a call of a generator or an `async fn`, suspended until resumed
+    Coroutine(Rc<Coroutine>),
+    // a value the executor is waiting for, like a timer or a spawned task
+    Future(Rc<Future>),
+    // a rust object like a socket, only its methods can look inside
+    Handle(Rc<Handle>),
+}
+
+impl Value {
+    /// A recoverable error without a cause
+    pub fn error(message: impl Into<EcoString>) -> Self {
+        Value::Error(Rc::new(ErrorValue {
+            message: message.into(),
+            cause: None,
+        }))
+    }
+
+    /// An error adding context to `cause`
+    pub fn wrap_error(cause: Rc<ErrorValue>, message: impl Into<EcoString>) -> Self {
+        Value::Error(Rc::new(ErrorValue {
+            message: message.into(),
+            cause: Some(cause),
+        }))
+    }
+
+    pub fn list(items: Vec<Value>) -> Self {
+        Value::List(Rc::new(RefCell::new(items)))
+    }
+
+    pub fn map(map: Map) -> Self {
+        Value::Map(Rc::new(RefCell::new(map)))
+    }
+
+    pub fn set(set: Set) -> Self {
+        Value::Set(Rc::new(RefCell::new(set)))
+    }
+
+    /// Name of the type of the value, used in error messages
+    pub fn type_name(&self) -> EcoString {
+        match self {
+            Value::Nil => "nil".into(),
+            Value::Bool(_) => "bool".into(),
+            Value::Int(_) => "int".into(),
+            Value::Float(_) => "float".into(),
+            Value::Str(_) => "string".into(),
+            Value::Range(..) => "range".into(),
+            Value::List(_) => "list".into(),
+            Value::Map(_) => "map".into(),
+            Value::Set(_) => "set".into(),
+            Value::Fn(_) | Value::Native(_) => "fn".into(),
+            Value::Class(class) => eco_format!("class {}", class.name),
+            Value::Instance(instance) => instance.class.name.clone(),
+            Value::Module(_) => "module".into(),
+            Value::Error(_) => "error".into(),
+            Value::Coroutine(coroutine) => match coroutine.kind {
+                CoroutineKind::Generator => "generator".into(),
+                CoroutineKind::Async => "future".into(),
+            },
+            Value::Future(_) => "future".into(),
+            Value::Handle(handle) => handle.kind.into(),
+        }
+    }
+}
+
+/// Values are equal by content, lists, maps and sets element by element, instances and classes only when they are the same object.
+/// Instances implementing `Eq` are compared by the interpreter instead.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        equal(self, other, &mut Vec::new())
+    }
+}
+
+// lists or maps being compared with each other
+type Pairs = Vec<(*const (), *const ())>;
+
+// `seen` holds the pairs of lists and maps being compared, a list holding itself is equal
+// to another holding itself when the rest of their items are
+fn equal(a: &Value, b: &Value, seen: &mut Pairs) -> bool {
+    match (a, b) {
+        (Value::List(a), Value::List(b)) if !Rc::ptr_eq(a, b) => {
+            nested(seen, (Rc::as_ptr(a).cast(), Rc::as_ptr(b).cast()), |seen| {
+                let (a, b) = (a.borrow(), b.borrow());
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| equal(a, b, seen))
+            })
+        }
+        (Value::Map(a), Value::Map(b)) if !Rc::ptr_eq(a, b) => {
+            nested(seen, (Rc::as_ptr(a).cast(), Rc::as_ptr(b).cast()), |seen| {
+                map_equal(&a.borrow(), &b.borrow(), seen)
+            })
+        }
+        _ => shallow_equal(a, b),
+    }
+}
+
+// runs `f` unless `pair` is already being compared further up, which is taken as equal
+fn nested(
+    seen: &mut Pairs,
+    pair: (*const (), *const ()),
+    f: impl FnOnce(&mut Pairs) -> bool,
+) -> bool {
+    if seen.contains(&pair) {
+        return true;
+    }
+    seen.push(pair);
+    let equal = f(seen);
+    seen.pop();
+    equal
+}
+
+fn map_equal(a: &Map, b: &Map, seen: &mut Pairs) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .all(|(key, value)| b.get(key).is_some_and(|other| equal(value, other, seen)))
+}
+
+// everything but two different lists or maps
+fn shallow_equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Nil, Value::Nil) => true,
+        (Value::Bool(a), Value::Bool(b)) => a == b,
+        (Value::Int(a), Value::Int(b)) => a == b,
+        (Value::Float(a), Value::Float(b)) => a == b,
+        (Value::Int(a), Value::Float(b)) | (Value::Float(b), Value::Int(a)) => *a as f64 == *b,
+        (Value::Str(a), Value::Str(b)) => a == b,
+        // `1..=3` is `1..4`, they have the same items
+        (Value::Range(a, b, _), Value::Range(c, d, _)) => a == c && b == d,
+        (Value::List(a), Value::List(b)) => Rc::ptr_eq(a, b),
+        (Value::Map(a), Value::Map(b)) => Rc::ptr_eq(a, b),
+        (Value::Set(a), Value::Set(b)) => Rc::ptr_eq(a, b) || *a.borrow() == *b.borrow(),
+        (Value::Fn(a), Value::Fn(b)) => a == b,
+        (Value::Native(a), Value::Native(b)) => a.name == b.name,
+        (Value::Class(a), Value::Class(b)) => Rc::ptr_eq(a, b),
+        (Value::Instance(a), Value::Instance(b)) => Rc::ptr_eq(a, b),
+        (Value::Module(a), Value::Module(b)) => a == b,
+        (Value::Error(a), Value::Error(b)) => a == b,
+        (Value::Coroutine(a), Value::Coroutine(b)) => Rc::ptr_eq(a, b),
+        (Value::Future(a), Value::Future(b)) => Rc::ptr_eq(a, b),
+        (Value::Handle(a), Value::Handle(b)) => Rc::ptr_eq(a, b),
+        _ => false,
+    }
+}
+
+/// A recoverable error, returned instead of panicking and passed on with `?`
+#[derive(Debug, PartialEq)]
+pub struct ErrorValue {
+    pub message: EcoString,
+    // the error this one was wrapped around
+    pub cause: Option<Rc<ErrorValue>>,
+}
+
+/// The message followed by the messages of every cause, `reading config: not found`
+impl Display for ErrorValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)?;
+        match &self.cause {
+            Some(cause) => write!(f, ": {cause}"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_value(f, self, &mut Vec::new())
+    }
+}
+
+// lists, maps and instances nested deeper are shown as `[...]` too
+const MAX_NESTING: usize = 128;
+
+// `seen` holds the lists, maps and instances being written, one holding itself shows as
+// `[...]`, `{...}` or `Node {...}` inside
+fn write_value(
+    f: &mut std::fmt::Formatter<'_>,
+    value: &Value,
+    seen: &mut Vec<*const ()>,
+) -> std::fmt::Result {
+    match value {
+        Value::Nil => write!(f, "nil"),
+        Value::Bool(value) => write!(f, "{value}"),
+        Value::Int(value) => write!(f, "{value}"),
+        // `1.0` instead of `1` so floats stay recognizable
+        Value::Float(value) if value.fract() == 0.0 && value.is_finite() => {
+            write!(f, "{value:.1}")
+        }
+        Value::Float(value) => write!(f, "{value}"),
+        Value::Str(value) => write!(f, "{value}"),
+        Value::Range(start, end, true) => write!(f, "{start}..={}", end - 1),
+        Value::Range(start, end, false) => write!(f, "{start}..{end}"),
+        Value::List(items) => {
+            write_object(f, seen, Rc::as_ptr(items).cast(), &"[...]", |f, seen| {
+                write!(f, "[")?;
+                for (i, item) in items.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write_nested(f, item, seen)?;
+                }
+                write!(f, "]")
+            })
+        }
+        Value::Map(map) => write_object(f, seen, Rc::as_ptr(map).cast(), &"{...}", |f, seen| {
+            let map = map.borrow();
+            if map.is_empty() {
+                return write!(f, "{{}}");
+            }
+
+            write!(f, "{{")?;
+            for (i, (key, value)) in map.iter().enumerate() {
+                let sep = if i == 0 { " " } else { ", " };
+                write!(f, "{sep}")?;
+                write_nested(f, &key.to_value(), seen)?;
+                write!(f, ": ")?;
+                write_nested(f, value, seen)?;
+            }
+            write!(f, " }}")
+        }),
+        // `{}` is the empty map
+        Value::Set(set) if set.borrow().is_empty() => write!(f, "set()"),
+        Value::Set(set) => {
+            write!(f, "{{")?;
+            for (i, key) in set.borrow().iter().enumerate() {
+                let sep = if i == 0 { " " } else { ", " };
+                write!(f, "{sep}")?;
+                write_nested(f, &key.to_value(), seen)?;
+            }
+            write!(f, " }}")
+        }
+        Value::Fn(_) => write!(f, "<fn>"),
+        Value::Native(native) => write!(f, "<native fn {}>", native.name),
+        Value::Class(class) => write!(f, "<class {}>", class.name),
+        Value::Instance(instance) => {
+            let name = &instance.class.name;
+            let cycle = format_args!("{name} {{...}}");
+            write_object(f, seen, Rc::as_ptr(instance).cast(), &cycle, |f, seen| {
+                write!(f, "{name} {{")?;
+                let fields = instance.fields.borrow();
+
+                for (i, (name, value)) in
+                    instance.class.fields.iter().zip(fields.iter()).enumerate()
+                {
+                    let sep = if i == 0 { " " } else { ", " };
+                    write!(f, "{sep}{name}: ")?;
+                    write_nested(f, value, seen)?;
+                }
+
+                if fields.is_empty() {
+                    write!(f, "}}")
+                } else {
+                    write!(f, " }}")
+                }
+            })
+        }
+        Value::Module(path) => write!(f, "<module {path}>"),
+        Value::Error(error) => write!(f, "{error}"),
+        Value::Coroutine(coroutine) => {
+            write!(f, "<{} {}>", value.type_name(), coroutine.name)
+        }
+        Value::Future(future) => write!(f, "<future {}>", future.name),
+        Value::Handle(handle) => write!(f, "<{}>", handle.kind),
+    }
+}
+
+// writes `object` with `body`, or `cycle` when it is already being written further up
+fn write_object(
+    f: &mut std::fmt::Formatter<'_>,
+    seen: &mut Vec<*const ()>,
+    object: *const (),
+    cycle: &dyn Display,
+    body: impl FnOnce(&mut std::fmt::Formatter<'_>, &mut Vec<*const ()>) -> std::fmt::Result,
+) -> std::fmt::Result {
+    if seen.len() == MAX_NESTING || seen.contains(&object) {
+        return write!(f, "{cycle}");
+    }
+    seen.push(object);
+    let result = body(f, seen);
+    seen.pop();
+    result
+}
+
+// strings inside other values are quoted so `["a, b"]` and `["a", "b"]` differ
+fn write_nested(
+    f: &mut std::fmt::Formatter<'_>,
+    value: &Value,
+    seen: &mut Vec<*const ()>,
+) -> std::fmt::Result {
+    match value {
+        Value::Str(value) => write!(f, "{value:?}"),
+        value => write_value(f, value, seen),
+    }
+}
+
+/// What a map can be keyed by, values which can't be hashed like floats and instances are
+/// left out
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    Bool(bool),
+    Int(i64),
+    Str(EcoString),
+}
+
+impl Key {
+    pub fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Bool(value) => Some(Key::Bool(*value)),
+            Value::Int(value) => Some(Key::Int(*value)),
+            Value::Str(value) => Some(Key::Str(value.clone())),
+            _ => None,
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        match self {
+            Key::Bool(value) => Value::Bool(*value),
+            Key::Int(value) => Value::Int(*value),
+            Key::Str(value) => Value::Str(value.clone()),
+        }
+    }
+}
+
+/// A map keeping its entries in insertion order, so printing and iterating it is
+/// deterministic
+#[derive(Debug, Clone, Default)]
+pub struct Map {
+    entries: Vec<(Key, Value)>,
+    index: HashMap<Key, usize>,
+}
+
+impl Map {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &Key) -> Option<&Value> {
+        let &idx = self.index.get(key)?;
+        Some(&self.entries[idx].1)
+    }
+
+    /// Sets the value of `key`, a new key goes after the others, returns the old value
+    pub fn insert(&mut self, key: Key, value: Value) -> Option<Value> {
+        match self.index.get(&key) {
+            Some(&idx) => Some(std::mem::replace(&mut self.entries[idx].1, value)),
+            None => {
+                self.index.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+                None
+            }
+        }
+    }
+
+    /// Removes `key` keeping the order of the others
+    pub fn remove(&mut self, key: &Key) -> Option<Value> {
+        let idx = self.index.remove(key)?;
+        let (_, value) = self.entries.remove(idx);
+
+        for (_, later) in self.index.iter_mut() {
+            if *later > idx {
+                *later -= 1;
+            }
+        }
+        Some(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Key, &Value)> {
+        self.entries.iter().map(|(key, value)| (key, value))
+    }
+}
+
+/// Maps are equal when they have the same entries, in any order
+impl PartialEq for Map {
+    fn eq(&self, other: &Self) -> bool {
+        map_equal(self, other, &mut Vec::new())
+    }
+}
+
+impl FromIterator<(Key, Value)> for Map {
+    fn from_iter<T: IntoIterator<Item = (Key, Value)>>(iter: T) -> Self {
+        let mut map = Map::new();
+        for (key, value) in iter {
+            map.insert(key, value);
+        }
+        map
+    }
+}
+
+/// A set of keys in insertion order, like the keys of a `Map`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Set(Map);
+
+impl Set {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn contains(&self, key: &Key) -> bool {
+        self.0.get(key).is_some()
+    }
+
+    /// Adds `key` after the others, returns whether it was new
+    pub fn insert(&mut self, key: Key) -> bool {
+        self.0.insert(key, Value::Nil).is_none()
+    }
+
+    /// Removes `key` keeping the order of the others, returns whether it was there
+    pub fn remove(&mut self, key: &Key) -> bool {
+        self.0.remove(key).is_some()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Key> {
+        self.0.iter().map(|(key, _)| key)
+    }
+}
+
+impl FromIterator<Key> for Set {
+    fn from_iter<T: IntoIterator<Item = Key>>(iter: T) -> Self {
+        Set(iter.into_iter().map(|key| (key, Value::Nil)).collect())
+    }
+}
+
+/// What native functions can ask of the backend calling them
+pub(crate) trait NativeContext {
+    fn stdout(&mut self) -> &mut dyn Write;
+
+    fn heap(&mut self) -> &mut Heap;
+
+    /// An error pointing at the native call being run
+    fn error(&self, message: String) -> RuntimeError;
+
+    /// Formats a value for printing, using its `Display` impl if it has one
+    fn display(&mut self, value: &Value) -> Result<String, RuntimeError>;
+
+    /// The tasks of the engine, for natives spawning them
+    fn executor(&mut self) -> &mut Executor;
+
+    /// The arguments the script was started with, see `Engine::set_args`
+    fn args(&self) -> &[EcoString];
+
+    /// What the engine grants, for natives which only sometimes touch the system
+    fn capabilities(&self) -> Capabilities;
+
+    /// Spends a step of the fuel of the run and checks its deadline, natives call it for
+    /// every item of loops which can run long
+    fn tick(&mut self) -> Result<(), RuntimeError>;
+
+    /// Calls a method of `receiver`, like the `to_json` of an instance being encoded
+    fn call(
+        &mut self,
+        receiver: Value,
+        method: &str,
+        args: Vec<Value>,
+    ) -> Result<Value, RuntimeError>;
+
+    /// Calls a function, like the one passed to `map` on a list
+    fn apply(&mut self, callee: Value, args: Vec<Value>) -> Result<Value, RuntimeError>;
+}
+
+pub(crate) type NativeFnPtr = fn(&mut dyn NativeContext, Vec<Value>) -> Result<Value, RuntimeError>;
+
+// standard library functions are plain fns, the ones registered by the host can capture
+pub(crate) type NativeClosure =
+    Rc<dyn Fn(&mut dyn NativeContext, Vec<Value>) -> Result<Value, RuntimeError>>;
+
+/// A function implemented in rust
+pub struct NativeFn {
+    pub name: EcoString,
+    pub(crate) func: NativeClosure,
+    // what the module of the function needs, checked by the backends on every call
+    pub capability: Option<Capability>,
+}
+
+impl std::fmt::Debug for NativeFn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NativeFn")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+/// A method of a class registered by the host
+#[derive(Debug, Clone)]
+pub struct NativeMethod {
+    pub native: Rc<NativeFn>,
+    // called on instances, which are passed as the first argument
+    pub has_self: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Class {
+    pub id: NodeId,
+    pub name: EcoString,
+    // in declaration order, instances store their fields in the same order
+    pub fields: Vec<EcoString>,
+    // own methods, methods from impls and interface defaults, dispatched by name
+    pub methods: HashMap<EcoString, NodeId>,
+    // the interfaces the runtime itself calls into
+    pub builtins: Vec<BuiltinInterface>,
+    // methods of classes registered by the host
+    pub natives: HashMap<EcoString, NativeMethod>,
+}
+
+impl Class {
+    pub fn field_index(&self, name: &str) -> Option<usize> {
+        self.fields.iter().position(|field| field == name)
+    }
+
+    pub fn implements(&self, iface: BuiltinInterface) -> bool {
+        self.builtins.contains(&iface)
+    }
+}
+
+#[derive(Debug)]
+pub struct Instance {
+    pub class: Rc<Class>,
+    pub fields: RefCell<Vec<Value>>,
+}
+
+impl Instance {
+    pub fn field(&self, name: &str) -> Option<Value> {
+        let idx = self.class.field_index(name)?;
+        Some(self.fields.borrow()[idx].clone())
+    }
+}
+
+/// A rust object natives hand to kai code, which can only pass it around and call the
+/// methods it comes with
+pub struct Handle {
+    // the type name kai code sees
+    pub kind: &'static str,
+    object: RefCell<Box<dyn Any>>,
+    // called with the handle as the first argument
+    methods: &'static [(&'static str, NativeFnPtr)],
+}
+
+impl Handle {
+    pub(crate) fn new(
+        kind: &'static str,
+        object: impl Any,
+        methods: &'static [(&'static str, NativeFnPtr)],
+    ) -> Self {
+        Self {
+            kind,
+            object: RefCell::new(Box::new(object)),
+            methods,
+        }
+    }
+
+    /// The object if it has type `T`
+    pub fn get<T: Any>(&self) -> Option<RefMut<'_, T>> {
+        RefMut::filter_map(self.object.borrow_mut(), |object| object.downcast_mut()).ok()
+    }
+
+    pub(crate) fn method(&self, name: &str) -> Option<NativeFn> {
+        let (name, func) = self.methods.iter().find(|(method, _)| *method == name)?;
+        Some(NativeFn {
+            name: (*name).into(),
+            func: Rc::new(*func),
+            capability: None,
+        })
+    }
+}
+
+impl std::fmt::Debug for Handle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Handle").field("kind", &self.kind).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn displays_values() {
+        let class = Rc::new(Class {
+            id: NodeId(0),
+            name: "Point".into(),
+            fields: vec!["x".into(), "label".into()],
+            methods: HashMap::new(),
+            builtins: Vec::new(),
+            natives: HashMap::new(),
+        });
+        let instance = Instance {
+            class,
+            fields: RefCell::new(vec![Value::Float(1.0), Value::Str("a".into())]),
+        };
+
+        assert_eq!(Value::Float(2.0).to_string(), "2.0");
+        assert_eq!(Value::Float(2.5).to_string(), "2.5");
+        assert_eq!(Value::Range(1, 11, false).to_string(), "1..11");
+        assert_eq!(Value::Range(1, 4, true).to_string(), "1..=3");
+        assert_eq!(Value::Range(1, 4, true), Value::Range(1, 4, false));
+        assert_eq!(
+            Value::list(vec![Value::Int(1), Value::Str("a, b".into())]).to_string(),
+            r#"[1, "a, b"]"#
+        );
+        let map = Map::from_iter([(Key::Str("a".into()), Value::list(Vec::new()))]);
+        assert_eq!(Value::map(map).to_string(), r#"{ "a": [] }"#);
+        assert_eq!(Value::map(Map::new()).to_string(), "{}");
+        assert_eq!(
+            Value::Instance(Rc::new(instance)).to_string(),
+            r#"Point { x: 1.0, label: "a" }"#
+        );
+
+        // deep nesting is cut off like a cycle instead of overflowing the stack
+        let deep = (0..1000).fold(Value::Nil, |inner, _| Value::list(vec![inner]));
+        let shown = deep.to_string();
+        assert!(shown.starts_with(&"[".repeat(MAX_NESTING)));
+        assert!(shown.contains("[...]") && !shown.contains("nil"));
+    }
+
+    #[test]
+    fn compares_values() {
+        assert_eq!(Value::Int(1), Value::Float(1.0));
+        assert_ne!(Value::Int(1), Value::Str("1".into()));
+        assert_eq!(Value::Str("a".into()), Value::Str("a".into()));
+        assert_ne!(Value::Nil, Value::Bool(false));
+        assert_eq!(
+            Value::list(vec![Value::Int(1)]),
+            Value::list(vec![Value::Float(1.0)])
+        );
+
+        let a = Map::from_iter([(Key::Int(1), Value::Nil), (Key::Int(2), Value::Nil)]);
+        let b = Map::from_iter([(Key::Int(2), Value::Nil), (Key::Int(1), Value::Nil)]);
+        assert_eq!(Value::map(a), Value::map(b));
+    }
+
+    #[test]
+    fn keeps_map_entries_in_insertion_order() {
+        let mut map: Map = ["c", "a", "b"]
+            .into_iter()
+            .map(|key| (Key::Str(key.into()), Value::Nil))
+            .collect();
+
+        assert_eq!(
+            map.insert(Key::Str("a".into()), Value::Int(1)),
+            Some(Value::Nil)
+        );
+        assert_eq!(map.remove(&Key::Str("c".into())), Some(Value::Nil));
+        map.insert(Key::Str("c".into()), Value::Int(2));
+
+        let keys: Vec<_> = map
+            .iter()
+            .map(|(key, _)| key.to_value().to_string())
+            .collect();
+        assert_eq!(keys, ["a", "b", "c"]);
+        assert_eq!(map.get(&Key::Str("a".into())), Some(&Value::Int(1)));
+    }
+}
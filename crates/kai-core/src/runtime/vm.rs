@@ -0,0 +1,820 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::rc::Rc;
+
+use ecow::EcoString;
+
+use super::bytecode::{Constant, Instr, Program};
+use super::coroutine::{self, Awaited, Coroutine, CoroutineState, Resume, Resumed};
+use super::error::RuntimeError;
+use super::executor::{self, Executor};
+use super::gc::Heap;
+use super::host::Host;
+use super::limits::{self, Budget, Capabilities, Limits};
+use super::ops::{self, Iter};
+use super::value::{Class, NativeContext, Value};
+use crate::ast::span::Span;
+use crate::ast::{BinOp, CoroutineKind, NodeId};
+
+// an active call
+struct Frame {
+    // index into `Program::functions`
+    func: usize,
+    ip: usize,
+    locals: Vec<Option<Value>>,
+    // the stack and iterator stack are cut back to these on return
+    base: usize,
+    iters: usize,
+    // span of the call, errors not tied to an instruction point here
+    span: Span,
+}
+
+/// A coroutine call suspended on the VM, with the part of the stack and iterator stack
+/// above its base
+pub(crate) struct Suspended {
+    frame: Frame,
+    stack: Vec<Value>,
+    iters: Vec<Iter>,
+}
+
+/// A stack machine running a compiled program
+pub(crate) struct Vm<'a> {
+    program: &'a Program,
+    fns: HashMap<NodeId, usize>,
+    classes: HashMap<NodeId, Rc<Class>>,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    // `for` loops in progress, kept apart as they are not values
+    iters: Vec<Iter>,
+    stdout: &'a mut dyn Write,
+    heap: &'a mut Heap,
+    limits: &'a Limits,
+    host: &'a Host,
+    budget: Budget,
+    // span of the native call being run, errors raised by natives point here
+    native_span: Span,
+    executor: &'a mut Executor,
+    // set by `yield` and `await` when they return from `run`, see `resume`
+    suspended: Option<Suspended>,
+}
+
+impl<'a> Vm<'a> {
+    pub fn new(
+        program: &'a Program,
+        stdout: &'a mut dyn Write,
+        heap: &'a mut Heap,
+        limits: &'a Limits,
+        host: &'a Host,
+        executor: &'a mut Executor,
+    ) -> Self {
+        Self {
+            program,
+            fns: program
+                .functions
+                .iter()
+                .enumerate()
+                .map(|(idx, function)| (function.id, idx))
+                .collect(),
+            classes: program
+                .classes
+                .iter()
+                .map(|class| (class.id, Rc::new(class.clone())))
+                .collect(),
+            stack: Vec::new(),
+            frames: Vec::new(),
+            iters: Vec::new(),
+            stdout,
+            heap,
+            limits,
+            host,
+            budget: Budget::new(limits),
+            native_span: Span::default(),
+            executor,
+            suspended: None,
+        }
+    }
+
+    /// Calls the top level `main` function
+    pub fn run_main(&mut self) -> Result<Value, RuntimeError> {
+        match self.program.main {
+            Some(id) => {
+                let value = self.run_fn(id, Vec::new())?;
+                self.run_tasks(self.program.span)?;
+                Ok(value)
+            }
+            None => Err(RuntimeError::new(
+                "no `main` function found",
+                self.program.span,
+            )),
+        }
+    }
+
+    /// Calls a fn item from outside of kai
+    pub fn run_fn(&mut self, id: NodeId, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        let function = &self.program.functions[self.fns[&id]];
+        let (name, span) = (function.name.clone(), function.span);
+        self.budget = Budget::new(self.limits);
+
+        let value = match self.push_frame(id, None, args, span)? {
+            Some(coroutine) => coroutine,
+            None => self.execute()?,
+        };
+        let value = executor::run_async(self, value, span)?;
+        ops::uncaught_error(value, &name, span)
+    }
+
+    /// Calls an `async fn` item as a new task of the executor
+    pub fn spawn_fn(&mut self, id: NodeId, args: Vec<Value>) -> Result<(), RuntimeError> {
+        let function = &self.program.functions[self.fns[&id]];
+        let (name, span) = (function.name.clone(), function.span);
+        self.budget = Budget::new(self.limits);
+
+        match self.push_frame(id, None, args, span)? {
+            Some(Value::Coroutine(coroutine)) if coroutine.kind == CoroutineKind::Async => {
+                self.executor.spawn(coroutine);
+                Ok(())
+            }
+            coroutine => {
+                if coroutine.is_none() {
+                    self.frames.pop();
+                }
+                let message = format!("`{name}` is not an async fn");
+                Err(RuntimeError::new(message, span))
+            }
+        }
+    }
+
+    /// Calls a `@test` fn, the tasks it spawned have to return too
+    pub fn run_test(&mut self, id: NodeId, span: Span) -> Result<Value, RuntimeError> {
+        let result = self.run_fn(id, Vec::new()).and_then(|value| {
+            self.run_tasks(span)?;
+            Ok(value)
+        });
+        // the tasks of a failed test don't run on in the next one
+        self.executor.clear();
+        result
+    }
+
+    /// Runs the tasks left by a call until every one returned
+    pub fn run_tasks(&mut self, span: Span) -> Result<(), RuntimeError> {
+        executor::run_all(self, span)
+    }
+
+    /// Runs the tasks until they all wait, see `Engine::poll`
+    pub fn poll_tasks(&mut self, span: Span) -> Result<executor::TaskStatus, RuntimeError> {
+        self.budget = Budget::new(self.limits);
+        executor::poll(self, span)
+    }
+
+    //BEGIN calls
+
+    /// Pushes the frame of a call, generators and async fns are not run but return a
+    /// coroutine holding the frame
+    fn push_frame(
+        &mut self,
+        id: NodeId,
+        this: Option<Value>,
+        args: Vec<Value>,
+        span: Span,
+    ) -> Result<Option<Value>, RuntimeError> {
+        let idx = self.fns[&id];
+        let function = &self.program.functions[idx];
+        let name = &function.name;
+
+        if !function.has_body {
+            return Err(RuntimeError::new(format!("`{name}` has no body"), span));
+        }
+
+        if usize::from(function.arity) != args.len() {
+            let arity = usize::from(function.arity);
+            return Err(ops::wrong_arg_count(name, arity, args.len(), span));
+        }
+
+        let mut locals = vec![None; function.locals.len()];
+        let first = usize::from(function.has_self);
+
+        if function.has_self {
+            locals[0] = this;
+        }
+
+        for (slot, arg) in locals[first..].iter_mut().zip(args) {
+            *slot = Some(arg);
+        }
+
+        let frame = Frame {
+            func: idx,
+            ip: 0,
+            locals,
+            base: self.stack.len(),
+            iters: self.iters.len(),
+            span,
+        };
+
+        if let Some(kind) = function.coroutine {
+            let suspended = Suspended {
+                frame,
+                stack: Vec::new(),
+                iters: Vec::new(),
+            };
+            let coroutine = Coroutine::new(name.clone(), kind, CoroutineState::Vm(suspended));
+            return Ok(Some(Value::Coroutine(Rc::new(coroutine))));
+        }
+
+        if self.frames.len() == self.limits.call_depth {
+            return Err(limits::stack_overflow(self.limits.call_depth, span));
+        }
+
+        self.frames.push(frame);
+        Ok(None)
+    }
+
+    /// Starts a call, user functions only get a frame and return `None`
+    fn invoke(
+        &mut self,
+        callee: Value,
+        args: Vec<Value>,
+        span: Span,
+    ) -> Result<Option<Value>, RuntimeError> {
+        match callee {
+            Value::Fn(id) => self.push_frame(id, None, args, span),
+            Value::Native(native) => {
+                limits::check_capability(self.limits.capabilities, &native, span)?;
+                let prev = std::mem::replace(&mut self.native_span, span);
+                // strings natives return are held against the heap limit like operator results
+                let result = (native.func)(self, args)
+                    .and_then(|value| limits::check_string(self.heap, value, span));
+                self.native_span = prev;
+                result.map(Some)
+            }
+            callee => Err(RuntimeError::new(
+                format!("`{}` is not callable", callee.type_name()),
+                span,
+            )),
+        }
+    }
+
+    /// Starts a method call, looking it up on the class of the receiver
+    fn invoke_method(
+        &mut self,
+        receiver: Value,
+        name: &str,
+        args: Vec<Value>,
+        span: Span,
+    ) -> Result<Option<Value>, RuntimeError> {
+        match &receiver {
+            Value::Instance(instance) => {
+                let class = &instance.class;
+                let Some(&id) = class.methods.get(name) else {
+                    let (native, args) = ops::native_method(&receiver, name, args, span)?;
+                    return self.invoke(native, args, span);
+                };
+
+                if !self.program.functions[self.fns[&id]].has_self {
+                    return Err(RuntimeError::new(
+                        format!(
+                            "`{name}` is a static method, call it as `{}.{name}(..)`",
+                            class.name
+                        ),
+                        span,
+                    ));
+                }
+
+                self.push_frame(id, Some(receiver.clone()), args, span)
+            }
+            Value::Class(class) => {
+                let Some(&id) = class.methods.get(name) else {
+                    let (native, args) = ops::native_method(&receiver, name, args, span)?;
+                    return self.invoke(native, args, span);
+                };
+
+                if self.program.functions[self.fns[&id]].has_self {
+                    return Err(RuntimeError::new(
+                        format!(
+                            "`{name}` takes `self`, call it on an instance of `{}`",
+                            class.name
+                        ),
+                        span,
+                    ));
+                }
+
+                self.push_frame(id, None, args, span)
+            }
+            Value::Module(path) => {
+                let member = ops::module_member(self.host, path, name, span)?;
+                self.invoke(member, args, span)
+            }
+            Value::Handle(_)
+            | Value::Str(_)
+            | Value::List(_)
+            | Value::Map(_)
+            | Value::Set(_)
+            | Value::Range(..)
+            | Value::Error(_) => {
+                let (native, args) = ops::native_method(&receiver, name, args, span)?;
+                self.invoke(native, args, span)
+            }
+            Value::Coroutine(coroutine) => {
+                coroutine::call_method(self, coroutine, name, args, span).map(Some)
+            }
+            receiver => Err(ops::no_method(name, receiver, span)),
+        }
+    }
+
+    /// Calls a method and runs it to completion, used when the runtime itself calls
+    /// into user code like `eq` or `to_string`
+    fn call_method(
+        &mut self,
+        receiver: Value,
+        name: &str,
+        args: Vec<Value>,
+        span: Span,
+    ) -> Result<Value, RuntimeError> {
+        match self.invoke_method(receiver, name, args, span)? {
+            Some(value) => Ok(value),
+            None => limits::grow(|| self.execute()),
+        }
+    }
+
+    /// Runs the frame on top until it returns, cleaning up after errors
+    fn execute(&mut self) -> Result<Value, RuntimeError> {
+        let stop = self.frames.len() - 1;
+        let frame = &self.frames[stop];
+        let (base, iters) = (frame.base, frame.iters);
+
+        let mut result = self.run(stop);
+
+        if let Err(err) = &mut result {
+            // the innermost `execute` records the whole stack, outer ones pass it on
+            if err.trace.is_empty() {
+                let calls = self.frames.iter().map(|frame| {
+                    let function = &self.program.functions[frame.func];
+                    (function.name.as_str(), frame.span)
+                });
+                err.trace = ops::trace(calls, err.span);
+            }
+
+            self.frames.truncate(stop);
+            self.stack.truncate(base);
+            self.iters.truncate(iters);
+        }
+        result
+    }
+
+    //END calls
+
+    //BEGIN execution
+
+    fn run(&mut self, stop: usize) -> Result<Value, RuntimeError> {
+        let program = self.program;
+
+        loop {
+            let frame = self.frames.last_mut().expect("frame should exist");
+            let chunk = &program.functions[frame.func].chunk;
+            let instr = chunk.code[frame.ip];
+            let span = chunk.spans[frame.ip];
+            frame.ip += 1;
+            self.budget.tick(span)?;
+
+            match instr {
+                Instr::Const(idx) => {
+                    let value = match &chunk.consts[idx as usize] {
+                        Constant::Int(value) => Value::Int(*value),
+                        Constant::Float(value) => Value::Float(*value),
+                        Constant::Str(value) => Value::Str(value.clone()),
+                    };
+                    self.stack.push(value);
+                }
+                Instr::Nil => self.stack.push(Value::Nil),
+                Instr::True => self.stack.push(Value::Bool(true)),
+                Instr::False => self.stack.push(Value::Bool(false)),
+                Instr::Pop => {
+                    self.pop();
+                }
+                Instr::PopN(n) => {
+                    let len = self.stack.len() - usize::from(n);
+                    self.stack.truncate(len);
+                }
+                Instr::Slide(n) => {
+                    let top = self.pop();
+                    let len = self.stack.len() - usize::from(n);
+                    self.stack.truncate(len);
+                    self.stack.push(top);
+                }
+                Instr::Dup => {
+                    let top = self
+                        .stack
+                        .last()
+                        .expect("stack should not be empty")
+                        .clone();
+                    self.stack.push(top);
+                }
+                Instr::Dup2 => {
+                    let len = self.stack.len();
+                    self.stack.extend_from_within(len - 2..);
+                }
+                Instr::Swap => {
+                    let len = self.stack.len();
+                    self.stack.swap(len - 1, len - 2);
+                }
+                Instr::Rot => {
+                    let len = self.stack.len();
+                    self.stack[len - 3..].rotate_left(1);
+                }
+                Instr::GetLocal(slot) => {
+                    let frame = self.frames.last().expect("frame should exist");
+                    let Some(value) = frame.locals[usize::from(slot)].clone() else {
+                        let name = &program.functions[frame.func].locals[usize::from(slot)];
+                        let message = format!("`{name}` is used before being assigned");
+                        return Err(RuntimeError::new(message, span));
+                    };
+                    self.stack.push(value);
+                }
+                Instr::SetLocal(slot) => {
+                    let value = self.pop();
+                    let frame = self.frames.last_mut().expect("frame should exist");
+                    frame.locals[usize::from(slot)] = Some(value);
+                }
+                Instr::Fn(id) => self.stack.push(Value::Fn(id)),
+                Instr::Class(id) => self.stack.push(Value::Class(self.classes[&id].clone())),
+                Instr::Import(idx) => {
+                    let path = chunk.str(idx);
+                    let value = self.host.lookup(path).ok_or_else(|| {
+                        RuntimeError::new(format!("unresolved import `{path}`"), span)
+                    })?;
+                    self.stack.push(value);
+                }
+                Instr::GetField(name) => {
+                    let base = self.pop();
+                    let value = ops::get_field(self.host, &base, chunk.str(name), span)?;
+                    self.stack.push(value);
+                }
+                Instr::SetField(name) => {
+                    let value = self.pop();
+                    let base = self.pop();
+                    ops::set_field(&base, chunk.str(name), value, span)?;
+                }
+                Instr::Unary(op) => {
+                    let value = self.pop();
+                    self.stack.push(ops::unary(op, value, span)?);
+                }
+                Instr::Binary(op) => {
+                    let rhs = self.pop();
+                    let lhs = self.pop();
+                    let value = self.binary(op, lhs, rhs, span)?;
+                    self.stack.push(value);
+                }
+                Instr::ExpectInt => match self.stack.last() {
+                    Some(Value::Int(_)) => {}
+                    value => {
+                        let ty = value.map(Value::type_name).unwrap_or_default();
+                        let message = format!("expected `int`, found `{ty}`");
+                        return Err(RuntimeError::new(message, span));
+                    }
+                },
+                Instr::Range(closed) => {
+                    let (Value::Int(end), Value::Int(start)) = (self.pop(), self.pop()) else {
+                        unreachable!("range bounds are checked by `ExpectInt`");
+                    };
+                    self.stack.push(ops::range(start, end, closed));
+                }
+                Instr::List(n) => {
+                    let items = self.stack.split_off(self.stack.len() - usize::from(n));
+                    self.stack
+                        .push(limits::track(self.heap, Value::list(items), span)?);
+                }
+                Instr::Map(n) => {
+                    let values = self.stack.split_off(self.stack.len() - 2 * usize::from(n));
+                    self.stack.push(ops::map(self.heap, values, span)?);
+                }
+                Instr::Set(n) => {
+                    let items = self.stack.split_off(self.stack.len() - usize::from(n));
+                    self.stack.push(ops::set(self.heap, items, span)?);
+                }
+                Instr::GetIndex => {
+                    let index = self.pop();
+                    let base = self.pop();
+                    self.stack.push(ops::index(self.heap, &base, &index, span)?);
+                }
+                Instr::SetIndex => {
+                    let value = self.pop();
+                    let index = self.pop();
+                    let base = self.pop();
+                    ops::set_index(self.heap, &base, &index, value, span)?;
+                }
+                Instr::Jump(target) => {
+                    self.frames.last_mut().expect("frame should exist").ip = target as usize;
+                }
+                Instr::JumpIfFalse(target) => match self.pop() {
+                    Value::Bool(true) => {}
+                    Value::Bool(false) => {
+                        self.frames.last_mut().expect("frame should exist").ip = target as usize;
+                    }
+                    value => {
+                        let message = format!("expected `bool`, found `{}`", value.type_name());
+                        return Err(RuntimeError::new(message, span));
+                    }
+                },
+                Instr::Call(argc) => {
+                    let args = self.stack.split_off(self.stack.len() - usize::from(argc));
+                    let callee = self.pop();
+
+                    if let Some(value) = self.invoke(callee, args, span)? {
+                        self.stack.push(value);
+                    }
+                }
+                Instr::CallMethod(name, argc) => {
+                    let args = self.stack.split_off(self.stack.len() - usize::from(argc));
+                    let receiver = self.pop();
+
+                    if let Some(value) =
+                        self.invoke_method(receiver, chunk.str(name), args, span)?
+                    {
+                        self.stack.push(value);
+                    }
+                }
+                Instr::Return => {
+                    let value = self.pop();
+                    let frame = self.frames.pop().expect("frame should exist");
+                    self.stack.truncate(frame.base);
+                    self.iters.truncate(frame.iters);
+
+                    if self.frames.len() == stop {
+                        return Ok(value);
+                    }
+                    self.stack.push(value);
+                }
+                Instr::NewInstance(id) => {
+                    let class = self.classes[&id].clone();
+                    let fields = vec![Value::Nil; class.fields.len()];
+                    let instance = self
+                        .heap
+                        .alloc(class, fields)
+                        .ok_or_else(|| limits::out_of_memory(self.heap.limit(), span))?;
+                    self.stack.push(Value::Instance(instance));
+                }
+                Instr::InitField(idx) => {
+                    let value = self.pop();
+                    let Some(Value::Instance(instance)) = self.stack.last() else {
+                        unreachable!("fields are initialized right after `NewInstance`");
+                    };
+                    instance.fields.borrow_mut()[usize::from(idx)] = value;
+                }
+                Instr::IterInit => {
+                    let value = self.pop();
+                    self.iters.push(Iter::new(value, span)?);
+                }
+                Instr::IterNext(target) => {
+                    // taken off while `next` runs, user code may start loops of its own
+                    let mut iter = self.iters.pop().expect("iterator should exist");
+                    let item = self.next_item(&mut iter, span);
+                    self.iters.push(iter);
+
+                    match item? {
+                        Some(item) => self.stack.push(item),
+                        None => {
+                            let frame = self.frames.last_mut().expect("frame should exist");
+                            frame.ip = target as usize;
+                        }
+                    }
+                }
+                Instr::IterDrop => {
+                    self.iters.pop();
+                }
+                Instr::Panic(has_message) => {
+                    let message = match has_message {
+                        true => {
+                            let value = self.pop();
+                            Some(self.display_at(&value, span)?)
+                        }
+                        false => None,
+                    };
+                    return Err(RuntimeError::new(ops::panic_message(message), span));
+                }
+                Instr::Error(message) => {
+                    return Err(RuntimeError::new(chunk.str(message), span));
+                }
+                Instr::Try(target) => {
+                    if !matches!(self.stack.last(), Some(Value::Error(_))) {
+                        self.frames.last_mut().expect("frame should exist").ip = target as usize;
+                    }
+                }
+                Instr::Escape => {
+                    let frame = self.frames.last().expect("frame should exist");
+                    let message = "`break` or `continue` outside of a loop";
+                    return Err(RuntimeError::new(message, frame.span));
+                }
+                Instr::Yield => {
+                    let value = self.pop();
+                    self.suspend();
+                    return Ok(value);
+                }
+                Instr::Await => {
+                    let input = self.pop();
+                    let awaited = self
+                        .stack
+                        .last()
+                        .expect("stack should not be empty")
+                        .clone();
+
+                    match coroutine::poll_await(self, &awaited, input, span)? {
+                        Awaited::Ready(value) => {
+                            self.pop();
+                            self.stack.push(value);
+                        }
+                        // resumed at the `await` again, polling the same value
+                        Awaited::Pending(future) => {
+                            self.frames.last_mut().expect("frame should exist").ip -= 1;
+                            self.suspend();
+                            return Ok(future);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("stack should not be empty")
+    }
+
+    // takes the coroutine frame on top off the stacks, `yield` and `await` only appear in
+    // coroutine fns so this is the frame `resume` runs
+    fn suspend(&mut self) {
+        let frame = self.frames.pop().expect("frame should exist");
+        let stack = self.stack.split_off(frame.base);
+        let iters = self.iters.split_off(frame.iters);
+        self.suspended = Some(Suspended {
+            frame,
+            stack,
+            iters,
+        });
+    }
+
+    //END execution
+
+    //BEGIN operators
+
+    fn binary(
+        &mut self,
+        op: BinOp,
+        lhs: Value,
+        rhs: Value,
+        span: Span,
+    ) -> Result<Value, RuntimeError> {
+        match op {
+            BinOp::Eq => Ok(Value::Bool(self.values_eq(&lhs, &rhs, span)?)),
+            BinOp::NotEq => Ok(Value::Bool(!self.values_eq(&lhs, &rhs, span)?)),
+            _ => {
+                let value = ops::binary(op, &lhs, &rhs, span)?;
+                limits::check_string(self.heap, value, span)
+            }
+        }
+    }
+
+    /// `==`, calling `eq` on instances implementing `Eq`
+    fn values_eq(&mut self, lhs: &Value, rhs: &Value, span: Span) -> Result<bool, RuntimeError> {
+        if ops::has_user_eq(lhs) {
+            let result = self.call_method(lhs.clone(), "eq", vec![rhs.clone()], span)?;
+            return ops::expect_eq_result(result, span);
+        }
+
+        Ok(lhs == rhs)
+    }
+
+    fn display_at(&mut self, value: &Value, span: Span) -> Result<String, RuntimeError> {
+        if ops::has_user_display(value) {
+            let result = self.call_method(value.clone(), "to_string", Vec::new(), span)?;
+            return Ok(result.to_string());
+        }
+
+        Ok(value.to_string())
+    }
+
+    fn next_item(&mut self, iter: &mut Iter, span: Span) -> Result<Option<Value>, RuntimeError> {
+        match iter {
+            Iter::Items(items) => Ok(items.next()),
+            // `nil` ends the loop
+            Iter::Object(value) => {
+                match self.call_method(value.clone(), "next", Vec::new(), span)? {
+                    Value::Nil => Ok(None),
+                    item => Ok(Some(item)),
+                }
+            }
+            Iter::Coroutine(coroutine) => coroutine::next(self, coroutine, Value::Nil, span),
+        }
+    }
+
+    //END operators
+}
+
+impl NativeContext for Vm<'_> {
+    fn stdout(&mut self) -> &mut dyn Write {
+        self.stdout
+    }
+
+    fn heap(&mut self) -> &mut Heap {
+        self.heap
+    }
+
+    fn error(&self, message: String) -> RuntimeError {
+        RuntimeError::new(message, self.native_span)
+    }
+
+    fn display(&mut self, value: &Value) -> Result<String, RuntimeError> {
+        self.display_at(value, self.native_span)
+    }
+
+    fn executor(&mut self) -> &mut Executor {
+        self.executor
+    }
+
+    fn args(&self) -> &[EcoString] {
+        self.host.args()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.limits.capabilities
+    }
+
+    fn tick(&mut self) -> Result<(), RuntimeError> {
+        self.budget.tick(self.native_span)
+    }
+
+    fn call(
+        &mut self,
+        receiver: Value,
+        method: &str,
+        args: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        self.call_method(receiver, method, args, self.native_span)
+    }
+
+    fn apply(&mut self, callee: Value, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        match self.invoke(callee, args, self.native_span)? {
+            Some(value) => Ok(value),
+            None => limits::grow(|| self.execute()),
+        }
+    }
+}
+
+impl Resume for Vm<'_> {
+    fn resume(
+        &mut self,
+        coroutine: &Rc<Coroutine>,
+        input: Value,
+        span: Span,
+    ) -> Result<Resumed, RuntimeError> {
+        let suspended = match coroutine.take(span)? {
+            CoroutineState::Vm(suspended) => suspended,
+            state => {
+                coroutine.suspend(state);
+                let message = format!("`{}` was created by another backend", coroutine.name);
+                return Err(RuntimeError::new(message, span));
+            }
+        };
+
+        if self.frames.len() == self.limits.call_depth {
+            coroutine.suspend(CoroutineState::Vm(suspended));
+            return Err(limits::stack_overflow(self.limits.call_depth, span));
+        }
+
+        let Suspended {
+            mut frame,
+            stack,
+            iters,
+        } = suspended;
+        frame.base = self.stack.len();
+        frame.iters = self.iters.len();
+        frame.span = span;
+
+        // a call which never ran has no `yield` or `await` waiting for the input
+        let started = frame.ip > 0;
+        self.stack.extend(stack);
+        self.iters.extend(iters);
+        if started {
+            self.stack.push(input);
+        }
+        self.frames.push(frame);
+
+        let result = limits::grow(|| self.execute());
+
+        match (result, self.suspended.take()) {
+            (Ok(value), Some(suspended)) => {
+                coroutine.suspend(CoroutineState::Vm(suspended));
+                Ok(Resumed::Yield(value))
+            }
+            (Ok(value), None) => {
+                coroutine.finish();
+                Ok(Resumed::Return(value))
+            }
+            (Err(err), _) => {
+                coroutine.finish();
+                Err(err)
+            }
+        }
+    }
+
+    fn budget(&self) -> &Budget {
+        &self.budget
+    }
+}
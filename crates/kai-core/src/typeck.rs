@@ -0,0 +1,2660 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use ecow::EcoString;
+
+use crate::ast::span::Span;
+use crate::ast::*;
+use crate::diagnostic::Diagnostic;
+use crate::resolve::{BuiltinBound, BuiltinInterface, PrimTy, Res, Resolutions};
+use crate::runtime::stdlib::{self, NativeSig};
+
+/// A checked type. Unannotated parameters and return types are `Unknown`, which is
+/// compatible with everything, so only annotated code is checked strictly.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Int,
+    Float,
+    String,
+    Bool,
+    Nil,
+    List(Box<Type>),
+    Map(Box<Type>, Box<Type>),
+    Set(Box<Type>),
+    // either a value of the inner type or an `error`, unwrapped with `?`
+    Result(Box<Type>),
+    Error,
+    // the value of `1..10`
+    Range,
+    // what calling an `async fn` returns, `await` gives the inner type
+    Future(Box<Type>),
+    // what calling a fn containing `yield` returns, the inner type is the yielded one
+    Generator(Box<Type>),
+    // `int?`, either a value of the inner type or `nil`
+    Optional(Box<Type>),
+    Fn(FnSig),
+    // an instance of a class, `args` are the type arguments of a generic class
+    Class {
+        id: NodeId,
+        name: EcoString,
+        args: Vec<Type>,
+    },
+    // the class itself, eg. `Person` in `Person.new()`
+    ClassObject {
+        id: NodeId,
+        name: EcoString,
+    },
+    // any instance of a class implementing the interface, methods are dispatched at runtime
+    Interface {
+        id: InterfaceId,
+        name: EcoString,
+    },
+    // a generic parameter inside the item declaring it
+    Param {
+        id: NodeId,
+        name: EcoString,
+    },
+    // type of expressions which never produce a value, `return`, `panic` ..
+    Never,
+    Unknown,
+    // an inference variable
+    Var(u32),
+}
+
+/// An interface declared with `interface` or one the runtime knows about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InterfaceId {
+    Item(NodeId),
+    Builtin(BuiltinInterface),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FnSig {
+    pub params: Vec<Type>,
+    pub ret: Box<Type>,
+}
+
+impl Type {
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, Type::Int | Type::Float)
+    }
+
+    /// `self` or `nil`, types which can already be `nil` stay as they are
+    pub fn optional(self) -> Type {
+        match self {
+            Type::Optional(_) | Type::Nil | Type::Unknown => self,
+            ty => Type::Optional(Box::new(ty)),
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::String => write!(f, "string"),
+            Type::Bool => write!(f, "bool"),
+            Type::Nil => write!(f, "nil"),
+            Type::List(elem) => write!(f, "list<{elem}>"),
+            Type::Map(key, value) => write!(f, "map<{key}, {value}>"),
+            Type::Set(elem) => write!(f, "set<{elem}>"),
+            Type::Result(ok) => write!(f, "result<{ok}>"),
+            Type::Error => write!(f, "error"),
+            Type::Range => write!(f, "range"),
+            Type::Future(output) => write!(f, "future<{output}>"),
+            Type::Generator(item) => write!(f, "generator<{item}>"),
+            Type::Optional(inner) => write!(f, "{inner}?"),
+            Type::Fn(sig) => {
+                write!(f, "fn(")?;
+                for (i, param) in sig.params.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{param}")?;
+                }
+                write!(f, ") -> {}", sig.ret)
+            }
+            Type::Class { name, args, .. } => {
+                write!(f, "{name}")?;
+                if !args.is_empty() {
+                    write!(f, "<")?;
+                    for (i, arg) in args.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ", ")?;
+                        }
+                        write!(f, "{arg}")?;
+                    }
+                    write!(f, ">")?;
+                }
+                Ok(())
+            }
+            Type::Param { name, .. } | Type::Interface { name, .. } => write!(f, "{name}"),
+            Type::ClassObject { name, .. } => write!(f, "class {name}"),
+            Type::Never => write!(f, "never"),
+            Type::Unknown | Type::Var(_) => write!(f, "_"),
+        }
+    }
+}
+
+/// What a generic parameter requires of its type arguments
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Builtin(BuiltinBound),
+    // the argument implements the interface, its methods can be called on the parameter
+    Interface(InterfaceId),
+}
+
+#[derive(Debug, Clone)]
+pub struct GenericParamInfo {
+    pub id: NodeId,
+    pub name: EcoString,
+    pub bounds: Vec<Bound>,
+}
+
+/// Signature of a fn item or a method, `sig` mentions the generics as `Type::Param`
+#[derive(Debug, Clone)]
+pub struct Signature {
+    pub generics: Vec<GenericParamInfo>,
+    pub has_self: bool,
+    pub sig: FnSig,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClassInfo {
+    pub name: EcoString,
+    pub generics: Vec<GenericParamInfo>,
+    pub fields: Vec<(String, Type)>,
+    pub methods: HashMap<String, Signature>,
+}
+
+impl ClassInfo {
+    pub fn field(&self, name: &str) -> Option<&Type> {
+        self.fields
+            .iter()
+            .find_map(|(field, ty)| (field == name).then_some(ty))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InterfaceInfo {
+    pub name: EcoString,
+    pub methods: HashMap<String, Signature>,
+    // methods with a body, implementations may leave these out
+    pub defaults: Vec<String>,
+}
+
+impl InterfaceInfo {
+    fn builtin(iface: BuiltinInterface) -> Self {
+        let (params, ret) = match iface {
+            BuiltinInterface::Display => (vec![], Type::String),
+            BuiltinInterface::Eq => (vec![Type::Unknown], Type::Bool),
+            // returns `nil` once exhausted, so the item type can't be named
+            BuiltinInterface::Iterator => (vec![], Type::Unknown),
+            // any value `kai.json` can encode
+            BuiltinInterface::Serialize => (vec![], Type::Unknown),
+        };
+
+        let sig = Signature {
+            generics: Vec::new(),
+            has_self: true,
+            sig: FnSig {
+                params,
+                ret: Box::new(ret),
+            },
+        };
+
+        Self {
+            name: iface.name().into(),
+            methods: HashMap::from([(iface.method().to_owned(), sig)]),
+            defaults: Vec::new(),
+        }
+    }
+}
+
+/// The output of type checking, a side table of expression types keyed by node id
+#[derive(Debug, Default)]
+pub struct TypeckResults {
+    types: HashMap<NodeId, Type>,
+    pub classes: HashMap<NodeId, ClassInfo>,
+    pub interfaces: HashMap<InterfaceId, InterfaceInfo>,
+    // class id -> the interfaces it implements
+    pub impls: HashMap<NodeId, Vec<InterfaceId>>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl TypeckResults {
+    pub fn implements(&self, class: NodeId, iface: InterfaceId) -> bool {
+        self.impls
+            .get(&class)
+            .is_some_and(|impls| impls.contains(&iface))
+    }
+
+    pub fn expr_ty(&self, id: NodeId) -> Option<&Type> {
+        self.types.get(&id)
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(Diagnostic::is_error)
+    }
+}
+
+pub fn check(file: &File, res: &Resolutions) -> TypeckResults {
+    let mut checker = Checker::new(res);
+    checker.check_file(file);
+    checker.finish()
+}
+
+pub struct Checker<'a> {
+    res: &'a Resolutions,
+    out: TypeckResults,
+    fns: HashMap<NodeId, Signature>,
+    locals: HashMap<NodeId, Type>,
+    // impl item id -> the class it is for
+    impl_classes: HashMap<NodeId, NodeId>,
+    param_bounds: HashMap<NodeId, Vec<Bound>>,
+    // bounds on inferred type arguments, checked once inference is done
+    obligations: Vec<Obligation>,
+    // inference variables, `None` until unified with something
+    vars: Vec<Option<Type>>,
+    // return type of the function being checked
+    ret_stack: Vec<Type>,
+    // type of the values the generator being checked yields
+    yield_ty: Option<Type>,
+    // type of `break` values of the enclosing loops, `None` if the loop has no `break`
+    loop_stack: Vec<Option<Type>>,
+    // full path -> type of the values an embedder provides, eg. `myapp.db.query`
+    externs: HashMap<String, Type>,
+}
+
+impl<'a> Checker<'a> {
+    pub fn new(res: &'a Resolutions) -> Self {
+        Self {
+            res,
+            out: TypeckResults::default(),
+            fns: HashMap::new(),
+            locals: HashMap::new(),
+            impl_classes: HashMap::new(),
+            param_bounds: HashMap::new(),
+            obligations: Vec::new(),
+            vars: Vec::new(),
+            ret_stack: Vec::new(),
+            yield_ty: None,
+            loop_stack: Vec::new(),
+            externs: HashMap::new(),
+        }
+    }
+
+    /// Declares the type of a value an embedder provides under `path`
+    pub fn add_extern(&mut self, path: &str, ty: Type) {
+        self.externs.insert(path.to_owned(), ty);
+    }
+
+    pub fn check_file(&mut self, file: &File) {
+        let items = file.all_items();
+
+        for iface in BuiltinInterface::ALL {
+            let info = InterfaceInfo::builtin(iface);
+            self.out
+                .interfaces
+                .insert(InterfaceId::Builtin(iface), info);
+        }
+
+        // class and interface names first so signatures can refer to any of them,
+        // interfaces before classes whose parameters they bound
+        for item in &items {
+            if let ItemKind::Interface(item_interface) = &item.kind {
+                let info = InterfaceInfo {
+                    name: item_interface.ident.name.as_str().into(),
+                    methods: HashMap::new(),
+                    defaults: Vec::new(),
+                };
+                self.out.interfaces.insert(InterfaceId::Item(item.id), info);
+            }
+        }
+        for item in &items {
+            if let ItemKind::Class(item_class) = &item.kind {
+                let info = ClassInfo {
+                    name: item_class.ident.name.as_str().into(),
+                    generics: self.lower_generics(&item_class.generics),
+                    fields: Vec::new(),
+                    methods: HashMap::new(),
+                };
+                self.out.classes.insert(item.id, info);
+            }
+        }
+
+        for item in &items {
+            match &item.kind {
+                ItemKind::Fn(item_fn) => {
+                    let sig = self.lower_signature(item_fn);
+                    self.fns.insert(item.id, sig);
+                }
+                ItemKind::Class(item_class) => self.collect_class(item.id, item_class),
+                ItemKind::Interface(item_interface) => {
+                    self.collect_interface(item.id, item_interface)
+                }
+                ItemKind::Use(_) | ItemKind::Impl(_) => {}
+            }
+        }
+
+        // impls add their methods to the class, so every class must be collected first
+        for item in &items {
+            if let ItemKind::Impl(item_impl) = &item.kind {
+                self.collect_impl(item.id, item_impl);
+            }
+        }
+
+        for item in &items {
+            let this = match &item.kind {
+                ItemKind::Fn(item_fn) => {
+                    self.check_fn(item.id, item_fn, None);
+                    continue;
+                }
+                ItemKind::Class(_) => Some(self.class_ty(item.id)),
+                ItemKind::Interface(item_interface) => Some(Type::Interface {
+                    id: InterfaceId::Item(item.id),
+                    name: item_interface.ident.name.as_str().into(),
+                }),
+                ItemKind::Impl(_) => self
+                    .impl_classes
+                    .get(&item.id)
+                    .map(|class| self.class_ty(*class)),
+                ItemKind::Use(_) => continue,
+            };
+
+            for method in item.kind.methods() {
+                if let ItemKind::Fn(item_fn) = &method.kind {
+                    self.check_fn(method.id, item_fn, this.clone());
+                }
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> TypeckResults {
+        for obligation in std::mem::take(&mut self.obligations) {
+            let ty = self.resolve_ty(&obligation.ty);
+
+            if !matches!(ty, Type::Var(_) | Type::Unknown) && !self.satisfies(&ty, obligation.bound)
+            {
+                let bound = self.bound_name(obligation.bound);
+                self.error(
+                    format!(
+                        "`{ty}` does not satisfy the bound `{bound}` required by `{}`",
+                        obligation.param
+                    ),
+                    obligation.span,
+                );
+            }
+        }
+
+        let types = std::mem::take(&mut self.out.types);
+        self.out.types = types
+            .into_iter()
+            .map(|(id, ty)| (id, self.resolve_ty(&ty)))
+            .collect();
+
+        self.out.diagnostics.sort_by_key(|d| d.span.start);
+        self.out
+    }
+
+    //BEGIN signatures
+
+    fn collect_class(&mut self, id: NodeId, item_class: &ItemClass) {
+        let fields = item_class
+            .fields
+            .iter()
+            .map(|field| (field.ident.name.clone(), self.lower_ty(&field.ty)))
+            .collect();
+
+        let mut methods = HashMap::new();
+
+        for method in &item_class.methods {
+            if let ItemKind::Fn(item_fn) = &method.kind {
+                let sig = self.lower_signature(item_fn);
+                self.fns.insert(method.id, sig.clone());
+                methods.insert(item_fn.ident.name.clone(), sig);
+            }
+        }
+
+        let info = self
+            .out
+            .classes
+            .get_mut(&id)
+            .expect("class should be registered");
+        info.fields = fields;
+        info.methods = methods;
+    }
+
+    fn collect_interface(&mut self, id: NodeId, item_interface: &ItemInterface) {
+        let mut methods = HashMap::new();
+        let mut defaults = Vec::new();
+
+        for method in &item_interface.methods {
+            let ItemKind::Fn(item_fn) = &method.kind else {
+                continue;
+            };
+
+            let sig = self.lower_signature(item_fn);
+            self.fns.insert(method.id, sig.clone());
+
+            if !sig.has_self {
+                self.error(
+                    format!("interface method `{}` must take `self`", item_fn.ident.name),
+                    item_fn.ident.span,
+                );
+            }
+
+            if item_fn.body.is_some() {
+                defaults.push(item_fn.ident.name.clone());
+            }
+            methods.insert(item_fn.ident.name.clone(), sig);
+        }
+
+        let info = self
+            .out
+            .interfaces
+            .get_mut(&InterfaceId::Item(id))
+            .expect("interface should be registered");
+        info.methods = methods;
+        info.defaults = defaults;
+    }
+
+    fn collect_impl(&mut self, id: NodeId, item_impl: &ItemImpl) {
+        for method in &item_impl.methods {
+            if let ItemKind::Fn(item_fn) = &method.kind {
+                let sig = self.lower_signature(item_fn);
+                self.fns.insert(method.id, sig);
+            }
+        }
+
+        let class = match self.res.get(item_impl.class.id) {
+            Some(Res::Item(class)) if self.out.classes.contains_key(&class) => class,
+            // already reported by the resolver
+            None => return,
+            Some(_) => {
+                self.error("expected a class", item_impl.class.span);
+                return;
+            }
+        };
+        self.impl_classes.insert(id, class);
+
+        let iface = match self.res.get(item_impl.interface.id) {
+            Some(Res::Item(iface))
+                if self.out.interfaces.contains_key(&InterfaceId::Item(iface)) =>
+            {
+                InterfaceId::Item(iface)
+            }
+            Some(Res::BuiltinInterface(builtin)) => InterfaceId::Builtin(builtin),
+            None => return,
+            Some(_) => {
+                self.error("expected an interface", item_impl.interface.span);
+                return;
+            }
+        };
+
+        let info = self.out.interfaces[&iface].clone();
+        let class_name = self.out.classes[&class].name.clone();
+        let iface_name = &info.name;
+
+        if self.out.implements(class, iface) {
+            self.error(
+                format!("`{iface_name}` is already implemented for `{class_name}`"),
+                item_impl.interface.span,
+            );
+            return;
+        }
+        self.out.impls.entry(class).or_default().push(iface);
+
+        let mut implemented = Vec::new();
+
+        for method in &item_impl.methods {
+            let ItemKind::Fn(item_fn) = &method.kind else {
+                continue;
+            };
+
+            let name = &item_fn.ident.name;
+            let sig = self.fns[&method.id].clone();
+
+            match info.methods.get(name) {
+                Some(expected) => {
+                    let matches = expected.has_self == sig.has_self
+                        && self.unify(&Type::Fn(expected.sig.clone()), &Type::Fn(sig.sig.clone()));
+
+                    if !matches {
+                        self.error(
+                            format!(
+                                "method `{name}` has an incompatible signature for interface `{iface_name}`: expected `{}`, found `{}`",
+                                Type::Fn(expected.sig.clone()),
+                                Type::Fn(sig.sig.clone()),
+                            ),
+                            item_fn.ident.span,
+                        );
+                    }
+                }
+                None => self.error(
+                    format!("method `{name}` is not a member of interface `{iface_name}`"),
+                    item_fn.ident.span,
+                ),
+            }
+
+            self.add_method(class, &item_fn.ident, sig);
+            implemented.push(name.as_str());
+        }
+
+        let mut missing: Vec<&String> = info
+            .methods
+            .keys()
+            .filter(|name| !implemented.contains(&name.as_str()) && !info.defaults.contains(name))
+            .collect();
+        missing.sort();
+
+        if !missing.is_empty() {
+            let names: Vec<String> = missing.iter().map(|name| format!("`{name}`")).collect();
+            self.error(
+                format!(
+                    "missing method{} {} in implementation of `{iface_name}` for `{class_name}`",
+                    if names.len() == 1 { "" } else { "s" },
+                    names.join(", ")
+                ),
+                item_impl.interface.span,
+            );
+        }
+
+        // default methods which were not overridden belong to the class too
+        for name in &info.defaults {
+            if !implemented.contains(&name.as_str()) {
+                let class = self.out.classes.get_mut(&class).unwrap();
+                class
+                    .methods
+                    .entry(name.clone())
+                    .or_insert_with(|| info.methods[name].clone());
+            }
+        }
+    }
+
+    // methods from impls can be called on the class like its own
+    fn add_method(&mut self, class: NodeId, ident: &Ident, sig: Signature) {
+        let info = self.out.classes.get_mut(&class).unwrap();
+
+        if info.methods.contains_key(&ident.name) {
+            let message = format!(
+                "`{}` already has a method named `{}`",
+                info.name, ident.name
+            );
+            self.error(message, ident.span);
+        } else {
+            info.methods.insert(ident.name.clone(), sig);
+        }
+    }
+
+    fn lower_signature(&mut self, item_fn: &ItemFn) -> Signature {
+        let mut sig = self.lower_sig(item_fn);
+
+        // the declared type is what awaiting the call gives
+        match item_fn.coroutine() {
+            Some(CoroutineKind::Async) => sig.ret = Box::new(Type::Future(sig.ret)),
+            Some(CoroutineKind::Generator) => match *sig.ret {
+                Type::Generator(_) => {}
+                Type::Unknown => sig.ret = Box::new(Type::Generator(Box::new(Type::Unknown))),
+                ref ret => {
+                    let message = format!(
+                        "`{}` contains `yield`, its return type must be a `generator`, not `{ret}`",
+                        item_fn.ident.name
+                    );
+                    self.error(message, item_fn.ident.span);
+                }
+            },
+            None => {}
+        }
+
+        Signature {
+            generics: self.lower_generics(&item_fn.generics),
+            has_self: item_fn.has_self(),
+            sig,
+        }
+    }
+
+    fn lower_generics(&mut self, generics: &Generics) -> Vec<GenericParamInfo> {
+        let mut lowered = Vec::new();
+
+        for param in &generics.params {
+            let mut bounds = Vec::new();
+
+            for bound in &param.bounds {
+                match self.res.get(bound.id) {
+                    Some(Res::Bound(builtin)) => bounds.push(Bound::Builtin(builtin)),
+                    Some(Res::Item(id))
+                        if self.out.interfaces.contains_key(&InterfaceId::Item(id)) =>
+                    {
+                        bounds.push(Bound::Interface(InterfaceId::Item(id)))
+                    }
+                    Some(Res::BuiltinInterface(builtin)) => {
+                        bounds.push(Bound::Interface(InterfaceId::Builtin(builtin)))
+                    }
+                    Some(_) => self.error(
+                        "expected a bound like `Num`, `Ord` or an interface",
+                        bound.span,
+                    ),
+                    // already reported by the resolver
+                    None => {}
+                }
+            }
+
+            self.param_bounds.insert(param.id, bounds.clone());
+            lowered.push(GenericParamInfo {
+                id: param.id,
+                name: param.ident.name.as_str().into(),
+                bounds,
+            });
+        }
+
+        lowered
+    }
+
+    // parameter types without `self`, unannotated types are unknown
+    fn lower_sig(&mut self, item_fn: &ItemFn) -> FnSig {
+        let params = item_fn
+            .inputs
+            .iter()
+            .filter(|param| !param.is_self())
+            .map(|param| match &param.ty {
+                Some(ty) => self.lower_ty(ty),
+                None => Type::Unknown,
+            })
+            .collect();
+
+        let ret = match &item_fn.output {
+            Some(ty) => self.lower_ty(ty),
+            None => Type::Unknown,
+        };
+
+        FnSig {
+            params,
+            ret: Box::new(ret),
+        }
+    }
+
+    fn lower_ty(&mut self, ty: &Ty) -> Type {
+        match &ty.kind {
+            TyKind::Optional(inner) => self.lower_ty(inner).optional(),
+            TyKind::Fn(inputs, output) => {
+                let params = inputs.iter().map(|ty| self.lower_ty(ty)).collect();
+                let ret = match output {
+                    Some(ty) => self.lower_ty(ty),
+                    None => Type::Nil,
+                };
+
+                Type::Fn(FnSig {
+                    params,
+                    ret: Box::new(ret),
+                })
+            }
+            TyKind::Path(path, args) => {
+                let args: Vec<Type> = args.iter().map(|ty| self.lower_ty(ty)).collect();
+                let name = path.segments.last().map(|i| i.name.as_str()).unwrap_or("");
+
+                let (mut lowered, arity) = match self.res.get(ty.id) {
+                    Some(Res::PrimTy(prim)) => match prim {
+                        PrimTy::Int => (Type::Int, 0),
+                        PrimTy::Float => (Type::Float, 0),
+                        PrimTy::String => (Type::String, 0),
+                        PrimTy::Bool => (Type::Bool, 0),
+                        PrimTy::Nil => (Type::Nil, 0),
+                        PrimTy::List => {
+                            let elem = args.first().cloned().unwrap_or(Type::Unknown);
+                            (Type::List(Box::new(elem)), 1)
+                        }
+                        PrimTy::Map => {
+                            let key = args.first().cloned().unwrap_or(Type::Unknown);
+                            let value = args.get(1).cloned().unwrap_or(Type::Unknown);
+                            (Type::Map(Box::new(key), Box::new(value)), 2)
+                        }
+                        PrimTy::Set => {
+                            let elem = args.first().cloned().unwrap_or(Type::Unknown);
+                            (Type::Set(Box::new(elem)), 1)
+                        }
+                        PrimTy::Result => {
+                            let ok = args.first().cloned().unwrap_or(Type::Unknown);
+                            (Type::Result(Box::new(ok)), 1)
+                        }
+                        PrimTy::Error => (Type::Error, 0),
+                        PrimTy::Future => {
+                            let output = args.first().cloned().unwrap_or(Type::Unknown);
+                            (Type::Future(Box::new(output)), 1)
+                        }
+                        PrimTy::Generator => {
+                            let item = args.first().cloned().unwrap_or(Type::Unknown);
+                            (Type::Generator(Box::new(item)), 1)
+                        }
+                    },
+                    Some(Res::Item(id)) if self.out.classes.contains_key(&id) => {
+                        let class = &self.out.classes[&id];
+                        let class_ty = Type::Class {
+                            id,
+                            name: class.name.clone(),
+                            args: Vec::new(),
+                        };
+                        (class_ty, class.generics.len())
+                    }
+                    Some(Res::Item(id))
+                        if self.out.interfaces.contains_key(&InterfaceId::Item(id)) =>
+                    {
+                        let iface = InterfaceId::Item(id);
+                        let name = self.out.interfaces[&iface].name.clone();
+                        (Type::Interface { id: iface, name }, 0)
+                    }
+                    Some(Res::BuiltinInterface(builtin)) => (
+                        Type::Interface {
+                            id: InterfaceId::Builtin(builtin),
+                            name: builtin.name().into(),
+                        },
+                        0,
+                    ),
+                    Some(Res::TyParam(id)) => (
+                        Type::Param {
+                            id,
+                            name: name.into(),
+                        },
+                        0,
+                    ),
+                    Some(Res::Item(_)) => {
+                        self.error(format!("expected a type, found function `{name}`"), ty.span);
+                        return Type::Unknown;
+                    }
+                    Some(Res::Bound(_)) => {
+                        self.error(format!("expected a type, found bound `{name}`"), ty.span);
+                        return Type::Unknown;
+                    }
+                    // already reported by the resolver, or a type from another module
+                    _ => return Type::Unknown,
+                };
+
+                if args.len() != arity {
+                    self.error(
+                        format!(
+                            "`{name}` expects {arity} type argument{}, found {}",
+                            if arity == 1 { "" } else { "s" },
+                            args.len()
+                        ),
+                        ty.span,
+                    );
+                }
+
+                if let Type::Class {
+                    args: class_args, ..
+                } = &mut lowered
+                {
+                    *class_args = (0..arity)
+                        .map(|i| args.get(i).cloned().unwrap_or(Type::Unknown))
+                        .collect();
+                }
+
+                lowered
+            }
+        }
+    }
+
+    // the type of `self` inside the class, generic over its own parameters
+    fn class_ty(&self, id: NodeId) -> Type {
+        let class = &self.out.classes[&id];
+        let args = class
+            .generics
+            .iter()
+            .map(|param| Type::Param {
+                id: param.id,
+                name: param.name.clone(),
+            })
+            .collect();
+
+        Type::Class {
+            id,
+            name: class.name.clone(),
+            args,
+        }
+    }
+
+    // fresh inference variables for every generic parameter
+    fn instantiate(&mut self, generics: &[GenericParamInfo], span: Span) -> HashMap<NodeId, Type> {
+        let mut map = HashMap::new();
+
+        for param in generics {
+            let var = self.fresh_var();
+
+            for bound in &param.bounds {
+                self.obligations.push(Obligation {
+                    ty: var.clone(),
+                    bound: *bound,
+                    param: param.name.clone(),
+                    span,
+                });
+            }
+
+            map.insert(param.id, var);
+        }
+
+        map
+    }
+
+    // maps the generic parameters of a class to the type arguments of `args`
+    fn class_subst(&self, id: NodeId, args: &[Type]) -> HashMap<NodeId, Type> {
+        self.out.classes[&id]
+            .generics
+            .iter()
+            .zip(args.iter())
+            .map(|(param, arg)| (param.id, arg.clone()))
+            .collect()
+    }
+
+    //END signatures
+
+    //BEGIN checking
+
+    fn check_fn(&mut self, id: NodeId, item_fn: &ItemFn, this: Option<Type>) {
+        let Some(body) = &item_fn.body else {
+            return;
+        };
+
+        let sig = self.fns[&id].sig.clone();
+        let mut params = sig.params.iter();
+
+        for param in &item_fn.inputs {
+            let ty = if param.is_self() {
+                this.clone().unwrap_or(Type::Unknown)
+            } else {
+                params.next().cloned().unwrap_or(Type::Unknown)
+            };
+            self.bind_pattern(&param.pat, ty);
+        }
+
+        // the body of a coroutine returns what the coroutine finishes with
+        let ret = match (item_fn.coroutine(), *sig.ret) {
+            (Some(CoroutineKind::Async), Type::Future(output)) => *output,
+            (Some(CoroutineKind::Generator), Type::Generator(item)) => {
+                self.yield_ty = Some(*item);
+                Type::Unknown
+            }
+            (_, ret) => ret,
+        };
+
+        self.ret_stack.push(ret.clone());
+        let ty = self.check_block(body);
+        self.ret_stack.pop();
+        self.yield_ty = None;
+
+        if !self.unify(&ret, &ty) {
+            let span = match body.stmts.last().map(|stmt| &stmt.kind) {
+                Some(StmtKind::Expr(expr)) => expr.span,
+                _ => body.span,
+            };
+            self.mismatch(&ret, &ty, span);
+        }
+    }
+
+    fn check_block(&mut self, block: &Block) -> Type {
+        let mut ty = Type::Nil;
+        let mut diverges = false;
+
+        for (i, stmt) in block.stmts.iter().enumerate() {
+            let is_last = i + 1 == block.stmts.len();
+
+            match &stmt.kind {
+                StmtKind::Let(local) => self.check_local(local),
+                StmtKind::Item(_) | StmtKind::Empty => {}
+                StmtKind::Semi(expr) => {
+                    let expr_ty = self.check_expr(expr);
+                    if matches!(self.resolve_ty(&expr_ty), Type::Future(_)) {
+                        self.check_discarded_future(expr);
+                    }
+                    diverges |= expr_ty == Type::Never;
+                }
+                StmtKind::Expr(expr) => {
+                    let expr_ty = self.check_expr(expr);
+                    if is_last {
+                        ty = expr_ty;
+                    } else {
+                        diverges |= expr_ty == Type::Never;
+                    }
+                }
+            }
+        }
+
+        if diverges {
+            Type::Never
+        } else {
+            ty
+        }
+    }
+
+    // a future does nothing until it is awaited, `time.sleep(500);` returns at once. Only
+    // the task of `task.spawn` runs without it.
+    fn check_discarded_future(&mut self, expr: &Expr) {
+        let callee = match &expr.kind {
+            ExprKind::Call(callee, _) => self.extern_path(callee),
+            ExprKind::MethodCall(call) => self
+                .extern_path(&call.receiver)
+                .map(|path| format!("{path}.{}", call.method.name)),
+            _ => None,
+        };
+        if callee.as_deref() == Some("kai.task.spawn") {
+            return;
+        }
+
+        let diagnostic = Diagnostic::warning(
+            "this future is never awaited, it does nothing unless it is",
+            expr.span,
+        );
+        self.out.diagnostics.push(diagnostic);
+    }
+
+    fn check_local(&mut self, local: &Local) {
+        let declared = local.ty.as_ref().map(|ty| self.lower_ty(ty));
+
+        let ty = match (declared, &local.init) {
+            (Some(declared), Some(init)) => {
+                self.expect_expr(init, &declared);
+                declared
+            }
+            (Some(declared), None) => declared,
+            (None, Some(init)) => self.check_expr(init),
+            (None, None) => self.fresh_var(),
+        };
+
+        self.bind_pattern(&local.pat, ty);
+    }
+
+    fn bind_pattern(&mut self, pat: &Pattern, ty: Type) {
+        if let PatternKind::Ident { .. } = pat.kind {
+            self.locals.insert(pat.id, ty);
+        }
+    }
+
+    fn expect_expr(&mut self, expr: &Expr, expected: &Type) -> Type {
+        let found = self.check_expr(expr);
+        if !self.unify(expected, &found) {
+            self.mismatch(expected, &found, expr.span);
+        }
+        found
+    }
+
+    pub fn check_expr(&mut self, expr: &Expr) -> Type {
+        let ty = self.check_expr_kind(expr);
+        self.out.types.insert(expr.id, ty.clone());
+        ty
+    }
+
+    fn check_expr_kind(&mut self, expr: &Expr) -> Type {
+        match &expr.kind {
+            ExprKind::Lit(lit) => match lit.kind {
+                LitKind::Int(_) => Type::Int,
+                LitKind::Float(_) => Type::Float,
+                LitKind::Str(_) => Type::String,
+                LitKind::Bool(_) => Type::Bool,
+                LitKind::Nil => Type::Nil,
+            },
+            ExprKind::Path(_) => match self.extern_ty(expr) {
+                Some(ty) => ty,
+                None => self.path_ty(expr.id, expr.span),
+            },
+            ExprKind::Unary(op, operand) => {
+                let ty = self.check_expr(operand);
+                let ty = self.resolve_ty(&ty);
+
+                match (op, &ty) {
+                    (_, Type::Unknown | Type::Var(_)) => ty,
+                    (UnOp::Neg, ty) if ty.is_numeric() => ty.clone(),
+                    (UnOp::Not, Type::Bool) => Type::Bool,
+                    (UnOp::Neg, ty) => {
+                        self.error(format!("cannot negate `{ty}`"), expr.span);
+                        Type::Unknown
+                    }
+                    (UnOp::Not, ty) => {
+                        self.error(format!("cannot apply `!` to `{ty}`"), expr.span);
+                        Type::Unknown
+                    }
+                }
+            }
+            ExprKind::Binary(op, lhs, rhs) => {
+                let lhs_ty = self.check_expr(lhs);
+                let rhs_ty = self.check_expr(rhs);
+                self.binary_ty(*op, &lhs_ty, &rhs_ty, expr.span)
+            }
+            ExprKind::Assign(lhs, rhs) => {
+                let lhs_ty = self.check_place(lhs);
+                self.expect_expr(rhs, &lhs_ty);
+                Type::Nil
+            }
+            ExprKind::AssignOp(op, lhs, rhs) => {
+                let lhs_ty = self.check_place(lhs);
+                let rhs_ty = self.check_expr(rhs);
+                let ty = self.binary_ty(*op, &lhs_ty, &rhs_ty, expr.span);
+
+                if !self.unify(&lhs_ty, &ty) {
+                    self.mismatch(&lhs_ty, &ty, expr.span);
+                }
+                Type::Nil
+            }
+            ExprKind::Call(callee, args) => {
+                let callee_ty = self.check_expr(callee);
+                if let Some(sig) = self.native_sig(callee) {
+                    return self.check_native_args(&sig, args, expr.span);
+                }
+                let callee_ty = self.resolve_ty(&callee_ty);
+
+                match callee_ty {
+                    Type::Fn(sig) => self.check_args(&sig, args, expr.span),
+                    Type::Unknown | Type::Var(_) => {
+                        self.check_exprs(args);
+                        Type::Unknown
+                    }
+                    ty => {
+                        self.check_exprs(args);
+                        self.error(format!("`{ty}` is not callable"), callee.span);
+                        Type::Unknown
+                    }
+                }
+            }
+            ExprKind::MethodCall(call) => self.check_method_call(call),
+            ExprKind::Field(base, ident) => {
+                let base_ty = self.check_expr(base);
+
+                if let Some(ty) = self.extern_ty(expr) {
+                    return ty;
+                }
+                if let Some(module) = self
+                    .extern_path(base)
+                    .filter(|path| stdlib::is_module(path))
+                {
+                    self.error(
+                        format!("cannot find `{}` in `{module}`", ident.name),
+                        ident.span,
+                    );
+                    return Type::Unknown;
+                }
+
+                match self.resolve_ty(&base_ty) {
+                    Type::Class { id, name, args } => {
+                        match self.out.classes[&id].field(&ident.name) {
+                            Some(ty) => subst(ty, &self.class_subst(id, &args)),
+                            None => {
+                                self.error(
+                                    format!("no field `{}` on `{name}`", ident.name),
+                                    ident.span,
+                                );
+                                Type::Unknown
+                            }
+                        }
+                    }
+                    Type::Error => match ident.name.as_str() {
+                        "message" => Type::String,
+                        // another error or `nil`
+                        "cause" => Type::Unknown,
+                        name => {
+                            self.error(format!("no field `{name}` on `error`"), ident.span);
+                            Type::Unknown
+                        }
+                    },
+                    _ => Type::Unknown,
+                }
+            }
+            ExprKind::ClassInit(init) => self.check_class_init(expr, init),
+            ExprKind::Range(start, end, _) => {
+                for bound in [start, end].into_iter().flatten() {
+                    self.expect_expr(bound, &Type::Int);
+                }
+                Type::Range
+            }
+            ExprKind::List(items) => Type::List(Box::new(self.check_elems(items))),
+            ExprKind::Set(items) => Type::Set(Box::new(self.check_elems(items))),
+            ExprKind::Map(entries) => {
+                let keys: Vec<Type> = entries
+                    .iter()
+                    .map(|(key, _)| self.check_expr(key))
+                    .collect();
+                let values: Vec<Type> = entries
+                    .iter()
+                    .map(|(_, value)| self.check_expr(value))
+                    .collect();
+                Type::Map(
+                    Box::new(self.common_ty(keys)),
+                    Box::new(self.common_ty(values)),
+                )
+            }
+            ExprKind::Index(base, index) => {
+                let base_ty = self.check_expr(base);
+
+                match self.resolve_ty(&base_ty) {
+                    Type::List(elem) => match self.check_expr(index) {
+                        ty if self.resolve_ty(&ty) == Type::Range => Type::List(elem),
+                        ty => {
+                            if !self.unify(&Type::Int, &ty) {
+                                self.mismatch(&Type::Int, &ty, index.span);
+                            }
+                            *elem
+                        }
+                    },
+                    // a missing key gives `nil`
+                    Type::Map(key, _) => {
+                        self.expect_expr(index, &key);
+                        Type::Unknown
+                    }
+                    Type::Unknown | Type::Var(_) => {
+                        self.check_expr(index);
+                        Type::Unknown
+                    }
+                    ty => {
+                        self.check_expr(index);
+                        self.error(format!("`{ty}` can't be indexed"), base.span);
+                        Type::Unknown
+                    }
+                }
+            }
+            ExprKind::If(cond, then, otherwise) => {
+                self.expect_expr(cond, &Type::Bool);
+                let narrowing = self.narrowing(cond);
+
+                let then_narrowing = narrowing.as_ref().filter(|n| n.then);
+                let then_ty = self.narrow(then_narrowing, |this| this.check_block(then));
+
+                match otherwise {
+                    Some(otherwise) => {
+                        let else_narrowing = narrowing.as_ref().filter(|n| !n.then);
+                        let else_ty =
+                            self.narrow(else_narrowing, |this| this.check_expr(otherwise));
+
+                        // one branch giving `nil` makes the other optional
+                        match (self.shallow(&then_ty), self.shallow(&else_ty)) {
+                            (Type::Nil, Type::Nil | Type::Var(_) | Type::Never)
+                            | (Type::Var(_) | Type::Never, Type::Nil) => {}
+                            (Type::Nil, ty) | (ty, Type::Nil) => return ty.optional(),
+                            _ => {}
+                        }
+                        if !self.unify(&then_ty, &else_ty) {
+                            self.error(
+                                format!(
+                                    "`if` and `else` have incompatible types: `{}` and `{}`",
+                                    self.resolve_ty(&then_ty),
+                                    self.resolve_ty(&else_ty)
+                                ),
+                                otherwise.span,
+                            );
+                            return Type::Unknown;
+                        }
+
+                        match then_ty {
+                            Type::Never => else_ty,
+                            _ => then_ty,
+                        }
+                    }
+                    None => Type::Nil,
+                }
+            }
+            ExprKind::While(cond, body) => {
+                self.expect_expr(cond, &Type::Bool);
+                self.loop_stack.push(None);
+                self.check_block(body);
+                self.loop_stack.pop();
+                Type::Nil
+            }
+            ExprKind::Loop(body) => {
+                self.loop_stack.push(None);
+                self.check_block(body);
+                // a loop without a `break` never finishes
+                self.loop_stack.pop().flatten().unwrap_or(Type::Never)
+            }
+            ExprKind::For(pat, iter, body) => {
+                let iter_ty = self.check_expr(iter);
+                let elem = match self.resolve_ty(&iter_ty) {
+                    Type::Range => Type::Int,
+                    Type::List(elem) | Type::Set(elem) => *elem,
+                    Type::Map(key, _) => *key,
+                    Type::String => Type::String,
+                    Type::Generator(item) => *item,
+                    Type::Class { id, args, .. } if self.has_next(id) => self.item_ty(id, &args),
+                    Type::Interface {
+                        id: InterfaceId::Builtin(BuiltinInterface::Iterator),
+                        ..
+                    } => Type::Unknown,
+                    Type::Unknown | Type::Var(_) => Type::Unknown,
+                    ty => {
+                        self.error(format!("`{ty}` is not iterable"), iter.span);
+                        Type::Unknown
+                    }
+                };
+
+                self.bind_pattern(pat, elem);
+                self.loop_stack.push(None);
+                self.check_block(body);
+                self.loop_stack.pop();
+                Type::Nil
+            }
+            ExprKind::Block(block) => self.check_block(block),
+            ExprKind::Paren(inner) => self.check_expr(inner),
+            ExprKind::Return(value) => {
+                let ret = self.ret_stack.last().cloned().unwrap_or(Type::Unknown);
+                let (ty, span) = match value {
+                    Some(value) => (self.check_expr(value), value.span),
+                    None => (Type::Nil, expr.span),
+                };
+                if !self.unify(&ret, &ty) {
+                    self.mismatch(&ret, &ty, span);
+                }
+                Type::Never
+            }
+            ExprKind::Break(value) => {
+                let ty = match value {
+                    Some(value) => self.check_expr(value),
+                    None => Type::Nil,
+                };
+
+                if let Some(slot) = self.loop_stack.last_mut() {
+                    match slot.clone() {
+                        Some(prev) => {
+                            if !self.unify(&prev, &ty) {
+                                self.mismatch(&prev, &ty, expr.span);
+                            }
+                        }
+                        None => *slot = Some(ty),
+                    }
+                }
+                Type::Never
+            }
+            ExprKind::Try(inner) => {
+                let ty = self.check_expr(inner);
+                let ret = self.ret_stack.last().cloned().unwrap_or(Type::Unknown);
+
+                // a fn without a return type returns the error to its caller
+                match self.resolve_ty(&ret) {
+                    Type::Result(_) | Type::Error | Type::Unknown | Type::Var(_) => {}
+                    ret => self.error(
+                        format!("`?` can only be used in a fn returning `result`, not `{ret}`"),
+                        expr.span,
+                    ),
+                }
+
+                match self.resolve_ty(&ty) {
+                    Type::Result(ok) => *ok,
+                    // the error is always returned
+                    Type::Error => Type::Never,
+                    Type::Unknown | Type::Var(_) => Type::Unknown,
+                    ty => {
+                        self.error(format!("`?` expects a `result`, found `{ty}`"), inner.span);
+                        Type::Unknown
+                    }
+                }
+            }
+            ExprKind::Yield(value) => {
+                let item = self.yield_ty.clone().unwrap_or(Type::Unknown);
+                match value {
+                    Some(value) => {
+                        self.expect_expr(value, &item);
+                    }
+                    None => {
+                        if !self.unify(&item, &Type::Nil) {
+                            self.mismatch(&item, &Type::Nil, expr.span);
+                        }
+                    }
+                }
+                // whatever the generator is resumed with
+                Type::Unknown
+            }
+            ExprKind::Await(inner) => {
+                let ty = self.check_expr(inner);
+                match self.resolve_ty(&ty) {
+                    Type::Future(output) => *output,
+                    Type::Unknown | Type::Var(_) => Type::Unknown,
+                    ty => {
+                        self.error(format!("`{ty}` cannot be awaited"), inner.span);
+                        Type::Unknown
+                    }
+                }
+            }
+            ExprKind::Continue | ExprKind::Todo => Type::Never,
+            ExprKind::Panic(value) => {
+                if let Some(value) = value {
+                    self.check_expr(value);
+                }
+                Type::Never
+            }
+        }
+    }
+
+    fn check_exprs(&mut self, exprs: &[Expr]) {
+        for expr in exprs {
+            self.check_expr(expr);
+        }
+    }
+
+    fn path_ty(&mut self, id: NodeId, span: Span) -> Type {
+        match self.res.get(id) {
+            Some(Res::Local(pat)) => self.locals.get(&pat).cloned().unwrap_or(Type::Unknown),
+            Some(Res::Item(item)) => {
+                if let Some(sig) = self.fns.get(&item).cloned() {
+                    let map = self.instantiate(&sig.generics, span);
+                    Type::Fn(subst_sig(&sig.sig, &map))
+                } else if let Some(class) = self.out.classes.get(&item) {
+                    Type::ClassObject {
+                        id: item,
+                        name: class.name.clone(),
+                    }
+                } else {
+                    Type::Unknown
+                }
+            }
+            _ => Type::Unknown,
+        }
+    }
+
+    // the full path of a module or value reached through imports or the prelude
+    fn extern_path(&self, expr: &Expr) -> Option<String> {
+        match &expr.kind {
+            ExprKind::Path(path) => {
+                let name = &path.segments.last()?.name;
+                match self.res.get(expr.id)? {
+                    Res::Import(tree) => Some(self.res.import(tree)?.full_path(name)),
+                    Res::Builtin => Some(name.clone()),
+                    _ => None,
+                }
+            }
+            ExprKind::Field(base, ident) => {
+                Some(format!("{}.{}", self.extern_path(base)?, ident.name))
+            }
+            _ => None,
+        }
+    }
+
+    fn extern_ty(&self, expr: &Expr) -> Option<Type> {
+        let path = self.extern_path(expr)?;
+        self.externs
+            .get(&path)
+            .cloned()
+            .or_else(|| stdlib::member_ty(&path))
+    }
+
+    fn native_sig(&self, callee: &Expr) -> Option<NativeSig> {
+        stdlib::signature(&self.extern_path(callee)?)
+    }
+
+    // the element type of a literal, `unknown` when the elements differ
+    fn check_elems(&mut self, items: &[Expr]) -> Type {
+        let tys = items.iter().map(|item| self.check_expr(item)).collect();
+        self.common_ty(tys)
+    }
+
+    fn common_ty(&mut self, tys: Vec<Type>) -> Type {
+        let mut tys = tys.into_iter();
+        let Some(first) = tys.next() else {
+            return self.fresh_var();
+        };
+
+        for ty in tys {
+            if !self.unify(&first, &ty) {
+                return Type::Unknown;
+            }
+        }
+        first
+    }
+
+    // a class is iterable when it implements `Iterator` or just has a `next` method
+    fn has_next(&self, class: NodeId) -> bool {
+        self.out
+            .implements(class, InterfaceId::Builtin(BuiltinInterface::Iterator))
+            || self.out.classes[&class]
+                .methods
+                .get("next")
+                .is_some_and(|method| method.has_self)
+    }
+
+    // what `next` of an iterable class returns besides the `nil` ending the loop
+    fn item_ty(&self, class: NodeId, args: &[Type]) -> Type {
+        let Some(method) = self.out.classes[&class].methods.get("next") else {
+            return Type::Unknown;
+        };
+        match subst(&method.sig.ret, &self.class_subst(class, args)) {
+            Type::Optional(item) => *item,
+            ty => ty,
+        }
+    }
+
+    // `x != nil` or `x == nil` on a local holding an optional
+    fn narrowing(&self, cond: &Expr) -> Option<Narrowing> {
+        let ExprKind::Binary(op, lhs, rhs) = &cond.kind else {
+            return None;
+        };
+        let then = match op {
+            BinOp::NotEq => true,
+            BinOp::Eq => false,
+            _ => return None,
+        };
+        let (
+            ExprKind::Path(_),
+            ExprKind::Lit(Lit {
+                kind: LitKind::Nil, ..
+            }),
+        ) = (&lhs.kind, &rhs.kind)
+        else {
+            return None;
+        };
+        let Some(Res::Local(local)) = self.res.get(lhs.id) else {
+            return None;
+        };
+
+        match self.resolve_ty(self.locals.get(&local)?) {
+            Type::Optional(inner) => Some(Narrowing {
+                local,
+                inner: *inner,
+                then,
+            }),
+            _ => None,
+        }
+    }
+
+    // checks a branch with the local of `narrowing` holding the inner type of its optional
+    fn narrow(&mut self, narrowing: Option<&Narrowing>, f: impl FnOnce(&mut Self) -> Type) -> Type {
+        let Some(narrowing) = narrowing else {
+            return f(self);
+        };
+
+        let prev = self.locals.insert(narrowing.local, narrowing.inner.clone());
+        let ty = f(self);
+        if let Some(prev) = prev {
+            self.locals.insert(narrowing.local, prev);
+        }
+        ty
+    }
+
+    // the left hand side of an assignment
+    fn check_place(&mut self, expr: &Expr) -> Type {
+        match &expr.kind {
+            ExprKind::Path(_) | ExprKind::Field(..) | ExprKind::Index(..) => self.check_expr(expr),
+            _ => {
+                self.check_expr(expr);
+                self.error("invalid left-hand side of assignment", expr.span);
+                Type::Unknown
+            }
+        }
+    }
+
+    fn binary_ty(&mut self, op: BinOp, lhs: &Type, rhs: &Type, span: Span) -> Type {
+        let lhs = self.resolve_ty(lhs);
+        let rhs = self.resolve_ty(rhs);
+
+        let unknown = |ty: &Type| matches!(ty, Type::Unknown | Type::Var(_) | Type::Never);
+
+        if unknown(&lhs) || unknown(&rhs) {
+            return match op {
+                _ if op.is_comparison() => Type::Bool,
+                BinOp::And | BinOp::Or => Type::Bool,
+                _ if !unknown(&lhs) => lhs,
+                _ if !unknown(&rhs) => rhs,
+                _ => Type::Unknown,
+            };
+        }
+
+        let ty = match op {
+            BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Rem => match (&lhs, &rhs) {
+                (Type::Int, Type::Int) => Some(Type::Int),
+                (Type::Param { id: a, .. }, Type::Param { id: b, .. })
+                    if a == b && self.satisfies(&lhs, Bound::Builtin(BuiltinBound::Num)) =>
+                {
+                    Some(lhs.clone())
+                }
+                (l, r) if l.is_numeric() && r.is_numeric() => Some(Type::Float),
+                (Type::String, Type::String) if op == BinOp::Add => Some(Type::String),
+                _ => None,
+            },
+            BinOp::And | BinOp::Or => {
+                (lhs == Type::Bool && rhs == Type::Bool).then_some(Type::Bool)
+            }
+            BinOp::Eq | BinOp::NotEq => {
+                let comparable = (lhs.is_numeric() && rhs.is_numeric()) || self.unify(&lhs, &rhs);
+                comparable.then_some(Type::Bool)
+            }
+            BinOp::Lt | BinOp::LtEq | BinOp::Gt | BinOp::GtEq => {
+                let ordered = (lhs.is_numeric() && rhs.is_numeric())
+                    || (lhs == Type::String && rhs == Type::String)
+                    || (matches!(lhs, Type::Param { .. })
+                        && lhs == rhs
+                        && self.satisfies(&lhs, Bound::Builtin(BuiltinBound::Ord)));
+                ordered.then_some(Type::Bool)
+            }
+        };
+
+        match ty {
+            Some(ty) => ty,
+            None => {
+                self.error(
+                    format!("cannot apply `{}` to `{lhs}` and `{rhs}`", op.as_str()),
+                    span,
+                );
+                Type::Unknown
+            }
+        }
+    }
+
+    fn check_args(&mut self, sig: &FnSig, args: &[Expr], span: Span) -> Type {
+        let count = sig.params.len();
+        if count != args.len() {
+            self.arg_count_error(count.to_string(), count, args.len(), span);
+        }
+
+        self.check_params(&sig.params, args);
+        (*sig.ret).clone()
+    }
+
+    // a function of `kai`, which can take a varying number of arguments
+    fn check_native_args(&mut self, sig: &NativeSig, args: &[Expr], span: Span) -> Type {
+        let (min, max) = (sig.required, sig.params.len());
+        if args.len() < min || (!sig.variadic && args.len() > max) {
+            let expected = match (sig.variadic, min == max) {
+                (true, _) => format!("at least {min}"),
+                (false, true) => min.to_string(),
+                (false, false) => format!("{min} to {max}"),
+            };
+            let count = if sig.variadic { min } else { max };
+            self.arg_count_error(expected, count, args.len(), span);
+        }
+
+        self.check_params(&sig.params, args);
+        sig.ret.clone()
+    }
+
+    fn check_params(&mut self, params: &[Type], args: &[Expr]) {
+        for (i, arg) in args.iter().enumerate() {
+            match params.get(i) {
+                Some(param) => {
+                    self.expect_expr(arg, param);
+                }
+                None => {
+                    self.check_expr(arg);
+                }
+            }
+        }
+    }
+
+    // `expected` is the number of arguments the function takes, `count` picks the plural
+    fn arg_count_error(&mut self, expected: String, count: usize, found: usize, span: Span) {
+        self.error(
+            format!(
+                "this function takes {expected} argument{} but {found} {} supplied",
+                if count == 1 { "" } else { "s" },
+                if found == 1 { "was" } else { "were" },
+            ),
+            span,
+        );
+    }
+
+    fn check_method_call(&mut self, call: &MethodCall) -> Type {
+        let receiver = self.check_expr(&call.receiver);
+        let name = &call.method.name;
+
+        // `db.query(..)` calls a function of a module the embedder provides, `io.print(..)`
+        // one of `kai`
+        if let Some(path) = self.extern_path(&call.receiver) {
+            let full_path = format!("{path}.{name}");
+            if let Some(sig) = stdlib::signature(&full_path) {
+                return self.check_native_args(&sig, &call.args, call.span);
+            }
+            if stdlib::is_module(&path) && stdlib::member_ty(&full_path).is_none() {
+                self.check_exprs(&call.args);
+                self.error(
+                    format!("cannot find `{name}` in `{path}`"),
+                    call.method.span,
+                );
+                return Type::Unknown;
+            }
+
+            match self.externs.get(&full_path).cloned() {
+                Some(Type::Fn(sig)) => return self.check_args(&sig, &call.args, call.span),
+                Some(ty) => {
+                    self.check_exprs(&call.args);
+                    self.error(format!("`{ty}` is not callable"), call.method.span);
+                    return Type::Unknown;
+                }
+                None => {}
+            }
+        }
+
+        let (id, on_instance, mut map) = match self.resolve_ty(&receiver) {
+            Type::Class { id, args, .. } => (id, true, self.class_subst(id, &args)),
+            Type::ClassObject { id, .. } => {
+                let generics = self.out.classes[&id].generics.clone();
+                (id, false, self.instantiate(&generics, call.span))
+            }
+            // dispatched on the class of the value at runtime
+            Type::Interface { id, name: iface } => {
+                let Some(method) = self.out.interfaces[&id].methods.get(name).cloned() else {
+                    self.check_exprs(&call.args);
+                    self.error(format!("no method `{name}` on `{iface}`"), call.method.span);
+                    return Type::Unknown;
+                };
+                return self.check_args(&method.sig, &call.args, call.span);
+            }
+            // only the interfaces bounding a parameter give it methods
+            Type::Param { id, name: param } => {
+                let bounds = self.param_bounds.get(&id).cloned().unwrap_or_default();
+                let method = bounds.iter().find_map(|bound| match bound {
+                    Bound::Interface(iface) => self.out.interfaces[iface].methods.get(name),
+                    Bound::Builtin(_) => None,
+                });
+                let Some(method) = method.cloned() else {
+                    self.check_exprs(&call.args);
+                    self.error(format!("no method `{name}` on `{param}`"), call.method.span);
+                    return Type::Unknown;
+                };
+                return self.check_args(&method.sig, &call.args, call.span);
+            }
+            // strings, collections, ranges and errors have the methods of the stdlib
+            ty if stdlib::has_methods(&ty) => {
+                let Some(sig) = stdlib::method_signature(&ty, name) else {
+                    self.check_exprs(&call.args);
+                    self.error(format!("no method `{name}` on `{ty}`"), call.method.span);
+                    return Type::Unknown;
+                };
+                return self.check_native_args(&sig, &call.args, call.span);
+            }
+            _ => {
+                self.check_exprs(&call.args);
+                return Type::Unknown;
+            }
+        };
+
+        let class = &self.out.classes[&id];
+        let class_name = class.name.clone();
+
+        let Some(method) = class.methods.get(name).cloned() else {
+            self.check_exprs(&call.args);
+            self.error(
+                format!("no method `{name}` on `{class_name}`"),
+                call.method.span,
+            );
+            return Type::Unknown;
+        };
+
+        if method.has_self && !on_instance {
+            self.error(
+                format!("`{name}` takes `self`, call it on an instance of `{class_name}`"),
+                call.method.span,
+            );
+        } else if !method.has_self && on_instance {
+            self.error(
+                format!("`{name}` is a static method, call it as `{class_name}.{name}(..)`"),
+                call.method.span,
+            );
+        }
+
+        map.extend(self.instantiate(&method.generics, call.span));
+        let sig = subst_sig(&method.sig, &map);
+        self.check_args(&sig, &call.args, call.span)
+    }
+
+    fn check_class_init(&mut self, expr: &Expr, init: &ClassInit) -> Type {
+        let name = init
+            .path
+            .segments
+            .last()
+            .map(|i| i.name.as_str())
+            .unwrap_or("");
+
+        let class = match self.res.get(expr.id) {
+            Some(Res::Item(id)) => self.out.classes.get(&id).map(|class| (id, class.clone())),
+            // an unresolved name, already reported
+            None => {
+                for field in &init.fields {
+                    self.check_expr(&field.expr);
+                }
+                return Type::Unknown;
+            }
+            _ => None,
+        };
+
+        let Some((id, class)) = class else {
+            self.error(format!("`{name}` is not a class"), init.path.span);
+            return Type::Unknown;
+        };
+
+        let map = self.instantiate(&class.generics, init.span);
+        let mut seen: HashMap<&str, Span> = HashMap::new();
+
+        for field in &init.fields {
+            let field_name = field.ident.name.as_str();
+
+            if let Some(previous) = seen.insert(field_name, field.ident.span) {
+                let diagnostic = Diagnostic::error(
+                    format!("field `{field_name}` specified more than once"),
+                    field.ident.span,
+                )
+                .with_label(previous, "first specified here");
+                self.out.diagnostics.push(diagnostic);
+            }
+
+            match class.field(field_name) {
+                Some(ty) => {
+                    self.expect_expr(&field.expr, &subst(ty, &map));
+                }
+                None => {
+                    self.check_expr(&field.expr);
+                    self.error(
+                        format!("`{name}` has no field named `{field_name}`"),
+                        field.ident.span,
+                    );
+                }
+            }
+        }
+
+        let missing: Vec<String> = class
+            .fields
+            .iter()
+            .filter(|(field, _)| !seen.contains_key(field.as_str()))
+            .map(|(field, _)| format!("`{field}`"))
+            .collect();
+
+        if !missing.is_empty() {
+            self.error(
+                format!(
+                    "missing field{} {} in initializer of `{name}`",
+                    if missing.len() == 1 { "" } else { "s" },
+                    missing.join(", ")
+                ),
+                init.span,
+            );
+        }
+
+        let args = class
+            .generics
+            .iter()
+            .map(|param| map[&param.id].clone())
+            .collect();
+
+        Type::Class {
+            id,
+            name: class.name.clone(),
+            args,
+        }
+    }
+
+    //END checking
+
+    //BEGIN inference
+
+    fn fresh_var(&mut self) -> Type {
+        self.vars.push(None);
+        Type::Var(self.vars.len() as u32 - 1)
+    }
+
+    // follows inference variables all the way down, unsolved ones become unknown
+    pub fn resolve_ty(&self, ty: &Type) -> Type {
+        match ty {
+            Type::Var(var) => match &self.vars[*var as usize] {
+                Some(ty) => self.resolve_ty(ty),
+                None => Type::Var(*var),
+            },
+            Type::List(elem) => Type::List(Box::new(self.resolve_ty(elem))),
+            Type::Set(elem) => Type::Set(Box::new(self.resolve_ty(elem))),
+            Type::Map(key, value) => Type::Map(
+                Box::new(self.resolve_ty(key)),
+                Box::new(self.resolve_ty(value)),
+            ),
+            Type::Result(ok) => Type::Result(Box::new(self.resolve_ty(ok))),
+            Type::Future(output) => Type::Future(Box::new(self.resolve_ty(output))),
+            Type::Generator(item) => Type::Generator(Box::new(self.resolve_ty(item))),
+            Type::Optional(inner) => Type::Optional(Box::new(self.resolve_ty(inner))),
+            Type::Fn(sig) => Type::Fn(FnSig {
+                params: sig.params.iter().map(|ty| self.resolve_ty(ty)).collect(),
+                ret: Box::new(self.resolve_ty(&sig.ret)),
+            }),
+            Type::Class { id, name, args } => Type::Class {
+                id: *id,
+                name: name.clone(),
+                args: args.iter().map(|ty| self.resolve_ty(ty)).collect(),
+            },
+            ty => ty.clone(),
+        }
+    }
+
+    fn shallow(&self, ty: &Type) -> Type {
+        match ty {
+            Type::Var(var) => match &self.vars[*var as usize] {
+                Some(ty) => self.shallow(ty),
+                None => ty.clone(),
+            },
+            ty => ty.clone(),
+        }
+    }
+
+    /// Makes `expected` and `found` the same type if possible
+    pub fn unify(&mut self, expected: &Type, found: &Type) -> bool {
+        let expected = self.shallow(expected);
+        let found = self.shallow(found);
+
+        match (&expected, &found) {
+            (Type::Unknown, _) | (_, Type::Unknown) => true,
+            (_, Type::Never) | (Type::Never, _) => true,
+            (Type::Var(a), Type::Var(b)) if a == b => true,
+            (Type::Var(var), ty) | (ty, Type::Var(var)) => {
+                self.vars[*var as usize] = Some(ty.clone());
+                true
+            }
+            (Type::List(a), Type::List(b)) | (Type::Set(a), Type::Set(b)) => self.unify(a, b),
+            (Type::Map(k1, v1), Type::Map(k2, v2)) => self.unify(k1, k2) && self.unify(v1, v2),
+            (Type::Result(a), Type::Result(b)) => self.unify(a, b),
+            (Type::Future(a), Type::Future(b)) | (Type::Generator(a), Type::Generator(b)) => {
+                self.unify(a, b)
+            }
+            // a `result` holds either an error or a plain value
+            (Type::Result(_), Type::Error) => true,
+            (Type::Result(ok), found) => self.unify(ok, found),
+            // and an optional `nil` or a plain value
+            (Type::Optional(a), Type::Optional(b)) => self.unify(a, b),
+            (Type::Optional(_), Type::Nil) => true,
+            (Type::Optional(inner), found) => self.unify(inner, found),
+            (Type::Fn(a), Type::Fn(b)) => {
+                a.params.len() == b.params.len()
+                    && a.params
+                        .iter()
+                        .zip(b.params.iter())
+                        .all(|(a, b)| self.unify(a, b))
+                    && self.unify(&a.ret, &b.ret)
+            }
+            (
+                Type::Class {
+                    id: a,
+                    args: a_args,
+                    ..
+                },
+                Type::Class {
+                    id: b,
+                    args: b_args,
+                    ..
+                },
+            ) => {
+                a == b
+                    && a_args.len() == b_args.len()
+                    && a_args
+                        .iter()
+                        .zip(b_args.iter())
+                        .all(|(a, b)| self.unify(a, b))
+            }
+            (Type::ClassObject { id: a, .. }, Type::ClassObject { id: b, .. })
+            | (Type::Param { id: a, .. }, Type::Param { id: b, .. }) => a == b,
+            (Type::Interface { id: a, .. }, Type::Interface { id: b, .. }) => a == b,
+            // classes can be used where an interface they implement is expected, and so
+            // can parameters bounded by it
+            (Type::Interface { id: iface, .. }, Type::Class { id: class, .. }) => {
+                self.out.implements(*class, *iface)
+            }
+            (Type::Interface { id: iface, .. }, found @ Type::Param { .. }) => {
+                self.satisfies(found, Bound::Interface(*iface))
+            }
+            (a, b) => a == b,
+        }
+    }
+
+    /// Whether a concrete type or a bounded generic parameter supports the bound
+    pub fn satisfies(&self, ty: &Type, bound: Bound) -> bool {
+        let num = Bound::Builtin(BuiltinBound::Num);
+        let ord = Bound::Builtin(BuiltinBound::Ord);
+
+        match (ty, bound) {
+            (Type::Int | Type::Float, Bound::Builtin(_)) => true,
+            (Type::String, _) => bound == ord,
+            (Type::Class { id, .. }, Bound::Interface(iface)) => self.out.implements(*id, iface),
+            (Type::Interface { id, .. }, Bound::Interface(iface)) => *id == iface,
+            (Type::Param { id, .. }, _) => self.param_bounds.get(id).is_some_and(|bounds| {
+                bounds.contains(&bound)
+                    // every number can be ordered
+                    || (bound == ord && bounds.contains(&num))
+            }),
+            (Type::Unknown, _) => true,
+            _ => false,
+        }
+    }
+
+    fn bound_name(&self, bound: Bound) -> EcoString {
+        match bound {
+            Bound::Builtin(builtin) => builtin.name().into(),
+            Bound::Interface(iface) => self.out.interfaces[&iface].name.clone(),
+        }
+    }
+
+    //END inference
+
+    fn mismatch(&mut self, expected: &Type, found: &Type, span: Span) {
+        let expected = self.resolve_ty(expected);
+        let found = self.resolve_ty(found);
+        self.error(
+            format!("mismatched types: expected `{expected}`, found `{found}`"),
+            span,
+        );
+    }
+
+    fn error(&mut self, message: impl Into<String>, span: Span) {
+        self.out.diagnostics.push(Diagnostic::error(message, span));
+    }
+}
+
+// a local compared with `nil` by the condition of an `if`, `then` is whether the `then`
+// branch is the one where it holds a value
+#[derive(Debug)]
+struct Narrowing {
+    local: NodeId,
+    inner: Type,
+    then: bool,
+}
+
+#[derive(Debug)]
+struct Obligation {
+    ty: Type,
+    bound: Bound,
+    // name of the generic parameter which requires the bound
+    param: EcoString,
+    span: Span,
+}
+
+/// Replaces generic parameters with the types in `map`
+pub fn subst(ty: &Type, map: &HashMap<NodeId, Type>) -> Type {
+    match ty {
+        Type::Param { id, .. } => map.get(id).cloned().unwrap_or_else(|| ty.clone()),
+        Type::List(elem) => Type::List(Box::new(subst(elem, map))),
+        Type::Set(elem) => Type::Set(Box::new(subst(elem, map))),
+        Type::Map(key, value) => Type::Map(Box::new(subst(key, map)), Box::new(subst(value, map))),
+        Type::Result(ok) => Type::Result(Box::new(subst(ok, map))),
+        Type::Future(output) => Type::Future(Box::new(subst(output, map))),
+        Type::Generator(item) => Type::Generator(Box::new(subst(item, map))),
+        Type::Optional(inner) => Type::Optional(Box::new(subst(inner, map))),
+        Type::Fn(sig) => Type::Fn(subst_sig(sig, map)),
+        Type::Class { id, name, args } => Type::Class {
+            id: *id,
+            name: name.clone(),
+            args: args.iter().map(|ty| subst(ty, map)).collect(),
+        },
+        ty => ty.clone(),
+    }
+}
+
+fn subst_sig(sig: &FnSig, map: &HashMap<NodeId, Type>) -> FnSig {
+    FnSig {
+        params: sig.params.iter().map(|ty| subst(ty, map)).collect(),
+        ret: Box::new(subst(&sig.ret, map)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::resolve::resolve;
+
+    fn errors(code: &str) -> Vec<String> {
+        let file = parse(code).unwrap();
+        let resolutions = resolve(&file);
+        assert!(
+            !resolutions.has_errors(),
+            "unexpected resolve errors {:?}",
+            resolutions.diagnostics
+        );
+
+        check(&file, &resolutions)
+            .diagnostics
+            .into_iter()
+            .map(|d| d.message)
+            .collect()
+    }
+
+    #[test]
+    fn checks_sample_program() {
+        let code = r#"
+            using kai.io;
+
+            fn square(a: int) -> int {
+                a * a
+            }
+
+            fn main() {
+                for i in 1..=10 {
+                    io.print(square(i));
+                }
+            }
+        "#;
+
+        assert_eq!(errors(code), Vec::<String>::new());
+    }
+
+    #[test]
+    fn reports_return_type_mismatch() {
+        let code = r#"fn name() -> string { 10 }"#;
+        let file = parse(code).unwrap();
+        let results = check(&file, &resolve(&file));
+
+        assert_eq!(results.diagnostics.len(), 1);
+        assert_eq!(
+            results.diagnostics[0].message,
+            "mismatched types: expected `string`, found `int`"
+        );
+        assert_eq!(results.diagnostics[0].span, Span::new(22, 24));
+    }
+
+    #[test]
+    fn reports_argument_mismatches() {
+        let code = r#"
+            fn add(a: int, b: int) -> int { a + b }
+            fn main() {
+                add(1, "two");
+                add(1);
+            }
+        "#;
+
+        assert_eq!(
+            errors(code),
+            vec![
+                "mismatched types: expected `int`, found `string`",
+                "this function takes 2 arguments but 1 was supplied",
+            ]
+        );
+    }
+
+    #[test]
+    fn infers_let_bindings() {
+        let code = r#"
+            fn main() {
+                let a = 1;
+                let b = a + 2.5;
+                let c: int = b;
+                let mut d;
+                d = "text";
+                let e: bool = d;
+            }
+        "#;
+
+        assert_eq!(
+            errors(code),
+            vec![
+                "mismatched types: expected `int`, found `float`",
+                "mismatched types: expected `bool`, found `string`",
+            ]
+        );
+    }
+
+    #[test]
+    fn infers_collection_literals() {
+        let code = r#"
+            fn main() {
+                let xs = [1, 2];
+                let a: string = xs[0];
+                let b: list<int> = xs[0..1];
+                let mixed: list<int> = [1, "two"];
+                let m = {"a": 1.5};
+                m[1] = 2.0;
+                let s: set<string> = {1, 2};
+                let e: list<string> = [];
+                3[0];
+            }
+        "#;
+
+        assert_eq!(
+            errors(code),
+            vec![
+                "mismatched types: expected `string`, found `int`",
+                "mismatched types: expected `string`, found `int`",
+                "mismatched types: expected `set<string>`, found `set<int>`",
+                "`int` can't be indexed",
+            ]
+        );
+    }
+
+    #[test]
+    fn records_expression_types() {
+        let file = parse("fn main() { let x = 1 < 2; x }").unwrap();
+        let results = check(&file, &resolve(&file));
+
+        let ItemKind::Fn(item_fn) = &file.items[0].kind else {
+            unreachable!()
+        };
+        let StmtKind::Expr(tail) = &item_fn.body.as_ref().unwrap().stmts[1].kind else {
+            panic!("expected a tail expression");
+        };
+        assert_eq!(results.expr_ty(tail.id), Some(&Type::Bool));
+    }
+
+    #[test]
+    fn checks_operators() {
+        let code = r#"
+            fn main(s: string, n: int, b: bool) {
+                s + s;
+                s - s;
+                n % 2 == 0 && b;
+                !n;
+                if n { }
+            }
+        "#;
+
+        assert_eq!(
+            errors(code),
+            vec![
+                "cannot apply `-` to `string` and `string`",
+                "cannot apply `!` to `int`",
+                "mismatched types: expected `bool`, found `int`",
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_classes() {
+        let code = r#"
+            class Person {
+                pub name: string;
+                pub age: int;
+
+                pub new(name: string, age: int) -> Person {
+                    Person { name, age }
+                }
+
+                pub fn is_adult(self) -> bool {
+                    self.age >= 18
+                }
+            }
+
+            fn main() {
+                let p = Person.new("Aadi", 20);
+                let adult: bool = p.is_adult();
+                let name: int = p.name;
+                p.height;
+                p.new("x", 1);
+                Person { name: "x" };
+            }
+        "#;
+
+        assert_eq!(
+            errors(code),
+            vec![
+                "mismatched types: expected `int`, found `string`",
+                "no field `height` on `Person`",
+                "`new` is a static method, call it as `Person.new(..)`",
+                "missing field `age` in initializer of `Person`",
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_lists_maps_and_function_types() {
+        let code = r#"
+            fn apply(f: fn(int) -> int, x: int) -> int { f(x) }
+            fn double(x: int) -> int { x * 2 }
+            fn first(xs: list<string>, m: map<string, int>) -> string {
+                for x in xs { return x; }
+                for k in m { return k; }
+                "none"
+            }
+            fn main() {
+                apply(double, 1);
+                apply(first, 1);
+            }
+        "#;
+
+        assert_eq!(
+            errors(code),
+            vec!["mismatched types: expected `fn(int) -> int`, found `fn(list<string>, map<string, int>) -> string`"]
+        );
+    }
+
+    #[test]
+    fn checks_calls_of_the_stdlib() {
+        let code = r#"
+            using kai.io;
+            using kai.math;
+            using kai.string.{len};
+
+            fn main() {
+                io.print(1, "two", 3.0);
+                io.prnt(1);
+                let x: string = math.sqrt(4);
+                let y: float = math.log(8, 2) + math.pi;
+                math.atan2(1.0);
+                math.log(1, 2, 3);
+                len("abc", 1);
+                let n: int = len(1);
+                let z = math.tau_;
+            }
+        "#;
+
+        assert_eq!(
+            errors(code),
+            vec![
+                "cannot find `prnt` in `kai.io`",
+                "mismatched types: expected `string`, found `float`",
+                "this function takes 2 arguments but 1 was supplied",
+                "this function takes 1 to 2 arguments but 3 were supplied",
+                "this function takes 1 argument but 2 were supplied",
+                "mismatched types: expected `string`, found `int`",
+                "cannot find `tau_` in `kai.math`",
+            ]
+        );
+    }
+
+    #[test]
+    fn warns_on_discarded_futures() {
+        let code = r#"
+            using kai.task;
+            using kai.time;
+            using kai.time.{sleep};
+
+            async fn work() -> int { 1 }
+
+            async fn main() {
+                time.sleep(500);
+                sleep(500);
+                work();
+                task.spawn(work());
+                await time.sleep(500);
+                let _pending = work();
+            }
+        "#;
+
+        assert_eq!(
+            errors(code),
+            vec![
+                "this future is never awaited, it does nothing unless it is",
+                "this future is never awaited, it does nothing unless it is",
+                "this future is never awaited, it does nothing unless it is",
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_methods_of_values() {
+        let code = r#"
+            fn main() {
+                let n: string = [1].len();
+                "abc".lenn();
+                let xs = [1, 2];
+                xs.push("three");
+                let x: int = xs.pop();
+                let ages = {"ann": 30};
+                let age: string = ages.remove("ann");
+                ages.contains(1);
+                let s: int = "abc".to_upper();
+                (0..3).take();
+                let ok: list<int> = (0..3).collect();
+            }
+        "#;
+
+        assert_eq!(
+            errors(code),
+            vec![
+                "mismatched types: expected `string`, found `int`",
+                "no method `lenn` on `string`",
+                "mismatched types: expected `int`, found `string`",
+                "mismatched types: expected `int`, found `int?`",
+                "mismatched types: expected `string`, found `int?`",
+                "mismatched types: expected `string`, found `int`",
+                "mismatched types: expected `int`, found `string`",
+                "this function takes 1 argument but 0 were supplied",
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_results_and_try() {
+        let code = r#"
+            fn parse(s: string) -> result<int> {
+                if s == "" { return fail(); }
+                1
+            }
+            fn fail() -> error { todo }
+            fn double(s: string) -> result<int> {
+                let n = parse(s)?;
+                n * 2
+            }
+            fn describe(e: error) -> string { e.message + e.code }
+            fn main() -> int {
+                let a: int = parse("1");
+                parse("1")? + 1
+            }
+            fn wrong() -> result<string> { 1? }
+            fn untyped() { parse("")?; }
+            fn typed() -> bool { parse("")? == 1 }
+        "#;
+
+        assert_eq!(
+            errors(code),
+            vec![
+                "no field `code` on `error`",
+                "mismatched types: expected `int`, found `result<int>`",
+                "`?` can only be used in a fn returning `result`, not `int`",
+                "`?` expects a `result`, found `int`",
+                "`?` can only be used in a fn returning `result`, not `bool`",
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_coroutines() {
+        let code = r#"
+            fn numbers() -> generator<int> {
+                yield 1;
+                yield "two";
+            }
+            fn plain() -> int { yield 1; 2 }
+            async fn fetch() -> int { 1 }
+            async fn main() {
+                let n: int = await fetch();
+                let f: future<int> = fetch();
+                let g: int = fetch();
+                for x in numbers() { let s: string = x; }
+                await 1;
+            }
+        "#;
+
+        assert_eq!(
+            errors(code),
+            vec![
+                "mismatched types: expected `int`, found `string`",
+                "`plain` contains `yield`, its return type must be a `generator`, not `int`",
+                "mismatched types: expected `int`, found `future<int>`",
+                "mismatched types: expected `string`, found `int`",
+                "`int` cannot be awaited",
+            ]
+        );
+    }
+
+    #[test]
+    fn infers_generic_type_arguments() {
+        let code = r#"
+            fn first<T>(xs: list<T>) -> T { todo }
+            fn pair<A, B>(a: A, b: B) -> B { b }
+            fn main(names: list<string>) {
+                let name: string = first(names);
+                let n: int = first(names);
+                let x: bool = pair(1, true);
+            }
+        "#;
+
+        assert_eq!(
+            errors(code),
+            vec!["mismatched types: expected `int`, found `string`"]
+        );
+    }
+
+    #[test]
+    fn generic_parameters_are_rigid_inside_their_item() {
+        let code = r#"
+            fn id<T>(x: T) -> T { x }
+            fn wrong<T>(x: T) -> T { 1 }
+            fn add<T>(a: T, b: T) -> T { a + b }
+        "#;
+
+        assert_eq!(
+            errors(code),
+            vec![
+                "mismatched types: expected `T`, found `int`",
+                "cannot apply `+` to `T` and `T`",
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_bounds() {
+        let code = r#"
+            fn sum<T: Num>(a: T, b: T) -> T { a + b }
+            fn max<T: Ord>(a: T, b: T) -> T { if a > b { a } else { b } }
+            fn main() {
+                let n: float = sum(1.5, 2.5);
+                sum("a", "b");
+                max("a", "b");
+                max(true, false);
+            }
+        "#;
+
+        assert_eq!(
+            errors(code),
+            vec![
+                "`string` does not satisfy the bound `Num` required by `T`",
+                "`bool` does not satisfy the bound `Ord` required by `T`",
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_interface_bounds() {
+        let code = r#"
+            interface Shape {
+                fn area(self) -> float;
+            }
+            class Square { side: float; }
+            impl Shape for Square {
+                fn area(self) -> float { self.side * self.side }
+            }
+            fn describe(shape: Shape) -> float { shape.area() }
+            fn total<T: Shape>(xs: list<T>) -> float {
+                let mut sum = 0.0;
+                for x in xs { sum += x.area() + describe(x); }
+                sum
+            }
+            fn wrong<T: Shape>(x: T) -> string { x.area() }
+            fn missing<T: Shape>(x: T) { x.perimeter(); }
+            fn plain<T>(x: T) { describe(x); }
+            fn main() {
+                let n: float = total([Square { side: 2.0 }]);
+                total([1, 2]);
+            }
+        "#;
+
+        assert_eq!(
+            errors(code),
+            vec![
+                "mismatched types: expected `string`, found `float`",
+                "no method `perimeter` on `T`",
+                "mismatched types: expected `Shape`, found `T`",
+                "`int` does not satisfy the bound `Shape` required by `T`",
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_generic_classes() {
+        let code = r#"
+            class Box<T> {
+                value: T;
+
+                pub new(value: T) -> Box<T> {
+                    Box { value }
+                }
+
+                pub fn get(self) -> T {
+                    self.value
+                }
+
+                pub fn map<U>(self, f: fn(T) -> U) -> Box<U> {
+                    Box { value: f(self.value) }
+                }
+            }
+
+            fn len(s: string) -> int { 0 }
+
+            fn main() {
+                let a = Box.new("text");
+                let s: string = a.get();
+                let b: Box<int> = a.map(len);
+                let c: Box<string> = Box { value: 1 };
+                let d: int = a.value;
+                let e: Box = b;
+            }
+        "#;
+
+        assert_eq!(
+            errors(code),
+            vec![
+                "mismatched types: expected `Box<string>`, found `Box<int>`",
+                "mismatched types: expected `int`, found `string`",
+                "`Box` expects 1 type argument, found 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_wrong_type_arguments() {
+        assert_eq!(
+            errors("fn main(a: list, b: int<string>) {}"),
+            vec![
+                "`list` expects 1 type argument, found 0",
+                "`int` expects 0 type arguments, found 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_interface_implementations() {
+        let code = r#"
+            interface Shape {
+                fn area(self) -> float;
+                fn name(self) -> string { "shape" }
+            }
+
+            class Square { side: float; }
+            class Circle { radius: float; }
+            class Dot {}
+
+            impl Shape for Square {
+                fn area(self) -> float { self.side * self.side }
+            }
+
+            impl Shape for Circle {
+                fn area(self) -> int { 1 }
+                fn perimeter(self) -> float { 0.0 }
+            }
+
+            impl Shape for Dot {}
+            impl Shape for Square {}
+            impl Square for Dot {}
+
+            fn total(a: Shape, b: Shape) -> float { a.area() + b.area() }
+
+            fn main() {
+                let square = Square { side: 2.0 };
+                let name: string = square.name();
+                total(square, Circle { radius: 1.0 });
+                total(square, Dot {});
+                total(square, 1);
+            }
+        "#;
+
+        assert_eq!(
+            errors(code),
+            vec![
+                "method `area` has an incompatible signature for interface `Shape`: expected `fn() -> float`, found `fn() -> int`",
+                "method `perimeter` is not a member of interface `Shape`",
+                "missing method `area` in implementation of `Shape` for `Dot`",
+                "`Shape` is already implemented for `Square`",
+                "expected an interface",
+                "mismatched types: expected `Shape`, found `int`",
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_builtin_interfaces() {
+        let code = r#"
+            class Point { x: int; y: int; }
+            class Countdown { n: int; }
+            class Naturals {
+                n: int;
+                fn next(self) -> int { self.n += 1; self.n }
+            }
+
+            impl Display for Point {
+                fn to_string(self) -> string { "point" }
+            }
+
+            impl Eq for Point {
+                fn eq(self, other: Point) -> bool { self.x == other.x }
+            }
+
+            impl Iterator for Countdown {
+                fn next(self) {
+                    if self.n == 0 { return nil; }
+                    self.n -= 1;
+                    self.n
+                }
+            }
+
+            fn show(value: Display) -> string { value.to_string() }
+
+            fn main() {
+                let p = Point { x: 1, y: 2 };
+                let s: string = show(p);
+                let same: bool = p == p;
+                let countdown = Countdown { n: 3 };
+                for i in countdown {}
+                let naturals = Naturals { n: 0 };
+                for n in naturals {}
+                for q in p {}
+                show(Countdown { n: 1 });
+            }
+        "#;
+
+        assert_eq!(
+            errors(code),
+            vec![
+                "`Point` is not iterable",
+                "mismatched types: expected `Display`, found `Countdown`",
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_typed_iterators() {
+        let code = r#"
+            class Countdown { n: int; }
+            class Words {
+                left: int;
+                fn next(self) -> string? {
+                    if self.left == 0 { return nil; }
+                    self.left -= 1;
+                    "word"
+                }
+            }
+            class Broken {
+                fn next(self) -> int {
+                    let y: int = if true { nil } else { 1 };
+                    y + 1
+                }
+            }
+
+            impl Iterator for Countdown {
+                fn next(self) -> int? {
+                    if self.n == 0 { nil } else { self.n -= 1; self.n + 1 }
+                }
+            }
+
+            fn main() {
+                let countdown = Countdown { n: 3 };
+                for i in countdown { let n: int = i; }
+                let words = Words { left: 2 };
+                for word in words { let n: int = word; }
+
+                let v: int = countdown.next();
+                let item = countdown.next();
+                if item != nil { let n: int = item; } else { let n: int? = item; }
+                if item == nil { item + 1; }
+            }
+        "#;
+
+        assert_eq!(
+            errors(code),
+            vec![
+                "mismatched types: expected `int`, found `int?`",
+                "mismatched types: expected `int`, found `string`",
+                "mismatched types: expected `int`, found `int?`",
+                "cannot apply `+` to `int?` and `int`",
+            ]
+        );
+    }
+
+    #[test]
+    fn interface_methods_must_take_self() {
+        assert_eq!(
+            errors("interface Factory { fn create() -> int; }"),
+            vec!["interface method `create` must take `self`"]
+        );
+    }
+}